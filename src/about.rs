@@ -0,0 +1,268 @@
+use crate::client::SubsonicClient;
+use crate::config::get_cache_dir;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use tokio::sync::oneshot;
+use url::Url;
+
+/// What the server told us about itself, fetched in the background.
+pub struct ServerInfo {
+    pub server_type: String,
+    pub api_version: String,
+    pub open_subsonic: bool,
+    pub extensions: Vec<String>,
+    pub latency_ms: u128,
+    pub artists: Option<usize>,
+    pub albums: Option<u64>,
+    pub songs: Option<u64>,
+    pub scanning: bool,
+}
+
+pub struct AboutInfo {
+    pub server_url: String,
+    pub mpris: String,
+    pub audio_device: Option<String>,
+    pub features: Vec<String>,
+    /// Plays waiting to be submitted, `None` when scrobbling is off
+    pub scrobbles: Option<usize>,
+    pub cache: Vec<(String, u64)>,
+    pub server: Option<Result<ServerInfo, String>>,
+    pub pending: Option<oneshot::Receiver<Result<ServerInfo, String>>>,
+}
+
+/// Strips any user:password from the URL so the screen is safe to paste.
+pub fn redact_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if !parsed.username().is_empty() || parsed.password().is_some() => {
+            let _ = parsed.set_username("redacted");
+            let _ = parsed.set_password(None);
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Size of each subdirectory of the cache dir, plus loose files as "other".
+fn cache_usage() -> Vec<(String, u64)> {
+    let Ok(cache_dir) = get_cache_dir() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&cache_dir) else {
+        return Vec::new();
+    };
+
+    let mut usage = Vec::new();
+    let mut other = 0;
+    for entry in entries.flatten() {
+        match entry.metadata() {
+            Ok(meta) if meta.is_dir() => {
+                usage.push((
+                    entry.file_name().to_string_lossy().into_owned(),
+                    dir_size(&entry.path()),
+                ));
+            }
+            Ok(meta) => other += meta.len(),
+            Err(_) => {}
+        }
+    }
+    usage.sort();
+    if other > 0 {
+        usage.push(("other".to_string(), other));
+    }
+    usage
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+async fn gather(client: &SubsonicClient) -> Result<ServerInfo, String> {
+    let started = Instant::now();
+    let ping = client.ping().await.map_err(|e| e.to_string())?;
+    let latency_ms = started.elapsed().as_millis();
+
+    let capabilities = client.capabilities().await.map_err(|e| e.to_string())?;
+    let extensions = capabilities
+        .extensions
+        .iter()
+        .map(|e| match e.versions.iter().max() {
+            Some(version) => format!("{} v{}", e.name, version),
+            None => e.name.clone(),
+        })
+        .collect();
+
+    let (artists, albums) = match client.get_artists().await {
+        Ok(response) => {
+            let artists: Vec<_> = response
+                .artists
+                .index
+                .into_iter()
+                .flat_map(|i| i.artist)
+                .collect();
+            let albums = artists
+                .iter()
+                .map(|a| a.album_count)
+                .sum::<Option<u64>>();
+            (Some(artists.len()), albums)
+        }
+        Err(_) => (None, None),
+    };
+    let (songs, scanning) = match client.get_scan_status().await {
+        Ok(response) => (response.scan_status.count, response.scan_status.scanning),
+        Err(_) => (None, false),
+    };
+
+    let server_type = capabilities.server.clone().unwrap_or_else(|| "Subsonic".to_string());
+
+    Ok(ServerInfo {
+        server_type,
+        api_version: ping.version,
+        open_subsonic: capabilities.open_subsonic,
+        extensions,
+        latency_ms,
+        artists,
+        albums,
+        songs,
+        scanning,
+    })
+}
+
+impl AboutInfo {
+    /// Collects the local details right away and starts fetching the server
+    /// ones; `poll` picks them up once they arrive.
+    pub fn new(
+        client: &SubsonicClient,
+        mpris_bus_name: &str,
+        features: Vec<String>,
+        scrobbles: Option<usize>,
+    ) -> Self {
+        let server_url = redact_url(&client.base_url);
+        let (tx, rx) = oneshot::channel();
+        let client = client.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(gather(&client).await);
+        });
+
+        Self {
+            server_url,
+            mpris: format!("registered as {}", mpris_bus_name),
+            audio_device: crate::audio::output_device_name(),
+            features,
+            scrobbles,
+            cache: cache_usage(),
+            server: None,
+            pending: Some(rx),
+        }
+    }
+
+    pub fn poll(&mut self) {
+        if let Some(rx) = self.pending.as_mut()
+            && let Ok(result) = rx.try_recv()
+        {
+            self.server = Some(result);
+            self.pending = None;
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("danavi {}", env!("CARGO_PKG_VERSION")),
+            String::new(),
+            format!("Server:        {}", self.server_url),
+        ];
+
+        match &self.server {
+            None => lines.push("Status:        loading…".to_string()),
+            Some(Err(e)) => lines.push(format!("Status:        unreachable ({})", e)),
+            Some(Ok(info)) => {
+                let count = |n: Option<u64>| n.map_or("unknown".to_string(), |n| n.to_string());
+                lines.push(format!("Type:          {}", info.server_type));
+                lines.push(format!("API version:   {}", info.api_version));
+                lines.push(format!("Latency:       {} ms", info.latency_ms));
+                lines.push(format!(
+                    "OpenSubsonic:  {}",
+                    if !info.open_subsonic {
+                        "no".to_string()
+                    } else if info.extensions.is_empty() {
+                        "yes (no extensions)".to_string()
+                    } else {
+                        info.extensions.join(", ")
+                    }
+                ));
+                lines.push(String::new());
+                lines.push(format!(
+                    "Artists:       {}",
+                    count(info.artists.map(|n| n as u64))
+                ));
+                lines.push(format!(
+                    "Albums:        {}",
+                    count(info.albums)
+                ));
+                lines.push(format!(
+                    "Songs:         {}{}",
+                    count(info.songs),
+                    if info.scanning { " (scan in progress)" } else { "" }
+                ));
+            }
+        }
+
+        lines.push(String::new());
+        lines.push(format!("MPRIS:         {}", self.mpris));
+        lines.push(format!(
+            "Audio device:  {}",
+            self.audio_device.as_deref().unwrap_or("unknown")
+        ));
+        lines.push(format!(
+            "Features:      {}",
+            if self.features.is_empty() {
+                "none".to_string()
+            } else {
+                self.features.join(", ")
+            }
+        ));
+        lines.push(format!(
+            "Scrobbles:     {}",
+            match self.scrobbles {
+                None => "off".to_string(),
+                Some(0) => "all sent".to_string(),
+                Some(n) => format!("{} waiting to be sent", n),
+            }
+        ));
+
+        if self.cache.is_empty() {
+            lines.push("Cache:         empty".to_string());
+        } else {
+            lines.push("Cache:".to_string());
+            for (name, size) in &self.cache {
+                lines.push(format!("  {:<13}{}", name, format_size(*size)));
+            }
+        }
+        lines
+    }
+}
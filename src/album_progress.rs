@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use danavi::config::get_cache_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const FILE_NAME: &str = "album_progress.json";
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Where an album was left off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Progress {
+    pub track_index: usize,
+    /// Seconds into the track
+    pub position: u64,
+    /// Unix time of the last update
+    pub updated: u64,
+}
+
+/// Partly played albums by album id, kept in the cache dir so long albums
+/// can be resumed without server bookmarks.
+#[derive(Default)]
+pub struct AlbumProgress {
+    // None when disabled or there's no cache dir
+    path: Option<PathBuf>,
+    entries: HashMap<String, Progress>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl AlbumProgress {
+    /// Loads the saved entries, pruning those older than `max_age_days`.
+    /// Zero days turns remembering off.
+    pub fn load(max_age_days: u64) -> Self {
+        if max_age_days == 0 {
+            return Self::default();
+        }
+        let path = get_cache_dir().ok().map(|dir| dir.join(FILE_NAME));
+        let mut entries: HashMap<String, Progress> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        let cutoff = now().saturating_sub(max_age_days * SECS_PER_DAY);
+        let count = entries.len();
+        entries.retain(|_, progress| progress.updated >= cutoff);
+        let progress = Self { path, entries };
+        if progress.entries.len() != count {
+            let _ = progress.save();
+        }
+        progress
+    }
+
+    pub fn get(&self, album_id: &str) -> Option<&Progress> {
+        self.entries.get(album_id)
+    }
+
+    pub fn record(&mut self, album_id: &str, track_index: usize, position: Duration) -> Result<()> {
+        if self.path.is_none() {
+            return Ok(());
+        }
+        self.entries.insert(
+            album_id.to_string(),
+            Progress {
+                track_index,
+                position: position.as_secs(),
+                updated: now(),
+            },
+        );
+        self.save()
+    }
+
+    /// Forgets an album, e.g. once it has been played to the end.
+    pub fn clear(&mut self, album_id: &str) -> Result<()> {
+        if self.entries.remove(album_id).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create cache directory")?;
+        }
+        let content = serde_json::to_string(&self.entries)?;
+        fs::write(path, content).context("Failed to write album progress")?;
+        Ok(())
+    }
+}
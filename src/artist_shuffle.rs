@@ -0,0 +1,95 @@
+use danavi::client::SubsonicClient;
+use danavi::types::Song;
+use futures_util::StreamExt;
+use rand::seq::SliceRandom;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+// Albums fetched at once
+const CONCURRENCY: usize = 4;
+
+enum Event {
+    Done(Vec<Song>),
+    Failed(String),
+}
+
+/// Every song by an artist, gathered album by album in the background to be
+/// played shuffled.
+pub struct ArtistShuffle {
+    pub artist: String,
+    pub total: usize,
+    pub done: usize,
+    pub failures: Vec<String>,
+    songs: Vec<Song>,
+    rx: mpsc::UnboundedReceiver<Event>,
+    handle: JoinHandle<()>,
+}
+
+impl ArtistShuffle {
+    /// Starts fetching the songs of each album.
+    pub fn start(client: &SubsonicClient, artist: String, album_ids: Vec<String>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let total = album_ids.len();
+        let client = client.clone();
+        let handle = tokio::spawn(async move {
+            let mut albums = futures_util::stream::iter(album_ids)
+                .map(|album_id| {
+                    let client = &client;
+                    async move {
+                        match crate::fetch_album_songs(client, &album_id).await {
+                            Ok((_, songs)) => Event::Done(songs),
+                            Err(e) => Event::Failed(format!("{}: {}", album_id, e)),
+                        }
+                    }
+                })
+                .buffer_unordered(CONCURRENCY);
+            while let Some(event) = albums.next().await {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            artist,
+            total,
+            done: 0,
+            failures: Vec::new(),
+            songs: Vec::new(),
+            rx,
+            handle,
+        }
+    }
+
+    /// Picks up fetched albums. Returns true once every album is in.
+    pub fn poll(&mut self) -> bool {
+        while let Ok(event) = self.rx.try_recv() {
+            self.done += 1;
+            match event {
+                Event::Done(songs) => self.songs.extend(songs),
+                Event::Failed(reason) => self.failures.push(reason),
+            }
+        }
+        self.done >= self.total
+    }
+
+    pub fn cancel(self) {
+        self.handle.abort();
+    }
+
+    pub fn progress(&self) -> String {
+        format!("Gathering {}: {}/{} albums", self.artist, self.done, self.total)
+    }
+
+    /// The gathered songs, shuffled, with any song on several albums kept once.
+    pub fn into_songs(self) -> Vec<Song> {
+        let mut seen = std::collections::HashSet::new();
+        let mut songs: Vec<Song> = self
+            .songs
+            .into_iter()
+            .filter(|s| seen.insert(s.id.clone()))
+            .collect();
+        songs.shuffle(&mut rand::thread_rng());
+        songs
+    }
+}
@@ -1,16 +1,506 @@
+//! Audio output.
+
+use crate::client::non_audio_error;
+use crate::loudness::{Leveler, Loudness, Measurement};
 use anyhow::{Context, Result};
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Source, Sink};
-use std::io::Cursor;
-use std::sync::Mutex;
+use rodio::cpal::traits::HostTrait;
+use rodio::source::{EmptyCallback, PeriodicAccess, SamplesConverter};
+use rodio::{DeviceTrait, Decoder, OutputStream, OutputStreamHandle, Source, Sink};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
+/// Plays tracks on the default output device: the current one, and for
+/// gapless albums the next lined up behind it.
+///
+/// Tracks play from a [`StreamBuffer`], so they can start while still
+/// downloading. Pausing can fade out and in, and loudness can be evened
+/// out between tracks. Each track sends a [`TrackEnd`] on the channel given to
+/// [`AudioPlayer::new`] when it plays out, which
+/// [`AudioPlayer::track_ended`] turns into what happened.
 pub struct AudioPlayer {
+    output: Mutex<Output>,
+    // Shared with the fade task
+    sink: Arc<Mutex<Option<Sink>>>,
+    // Shared with the fade task, which stops it once a fade out is over
+    clock: Arc<Mutex<Clock>>,
+    // The current track, kept for seeking by decoding it again
+    track: Mutex<Option<Arc<StreamBuffer>>>,
+    // Where in the song the current track begins, past zero when the server
+    // cut the stream to seek
+    start: Mutex<Duration>,
+    // The current track's length, when its format says
+    duration: Mutex<Option<Duration>>,
+    // Level to normalize tracks to in dBFS, if at all
+    normalize: Mutex<Option<f32>>,
+    // The current track's loudness, when it was all there to measure
+    loudness: Mutex<Option<Measurement>>,
+    // Kept here rather than on the sink, since each track gets a new sink
+    volume: Arc<Mutex<Volume>>,
+    // Paused as far as callers are concerned, even while fading out
+    paused: AtomicBool,
+    fade: Mutex<Duration>,
+    // How far a pause or resume fade has got, from 0.0 silent to 1.0
+    fade_level: Arc<Mutex<f32>>,
+    // Bumped to call off a fade in progress
+    fade_id: Arc<AtomicU64>,
+    // The track lined up to follow the current one without a gap
+    queued: Mutex<Option<Queued>>,
+    ended: mpsc::UnboundedSender<TrackEnd>,
+    // Ticks while the output pulls samples, to notice it dying
+    heartbeat: Arc<AtomicU64>,
+    last_beat: Mutex<(u64, Instant)>,
+    // Which track's end counts right now; 0 for none
+    current_id: Mutex<u64>,
+    next_id: AtomicU64,
+}
+
+/// The player's volume level and mute switch.
+///
+/// ```
+/// use danavi::audio::Volume;
+///
+/// let mut volume = Volume::default();
+/// volume.set(0.4);
+/// assert!(volume.toggle_mute());
+/// assert_eq!(volume.effective(), 0.0);
+/// assert!(!volume.toggle_mute());
+/// assert_eq!(volume.effective(), 0.4);
+///
+/// // A new level while muted unmutes
+/// volume.toggle_mute();
+/// volume.set(0.7);
+/// assert!(!volume.muted);
+/// assert_eq!(volume.effective(), 0.7);
+///
+/// // Levels stay in range
+/// volume.set(1.5);
+/// assert_eq!(volume.effective(), 1.0);
+///
+/// // ReplayGain scales what's played, not the level shown
+/// volume.gain = 0.5;
+/// assert_eq!(volume.effective(), 0.5);
+/// assert_eq!(volume.reported(), 1.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Volume {
+    /// 1.0 leaves the track as it is
+    pub level: f32,
+    /// Silences the track without losing the level
+    pub muted: bool,
+    /// The current track's ReplayGain multiplier
+    pub gain: f32,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            level: 1.0,
+            muted: false,
+            gain: 1.0,
+        }
+    }
+}
+
+impl Volume {
+    /// Sets the level, clamped to 0.0-1.0, and unmutes.
+    pub fn set(&mut self, level: f32) {
+        self.level = level.clamp(0.0, 1.0);
+        self.muted = false;
+    }
+
+    /// Returns whether it's muted now.
+    pub fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+
+    /// The volume as the user sees it: the level, or 0.0 while muted.
+    pub fn reported(&self) -> f32 {
+        if self.muted { 0.0 } else { self.level }
+    }
+
+    /// What the sink should play at.
+    pub fn effective(&self) -> f32 {
+        self.reported() * self.gain
+    }
+}
+
+/// A track reaching its end, to pass to [`AudioPlayer::track_ended`].
+#[derive(Debug)]
+pub struct TrackEnd(u64);
+
+/// What a [`TrackEnd`] meant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackEvent {
+    /// The track lined up with [`AudioPlayer::queue_stream`] took over.
+    Advanced,
+    /// The track played out and nothing follows.
+    Finished,
+}
+
+struct Output {
+    // None after a failed reconnect
     _stream: Option<OutputStream>,
     handle: OutputStreamHandle,
-    sink: Mutex<Option<Sink>>,
+}
+
+impl Output {
+    fn reopen(&mut self) -> Result<()> {
+        // Let go of the device first, in case it's opened exclusively
+        self._stream = None;
+        let (stream, handle) =
+            OutputStream::try_default().context("No audio output to reconnect to")?;
+        *self = Output {
+            _stream: Some(stream),
+            handle,
+        };
+        Ok(())
+    }
+}
+
+// How long playing without the output taking samples counts as it being gone
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+// How much of an undecodable track is checked for being an error message
+const SNIFF_LEN: usize = 64 * 1024;
+
+struct Queued {
+    id: u64,
+    track: Arc<StreamBuffer>,
+    duration: Option<Duration>,
+    loudness: Option<Measurement>,
+    // Set to skip it once the sink gets to it, as sinks can't unqueue
+    dropped: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct StreamState {
+    data: Vec<u8>,
+    // From Content-Length, when the server sent one
+    len: Option<u64>,
+    finished: bool,
+    cancelled: bool,
+    error: Option<String>,
+    error_reported: bool,
+    // Readers that caught up with the download
+    waiting: usize,
+    // Held off while paused, see StreamBuffer::suspend
+    suspended: bool,
+    // Counts downloads into the buffer; only the latest one's chunks are taken
+    feed: u64,
+}
+
+/// A track's bytes as they arrive. Whoever downloads it calls
+/// [`push`](Self::push) with each chunk and then [`finish`](Self::finish) or
+/// [`fail`](Self::fail); the decoder reads along, waiting when it catches
+/// up. Reads past the end of a failed or cancelled download come back
+/// empty, which ends the track.
+///
+/// ```
+/// use danavi::audio::StreamBuffer;
+/// use std::io::Read;
+///
+/// let buffer = StreamBuffer::new(None);
+/// buffer.push(b"RIFF");
+/// buffer.finish();
+/// let mut bytes = Vec::new();
+/// buffer.reader().read_to_end(&mut bytes).unwrap();
+/// assert_eq!(bytes, b"RIFF");
+/// ```
+#[derive(Default)]
+pub struct StreamBuffer {
+    state: Mutex<StreamState>,
+    changed: Condvar,
+}
+
+impl StreamBuffer {
+    /// An empty buffer for a download of `len` bytes, if known.
+    pub fn new(len: Option<u64>) -> Arc<Self> {
+        let buffer = Self::default();
+        buffer.state.lock().unwrap().len = len;
+        Arc::new(buffer)
+    }
+
+    /// A buffer holding a whole track already.
+    pub fn complete(bytes: Vec<u8>) -> Arc<Self> {
+        let buffer = Self::new(Some(bytes.len() as u64));
+        buffer.push(&bytes);
+        buffer.finish();
+        buffer
+    }
+
+    /// Adds downloaded bytes, waking a reader waiting for them.
+    pub fn push(&self, chunk: &[u8]) {
+        self.state.lock().unwrap().data.extend_from_slice(chunk);
+        self.changed.notify_all();
+    }
+
+    /// Marks the download complete.
+    pub fn finish(&self) {
+        self.state.lock().unwrap().finished = true;
+        self.changed.notify_all();
+    }
+
+    /// Ends the download early; playback stops where the data runs out.
+    pub fn fail(&self, error: String) {
+        self.state.lock().unwrap().error = Some(error);
+        self.changed.notify_all();
+    }
+
+    /// Tells the downloader to give up, as the track is no longer wanted.
+    pub fn cancel(&self) {
+        self.state.lock().unwrap().cancelled = true;
+        self.changed.notify_all();
+    }
+
+    /// Whether [`cancel`](Self::cancel) was called, for the downloader to
+    /// check between chunks.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().cancelled
+    }
+
+    /// A way in for one download. Suspending turns away the feeds handed out
+    /// before, so a download left running can't add to the track once
+    /// another has taken over.
+    pub fn feed(self: &Arc<Self>) -> Feed {
+        Feed {
+            id: self.state.lock().unwrap().feed,
+            buffer: self.clone(),
+        }
+    }
+
+    /// Holds off the download, e.g. while paused: its feed stops taking
+    /// chunks, and readers wait as they would on a slow connection until
+    /// [`resume`](Self::resume). Returns false if the download was over
+    /// anyway.
+    ///
+    /// ```
+    /// use danavi::audio::StreamBuffer;
+    ///
+    /// let buffer = StreamBuffer::new(Some(8));
+    /// let feed = buffer.feed();
+    /// assert!(feed.push(b"RI"));
+    /// assert!(buffer.suspend());
+    /// assert!(!feed.push(b"FF"));
+    ///
+    /// // The rest comes from where it got to
+    /// assert_eq!(buffer.resume(), Some(2));
+    /// let rest = buffer.feed();
+    /// assert!(rest.push(b"FFWAVE"));
+    /// feed.fail("stale".to_string());
+    /// rest.finish();
+    /// assert_eq!(buffer.completed().as_deref(), Some(&b"RIFFWAVE"[..]));
+    /// ```
+    pub fn suspend(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.finished || state.cancelled || state.error.is_some() || state.suspended {
+            return false;
+        }
+        state.suspended = true;
+        state.feed += 1;
+        true
+    }
+
+    /// Takes a suspended download back up. Returns the bytes it already
+    /// has, where the rest should start from, or None if it wasn't
+    /// suspended or has them all.
+    pub fn resume(&self) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        if !std::mem::take(&mut state.suspended) {
+            return None;
+        }
+        let received = state.data.len();
+        if state.len == Some(received as u64) {
+            state.finished = true;
+            drop(state);
+            self.changed.notify_all();
+            return None;
+        }
+        Some(received)
+    }
+
+    /// Whether playback is held up waiting for more of the download.
+    pub fn is_buffering(&self) -> bool {
+        self.state.lock().unwrap().waiting > 0
+    }
+
+    /// Bytes received so far.
+    pub fn buffered(&self) -> usize {
+        self.state.lock().unwrap().data.len()
+    }
+
+    /// The whole track, once all of it has arrived. None while it's still
+    /// coming, or if the download failed, was cancelled or came up short.
+    pub fn completed(&self) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        let whole = state.finished
+            && !state.cancelled
+            && state.error.is_none()
+            && state.len.is_none_or(|len| len == state.data.len() as u64);
+        whole.then(|| state.data.clone())
+    }
+
+    /// How much of the track has arrived, from 0.0 to 1.0 once it's all
+    /// there. None while a download of unknown length is still going.
+    ///
+    /// ```
+    /// use danavi::audio::StreamBuffer;
+    ///
+    /// let buffer = StreamBuffer::new(Some(8));
+    /// buffer.push(b"RIFF");
+    /// assert_eq!(buffer.downloaded_share(), Some(0.5));
+    ///
+    /// let endless = StreamBuffer::new(None);
+    /// endless.push(b"RIFF");
+    /// assert_eq!(endless.downloaded_share(), None);
+    /// endless.finish();
+    /// assert_eq!(endless.downloaded_share(), Some(1.0));
+    /// ```
+    pub fn downloaded_share(&self) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        let received = state.data.len() as f64;
+        match state.len {
+            _ if state.finished => Some(1.0),
+            Some(len) if len > 0 => Some((received / len as f64).min(1.0)),
+            _ => None,
+        }
+    }
+
+    /// Whether no more data is coming.
+    pub fn is_done(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.finished || state.cancelled || state.error.is_some()
+    }
+
+    /// Waits until `bytes` have arrived or no more will.
+    pub fn wait_for(&self, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        while state.data.len() < bytes && !(state.finished || state.cancelled || state.error.is_some()) {
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    /// A reader from the start of the track, blocking for bytes not yet
+    /// downloaded.
+    pub fn reader(self: &Arc<Self>) -> StreamReader {
+        StreamReader {
+            buffer: self.clone(),
+            pos: 0,
+        }
+    }
+}
+
+/// One download into a [`StreamBuffer`], from [`StreamBuffer::feed`].
+/// Once the buffer is cancelled or suspended it takes nothing more.
+pub struct Feed {
+    buffer: Arc<StreamBuffer>,
+    id: u64,
+}
+
+impl Feed {
+    /// Adds downloaded bytes, as [`StreamBuffer::push`] does. Returns false
+    /// when the download should stop instead.
+    pub fn push(&self, chunk: &[u8]) -> bool {
+        let mut state = self.buffer.state.lock().unwrap();
+        if state.cancelled || state.feed != self.id {
+            return false;
+        }
+        state.data.extend_from_slice(chunk);
+        drop(state);
+        self.buffer.changed.notify_all();
+        true
+    }
+
+    /// Marks the download complete, unless it was turned away.
+    pub fn finish(&self) {
+        let mut state = self.buffer.state.lock().unwrap();
+        if state.feed == self.id {
+            state.finished = true;
+            drop(state);
+            self.buffer.changed.notify_all();
+        }
+    }
+
+    /// Ends the download early, unless it was turned away.
+    pub fn fail(&self, error: String) {
+        let mut state = self.buffer.state.lock().unwrap();
+        if state.feed == self.id {
+            state.error = Some(error);
+            drop(state);
+            self.buffer.changed.notify_all();
+        }
+    }
+}
+
+/// Reads a [`StreamBuffer`] from the start, blocking until data arrives.
+pub struct StreamReader {
+    buffer: Arc<StreamBuffer>,
+    pos: u64,
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.buffer.state.lock().unwrap();
+        loop {
+            let available = state.data.len() as u64;
+            if self.pos < available {
+                let start = self.pos as usize;
+                let n = buf.len().min(state.data.len() - start);
+                buf[..n].copy_from_slice(&state.data[start..start + n]);
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            if state.finished || state.cancelled || state.error.is_some() {
+                return Ok(0);
+            }
+            state.waiting += 1;
+            state = self.buffer.changed.wait(state).unwrap();
+            state.waiting -= 1;
+        }
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+            SeekFrom::End(offset) => {
+                // Without a length the end is only known once it's all here
+                let mut state = self.buffer.state.lock().unwrap();
+                while state.len.is_none() && !(state.finished || state.cancelled || state.error.is_some()) {
+                    state = self.buffer.changed.wait(state).unwrap();
+                }
+                state.len.unwrap_or(state.data.len() as u64) as i64 + offset
+            }
+        };
+        if target < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start"));
+        }
+        self.pos = target as u64;
+        Ok(self.pos)
+    }
+}
+
+/// Wall-clock position in the current track, since the sink can't report
+/// one itself.
+#[derive(Default)]
+struct Clock {
+    resumed_at: Option<Instant>,
+    elapsed: Duration,
+}
+
+impl Clock {
+    fn position(&self) -> Duration {
+        self.elapsed + self.resumed_at.map_or(Duration::ZERO, |t| t.elapsed())
+    }
 }
 
 impl AudioPlayer {
-    pub fn new() -> Result<Self> {
+    /// Opens the default output device. Track ends are sent to `ended`.
+    pub fn new(ended: mpsc::UnboundedSender<TrackEnd>) -> Result<Self> {
         let (_stream, stream_handle) = OutputStream::try_default()
             .context("Failed to create audio output stream. Make sure PipeWire/WirePlumber is running and audio devices are available.")?;
 
@@ -18,21 +508,107 @@ impl AudioPlayer {
         sink.set_volume(1.0);
 
         Ok(Self {
-            _stream: Some(_stream),
-            handle: stream_handle,
-            sink: Mutex::new(Some(sink)),
+            output: Mutex::new(Output {
+                _stream: Some(_stream),
+                handle: stream_handle,
+            }),
+            sink: Arc::new(Mutex::new(Some(sink))),
+            clock: Arc::new(Mutex::new(Clock::default())),
+            track: Mutex::new(None),
+            start: Mutex::new(Duration::ZERO),
+            duration: Mutex::new(None),
+            normalize: Mutex::new(None),
+            loudness: Mutex::new(None),
+            volume: Arc::new(Mutex::new(Volume::default())),
+            paused: AtomicBool::new(false),
+            fade: Mutex::new(Duration::ZERO),
+            fade_level: Arc::new(Mutex::new(1.0)),
+            fade_id: Arc::new(AtomicU64::new(0)),
+            queued: Mutex::new(None),
+            ended,
+            heartbeat: Arc::new(AtomicU64::new(0)),
+            last_beat: Mutex::new((0, Instant::now())),
+            current_id: Mutex::new(0),
+            next_id: AtomicU64::new(1),
         })
     }
 
-    pub fn play_bytes(&self, bytes: Vec<u8>) -> Result<()> {
-        let cursor = Cursor::new(bytes);
+    // Counts samples being pulled, a tick per 100 ms of audio
+    fn with_heartbeat<S: Source>(&self, source: S) -> PeriodicAccess<S, impl FnMut(&mut S) + use<S>>
+    where
+        S::Item: rodio::Sample,
+    {
+        let heartbeat = self.heartbeat.clone();
+        source.periodic_access(Duration::from_millis(100), move |_| {
+            heartbeat.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    // Also reopens the output if an earlier reconnect failed
+    fn new_sink(&self) -> Result<Sink> {
+        let mut output = self.output.lock().unwrap();
+        if output._stream.is_none() {
+            output.reopen()?;
+        }
+        Sink::try_new(&output.handle).context("Failed to create audio sink")
+    }
+
+    /// Whether the output seems to have died: a track is playing, it isn't
+    /// waiting on the download, and yet no samples have been taken for a few
+    /// seconds. Polled from the main loop.
+    pub fn is_stalled(&self) -> bool {
+        let beat = self.heartbeat.load(Ordering::Relaxed);
+        let buffering = self
+            .track
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|track| track.is_buffering());
+        let mut last = self.last_beat.lock().unwrap();
+        if beat != last.0 || buffering || self.is_paused() || self.is_finished() {
+            *last = (beat, Instant::now());
+            return false;
+        }
+        last.1.elapsed() >= STALL_TIMEOUT
+    }
+
+    /// Opens the default output device again and carries on with the
+    /// current track from where it was, paused or not.
+    pub fn reconnect(&self) -> Result<()> {
+        self.output.lock().unwrap().reopen()?;
+        *self.last_beat.lock().unwrap() = (self.heartbeat.load(Ordering::Relaxed), Instant::now());
+        if self.track.lock().unwrap().is_some() {
+            self.seek(self.position())
+        } else {
+            *self.sink.lock().unwrap() = Some(self.new_sink()?);
+            Ok(())
+        }
+    }
 
-        let source = Decoder::new(cursor).map_err(|e| {
-            anyhow::anyhow!(
-                "Failed to decode audio: {}. The server may have returned an unsupported format.",
-                e
-            )
-        })?;
+    // A silent source that reports the end of track `id` when reached. Tracks
+    // cut short by stop, seek or another track never get to it.
+    fn end_marker(&self, id: u64) -> EmptyCallback<f32> {
+        let ended = self.ended.clone();
+        EmptyCallback::new(Box::new(move || {
+            let _ = ended.send(TrackEnd(id));
+        }))
+    }
+
+    fn decode(track: &Arc<StreamBuffer>) -> Result<Decoder<StreamReader>> {
+        let source = match Decoder::new(track.reader()) {
+            Ok(source) => source,
+            Err(e) => {
+                // Text where the audio should be says more than the decoder
+                let head = {
+                    let state = track.state.lock().unwrap();
+                    state.data[..state.data.len().min(SNIFF_LEN)].to_vec()
+                };
+                return Err(non_audio_error(None, &head).unwrap_or_else(|| {
+                    anyhow::Error::new(e)
+                        .context("Failed to decode audio. The server may have returned an unsupported format")
+                }));
+            }
+        };
 
         if source.channels() == 0 || source.sample_rate() == 0 {
             anyhow::bail!(
@@ -40,48 +616,373 @@ impl AudioPlayer {
                  The server may have returned empty or invalid audio data."
             );
         }
+        Ok(source)
+    }
+
+    // Starts measuring a track for normalizing on a thread of its own, when
+    // normalization is on and the whole track has arrived. Decoding it all
+    // takes a while, so playback goes on the running estimate meanwhile.
+    fn measure(&self, track: &Arc<StreamBuffer>) -> Option<Measurement> {
+        self.normalize.lock().unwrap().as_ref()?;
+        {
+            let state = track.state.lock().unwrap();
+            if !state.finished || state.error.is_some() {
+                return None;
+            }
+        }
+        let measured = Measurement::default();
+        let (track, cell) = (track.clone(), measured.clone());
+        std::thread::spawn(move || {
+            if let Ok(source) = Self::decode(&track) {
+                let _ = cell.set(Loudness::measure(source.convert_samples()));
+            }
+        });
+        Some(measured)
+    }
+
+    fn level<S: Source<Item = i16>>(
+        &self,
+        source: S,
+        measured: Option<Measurement>,
+    ) -> Leveler<SamplesConverter<S, f32>> {
+        Leveler::new(source.convert_samples(), *self.normalize.lock().unwrap(), measured.unwrap_or_default())
+    }
+
+    /// Evens out loudness between tracks by bringing each to `target` dBFS
+    /// RMS, or stops doing so with None. Applies from the next track on.
+    /// Tracks that are all there are measured in the background, following
+    /// a running estimate until that's done; ones still downloading only
+    /// follow the estimate.
+    pub fn set_normalization(&self, target: Option<f32>) {
+        *self.normalize.lock().unwrap() = target;
+    }
+
+    /// Decodes an encoded track (MP3, FLAC, ...) and starts playing it,
+    /// replacing whatever was playing before.
+    pub fn play_bytes(&self, bytes: Vec<u8>) -> Result<()> {
+        self.play_stream(StreamBuffer::complete(bytes))
+    }
+
+    /// Like [`play_bytes`](Self::play_bytes), for a track still arriving.
+    /// Blocks until there's enough of it to tell the format.
+    pub fn play_stream(&self, track: Arc<StreamBuffer>) -> Result<()> {
+        let source = Self::decode(&track)?;
+        *self.duration.lock().unwrap() = source.total_duration();
+        let measured = self.measure(&track);
+        *self.loudness.lock().unwrap() = measured.clone();
+        let source = self.level(source, measured);
 
         let mut sink_guard = self.sink.lock().unwrap();
 
         // Create a fresh sink for this song to avoid race conditions
         // with stop/clear/append/play on a reused sink
-        let new_sink = Sink::try_new(&self.handle)
-            .context("Failed to create audio sink")?;
-        new_sink.set_volume(1.0);
-        new_sink.append(source);
+        let new_sink = self.new_sink()?;
+        self.fade_id.fetch_add(1, Ordering::Relaxed);
+        *self.fade_level.lock().unwrap() = 1.0;
+        self.paused.store(false, Ordering::Relaxed);
+        new_sink.set_volume(self.volume.lock().unwrap().effective());
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        new_sink.append(self.with_heartbeat(source));
+        new_sink.append(self.end_marker(id));
         new_sink.play();
 
         *sink_guard = Some(new_sink);
+        *self.current_id.lock().unwrap() = id;
+        if let Some(old) = self.track.lock().unwrap().replace(track) {
+            old.cancel();
+        }
+        *self.start.lock().unwrap() = Duration::ZERO;
+        self.drop_queued();
+        *self.clock.lock().unwrap() = Clock {
+            resumed_at: Some(Instant::now()),
+            elapsed: Duration::ZERO,
+        };
+        Ok(())
+    }
+
+    /// Jumps to `position` in the current track, staying paused if it was.
+    /// The track is decoded again up to that point, so far seeks into long
+    /// tracks take a moment. Fails before the start of a stream the server
+    /// cut, see [`seek_stream`](Self::seek_stream).
+    pub fn seek(&self, position: Duration) -> Result<()> {
+        let Some(track) = self.track.lock().unwrap().clone() else {
+            anyhow::bail!("Nothing is playing");
+        };
+        let start = *self.start.lock().unwrap();
+        if position < start {
+            anyhow::bail!("The stream starts later than that");
+        }
+        // A track streamed in may have finished arriving since it started.
+        // One cut short would only be measured from where it starts.
+        let measured = {
+            let mut loudness = self.loudness.lock().unwrap();
+            if loudness.is_none() && start.is_zero() {
+                *loudness = self.measure(&track);
+            }
+            loudness.clone()
+        };
+        let source = self.level(Self::decode(&track)?, measured).skip_duration(position - start);
+        self.replace_sink(source, position)
+    }
+
+    /// Carries on the current track from `track`, a stream of it the server
+    /// began `start` in, staying paused if it was. For seeking beyond what
+    /// has downloaded; positions still count from the top of the song.
+    /// Blocks until there's enough of it to tell the format.
+    pub fn seek_stream(&self, track: Arc<StreamBuffer>, start: Duration) -> Result<()> {
+        let source = self.level(Self::decode(&track)?, self.loudness.lock().unwrap().clone());
+        self.replace_sink(source, start)?;
+        if let Some(old) = self.track.lock().unwrap().replace(track) {
+            old.cancel();
+        }
+        *self.start.lock().unwrap() = start;
+        Ok(())
+    }
+
+    // Plays `source` from `position` in place of what the sink had,
+    // keeping pause, volume and any fade
+    fn replace_sink<S>(&self, source: S, position: Duration) -> Result<()>
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        let mut sink_guard = self.sink.lock().unwrap();
+        let paused = self.paused.load(Ordering::Relaxed);
+        let new_sink = self.new_sink()?;
+        // A fade in progress carries on with this sink
+        new_sink.set_volume(self.volume.lock().unwrap().effective() * *self.fade_level.lock().unwrap());
+        if paused {
+            new_sink.pause();
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        new_sink.append(self.with_heartbeat(source));
+        new_sink.append(self.end_marker(id));
+
+        *sink_guard = Some(new_sink);
+        *self.current_id.lock().unwrap() = id;
+        *self.clock.lock().unwrap() = Clock {
+            resumed_at: (!paused).then(Instant::now),
+            elapsed: position,
+        };
+        // It went with the old sink
+        self.drop_queued();
         Ok(())
     }
 
+    /// Lines up `track` to start the moment the current one ends, for
+    /// gapless albums. Blocks until there's enough of it to tell the format.
+    /// [`TrackEvent::Advanced`] says when it has started.
+    pub fn queue_stream(&self, track: Arc<StreamBuffer>) -> Result<()> {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let source = Self::decode(&track)?;
+        let duration = source.total_duration();
+        let loudness = self.measure(&track);
+        let source = self.level(source, loudness.clone()).skippable().periodic_access(Duration::from_millis(5), {
+            let dropped = dropped.clone();
+            move |source| {
+                if dropped.load(Ordering::Relaxed) {
+                    source.skip();
+                }
+            }
+        });
+
+        let sink_guard = self.sink.lock().unwrap();
+        let Some(sink) = sink_guard.as_ref().filter(|s| !s.empty()) else {
+            anyhow::bail!("Nothing is playing");
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        sink.append(self.with_heartbeat(source));
+        sink.append(self.end_marker(id));
+        if let Some(old) = self.queued.lock().unwrap().replace(Queued {
+            id,
+            track,
+            duration,
+            loudness,
+            dropped,
+        }) {
+            old.dropped.store(true, Ordering::Relaxed);
+            old.track.cancel();
+        }
+        Ok(())
+    }
+
+    /// Whether a track is lined up by [`queue_stream`](Self::queue_stream).
+    pub fn has_queued(&self) -> bool {
+        self.queued.lock().unwrap().is_some()
+    }
+
+    /// Skips the lined up track when its turn comes.
+    pub fn drop_queued(&self) {
+        if let Some(queued) = self.queued.lock().unwrap().take() {
+            queued.dropped.store(true, Ordering::Relaxed);
+            queued.track.cancel();
+        }
+    }
+
+    /// Works out what a track ending means, making the lined up track the
+    /// current one if there is one. Ends of tracks that are no longer
+    /// current give None.
+    pub fn track_ended(&self, end: TrackEnd) -> Option<TrackEvent> {
+        let mut current_id = self.current_id.lock().unwrap();
+        if end.0 != *current_id {
+            return None;
+        }
+        let Some(next) = self.queued.lock().unwrap().take() else {
+            *current_id = 0;
+            return Some(TrackEvent::Finished);
+        };
+        *current_id = next.id;
+        let paused = self.paused.load(Ordering::Relaxed);
+        *self.track.lock().unwrap() = Some(next.track);
+        *self.start.lock().unwrap() = Duration::ZERO;
+        *self.duration.lock().unwrap() = next.duration;
+        *self.loudness.lock().unwrap() = next.loudness;
+        *self.clock.lock().unwrap() = Clock {
+            resumed_at: (!paused).then(Instant::now),
+            elapsed: Duration::ZERO,
+        };
+        Some(TrackEvent::Advanced)
+    }
+
+    /// Stops playback and drops the current track.
     pub fn stop(&self) {
         if let Some(sink) = self.sink.lock().unwrap().as_ref() {
             sink.stop();
             sink.clear();
         }
+        if let Some(track) = self.track.lock().unwrap().take() {
+            track.cancel();
+        }
+        *self.start.lock().unwrap() = Duration::ZERO;
+        *self.loudness.lock().unwrap() = None;
+        self.drop_queued();
+        self.fade_id.fetch_add(1, Ordering::Relaxed);
+        *self.fade_level.lock().unwrap() = 1.0;
+        self.paused.store(false, Ordering::Relaxed);
+        *self.current_id.lock().unwrap() = 0;
+        *self.clock.lock().unwrap() = Clock::default();
+    }
+
+    /// The current track's download, to see how far it has got.
+    pub fn current_track(&self) -> Option<Arc<StreamBuffer>> {
+        self.track.lock().unwrap().clone()
     }
 
+    /// Where in the song the current track's stream begins: zero, unless
+    /// [`seek_stream`](Self::seek_stream) started it partway.
+    pub fn stream_start(&self) -> Duration {
+        *self.start.lock().unwrap()
+    }
+
+    /// Why the current track's download broke off, reported once.
+    pub fn take_stream_error(&self) -> Option<String> {
+        let track = self.track.lock().unwrap();
+        let mut state = track.as_ref()?.state.lock().unwrap();
+        if state.error_reported {
+            return None;
+        }
+        let error = state.error.clone()?;
+        state.error_reported = true;
+        Some(error)
+    }
+
+    /// How long pausing and resuming take to fade out and in; zero cuts
+    /// straight away.
+    pub fn set_fade(&self, fade: Duration) {
+        *self.fade.lock().unwrap() = fade;
+    }
+
+    /// Pauses, or resumes if already paused. Does nothing when idle. With a
+    /// fade set, the sound ramps down before the sink pauses and back up
+    /// after it resumes, in a background task. The position keeps counting
+    /// until the fade out is over, as the track plays on until then.
     pub fn toggle_pause(&self) {
         let sink_guard = self.sink.lock().unwrap();
-        if let Some(sink) = sink_guard.as_ref() {
-            if sink.is_paused() {
-                sink.play();
-            } else if !sink.empty() {
-                sink.pause();
+        let Some(sink) = sink_guard.as_ref() else {
+            return;
+        };
+        let mut clock = self.clock.lock().unwrap();
+        if self.paused.load(Ordering::Relaxed) {
+            self.paused.store(false, Ordering::Relaxed);
+            sink.play();
+            // Still running if the fade out hadn't finished
+            clock.resumed_at.get_or_insert_with(Instant::now);
+            drop((clock, sink_guard));
+            self.fade_to(1.0);
+        } else if !sink.empty() {
+            self.paused.store(true, Ordering::Relaxed);
+            drop((clock, sink_guard));
+            self.fade_to(0.0);
+        }
+    }
+
+    // Ramps the fade level to `target`, pausing the sink and the clock at
+    // the end of a fade out. Volume changes meanwhile scale along with the
+    // ramp.
+    fn fade_to(&self, target: f32) {
+        let fade_id = self.fade_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let (sink, clock, volume, level, current_id) = (
+            self.sink.clone(),
+            self.clock.clone(),
+            self.volume.clone(),
+            self.fade_level.clone(),
+            self.fade_id.clone(),
+        );
+        let set_level = move |value: f32| {
+            *level.lock().unwrap() = value;
+            let volume = volume.lock().unwrap().effective() * value;
+            if let Some(sink) = sink.lock().unwrap().as_ref() {
+                sink.set_volume(volume);
+                if value == 0.0 {
+                    sink.pause();
+                    let mut clock = clock.lock().unwrap();
+                    clock.elapsed = clock.position();
+                    clock.resumed_at = None;
+                }
             }
+        };
+        let fade = *self.fade.lock().unwrap();
+        let start = *self.fade_level.lock().unwrap();
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            set_level(target);
+            return;
+        };
+        if fade.is_zero() {
+            set_level(target);
+            return;
         }
+        runtime.spawn(async move {
+            const STEP: Duration = Duration::from_millis(10);
+            let steps = (fade.as_millis() / STEP.as_millis()).max(1) as u32;
+            // A partly done fade only goes the rest of the way
+            let steps = ((steps as f32 * (target - start).abs()).ceil() as u32).max(1);
+            for i in 1..=steps {
+                tokio::time::sleep(STEP).await;
+                if current_id.load(Ordering::Relaxed) != fade_id {
+                    return;
+                }
+                set_level(start + (target - start) * i as f32 / steps as f32);
+            }
+        });
+    }
+
+    /// The current track's length as decoded, for when the server doesn't
+    /// say. Not every format knows it up front, MP3 in particular.
+    pub fn track_duration(&self) -> Option<Duration> {
+        *self.duration.lock().unwrap()
     }
 
+    /// How far into the current track playback is.
+    pub fn position(&self) -> Duration {
+        self.clock.lock().unwrap().position()
+    }
+
+    /// Whether the current track is paused, which it is as soon as a fade
+    /// out starts.
     pub fn is_paused(&self) -> bool {
-        self.sink
-            .lock()
-            .unwrap()
-            .as_ref()
-            .map(|s| s.is_paused())
-            .unwrap_or(false)
+        self.paused.load(Ordering::Relaxed)
     }
 
+    /// Whether there is nothing left to play.
     pub fn is_finished(&self) -> bool {
         self.sink
             .lock()
@@ -91,24 +992,66 @@ impl AudioPlayer {
             .unwrap_or(true)
     }
 
+    /// Sets the volume, 1.0 being unchanged. Later tracks keep it. Unmutes.
     pub fn set_volume(&self, volume: f64) {
+        let state = {
+            let mut state = self.volume.lock().unwrap();
+            state.set(volume as f32);
+            *state
+        };
+        self.apply_volume(state);
+    }
+
+    /// Silences playback, or brings back the volume from before muting.
+    /// Returns whether it's muted now.
+    pub fn toggle_mute(&self) -> bool {
+        let state = {
+            let mut state = self.volume.lock().unwrap();
+            state.toggle_mute();
+            *state
+        };
+        self.apply_volume(state);
+        state.muted
+    }
+
+    fn apply_volume(&self, volume: Volume) {
+        let volume = volume.effective() * *self.fade_level.lock().unwrap();
         if let Some(sink) = self.sink.lock().unwrap().as_ref() {
-            sink.set_volume(volume as f32);
+            sink.set_volume(volume);
         }
     }
 
+    /// Whether the output is muted.
+    pub fn is_muted(&self) -> bool {
+        self.volume.lock().unwrap().muted
+    }
+
+    /// The volume, 1.0 until it's changed and 0.0 while muted.
     pub fn get_volume(&self) -> f64 {
-        self.sink
-            .lock()
-            .unwrap()
-            .as_ref()
-            .map(|s| s.volume() as f64)
-            .unwrap_or(1.0)
+        self.volume.lock().unwrap().reported() as f64
     }
-}
 
-impl Default for AudioPlayer {
-    fn default() -> Self {
-        panic!("AudioPlayer::default() called - please use AudioPlayer::new() instead for proper error handling");
+    /// Scales the current track by a ReplayGain multiplier, on top of the
+    /// volume.
+    pub fn set_gain(&self, gain: f32) {
+        let state = {
+            let mut state = self.volume.lock().unwrap();
+            state.gain = gain;
+            *state
+        };
+        self.apply_volume(state);
     }
 }
+
+/// Name of the device audio goes to, if the host can tell.
+pub fn output_device_name() -> Option<String> {
+    rodio::cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.name().ok())
+}
+
+/// Whether `error` came from the decoder not understanding the track, as
+/// opposed to the download or the output failing.
+pub fn is_unsupported_format(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<rodio::decoder::DecoderError>().is_some()
+}
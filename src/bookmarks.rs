@@ -0,0 +1,116 @@
+use danavi::client::SubsonicClient;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Stopping this close to the start isn't worth coming back to
+const MIN_POSITION: Duration = Duration::from_secs(30);
+// Stopping this close to the end counts as having finished
+const END_MARGIN: Duration = Duration::from_secs(30);
+// Only a song starting about this close to the top is resumed, so a seek or
+// a resume from album progress isn't undone
+const RESUME_WINDOW: Duration = Duration::from_secs(2);
+
+// The song being listened to
+struct Listen {
+    id: String,
+    duration: Option<Duration>,
+    position: Duration,
+}
+
+/// Saves where long songs were left as server bookmarks, so they pick up
+/// from there next time, here or in any other client.
+pub struct Bookmarks {
+    client: SubsonicClient,
+    min_duration: Duration,
+    // Song id to position; filled in the background at startup
+    saved: Arc<Mutex<HashMap<String, Duration>>>,
+    listen: Option<Listen>,
+}
+
+impl Bookmarks {
+    /// Starts fetching the bookmarks the server already has.
+    pub fn start(client: &SubsonicClient, min_duration: Duration) -> Self {
+        let saved = Arc::new(Mutex::new(HashMap::new()));
+        let loading = client.clone();
+        let fill = saved.clone();
+        tokio::spawn(async move {
+            match loading.get_bookmarks().await {
+                Ok(response) => {
+                    let mut saved = fill.lock().unwrap();
+                    for bookmark in response.bookmarks.bookmark {
+                        saved.insert(bookmark.entry.id, Duration::from_millis(bookmark.position));
+                    }
+                }
+                Err(e) => log::debug!("Failed to load bookmarks: {}", e),
+            }
+        });
+        Self {
+            client: client.clone(),
+            min_duration,
+            saved,
+            listen: None,
+        }
+    }
+
+    /// Called with the playing song and position. Returns where to seek to
+    /// when a bookmarked song has just started.
+    pub fn update(&mut self, song: Option<(&str, Option<i64>)>, position: Duration) -> Option<Duration> {
+        if self.listen.as_ref().map(|l| l.id.as_str()) == song.map(|(id, _)| id) {
+            if let Some(listen) = self.listen.as_mut() {
+                listen.position = position;
+            }
+            return None;
+        }
+        if let Some(listen) = self.listen.take() {
+            self.settle(listen);
+        }
+        let (id, duration) = song?;
+        self.listen = Some(Listen {
+            id: id.to_string(),
+            duration: duration.filter(|&secs| secs > 0).map(|secs| Duration::from_secs(secs as u64)),
+            position,
+        });
+        if position > RESUME_WINDOW {
+            return None;
+        }
+        self.saved.lock().unwrap().get(id).copied()
+    }
+
+    /// Saves the position of whatever is playing, waiting for the server,
+    /// for use on quitting.
+    pub async fn flush(&mut self) {
+        let Some(listen) = self.listen.take() else {
+            return;
+        };
+        if let Some(request) = self.settle(listen) {
+            let _ = request.await;
+        }
+    }
+
+    // Bookmarks a song left part way through and forgets one played out.
+    // Returns the server request, which is also running on its own.
+    fn settle(&mut self, listen: Listen) -> Option<tokio::task::JoinHandle<()>> {
+        let Listen { id, duration, position } = listen;
+        let duration = duration?;
+        let mut saved = self.saved.lock().unwrap();
+        let client = self.client.clone();
+        if position + END_MARGIN >= duration {
+            saved.remove(&id)?;
+            return Some(tokio::spawn(async move {
+                if let Err(e) = client.delete_bookmark(&id).await {
+                    log::warn!("Failed to delete bookmark: {}", e);
+                }
+            }));
+        }
+        if duration < self.min_duration || position < MIN_POSITION {
+            return None;
+        }
+        saved.insert(id.clone(), position);
+        Some(tokio::spawn(async move {
+            if let Err(e) = client.create_bookmark(&id, position).await {
+                log::warn!("Failed to save bookmark: {}", e);
+            }
+        }))
+    }
+}
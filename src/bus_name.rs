@@ -0,0 +1,79 @@
+//! Picking the MPRIS bus name, so several danavi instances can coexist.
+
+use std::future::Future;
+
+/// The name a lone danavi registers under.
+pub const BASE_NAME: &str = "org.mpris.MediaPlayer2.danavi";
+
+/// Turns a user-chosen instance name into a valid bus name element:
+/// anything but ASCII letters, digits and `_` becomes `_`, and a leading
+/// digit gets an `_` in front.
+///
+/// ```
+/// assert_eq!(danavi::bus_name::sanitize("work-laptop"), "work_laptop");
+/// assert_eq!(danavi::bus_name::sanitize("2nd"), "_2nd");
+/// ```
+pub fn sanitize(instance: &str) -> String {
+    let mut element: String = instance
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if element.is_empty() || element.starts_with(|c: char| c.is_ascii_digit()) {
+        element.insert(0, '_');
+    }
+    element
+}
+
+/// The MPRIS Identity for a bus name, e.g. "danavi (work)", so `playerctl -l`
+/// and desktop widgets can tell instances apart.
+///
+/// ```
+/// use danavi::bus_name::identity;
+///
+/// assert_eq!(identity("org.mpris.MediaPlayer2.danavi"), "danavi");
+/// assert_eq!(identity("org.mpris.MediaPlayer2.danavi.work"), "danavi (work)");
+/// ```
+pub fn identity(bus_name: &str) -> String {
+    match bus_name.strip_prefix(BASE_NAME).and_then(|rest| rest.strip_prefix('.')) {
+        Some(instance) => format!("danavi ({})", instance),
+        None => "danavi".to_string(),
+    }
+}
+
+/// Claims the base name, or `BASE_NAME.<instance>` when an instance is
+/// asked for. If that name is taken, falls back to
+/// `BASE_NAME.instance<pid>` as the MPRIS spec suggests. `request` asks the
+/// bus for a name; the one that was granted is returned.
+///
+/// ```
+/// use danavi::bus_name::claim;
+///
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// let name = runtime.block_on(claim(None, 4242, |name| async move {
+///     if name == "org.mpris.MediaPlayer2.danavi" {
+///         Err(zbus::Error::NameTaken)
+///     } else {
+///         Ok(())
+///     }
+/// }));
+/// assert_eq!(name.unwrap(), "org.mpris.MediaPlayer2.danavi.instance4242");
+/// ```
+pub async fn claim<F, Fut>(instance: Option<&str>, pid: u32, mut request: F) -> zbus::Result<String>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = zbus::Result<()>>,
+{
+    let wanted = match instance {
+        Some(instance) => format!("{}.{}", BASE_NAME, sanitize(instance)),
+        None => BASE_NAME.to_string(),
+    };
+    match request(wanted.clone()).await {
+        Ok(()) => Ok(wanted),
+        Err(zbus::Error::NameTaken) => {
+            let fallback = format!("{}.instance{}", BASE_NAME, pid);
+            request(fallback.clone()).await?;
+            Ok(fallback)
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,450 @@
+//! Songs downloaded ahead of time, for playing without the server, songs
+//! kept as they stream so they needn't stream again, cover art kept so it's
+//! only downloaded once, and library listings kept so views open without
+//! waiting on it.
+
+use crate::client::SubsonicClient;
+use crate::config::get_cache_dir;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+const SONGS_DIR_NAME: &str = "songs";
+const STREAMS_DIR_NAME: &str = "streams";
+const COVERS_DIR_NAME: &str = "covers";
+const METADATA_DIR_NAME: &str = "metadata";
+
+// Keeps opaque ids from escaping the directory
+fn file_name(id: &str) -> String {
+    id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Downloaded tracks stored one file per song id, in the same format
+/// [`SubsonicClient::stream_song`] returns.
+#[derive(Debug, Clone)]
+pub struct SongCache {
+    dir: PathBuf,
+}
+
+impl SongCache {
+    /// The `songs` directory under danavi's cache dir.
+    pub fn open() -> Result<Self> {
+        Ok(Self::new(get_cache_dir()?.join(SONGS_DIR_NAME)))
+    }
+
+    /// Songs in `dir`, which is created on the first store.
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(file_name(id))
+    }
+
+    /// Whether song `id` is downloaded.
+    pub fn contains(&self, id: &str) -> bool {
+        self.path(id).is_file()
+    }
+
+    /// The cached track, if there is one.
+    pub fn get(&self, id: &str) -> Option<Vec<u8>> {
+        fs::read(self.path(id)).ok()
+    }
+
+    /// Stores a track. It's written under a temporary name first, so an
+    /// interrupted download never looks cached.
+    pub fn store(&self, id: &str, bytes: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir).context("Failed to create song cache directory")?;
+        let path = self.path(id);
+        let partial = path.with_extension("part");
+        fs::write(&partial, bytes).context("Failed to write cached song")?;
+        fs::rename(&partial, &path).context("Failed to write cached song")?;
+        Ok(())
+    }
+
+    /// Downloads a song into the cache unless it's already there. Returns
+    /// the number of bytes downloaded.
+    pub async fn fetch(&self, client: &SubsonicClient, id: &str) -> Result<u64> {
+        if self.contains(id) {
+            return Ok(0);
+        }
+        let bytes = client.stream_song(id).await?;
+        self.store(id, &bytes)?;
+        Ok(bytes.len() as u64)
+    }
+
+    /// Like [`SongCache::fetch`], but streams to disk and picks up an
+    /// interrupted download where it stopped if the server supports ranges.
+    /// `progress` gets the size of each chunk as it arrives.
+    pub async fn download(
+        &self,
+        client: &SubsonicClient,
+        id: &str,
+        mut progress: impl FnMut(u64),
+    ) -> Result<u64> {
+        if self.contains(id) {
+            return Ok(0);
+        }
+        fs::create_dir_all(&self.dir).context("Failed to create song cache directory")?;
+        let path = self.path(id);
+        let partial = path.with_extension("part");
+        let offset = fs::metadata(&partial).map_or(0, |meta| meta.len());
+
+        let (mut response, resumed) = client.stream_song_from(id, offset).await?;
+        let mut file = if resumed {
+            OpenOptions::new().append(true).open(&partial)
+        } else {
+            fs::File::create(&partial)
+        }
+        .context("Failed to write cached song")?;
+
+        let mut bytes = 0;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to read response data")?
+        {
+            file.write_all(&chunk).context("Failed to write cached song")?;
+            bytes += chunk.len() as u64;
+            progress(chunk.len() as u64);
+        }
+        if bytes == 0 && !resumed {
+            anyhow::bail!("Server returned empty data");
+        }
+        fs::rename(&partial, &path).context("Failed to write cached song")?;
+        Ok(bytes)
+    }
+
+    /// Throws away a partly downloaded song.
+    pub fn discard_partial(&self, id: &str) {
+        let _ = fs::remove_file(self.path(id).with_extension("part"));
+    }
+}
+
+/// Whether `bytes` start like an image format servers hand out, so a
+/// truncated or mangled file isn't passed on as art.
+fn is_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xff, 0xd8, 0xff])
+        || bytes.starts_with(b"\x89PNG")
+        || bytes.starts_with(b"GIF8")
+        || bytes.starts_with(b"BM")
+        || (bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP"))
+}
+
+/// Songs kept as they were streamed, one file per server, song id and
+/// variant, the format and bitrate they came as (see
+/// [`SubsonicClient::stream_variant`]). Ids are only unique on their own
+/// server, so the server is part of the name. Each has its MD5 stored
+/// alongside, so a damaged file is noticed and dropped. Once the files pass
+/// the size cap, the ones played longest ago go.
+///
+/// ```
+/// use danavi::cache::StreamCache;
+///
+/// let dir = std::env::temp_dir().join(format!("danavi-doctest-streams-{}", std::process::id()));
+/// let cache = StreamCache::new(dir.clone(), "home", 10);
+/// cache.store("tr-1", "raw-0", b"RIFF1234")?;
+/// assert_eq!(cache.get("tr-1", "raw-0").as_deref(), Some(&b"RIFF1234"[..]));
+/// // Another format or bitrate is another file, as is another server
+/// assert_eq!(cache.get("tr-1", "mp3-128"), None);
+/// assert_eq!(StreamCache::new(dir.clone(), "work", 10).get("tr-1", "raw-0"), None);
+///
+/// // A file that no longer matches its checksum is thrown away
+/// std::fs::write(dir.join("home-tr-1-raw-0"), b"RIFF12")?;
+/// assert_eq!(cache.get("tr-1", "raw-0"), None);
+///
+/// // Staying under the cap pushes older songs out
+/// cache.store("tr-2", "raw-0", b"RIFF2222")?;
+/// cache.store("tr-3", "raw-0", b"RIFF3333")?;
+/// assert_eq!(cache.get("tr-2", "raw-0"), None);
+/// assert!(cache.get("tr-3", "raw-0").is_some());
+/// # std::fs::remove_dir_all(dir)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamCache {
+    dir: PathBuf,
+    server: String,
+    max_bytes: u64,
+}
+
+impl StreamCache {
+    /// The `streams` directory under danavi's cache dir, for `server` as
+    /// [`SubsonicClient::server_key`] names it. The cap covers every
+    /// server's songs together.
+    pub fn open(server: &str, max_bytes: u64) -> Result<Self> {
+        Ok(Self::new(get_cache_dir()?.join(STREAMS_DIR_NAME), server, max_bytes))
+    }
+
+    /// Streams in `dir`, capped at `max_bytes` for all servers together.
+    pub fn new(dir: PathBuf, server: &str, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            server: file_name(server),
+            max_bytes,
+        }
+    }
+
+    fn path(&self, id: &str, variant: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}-{}-{}", self.server, file_name(id), file_name(variant)))
+    }
+
+    /// The kept song, if it's there and intact. Getting one counts as
+    /// playing it, so it's the last to be evicted.
+    pub fn get(&self, id: &str, variant: &str) -> Option<Vec<u8>> {
+        let path = self.path(id, variant);
+        let bytes = fs::read(&path).ok()?;
+        let intact = fs::read_to_string(path.with_extension("md5"))
+            .is_ok_and(|sum| sum == format!("{:x}", md5::compute(&bytes)));
+        if !intact {
+            log::debug!("Dropping damaged cached stream {}", path.display());
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(path.with_extension("md5"));
+            return None;
+        }
+        if let Ok(file) = OpenOptions::new().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(bytes)
+    }
+
+    /// Keeps a whole song, then evicts what no longer fits. A song bigger
+    /// than the cap on its own isn't kept.
+    pub fn store(&self, id: &str, variant: &str, bytes: &[u8]) -> Result<()> {
+        if bytes.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir).context("Failed to create stream cache directory")?;
+        let path = self.path(id, variant);
+        let partial = path.with_extension("part");
+        fs::write(&partial, bytes).context("Failed to write cached stream")?;
+        fs::write(path.with_extension("md5"), format!("{:x}", md5::compute(bytes)))
+            .context("Failed to write cached stream")?;
+        fs::rename(&partial, &path).context("Failed to write cached stream")?;
+        self.evict(&path)
+    }
+
+    // Removes the least recently played songs, other than `keep`, until the
+    // rest fit under the cap
+    fn evict(&self, keep: &Path) -> Result<()> {
+        let mut songs: Vec<(SystemTime, u64, PathBuf)> = fs::read_dir(&self.dir)
+            .context("Failed to read stream cache directory")?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let meta = fs::metadata(&path).ok().filter(|m| m.is_file() && path.extension().is_none())?;
+                Some((meta.modified().ok()?, meta.len(), path))
+            })
+            .collect();
+        let mut total: u64 = songs.iter().map(|(_, len, _)| len).sum();
+        songs.sort();
+        for (_, len, path) in songs {
+            if total <= self.max_bytes {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(path.with_extension("md5"));
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+/// Cover art stored one file per art id and size. Anything that goes wrong,
+/// from a server without art to a corrupted file, just means no art.
+#[derive(Debug, Clone)]
+pub struct CoverArtCache {
+    dir: PathBuf,
+}
+
+impl CoverArtCache {
+    /// The `covers` directory under danavi's cache dir.
+    pub fn open() -> Result<Self> {
+        Ok(Self::new(get_cache_dir()?.join(COVERS_DIR_NAME)))
+    }
+
+    /// Covers in `dir`, which is created on the first store.
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, id: &str, size: Option<u32>) -> PathBuf {
+        let name = file_name(id);
+        self.dir.join(match size {
+            Some(size) => format!("{}-{}", name, size),
+            None => name,
+        })
+    }
+
+    /// The cached image, if there's a good one. A bad one is removed so the
+    /// next fetch replaces it.
+    pub fn get(&self, id: &str, size: Option<u32>) -> Option<Vec<u8>> {
+        let path = self.path(id, size);
+        let bytes = fs::read(&path).ok()?;
+        if is_image(&bytes) {
+            return Some(bytes);
+        }
+        let _ = fs::remove_file(&path);
+        None
+    }
+
+    /// The path of the cached image, downloading it first if needed.
+    pub async fn fetch(&self, client: &SubsonicClient, id: &str, size: Option<u32>) -> Option<PathBuf> {
+        if self.get(id, size).is_some() {
+            return Some(self.path(id, size));
+        }
+        match self.download(client, id, size).await {
+            Ok(path) => Some(path),
+            Err(e) => {
+                log::debug!("No cover art for {}: {:#}", id, e);
+                None
+            }
+        }
+    }
+
+    async fn download(&self, client: &SubsonicClient, id: &str, size: Option<u32>) -> Result<PathBuf> {
+        let bytes = client.get_cover_art(id, size).await?;
+        if !is_image(&bytes) {
+            anyhow::bail!("Server returned something other than an image");
+        }
+        fs::create_dir_all(&self.dir).context("Failed to create cover art directory")?;
+        let path = self.path(id, size);
+        let partial = path.with_extension("part");
+        fs::write(&partial, &bytes).context("Failed to write cover art")?;
+        fs::rename(&partial, &path).context("Failed to write cover art")?;
+        Ok(path)
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[derive(Serialize, Deserialize)]
+struct MetadataEntry<T> {
+    /// Unix time in seconds it was fetched
+    fetched: u64,
+    value: T,
+}
+
+/// Library listings stored one JSON file per endpoint and id, e.g. an
+/// artist's albums, with when they were fetched. Entries past the TTL are
+/// still handed out, marked stale, since an old listing beats none while a
+/// new one loads. Each server and user gets a directory of its own.
+///
+/// ```
+/// use danavi::cache::MetadataCache;
+/// use std::time::Duration;
+///
+/// let dir = std::env::temp_dir().join(format!("danavi-doctest-metadata-{}", std::process::id()));
+/// let cache = MetadataCache::new(dir.clone(), Duration::from_secs(3600));
+/// assert_eq!(cache.get::<Vec<String>>("getArtist", "ar-1"), None);
+///
+/// cache.store("getArtist", "ar-1", &vec!["Blue Train".to_string()])?;
+/// assert_eq!(cache.get("getArtist", "ar-1"), Some((vec!["Blue Train".to_string()], true)));
+/// // With no TTL to speak of it's already stale
+/// let stale = MetadataCache::new(dir.clone(), Duration::ZERO);
+/// assert_eq!(stale.get::<Vec<String>>("getArtist", "ar-1").map(|(_, fresh)| fresh), Some(false));
+///
+/// cache.remove("getArtist", "ar-1");
+/// assert_eq!(cache.get::<Vec<String>>("getArtist", "ar-1"), None);
+///
+/// // Every listing from one endpoint at once, leaving the others
+/// cache.store("getAlbum", "al-1", &1)?;
+/// cache.store("getAlbum", "al-2", &2)?;
+/// cache.store("getArtists", "", &3)?;
+/// cache.remove_all("getAlbum");
+/// assert_eq!(cache.get::<i32>("getAlbum", "al-2"), None);
+/// assert_eq!(cache.get::<i32>("getArtists", ""), Some((3, true)));
+/// cache.clear()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MetadataCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl MetadataCache {
+    /// The directory for `server`, as [`SubsonicClient::server_key`] names
+    /// it, under `metadata` in danavi's cache dir.
+    pub fn open(server: &str, ttl: Duration) -> Result<Self> {
+        Ok(Self::new(get_cache_dir()?.join(METADATA_DIR_NAME).join(file_name(server)), ttl))
+    }
+
+    /// Listings in `dir`, fresh for `ttl` after they were fetched.
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    fn path(&self, endpoint: &str, id: &str) -> PathBuf {
+        self.dir.join(format!("{}-{}.json", file_name(endpoint), file_name(id)))
+    }
+
+    /// The stored value and whether it's younger than the TTL. One that
+    /// can't be read, say from an older danavi, counts as missing.
+    pub fn get<T: DeserializeOwned>(&self, endpoint: &str, id: &str) -> Option<(T, bool)> {
+        let bytes = fs::read(self.path(endpoint, id)).ok()?;
+        let entry: MetadataEntry<T> = serde_json::from_slice(&bytes).ok()?;
+        let age = Duration::from_secs(unix_secs().saturating_sub(entry.fetched));
+        Some((entry.value, age < self.ttl))
+    }
+
+    /// Stores a freshly fetched value.
+    pub fn store<T: Serialize>(&self, endpoint: &str, id: &str, value: &T) -> Result<()> {
+        fs::create_dir_all(&self.dir).context("Failed to create metadata cache directory")?;
+        let entry = MetadataEntry { fetched: unix_secs(), value };
+        let json = serde_json::to_vec(&entry).context("Failed to serialize cached listing")?;
+        let path = self.path(endpoint, id);
+        let partial = path.with_extension("part");
+        fs::write(&partial, json).context("Failed to write cached listing")?;
+        fs::rename(&partial, &path).context("Failed to write cached listing")?;
+        Ok(())
+    }
+
+    /// Forgets a listing, so the next read goes to the server.
+    pub fn remove(&self, endpoint: &str, id: &str) {
+        let _ = fs::remove_file(self.path(endpoint, id));
+    }
+
+    /// Forgets every listing from `endpoint`, whatever its id.
+    pub fn remove_all(&self, endpoint: &str) {
+        let prefix = format!("{}-", file_name(endpoint));
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    /// Forgets everything, e.g. once a scan has changed the library.
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).context("Failed to clear the metadata cache")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A `file://` URL for a cached file, as MPRIS `mpris:artUrl` wants.
+pub fn file_url(path: &Path) -> Option<String> {
+    Url::from_file_path(path).ok().map(String::from)
+}
@@ -1,38 +1,892 @@
+//! Subsonic API client.
+
 use crate::types::*;
 use anyhow::{Context, Result};
 use rand::Rng;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Deserialize;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::sync::OnceCell;
 use url::Url;
 
-const CLIENT_NAME: &str = "danavi";
+/// The client name sent with every request, which servers record as who
+/// saved a play queue or played a song.
+pub const CLIENT_NAME: &str = "danavi";
 const VERSION: &str = "1.16.1";
+// Song ids sent per playlist request, keeping the URL a safe length
+const IDS_PER_REQUEST: usize = 100;
+// The play queue can't be split over requests, so longer ones are cut short
+const PLAY_QUEUE_IDS: usize = 200;
+
+// Whether `e` is the server not knowing `search3`: too old an API version,
+// or an error that names the endpoint as unknown. A bare 404 says more
+// about the server's address than about the endpoint, so it doesn't count
+fn search3_missing(e: &anyhow::Error) -> bool {
+    if let Some(e) = e.downcast_ref::<SubsonicError>() {
+        return match e {
+            SubsonicError::ServerTooOld(_) => true,
+            SubsonicError::Generic(message) | SubsonicError::Other { message, .. } => {
+                message.to_lowercase().contains("unknown endpoint")
+            }
+            _ => false,
+        };
+    }
+    matches!(
+        e.downcast_ref::<TransportError>(),
+        Some(TransportError::Http(StatusCode::NOT_IMPLEMENTED))
+    )
+}
+
+/// Which certificates the client trusts, for servers without one from a
+/// public CA.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// PEM file of certificates to trust on top of the system's
+    pub ca_file: Option<PathBuf>,
+    /// Accept any certificate at all, which anyone in between can fake
+    pub accept_invalid_certs: bool,
+}
+
+impl TlsOptions {
+    fn http_client(&self) -> Result<Client> {
+        let mut builder = Client::builder().danger_accept_invalid_certs(self.accept_invalid_certs);
+        if let Some(path) = &self.ca_file {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read tls_ca_file {}", path.display()))?;
+            let certs = reqwest::Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("tls_ca_file {} isn't a valid PEM file", path.display()))?;
+            if certs.is_empty() {
+                anyhow::bail!(
+                    "tls_ca_file {} has no certificates in it; it should hold one or more \
+                     -----BEGIN CERTIFICATE----- blocks",
+                    path.display()
+                );
+            }
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        builder.build().context("Failed to create HTTP client")
+    }
+}
+
+/// Why a request got no Subsonic answer at all. Like [`SubsonicError`], it
+/// comes back inside the client's `anyhow::Error`s.
+///
+/// ```
+/// use danavi::client::{SubsonicClient, TransportError};
+///
+/// // Nothing listens on port 1
+/// let client = SubsonicClient::new(
+///     "http://127.0.0.1:1".to_string(),
+///     "alice".to_string(),
+///     "secret".to_string(),
+/// )?;
+/// let error = tokio::runtime::Runtime::new()?.block_on(client.ping()).unwrap_err();
+/// assert!(matches!(error.downcast_ref(), Some(TransportError::Refused)));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportError {
+    /// The host name didn't resolve
+    Dns(String),
+    /// Nothing is listening at that address and port
+    Refused,
+    /// The TLS handshake failed, as it does on a self-signed certificate
+    Tls(String),
+    /// No answer in time
+    TimedOut,
+    /// Any other failure to connect or to read the answer
+    Connect(String),
+    /// An HTTP error status without a Subsonic answer
+    Http(StatusCode),
+    /// Something answered, but not in the Subsonic API's terms, as a web
+    /// server does when `base_url` points at the wrong place
+    NotSubsonic,
+}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return TransportError::TimedOut;
+        }
+        // What went wrong is buried under hyper's errors, down to the OS's.
+        // The URL, which carries the credentials, is never among them.
+        let mut causes = Vec::new();
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            if cause
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|io| io.kind() == std::io::ErrorKind::ConnectionRefused)
+            {
+                return TransportError::Refused;
+            }
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        let detail = causes.last().cloned().unwrap_or_else(|| e.without_url().to_string());
+        let all = causes.join(": ").to_lowercase();
+        if all.contains("dns error") || all.contains("lookup address") {
+            TransportError::Dns(detail)
+        } else if ["certificate", "tls", "ssl", "handshake"].iter().any(|word| all.contains(word)) {
+            TransportError::Tls(detail)
+        } else {
+            TransportError::Connect(detail)
+        }
+    }
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::Dns(detail) => write!(f, "Couldn't look up the server's host name ({})", detail),
+            TransportError::Refused => write!(f, "Connection refused, is the server running on that port?"),
+            TransportError::Tls(detail) => write!(f, "TLS handshake failed: {}", detail),
+            TransportError::TimedOut => write!(f, "The server didn't answer in time"),
+            TransportError::Connect(detail) => write!(f, "Couldn't reach the server: {}", detail),
+            TransportError::Http(status) if matches!(*status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+                write!(f, "The server returned {}, check the username and password", status)
+            }
+            TransportError::Http(status) => write!(f, "The server returned {}", status),
+            TransportError::NotSubsonic => {
+                write!(f, "The server's answer isn't a Subsonic API response, check base_url")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// An error the server answered a request with, by its Subsonic error code.
+/// It comes back inside the `anyhow::Error`s the client returns, so callers
+/// can tell what went wrong with `downcast_ref`.
+///
+/// ```
+/// use danavi::client::SubsonicError;
+/// use serde_json::json;
+///
+/// let error = SubsonicError::parse(&json!({ "code": 40, "message": "Wrong username or password" }));
+/// assert_eq!(error, SubsonicError::WrongCredentials("Wrong username or password".to_string()));
+/// assert!(error.is_auth());
+///
+/// // Without a message the code's usual meaning stands in
+/// let error = SubsonicError::parse(&json!({ "code": 10 }));
+/// assert_eq!(error.message(), "Required parameter is missing");
+/// assert_eq!(error.to_string(), "API error 10: Required parameter is missing");
+///
+/// let error = SubsonicError::parse(&json!({ "code": 99, "message": "Out of cheese" }));
+/// assert_eq!(error.code(), 99);
+/// assert_eq!(SubsonicError::parse(&json!({})).code(), 0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubsonicError {
+    /// 0, anything without a code of its own
+    Generic(String),
+    /// 10
+    MissingParameter(String),
+    /// 20, the server wants a newer API version than this client speaks
+    ClientTooOld(String),
+    /// 30, the server speaks an older API version than this client
+    ServerTooOld(String),
+    /// 40
+    WrongCredentials(String),
+    /// 41 to 44, the server won't take this way of logging in
+    #[allow(missing_docs)]
+    AuthUnsupported { code: i64, message: String },
+    /// 50, the user lacks the role for this, e.g. admin for scans
+    NotAuthorized(String),
+    /// 60
+    TrialExpired(String),
+    /// 70
+    NotFound(String),
+    /// A code the API docs don't list
+    #[allow(missing_docs)]
+    Other { code: i64, message: String },
+}
+
+impl SubsonicError {
+    /// Reads the `error` object of a failed response.
+    pub fn parse(error: &Value) -> Self {
+        let code = error.get("code").and_then(Value::as_i64).unwrap_or(0);
+        let message = error
+            .get("message")
+            .and_then(Value::as_str)
+            .unwrap_or_else(|| Self::meaning(code))
+            .to_string();
+        match code {
+            0 => SubsonicError::Generic(message),
+            10 => SubsonicError::MissingParameter(message),
+            20 => SubsonicError::ClientTooOld(message),
+            30 => SubsonicError::ServerTooOld(message),
+            40 => SubsonicError::WrongCredentials(message),
+            41..=44 => SubsonicError::AuthUnsupported { code, message },
+            50 => SubsonicError::NotAuthorized(message),
+            60 => SubsonicError::TrialExpired(message),
+            70 => SubsonicError::NotFound(message),
+            _ => SubsonicError::Other { code, message },
+        }
+    }
+
+    // What a code means according to the API docs
+    fn meaning(code: i64) -> &'static str {
+        match code {
+            10 => "Required parameter is missing",
+            20 => "Incompatible Subsonic REST protocol version, client must upgrade",
+            30 => "Incompatible Subsonic REST protocol version, server must upgrade",
+            40 => "Wrong username or password",
+            41 => "Token authentication not supported",
+            42 => "Provided authentication mechanism not supported",
+            43 => "Multiple conflicting authentication mechanisms provided",
+            44 => "Invalid API key",
+            50 => "User is not authorized for the given operation",
+            60 => "The trial period for the Subsonic server is over",
+            70 => "The requested data was not found",
+            _ => "Unknown error",
+        }
+    }
+
+    /// The error code the server sent.
+    pub fn code(&self) -> i64 {
+        match self {
+            SubsonicError::Generic(_) => 0,
+            SubsonicError::MissingParameter(_) => 10,
+            SubsonicError::ClientTooOld(_) => 20,
+            SubsonicError::ServerTooOld(_) => 30,
+            SubsonicError::WrongCredentials(_) => 40,
+            SubsonicError::NotAuthorized(_) => 50,
+            SubsonicError::TrialExpired(_) => 60,
+            SubsonicError::NotFound(_) => 70,
+            SubsonicError::AuthUnsupported { code, .. } | SubsonicError::Other { code, .. } => *code,
+        }
+    }
+
+    /// The server's message, or what the code means when it sent none.
+    pub fn message(&self) -> &str {
+        match self {
+            SubsonicError::Generic(message)
+            | SubsonicError::MissingParameter(message)
+            | SubsonicError::ClientTooOld(message)
+            | SubsonicError::ServerTooOld(message)
+            | SubsonicError::WrongCredentials(message)
+            | SubsonicError::NotAuthorized(message)
+            | SubsonicError::TrialExpired(message)
+            | SubsonicError::NotFound(message)
+            | SubsonicError::AuthUnsupported { message, .. }
+            | SubsonicError::Other { message, .. } => message,
+        }
+    }
+
+    /// Whether logging in failed, wrong credentials or otherwise.
+    pub fn is_auth(&self) -> bool {
+        matches!(self, SubsonicError::WrongCredentials(_) | SubsonicError::AuthUnsupported { .. })
+    }
+}
+
+impl std::fmt::Display for SubsonicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API error {}: {}", self.code(), self.message())
+    }
+}
 
+impl std::error::Error for SubsonicError {}
+
+enum FetchError {
+    Auth(anyhow::Error),
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for FetchError {
+    fn from(e: anyhow::Error) -> Self {
+        FetchError::Other(e)
+    }
+}
+
+/// How a song should come from the server. The default is the file as it
+/// is, unless the client has a bitrate cap of its own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamOptions {
+    /// Format to transcode to, e.g. "mp3"
+    pub format: Option<String>,
+    /// Highest bitrate in kbps; 0 leaves it to the client's cap
+    pub max_bit_rate: u32,
+    /// Seconds into the song to start at. Servers only cut transcoded
+    /// streams; a raw file comes from the top whatever this says.
+    pub time_offset: u64,
+}
+
+impl StreamOptions {
+    /// Transcoded to `format`, at whatever bitrate the server picks.
+    pub fn format(format: &str) -> Self {
+        Self {
+            format: Some(format.to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+/// What to tell the server's own player, see [`SubsonicClient::jukebox_control`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JukeboxAction<'a> {
+    /// Only report where the jukebox is
+    Status,
+    /// Replace the jukebox playlist with these songs
+    Set(&'a [String]),
+    /// Add these songs to the end of the jukebox playlist
+    Add(&'a [String]),
+    /// Play, or carry on playing
+    Start,
+    /// Pause where it is
+    Stop,
+    /// Play the song at `index` in the jukebox playlist, `offset` seconds in
+    #[allow(missing_docs)]
+    Skip { index: usize, offset: u64 },
+    /// Volume from 0 to 1
+    SetGain(f32),
+}
+
+/// A song's original file, from [`SubsonicClient::download`].
+#[derive(Debug, Clone)]
+pub struct Download {
+    /// The whole file
+    pub bytes: Vec<u8>,
+    /// The name the server suggests saving it as
+    pub file_name: Option<String>,
+    /// The MIME type, when the server sent one
+    pub content_type: Option<String>,
+}
+
+impl Download {
+    /// The file extension, from the suggested name or else the content type.
+    ///
+    /// ```
+    /// use danavi::client::Download;
+    ///
+    /// let download = |file_name: Option<&str>, content_type: Option<&str>| Download {
+    ///     bytes: Vec::new(),
+    ///     file_name: file_name.map(str::to_string),
+    ///     content_type: content_type.map(str::to_string),
+    /// };
+    /// assert_eq!(download(Some("01 - Intro.FLAC"), Some("audio/mpeg")).extension().as_deref(), Some("flac"));
+    /// assert_eq!(download(None, Some("audio/x-flac")).extension().as_deref(), Some("flac"));
+    /// assert_eq!(download(None, Some("audio/mp4; codecs=alac")).extension().as_deref(), Some("m4a"));
+    /// assert_eq!(download(None, Some("application/octet-stream")).extension(), None);
+    /// ```
+    pub fn extension(&self) -> Option<String> {
+        let from_name = self
+            .file_name
+            .as_deref()
+            .and_then(|name| std::path::Path::new(name).extension()?.to_str())
+            .map(str::to_lowercase);
+        from_name.or_else(|| {
+            let mime = self.content_type.as_deref()?.split(';').next()?.trim().to_lowercase();
+            let extension = match mime.as_str() {
+                "audio/flac" | "audio/x-flac" => "flac",
+                "audio/mpeg" | "audio/mp3" => "mp3",
+                "audio/ogg" | "audio/vorbis" | "application/ogg" => "ogg",
+                "audio/opus" => "opus",
+                "audio/mp4" | "audio/m4a" | "audio/x-m4a" => "m4a",
+                "audio/aac" | "audio/aacp" => "aac",
+                "audio/wav" | "audio/wave" | "audio/x-wav" => "wav",
+                "audio/aiff" | "audio/x-aiff" => "aiff",
+                "audio/x-ms-wma" => "wma",
+                "audio/x-ape" | "audio/ape" => "ape",
+                "audio/x-wavpack" | "audio/wavpack" => "wv",
+                "audio/dsf" | "audio/x-dsf" => "dsf",
+                _ => return None,
+            };
+            Some(extension.to_string())
+        })
+    }
+}
+
+// The filename parameter of a Content-Disposition header
+fn disposition_file_name(header: &str) -> Option<String> {
+    let name = header
+        .split(';')
+        .filter_map(|part| part.trim().strip_prefix("filename="))
+        .next()?
+        .trim_matches('"');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// Just enough of a response to tell success from failure, skipping the rest
+#[derive(Deserialize)]
+struct ResponseStatus {
+    status: String,
+    #[serde(default)]
+    error: Option<Value>,
+}
+
+// createPlaylist's answer, which servers before API 1.14 leave empty
+#[derive(Deserialize)]
+struct CreatedPlaylist {
+    #[serde(default)]
+    playlist: Option<CreatedPlaylistId>,
+}
+
+#[derive(Deserialize)]
+struct CreatedPlaylistId {
+    id: String,
+}
+
+/// Reads a JSON answer from `endpoint` straight into its response type,
+/// once its status says it worked. A failed one comes back as its
+/// [`SubsonicError`], and anything else as [`TransportError::NotSubsonic`].
+///
+/// ```
+/// use danavi::client::{parse_response, SubsonicError, TransportError};
+/// use danavi::types::ArtistsResponse;
+/// use serde_json::json;
+///
+/// let artists: Vec<_> = (0..20_000)
+///     .map(|i| json!({ "id": format!("ar-{}", i), "name": format!("Artist {}", i), "albumCount": 2 }))
+///     .collect();
+/// let body = serde_json::to_vec(&json!({ "subsonic-response": {
+///     "status": "ok",
+///     "version": "1.16.1",
+///     "artists": { "index": [{ "name": "A", "artist": artists }] },
+/// }}))?;
+/// let response: ArtistsResponse = parse_response("getArtists", &body)?;
+/// assert_eq!(response.artists.index[0].artist.len(), 20_000);
+/// assert_eq!(response.artists.index[0].artist[19_999].name, "Artist 19999");
+///
+/// let body = br#"{"subsonic-response": {"status": "failed", "error": {"code": 70, "message": "Artist not found"}}}"#;
+/// let error = parse_response::<ArtistsResponse>("getArtists", body).unwrap_err();
+/// assert!(matches!(error.downcast_ref(), Some(SubsonicError::NotFound(_))));
+///
+/// let error = parse_response::<ArtistsResponse>("getArtists", b"<html></html>").unwrap_err();
+/// assert_eq!(error.downcast_ref(), Some(&TransportError::NotSubsonic));
+/// let body = br#"{"subsonic-response": {"status": "ok"}}"#;
+/// let error = parse_response::<ArtistsResponse>("getArtists", body).unwrap_err();
+/// assert_eq!(error.to_string(), "Failed to parse getArtists response");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn parse_response<T: DeserializeOwned>(endpoint: &str, body: &[u8]) -> Result<T> {
+    let status = serde_json::from_slice::<SubsonicResponse<ResponseStatus>>(body)
+        .map_err(|_| TransportError::NotSubsonic)?
+        .response;
+    if status.status != "ok" {
+        return Err(SubsonicError::parse(status.error.as_ref().unwrap_or(&Value::Null)).into());
+    }
+    let response = serde_json::from_slice::<SubsonicResponse<T>>(body)
+        .with_context(|| format!("Failed to parse {} response", endpoint))?;
+    Ok(response.response)
+}
+
+// Whether a content type is for text rather than media
+fn is_text_type(content_type: &str) -> bool {
+    let content_type = content_type.to_ascii_lowercase();
+    ["json", "xml", "html", "text/"].iter().any(|kind| content_type.contains(kind))
+}
+
+/// What went wrong when the stream endpoint answered with text in place of
+/// audio: a Subsonic error, in JSON or XML, comes back as its
+/// [`SubsonicError`]; a web page or other text, as a login page or proxy in
+/// front of the server sends, as what it says. The content type is believed
+/// when it says text, and otherwise the start of `body` decides. None when
+/// it looks like audio.
+///
+/// ```
+/// use danavi::client::{non_audio_error, SubsonicError};
+///
+/// // Sent with 200 and a media type, even
+/// let json = br#"{"subsonic-response": {"status": "failed", "error": {"code": 40, "message": "Wrong username or password"}}}"#;
+/// let error = non_audio_error(Some("application/octet-stream"), json).unwrap();
+/// assert_eq!(error.downcast_ref(), Some(&SubsonicError::WrongCredentials("Wrong username or password".to_string())));
+///
+/// let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+/// <subsonic-response xmlns="http://subsonic.org/restapi" status="failed" version="1.16.1">
+///     <error code="70" message="Song &quot;tr-9&quot; not found"/>
+/// </subsonic-response>"#;
+/// let error = non_audio_error(Some("text/xml; charset=utf-8"), xml).unwrap();
+/// assert_eq!(error.downcast_ref(), Some(&SubsonicError::NotFound("Song \"tr-9\" not found".to_string())));
+///
+/// let page = b"\n<!DOCTYPE html><html><head><title> Sign in \xc2\xb7 Authelia </title></head><body>...";
+/// let error = non_audio_error(None, page).unwrap();
+/// assert_eq!(
+///     error.to_string(),
+///     "The server sent a web page (\"Sign in \u{b7} Authelia\") instead of the song, is a login page or proxy in the way?"
+/// );
+/// let error = non_audio_error(Some("text/plain"), b"\r\n502 Bad Gateway\r\nnginx").unwrap();
+/// assert_eq!(error.to_string(), "The server sent text instead of the song: 502 Bad Gateway");
+///
+/// assert!(non_audio_error(Some("audio/mpeg"), b"ID3\x04\x00\x00\x00\x00\x00\x00\xff\xfb").is_none());
+/// assert!(non_audio_error(None, b"RIFF\x24\x08\x00\x00WAVEfmt \x10\x00\x00\x00").is_none());
+/// assert!(non_audio_error(None, b"fLaC\x00\x00\x00\x22").is_none());
+/// assert!(non_audio_error(None, b"").is_none());
+/// ```
+pub fn non_audio_error(content_type: Option<&str>, body: &[u8]) -> Option<anyhow::Error> {
+    let head = &body[..body.len().min(512)];
+    let head = match std::str::from_utf8(head) {
+        Ok(head) => head,
+        // Cut off in the middle of a character
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+        Err(_) => "",
+    };
+    let looks_like_text = !head.trim().is_empty() && !head.chars().any(|c| c.is_control() && !c.is_whitespace());
+    if !content_type.is_some_and(is_text_type) && !looks_like_text {
+        return None;
+    }
+
+    let text = String::from_utf8_lossy(body);
+    let text = text.trim_start_matches('\u{feff}').trim();
+    if text.is_empty() {
+        return None;
+    }
+    let error = serde_json::from_str::<Value>(text)
+        .ok()
+        .and_then(|json| json.get("subsonic-response")?.get("error").cloned())
+        .or_else(|| xml_error(text));
+    if let Some(error) = error {
+        return Some(SubsonicError::parse(&error).into());
+    }
+    let lower = text.to_ascii_lowercase();
+    if lower.contains("<html") || lower.starts_with("<!doctype html") {
+        let title = lower
+            .find("<title>")
+            .map(|start| &text[start + "<title>".len()..])
+            .and_then(|rest| Some(rest[..rest.to_ascii_lowercase().find("</title>")?].trim()))
+            .filter(|title| !title.is_empty());
+        return Some(match title {
+            Some(title) => anyhow::anyhow!(
+                "The server sent a web page (\"{}\") instead of the song, is a login page or proxy in the way?",
+                title
+            ),
+            None => anyhow::anyhow!("The server sent a web page instead of the song, is a login page or proxy in the way?"),
+        });
+    }
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    let line: String = line.chars().take(100).collect();
+    Some(anyhow::anyhow!("The server sent text instead of the song: {}", line))
+}
+
+// The `error` element of an XML answer, as the JSON one would have it
+fn xml_error(text: &str) -> Option<Value> {
+    let start = text.find("<error ")?;
+    let tag = &text[start..start + text[start..].find('>')?];
+    let attribute = |name: &str| {
+        let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+        let value = &tag[start..start + tag[start..].find('"')?];
+        Some(
+            value
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&amp;", "&"),
+        )
+    };
+    let mut error = serde_json::Map::new();
+    if let Some(code) = attribute("code").and_then(|code| code.parse::<i64>().ok()) {
+        error.insert("code".to_string(), code.into());
+    }
+    if let Some(message) = attribute("message") {
+        error.insert("message".to_string(), message.into());
+    }
+    Some(Value::Object(error))
+}
+
+/// What the server says it can do, from `ping` and, on OpenSubsonic
+/// servers, `getOpenSubsonicExtensions`. See [`SubsonicClient::capabilities`].
+///
+/// ```
+/// use danavi::client::{parse_response, ServerCapabilities};
+/// use danavi::types::{OpenSubsonicExtensionsResponse, PingResponse};
+///
+/// let ping: PingResponse = parse_response("ping", br#"{"subsonic-response": {"status": "ok",
+///     "version": "1.16.1", "type": "navidrome", "serverVersion": "0.53.3", "openSubsonic": true}}"#)?;
+/// let found: OpenSubsonicExtensionsResponse = parse_response("getOpenSubsonicExtensions",
+///     br#"{"subsonic-response": {"status": "ok", "openSubsonicExtensions": [
+///         {"name": "songLyrics", "versions": [1]}, {"name": "transcodeOffset", "versions": [1]}
+///     ]}}"#)?;
+/// let navidrome = ServerCapabilities::new(&ping, Some(found.extensions));
+/// assert!(navidrome.synced_lyrics() && navidrome.transcode_offset() && navidrome.search3());
+/// assert!(!navidrome.api_key_auth());
+/// assert_eq!(navidrome.server.as_deref(), Some("navidrome 0.53.3"));
+///
+/// // Old Subsonic: no extensions and no search3
+/// let ping: PingResponse = parse_response("ping", br#"{"subsonic-response": {"status": "ok", "version": "1.7.0"}}"#)?;
+/// let old = ServerCapabilities::new(&ping, None);
+/// assert!(!old.open_subsonic && !old.synced_lyrics() && !old.search3());
+/// // Plain Subsonic can't say either way, so timeOffset is tried
+/// assert!(old.transcode_offset());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ServerCapabilities {
+    /// REST API version, e.g. "1.16.1"
+    pub api_version: String,
+    /// The server's name and version, when it says
+    pub server: Option<String>,
+    /// Whether it speaks OpenSubsonic; not when the extensions can't be listed
+    pub open_subsonic: bool,
+    /// The OpenSubsonic extensions it supports, empty when it doesn't
+    pub extensions: Vec<OpenSubsonicExtension>,
+}
+
+impl ServerCapabilities {
+    /// From a ping and the extensions listed after it, None when listing
+    /// them failed or wasn't tried.
+    pub fn new(ping: &PingResponse, extensions: Option<Vec<OpenSubsonicExtension>>) -> Self {
+        let server = match (&ping.server_type, &ping.server_version) {
+            (Some(name), Some(version)) => Some(format!("{} {}", name, version)),
+            (name, _) => name.clone(),
+        };
+        Self {
+            api_version: ping.version.clone(),
+            server,
+            open_subsonic: ping.open_subsonic && extensions.is_some(),
+            extensions: extensions.unwrap_or_default(),
+        }
+    }
+
+    /// Whether the server supports OpenSubsonic extension `name`.
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.iter().any(|e| e.name == name)
+    }
+
+    /// Whether the API version is `version` or later. An unreadable version
+    /// counts as new enough.
+    pub fn api_at_least(&self, version: &str) -> bool {
+        let parse = |v: &str| -> Option<Vec<u32>> { v.split('.').map(|n| n.parse().ok()).collect() };
+        match (parse(&self.api_version), parse(version)) {
+            (Some(have), Some(want)) => have >= want,
+            _ => true,
+        }
+    }
+
+    /// `getLyricsBySongId`, with synced lyrics
+    pub fn synced_lyrics(&self) -> bool {
+        self.has_extension("songLyrics")
+    }
+
+    /// Logging in with an API key instead of a password
+    pub fn api_key_auth(&self) -> bool {
+        self.has_extension("apiKeyAuthentication")
+    }
+
+    /// `search3`, which came with API 1.8.0
+    pub fn search3(&self) -> bool {
+        self.api_at_least("1.8.0")
+    }
+
+    /// `createPlaylist` answering with the new playlist, as since API 1.14.0
+    pub fn returns_created_playlist(&self) -> bool {
+        self.api_at_least("1.14.0")
+    }
+
+    /// Transcoded streams starting at `timeOffset`. OpenSubsonic servers say
+    /// whether they do; plain Subsonic ones may.
+    pub fn transcode_offset(&self) -> bool {
+        !self.open_subsonic || self.has_extension("transcodeOffset")
+    }
+}
+
+/// Async client for the Subsonic REST API, as served by Navidrome, Gonic,
+/// Airsonic and friends. Each request authenticates with a fresh salted
+/// token, so the password itself never goes over the wire, unless the server
+/// only takes legacy auth (see [`SubsonicClient::set_auth_method`]).
+///
+/// Cloning is cheap and clones share the connection pool.
+///
+/// ```no_run
+/// use danavi::client::SubsonicClient;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let client = SubsonicClient::new(
+///     "http://localhost:4533".to_string(),
+///     "alice".to_string(),
+///     "secret".to_string(),
+/// )?;
+///
+/// for index in client.get_artists().await?.artists.index {
+///     for artist in index.artist {
+///         println!("{}", artist.name);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
 pub struct SubsonicClient {
+    /// Server root without a trailing slash
     pub base_url: String,
     username: String,
     password: String,
     client: Client,
+    // Cap in kbps for every stream request, or 0 for none
+    max_bit_rate: u32,
+    // What songs are asked for as when nothing else is said
+    stream_format: StreamFormat,
+    // Whether requests carry the password instead of a token. Shared between
+    // clones so one falling back moves them all.
+    legacy_auth: Arc<AtomicBool>,
+    // Set when the client moved to legacy auth by itself, until the UI has
+    // said so
+    fell_back: Arc<AtomicBool>,
+    // Whether the server lacks `search3`, found out by the first search and
+    // shared like `legacy_auth`
+    search2_only: Arc<AtomicBool>,
+    capabilities: Arc<OnceCell<ServerCapabilities>>,
 }
 
 impl SubsonicClient {
+    /// Creates a client. Nothing is sent until the first request, so a wrong
+    /// URL or password only shows up then; [`SubsonicClient::ping`] is a cheap
+    /// way to check.
     pub fn new(base_url: String, username: String, password: String) -> Result<Self> {
         // Remove trailing slash
         let base_url = base_url.trim_end_matches('/').to_string();
 
-        let client = Client::builder()
-            .build()
-            .context("Failed to create HTTP client")?;
+        let client = TlsOptions::default().http_client()?;
 
         Ok(Self {
             base_url,
             username,
             password,
             client,
+            max_bit_rate: 0,
+            stream_format: StreamFormat::Raw,
+            legacy_auth: Arc::new(AtomicBool::new(false)),
+            fell_back: Arc::new(AtomicBool::new(false)),
+            search2_only: Arc::new(AtomicBool::new(false)),
+            capabilities: Arc::new(OnceCell::new()),
         })
     }
 
+    /// Has the server transcode every stream down to `kbps` when the file
+    /// is above it, e.g. to save mobile data. 0, the default, streams files
+    /// at their own bitrate.
+    pub fn set_max_bit_rate(&mut self, kbps: u32) {
+        self.max_bit_rate = kbps;
+    }
+
+    /// Trusts certificates as `tls` says, for every request including
+    /// streams. Call it before cloning, since clones made earlier keep the
+    /// old settings. Fails when the CA file can't be read or has no
+    /// certificates.
+    ///
+    /// ```
+    /// use danavi::client::{SubsonicClient, TlsOptions};
+    ///
+    /// let mut client = SubsonicClient::new(
+    ///     "https://music.lan".to_string(),
+    ///     "alice".to_string(),
+    ///     "secret".to_string(),
+    /// )?;
+    /// let path = std::env::temp_dir().join("danavi-doctest-not-a-cert.pem");
+    /// std::fs::write(&path, "hello")?;
+    /// let tls = TlsOptions { ca_file: Some(path), accept_invalid_certs: false };
+    /// assert!(client.set_tls(&tls).unwrap_err().to_string().contains("no certificates"));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn set_tls(&mut self, tls: &TlsOptions) -> Result<()> {
+        self.client = tls.http_client()?;
+        Ok(())
+    }
+
+    /// What [`SubsonicClient::stream_song`] and friends ask for. The
+    /// default, [`StreamFormat::Raw`], is the original file.
+    pub fn set_stream_format(&mut self, format: StreamFormat) {
+        self.stream_format = format;
+    }
+
+    /// How requests log in. With [`AuthMethod::Token`], the default, the
+    /// client still moves to legacy auth if the server turns tokens down,
+    /// but only over HTTPS, where the password can't be read off the wire.
+    ///
+    /// The password is never part of an error, even once legacy auth puts
+    /// it in every URL:
+    ///
+    /// ```
+    /// use danavi::client::SubsonicClient;
+    /// use danavi::types::AuthMethod;
+    ///
+    /// let mut client = SubsonicClient::new(
+    ///     "http://127.0.0.1:1".to_string(),
+    ///     "alice".to_string(),
+    ///     "secret".to_string(),
+    /// )?;
+    /// client.set_auth_method(AuthMethod::Legacy);
+    /// let runtime = tokio::runtime::Runtime::new()?;
+    /// let error = runtime.block_on(client.ping()).unwrap_err();
+    /// let logged = format!("{:?}", error);
+    /// assert!(!logged.contains("secret") && !logged.contains("736563726574"));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn set_auth_method(&mut self, method: AuthMethod) {
+        self.legacy_auth.store(method == AuthMethod::Legacy, Ordering::Relaxed);
+    }
+
+    // Moves to legacy auth when `e` is the server turning down token auth
+    // over HTTPS. Returns whether it did, so the request is worth another go.
+    fn fall_back_to_legacy(&self, e: &anyhow::Error) -> bool {
+        let refused = e.downcast_ref::<SubsonicError>().is_some_and(|e| e.code() == 41);
+        if !refused || self.legacy_auth.load(Ordering::Relaxed) {
+            return false;
+        }
+        // Anyone in the middle could answer 41 just to be sent the password
+        if !self.base_url.to_ascii_lowercase().starts_with("https://") {
+            log::warn!("Server doesn't support token auth; set \"auth_method\": \"legacy\" to send the password over plain HTTP");
+            return false;
+        }
+        if !self.legacy_auth.swap(true, Ordering::Relaxed) {
+            log::info!("Server doesn't support token auth, falling back to legacy auth");
+            self.fell_back.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    /// Whether the client has moved to legacy auth by itself since this was
+    /// last asked, for telling the user.
+    pub fn take_legacy_fallback(&self) -> bool {
+        self.fell_back.swap(false, Ordering::Relaxed)
+    }
+
+    /// A short name for the server and user, to keep what's cached for one
+    /// apart from another's.
+    ///
+    /// ```
+    /// use danavi::client::SubsonicClient;
+    ///
+    /// let client = |url: &str, user: &str| SubsonicClient::new(url.to_string(), user.to_string(), "pw".to_string());
+    /// let home = client("http://music.lan", "alice")?.server_key();
+    /// assert_eq!(home, client("http://music.lan/", "alice")?.server_key());
+    /// assert_ne!(home, client("http://music.lan", "bob")?.server_key());
+    /// assert_ne!(home, client("http://other.lan", "alice")?.server_key());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn server_key(&self) -> String {
+        let digest = md5::compute(format!("{}\n{}", self.base_url, self.username));
+        format!("{:x}", digest)[..16].to_string()
+    }
+
+    /// Names what [`SubsonicClient::stream_song`] returns by format and
+    /// bitrate cap, e.g. `mp3-128`, so a copy kept of one isn't taken for
+    /// another.
+    pub fn stream_variant(&self) -> String {
+        format!("{}-{}", self.stream_format.as_str(), self.max_bit_rate)
+    }
+
+    /// The options songs are streamed with unless asked otherwise.
+    pub fn stream_options(&self) -> StreamOptions {
+        StreamOptions::format(self.stream_format.as_str())
+    }
+
+    /// Whether songs stream transcoded, and so can be started partway with
+    /// [`SubsonicClient::stream_song_at`], as far as the server has said.
+    pub fn transcodes(&self) -> bool {
+        self.stream_format != StreamFormat::Raw
+            && self.known_capabilities().is_none_or(ServerCapabilities::transcode_offset)
+    }
+
     fn generate_salt(&self) -> String {
         let mut rng = rand::thread_rng();
         (0..8)
@@ -45,75 +899,510 @@ impl SubsonicClient {
         format!("{:x}", md5::compute(input.as_bytes()))
     }
 
-    async fn api_call(&self, endpoint: &str, params: &HashMap<&str, String>) -> Result<Value> {
-        let salt = self.generate_salt();
-        let token = self.generate_token(&salt);
-
-        let mut url = Url::parse(&format!("{}/rest/{}", self.base_url, endpoint))
-            .context("Invalid base URL")?;
-
-        let mut query_params = params.clone();
-        query_params.insert("u", self.username.clone());
-        query_params.insert("t", token);
-        query_params.insert("s", salt);
-        query_params.insert("v", VERSION.to_string());
-        query_params.insert("c", CLIENT_NAME.to_string());
-        query_params.insert("f", "json".to_string());
+    async fn api_call<T: DeserializeOwned>(&self, endpoint: &str, params: &HashMap<&str, String>) -> Result<T> {
+        let pairs: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        self.api_call_pairs(endpoint, &pairs).await
+    }
 
-        for (key, value) in query_params {
-            url.query_pairs_mut().append_pair(key, &value);
+    /// Like `api_call`, for endpoints that take a parameter more than once.
+    async fn api_call_pairs<T: DeserializeOwned>(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<T> {
+        match self.try_api_call(endpoint, params).await {
+            Err(e) if self.fall_back_to_legacy(&e) => self.try_api_call(endpoint, params).await,
+            result => result,
         }
+    }
+
+    async fn try_api_call<T: DeserializeOwned>(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<T> {
+        let mut url = self.authed_url(endpoint, params)?;
+        url.query_pairs_mut().append_pair("f", "json");
 
         let response = self
             .client
             .get(url.as_str())
             .send()
             .await
-            .context("Failed to send request")?;
+            .map_err(TransportError::from)?;
+        let http_status = response.status();
+        let body = response.bytes().await.map_err(TransportError::from)?;
+
+        // Some servers send their errors with an HTTP error status too, so
+        // the body gets a look first
+        match parse_response(endpoint, &body) {
+            Err(e) if !http_status.is_success() && e.downcast_ref() == Some(&TransportError::NotSubsonic) => {
+                Err(TransportError::Http(http_status).into())
+            }
+            result => result,
+        }
+    }
+
+    /// Checks that the server is reachable and the credentials work.
+    pub async fn ping(&self) -> Result<PingResponse> {
+        let params = HashMap::new();
+        self.api_call("ping", &params).await
+    }
 
-        let json: Value = response.json().await.context("Failed to parse response")?;
+    /// What the server can do, asked the first time and kept from then on.
+    /// Only a failed ping is an error; a server that fails to list its
+    /// extensions is taken for plain Subsonic.
+    pub async fn capabilities(&self) -> Result<&ServerCapabilities> {
+        self.capabilities
+            .get_or_try_init(|| async {
+                let ping = self.ping().await?;
+                let extensions = if ping.open_subsonic {
+                    match self.get_open_subsonic_extensions().await {
+                        Ok(response) => Some(response.extensions),
+                        Err(e) => {
+                            log::info!("Couldn't list OpenSubsonic extensions, taking the server for plain Subsonic: {}", e);
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+                Ok(ServerCapabilities::new(&ping, extensions))
+            })
+            .await
+    }
 
-        let subsonic_response = json
-            .get("subsonic-response")
-            .context("Invalid response format")?;
+    /// The capabilities if [`SubsonicClient::capabilities`] has found them
+    /// out yet.
+    pub fn known_capabilities(&self) -> Option<&ServerCapabilities> {
+        self.capabilities.get()
+    }
 
-        let status = subsonic_response
-            .get("status")
-            .and_then(|s| s.as_str())
-            .context("Missing status field")?;
+    /// Lists OpenSubsonic extensions. Plain Subsonic servers return an error.
+    pub async fn get_open_subsonic_extensions(&self) -> Result<OpenSubsonicExtensionsResponse> {
+        let params = HashMap::new();
+        self.api_call("getOpenSubsonicExtensions", &params).await
+    }
 
-        if status == "ok" {
-            Ok(subsonic_response.clone())
-        } else {
-            let error_msg = subsonic_response
-                .get("error")
-                .and_then(|e| e.get("message"))
-                .and_then(|m| m.as_str())
-                .unwrap_or("Unknown error");
-            anyhow::bail!("API error: {}", error_msg);
-        }
+    /// The account we're logged in as, with what it's allowed to do.
+    pub async fn get_user(&self) -> Result<UserResponse> {
+        let mut params = HashMap::new();
+        params.insert("username", self.username.clone());
+        self.api_call("getUser", &params).await
     }
 
+    /// Starts scanning the library for new and changed files. Servers only
+    /// let admins do this.
+    pub async fn start_scan(&self) -> Result<ScanStatusResponse> {
+        self.api_call("startScan", &HashMap::new()).await
+    }
+
+    /// Library scan state, including the song count on most servers.
+    pub async fn get_scan_status(&self) -> Result<ScanStatusResponse> {
+        let params = HashMap::new();
+        self.api_call("getScanStatus", &params).await
+    }
+
+    /// All artists, grouped by index letter.
     pub async fn get_artists(&self) -> Result<ArtistsResponse> {
         let params = HashMap::new();
-        let response = self.api_call("getArtists", &params).await?;
-        serde_json::from_value(response).context("Failed to parse artists response")
+        self.api_call("getArtists", &params).await
     }
 
+    /// An artist and their albums.
     pub async fn get_artist(&self, id: &str) -> Result<ArtistResponse> {
         let mut params = HashMap::new();
         params.insert("id", id.to_string());
-        let response = self.api_call("getArtist", &params).await?;
-        serde_json::from_value(response).context("Failed to parse artist response")
+        self.api_call("getArtist", &params).await
     }
 
+    /// The artist's biography and similar artists, including those not in
+    /// the library.
+    pub async fn get_artist_info2(&self, id: &str) -> Result<ArtistInfo2Response> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        params.insert("includeNotPresent", "true".to_string());
+        self.api_call("getArtistInfo2", &params).await
+    }
+
+    /// The album's notes and links. Many servers answer with an empty info
+    /// object when they have nothing.
+    pub async fn get_album_info2(&self, id: &str) -> Result<AlbumInfo2Response> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        self.api_call("getAlbumInfo2", &params).await
+    }
+
+    /// An album and its songs in track order.
     pub async fn get_album(&self, id: &str) -> Result<AlbumResponse> {
         let mut params = HashMap::new();
         params.insert("id", id.to_string());
-        let response = self.api_call("getAlbum", &params).await?;
-        serde_json::from_value(response).context("Failed to parse album response")
+        self.api_call("getAlbum", &params).await
+    }
+
+    /// A single song by id.
+    pub async fn get_song(&self, id: &str) -> Result<SongResponse> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        self.api_call("getSong", &params).await
+    }
+
+    /// Lyrics for a song, synced where the server has them. Needs the
+    /// OpenSubsonic `songLyrics` extension.
+    pub async fn get_lyrics_by_song_id(&self, id: &str) -> Result<LyricsListResponse> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        self.api_call("getLyricsBySongId", &params).await
+    }
+
+    /// Plain lyrics looked up by artist and title, for servers without
+    /// `songLyrics`.
+    pub async fn get_lyrics(&self, artist: &str, title: &str) -> Result<LyricsResponse> {
+        let mut params = HashMap::new();
+        params.insert("artist", artist.to_string());
+        params.insert("title", title.to_string());
+        self.api_call("getLyrics", &params).await
+    }
+
+    /// Up to `count` random songs, optionally limited to a genre and years.
+    pub async fn get_random_songs(
+        &self,
+        count: u32,
+        genre: Option<&str>,
+        from_year: Option<i32>,
+        to_year: Option<i32>,
+    ) -> Result<RandomSongsResponse> {
+        let mut params = HashMap::new();
+        params.insert("size", count.to_string());
+        if let Some(genre) = genre {
+            params.insert("genre", genre.to_string());
+        }
+        if let Some(from_year) = from_year {
+            params.insert("fromYear", from_year.to_string());
+        }
+        if let Some(to_year) = to_year {
+            params.insert("toYear", to_year.to_string());
+        }
+        self.api_call("getRandomSongs", &params).await
+    }
+
+    /// A page of albums in the order `list_type` names, e.g. "newest" or
+    /// "alphabeticalByName". Servers cap `size`, so long lists take several.
+    pub async fn get_album_list2(&self, list_type: &str, size: u32, offset: usize) -> Result<AlbumList2Response> {
+        let mut params = HashMap::new();
+        params.insert("type", list_type.to_string());
+        params.insert("size", size.to_string());
+        params.insert("offset", offset.to_string());
+        self.api_call("getAlbumList2", &params).await
+    }
+
+    /// The artist's most popular songs, which servers usually look up on
+    /// Last.fm, so may be empty.
+    pub async fn get_top_songs(&self, artist: &str, count: u32) -> Result<TopSongsResponse> {
+        let mut params = HashMap::new();
+        params.insert("artist", artist.to_string());
+        params.insert("count", count.to_string());
+        self.api_call("getTopSongs", &params).await
+    }
+
+    /// Up to `count` songs like the given one, from similar artists.
+    pub async fn get_similar_songs2(&self, id: &str, count: u32) -> Result<SimilarSongs2Response> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        params.insert("count", count.to_string());
+        self.api_call("getSimilarSongs2", &params).await
+    }
+
+    /// The podcast channels the server follows, without their episodes.
+    pub async fn get_podcasts(&self) -> Result<PodcastsResponse> {
+        let mut params = HashMap::new();
+        params.insert("includeEpisodes", "false".to_string());
+        self.api_call("getPodcasts", &params).await
+    }
+
+    /// A podcast channel's episodes, as the server lists them (usually
+    /// newest first).
+    pub async fn get_podcast_episodes(&self, channel_id: &str) -> Result<Vec<PodcastEpisode>> {
+        let mut params = HashMap::new();
+        params.insert("id", channel_id.to_string());
+        params.insert("includeEpisodes", "true".to_string());
+        let response: PodcastsResponse = self.api_call("getPodcasts", &params).await?;
+        let channel = response
+            .podcasts
+            .channel
+            .into_iter()
+            .find(|channel| channel.id == channel_id)
+            .context("Podcast channel not found")?;
+        Ok(channel.episode)
+    }
+
+    /// Up to `count` songs of a genre.
+    pub async fn get_songs_by_genre(&self, genre: &str, count: u32, offset: usize) -> Result<SongsByGenreResponse> {
+        let mut params = HashMap::new();
+        params.insert("genre", genre.to_string());
+        params.insert("count", count.to_string());
+        params.insert("offset", offset.to_string());
+        self.api_call("getSongsByGenre", &params).await
+    }
+
+    /// Everything the user has starred.
+    pub async fn get_starred2(&self) -> Result<Starred2Response> {
+        let params = HashMap::new();
+        self.api_call("getStarred2", &params).await
+    }
+
+    /// The playlists the user can see on the server, without their songs.
+    pub async fn get_playlists(&self) -> Result<PlaylistsResponse> {
+        let params = HashMap::new();
+        self.api_call("getPlaylists", &params).await
+    }
+
+    /// A single playlist with its songs.
+    pub async fn get_playlist(&self, id: &str) -> Result<PlaylistResponse> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        self.api_call("getPlaylist", &params).await
+    }
+
+    /// Creates a playlist holding `song_ids` in order and returns its id.
+    /// Long lists go up in several requests, since every id is its own
+    /// `songId` parameter and servers cap how long a URL may be.
+    pub async fn create_playlist(&self, name: &str, song_ids: &[String]) -> Result<String> {
+        // Servers before API 1.14 don't send the new playlist back, so it's
+        // the one that wasn't there before
+        let before: Option<HashSet<String>> =
+            if self.known_capabilities().is_some_and(ServerCapabilities::returns_created_playlist) {
+                None
+            } else {
+                Some(self.get_playlists().await?.playlists.playlist.into_iter().map(|p| p.id).collect())
+            };
+        let mut chunks = song_ids.chunks(IDS_PER_REQUEST);
+        let params: Vec<(&str, &str)> = std::iter::once(("name", name))
+            .chain(chunks.next().unwrap_or_default().iter().map(|id| ("songId", id.as_str())))
+            .collect();
+        let response: CreatedPlaylist = self.api_call_pairs("createPlaylist", &params).await?;
+        let id = match (response.playlist, before) {
+            (Some(playlist), _) => playlist.id,
+            (None, Some(before)) => self
+                .get_playlists()
+                .await?
+                .playlists
+                .playlist
+                .into_iter()
+                .find(|p| p.name == name && !before.contains(&p.id))
+                .context("Created the playlist but couldn't find it again")?
+                .id,
+            (None, None) => anyhow::bail!("Created the playlist but the server didn't say which it is"),
+        };
+        for chunk in chunks {
+            self.update_playlist(&id, chunk).await?;
+        }
+        Ok(id)
+    }
+
+    /// Appends `add_ids` to the end of a playlist.
+    pub async fn update_playlist(&self, id: &str, add_ids: &[String]) -> Result<()> {
+        for chunk in add_ids.chunks(IDS_PER_REQUEST) {
+            let params: Vec<(&str, &str)> = std::iter::once(("playlistId", id))
+                .chain(chunk.iter().map(|id| ("songIdToAdd", id.as_str())))
+                .collect();
+            self.api_call_pairs::<IgnoredAny>("updatePlaylist", &params).await?;
+        }
+        Ok(())
     }
 
+    /// Removes the songs at `indices` from a playlist, counting from 0 in
+    /// the order the server keeps them.
+    pub async fn remove_from_playlist(&self, id: &str, indices: &[usize]) -> Result<()> {
+        let indices: Vec<String> = indices.iter().map(usize::to_string).collect();
+        for chunk in indices.chunks(IDS_PER_REQUEST) {
+            let params: Vec<(&str, &str)> = std::iter::once(("playlistId", id))
+                .chain(chunk.iter().map(|i| ("songIndexToRemove", i.as_str())))
+                .collect();
+            self.api_call_pairs::<IgnoredAny>("updatePlaylist", &params).await?;
+        }
+        Ok(())
+    }
+
+    /// Replaces a playlist's songs with `song_ids`. The API can't move a
+    /// song, so reordering writes the whole list again.
+    pub async fn set_playlist_songs(&self, id: &str, song_ids: &[String]) -> Result<()> {
+        let mut chunks = song_ids.chunks(IDS_PER_REQUEST);
+        // Given a playlist id, createPlaylist overwrites that playlist
+        let params: Vec<(&str, &str)> = std::iter::once(("playlistId", id))
+            .chain(chunks.next().unwrap_or_default().iter().map(|id| ("songId", id.as_str())))
+            .collect();
+        self.api_call_pairs::<IgnoredAny>("createPlaylist", &params).await?;
+        for chunk in chunks {
+            self.update_playlist(id, chunk).await?;
+        }
+        Ok(())
+    }
+
+    /// Renames one of the user's playlists.
+    pub async fn rename_playlist(&self, id: &str, name: &str) -> Result<()> {
+        self.api_call_pairs::<IgnoredAny>("updatePlaylist", &[("playlistId", id), ("name", name)])
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes one of the user's playlists.
+    pub async fn delete_playlist(&self, id: &str) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        self.api_call::<IgnoredAny>("deletePlaylist", &params).await?;
+        Ok(())
+    }
+
+    /// The top-level folders of every music folder.
+    pub async fn get_indexes(&self) -> Result<IndexesResponse> {
+        self.api_call("getIndexes", &HashMap::new()).await
+    }
+
+    /// A folder's subfolders and files, by the id of the folder.
+    pub async fn get_music_directory(&self, id: &str) -> Result<MusicDirectoryResponse> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        self.api_call("getMusicDirectory", &params).await
+    }
+
+    /// Saves a position in a song, replacing any earlier bookmark for it.
+    pub async fn create_bookmark(&self, id: &str, position: Duration) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        params.insert("position", position.as_millis().to_string());
+        self.api_call::<IgnoredAny>("createBookmark", &params).await?;
+        Ok(())
+    }
+
+    /// Makes a public link to songs, an album or a playlist by id. It works
+    /// for `expires` from now, or until deleted without it or when that's
+    /// further off than a clock can tell.
+    pub async fn create_share(&self, id: &str, description: Option<&str>, expires: Option<Duration>) -> Result<Share> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        if let Some(description) = description {
+            params.insert("description", description.to_string());
+        }
+        if let Some(at) = expires.and_then(|expires| SystemTime::now().checked_add(expires)) {
+            let at = at.duration_since(SystemTime::UNIX_EPOCH)?;
+            params.insert("expires", at.as_millis().to_string());
+        }
+        let response: SharesResponse = self.api_call("createShare", &params).await?;
+        response.shares.share.into_iter().next().context("The server returned no share")
+    }
+
+    /// The user's bookmarks, newest first on most servers.
+    pub async fn get_bookmarks(&self) -> Result<BookmarksResponse> {
+        self.api_call("getBookmarks", &HashMap::new()).await
+    }
+
+    /// Removes the bookmark on song `id`.
+    pub async fn delete_bookmark(&self, id: &str) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        self.api_call::<IgnoredAny>("deleteBookmark", &params).await?;
+        Ok(())
+    }
+
+    /// Saves the queue shared with the user's other clients. Only the first
+    /// 200 songs go up.
+    pub async fn save_play_queue(&self, ids: &[String], current: Option<&str>, position: Duration) -> Result<()> {
+        let position = position.as_millis().to_string();
+        let mut params: Vec<(&str, &str)> = ids.iter().take(PLAY_QUEUE_IDS).map(|id| ("id", id.as_str())).collect();
+        if let Some(current) = current {
+            params.push(("current", current));
+            params.push(("position", &position));
+        }
+        self.api_call_pairs::<IgnoredAny>("savePlayQueue", &params).await?;
+        Ok(())
+    }
+
+    /// Drives the server's own audio output, for a server hooked up to
+    /// speakers. Needs the jukebox role on the account. Only the first 200
+    /// songs of a `Set` or `Add` go up.
+    pub async fn jukebox_control(&self, action: JukeboxAction<'_>) -> Result<JukeboxStatus> {
+        let (name, ids) = match action {
+            JukeboxAction::Status => ("status", &[][..]),
+            JukeboxAction::Set(ids) => ("set", ids),
+            JukeboxAction::Add(ids) => ("add", ids),
+            JukeboxAction::Start => ("start", &[][..]),
+            JukeboxAction::Stop => ("stop", &[][..]),
+            JukeboxAction::Skip { .. } => ("skip", &[][..]),
+            JukeboxAction::SetGain(_) => ("setGain", &[][..]),
+        };
+        let extra = match action {
+            JukeboxAction::Skip { index, offset } => vec![("index", index.to_string()), ("offset", offset.to_string())],
+            JukeboxAction::SetGain(gain) => vec![("gain", format!("{:.2}", gain.clamp(0.0, 1.0)))],
+            _ => Vec::new(),
+        };
+        let params: Vec<(&str, &str)> = std::iter::once(("action", name))
+            .chain(ids.iter().take(PLAY_QUEUE_IDS).map(|id| ("id", id.as_str())))
+            .chain(extra.iter().map(|(k, v)| (*k, v.as_str())))
+            .collect();
+        let response: JukeboxStatusResponse = self.api_call_pairs("jukeboxControl", &params).await?;
+        Ok(response.jukebox_status)
+    }
+
+    /// The play queue last saved by any of the user's clients.
+    pub async fn get_play_queue(&self) -> Result<PlayQueueResponse> {
+        self.api_call("getPlayQueue", &HashMap::new()).await
+    }
+
+    /// Rates a song or album from 1 to 5, or clears its rating with 0. Not
+    /// every server takes ratings for albums.
+    pub async fn set_rating(&self, id: &str, rating: u8) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        params.insert("rating", rating.to_string());
+        self.api_call::<IgnoredAny>("setRating", &params).await?;
+        Ok(())
+    }
+
+    /// Stars songs and albums in a single request.
+    pub async fn star(&self, ids: &[String], album_ids: &[String]) -> Result<()> {
+        self.set_starred("star", ids, album_ids, &[]).await
+    }
+
+    /// Removes stars from songs and albums in a single request.
+    pub async fn unstar(&self, ids: &[String], album_ids: &[String]) -> Result<()> {
+        self.set_starred("unstar", ids, album_ids, &[]).await
+    }
+
+    /// Stars an artist.
+    pub async fn star_artist(&self, id: &str) -> Result<()> {
+        self.set_starred("star", &[], &[], &[id.to_string()]).await
+    }
+
+    /// Removes the star from an artist.
+    pub async fn unstar_artist(&self, id: &str) -> Result<()> {
+        self.set_starred("unstar", &[], &[], &[id.to_string()]).await
+    }
+
+    /// Registers a play of a song, or with `submission` false only tells the
+    /// server it's now playing. `time` is when it was played, in
+    /// milliseconds since the epoch, so late submissions keep their date.
+    pub async fn scrobble(&self, id: &str, submission: bool, time: u64) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("id", id.to_string());
+        params.insert("time", time.to_string());
+        params.insert("submission", submission.to_string());
+        self.api_call::<IgnoredAny>("scrobble", &params).await?;
+        Ok(())
+    }
+
+    async fn set_starred(
+        &self,
+        endpoint: &str,
+        ids: &[String],
+        album_ids: &[String],
+        artist_ids: &[String],
+    ) -> Result<()> {
+        let params: Vec<(&str, &str)> = ids
+            .iter()
+            .map(|id| ("id", id.as_str()))
+            .chain(album_ids.iter().map(|id| ("albumId", id.as_str())))
+            .chain(artist_ids.iter().map(|id| ("artistId", id.as_str())))
+            .collect();
+        self.api_call_pairs::<IgnoredAny>(endpoint, &params).await?;
+        Ok(())
+    }
+
+    /// Searches artists, albums and songs, with a separate limit for each.
+    /// Servers without `search3` get `search2` instead, from the first time
+    /// one turns it down on.
     pub async fn search3(
         &self,
         query: &str,
@@ -126,47 +1415,378 @@ impl SubsonicClient {
         params.insert("artistCount", artist_count.to_string());
         params.insert("albumCount", album_count.to_string());
         params.insert("songCount", song_count.to_string());
-        let response = self.api_call("search3", &params).await?;
-        serde_json::from_value(response).context("Failed to parse search response")
+        self.search(&params).await
     }
 
-    pub async fn stream_song(&self, id: &str) -> Result<Vec<u8>> {
-        let salt = self.generate_salt();
-        let token = self.generate_token(&salt);
+    /// The albums and songs of a search from the given offsets on, for
+    /// results past the first page. Artists aren't asked for.
+    pub async fn search3_page(
+        &self,
+        query: &str,
+        album_count: u32,
+        album_offset: usize,
+        song_count: u32,
+        song_offset: usize,
+    ) -> Result<SearchResponse> {
+        let mut params = HashMap::new();
+        params.insert("query", query.to_string());
+        params.insert("artistCount", "0".to_string());
+        params.insert("albumCount", album_count.to_string());
+        params.insert("albumOffset", album_offset.to_string());
+        params.insert("songCount", song_count.to_string());
+        params.insert("songOffset", song_offset.to_string());
+        self.search(&params).await
+    }
+
+    // `search3`, or `search2` on servers that don't have it. Both take the
+    // same parameters; [`SearchResponse::into_result`] evens out the answers.
+    async fn search(&self, params: &HashMap<&str, String>) -> Result<SearchResponse> {
+        let search3 = self.known_capabilities().is_none_or(ServerCapabilities::search3);
+        if search3 && !self.search2_only.load(Ordering::Relaxed) {
+            match self.api_call("search3", params).await {
+                Err(e) if search3_missing(&e) => {
+                    log::info!("Server doesn't support search3, falling back to search2");
+                    self.search2_only.store(true, Ordering::Relaxed);
+                }
+                result => return result,
+            }
+        }
+        self.api_call("search2", params).await
+    }
+
+    fn endpoint_url(&self, endpoint: &str) -> Result<Url> {
+        Url::parse(&format!("{}/rest/{}", self.base_url, endpoint)).context("Invalid base URL")
+    }
 
-        let mut url = Url::parse(&format!("{}/rest/stream", self.base_url))
-            .context("Invalid base URL")?;
+    /// A URL for a binary endpoint carrying its own credentials, so it works
+    /// without any other state.
+    fn authed_url(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<Url> {
+        let mut url = self.endpoint_url(endpoint)?;
+
+        {
+            let mut query = url.query_pairs_mut();
+            for (key, value) in params {
+                query.append_pair(key, value);
+            }
+            query.append_pair("u", &self.username);
+            if self.legacy_auth.load(Ordering::Relaxed) {
+                let hex: String = self.password.bytes().map(|b| format!("{:02x}", b)).collect();
+                query.append_pair("p", &format!("enc:{}", hex));
+            } else {
+                let salt = self.generate_salt();
+                query
+                    .append_pair("t", &self.generate_token(&salt))
+                    .append_pair("s", &salt);
+            }
+            query
+                .append_pair("v", VERSION)
+                .append_pair("c", CLIENT_NAME);
+        }
+
+        Ok(url)
+    }
+
+    /// A self-authenticating URL that streams the song as `options` say.
+    /// Anyone holding it can play the song until the server expires the
+    /// token, so treat it like a password.
+    ///
+    /// ```
+    /// use danavi::client::{StreamOptions, SubsonicClient};
+    /// use danavi::types::{AuthMethod, StreamFormat};
+    ///
+    /// let mut client = SubsonicClient::new(
+    ///     "http://localhost:4533/".to_string(),
+    ///     "alice".to_string(),
+    ///     "secret".to_string(),
+    /// )?;
+    /// let url = client.get_stream_url("tr 1", &StreamOptions::default())?;
+    /// assert!(url.starts_with("http://localhost:4533/rest/stream?id=tr+1&u=alice&t="));
+    ///
+    /// let options = StreamOptions { format: Some("mp3".to_string()), max_bit_rate: 320, time_offset: 0 };
+    /// assert!(client.get_stream_url("tr-1", &options)?.contains("?id=tr-1&format=mp3&maxBitRate=320&"));
+    /// let later = StreamOptions { time_offset: 95, ..StreamOptions::format("mp3") };
+    /// assert!(client.get_stream_url("tr-1", &later)?.contains("?id=tr-1&format=mp3&timeOffset=95&"));
+    /// // The lower of the two caps wins
+    /// client.set_max_bit_rate(128);
+    /// assert!(client.get_stream_url("tr-1", &options)?.contains("&maxBitRate=128&"));
+    ///
+    /// // The configured format, raw unless set
+    /// assert!(client.get_stream_url("tr-1", &client.stream_options())?.contains("?id=tr-1&format=raw&"));
+    /// client.set_stream_format(StreamFormat::Mp3);
+    /// assert!(client.get_stream_url("tr-1", &client.stream_options())?.contains("?id=tr-1&format=mp3&"));
+    ///
+    /// // Legacy auth sends the hex encoded password in place of a token
+    /// client.set_auth_method(AuthMethod::Legacy);
+    /// let url = client.get_stream_url("tr-1", &StreamOptions::default())?;
+    /// assert!(url.contains("&u=alice&p=enc%3A736563726574&v="));
+    /// assert!(!url.contains("secret") && !url.contains("&t=") && !url.contains("&s="));
+    ///
+    /// // Credentials and ids come back out of the query as they went in
+    /// let (user, password, id) = ("al ice+@&#", "pä ss&+#=", "Artist/Album & Co #1 ü+");
+    /// let query = |client: &SubsonicClient| -> anyhow::Result<_> {
+    ///     let url = url::Url::parse(&client.get_stream_url(id, &StreamOptions::default())?)?;
+    ///     assert!(url.fragment().is_none());
+    ///     Ok(url.query_pairs().into_owned().collect::<std::collections::HashMap<_, _>>())
+    /// };
+    /// let mut client = SubsonicClient::new("http://localhost:4533".to_string(), user.to_string(), password.to_string())?;
+    /// let pairs = query(&client)?;
+    /// assert_eq!((pairs["id"].as_str(), pairs["u"].as_str()), (id, user));
+    /// let token = md5::compute(format!("{}{}", password, pairs["s"]));
+    /// assert_eq!(pairs["t"], format!("{:x}", token));
+    ///
+    /// client.set_auth_method(AuthMethod::Legacy);
+    /// let pairs = query(&client)?;
+    /// let hex = pairs["p"].strip_prefix("enc:").unwrap();
+    /// let decoded: Vec<u8> = (0..hex.len())
+    ///     .step_by(2)
+    ///     .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+    ///     .collect::<Result<_, _>>()?;
+    /// assert_eq!(decoded, password.as_bytes());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn get_stream_url(&self, id: &str, options: &StreamOptions) -> Result<String> {
+        Ok(self.stream_url(id, options)?.into())
+    }
+
+    /// The stream URL for a song without credentials: enough to tell which
+    /// song it is, e.g. in MPRIS metadata, but not to play it.
+    pub fn song_url(&self, id: &str) -> Result<String> {
+        let mut url = self.endpoint_url("stream")?;
+        url.query_pairs_mut().append_pair("id", id);
+        Ok(url.into())
+    }
+
+    // The query for a stream request, with the lower of the two bitrate caps
+    fn stream_params(&self, id: &str, options: &StreamOptions) -> Vec<(&'static str, String)> {
+        let max_bit_rate = [options.max_bit_rate, self.max_bit_rate]
+            .into_iter()
+            .filter(|&kbps| kbps > 0)
+            .min();
+        std::iter::once(("id", id.to_string()))
+            .chain(options.format.clone().map(|format| ("format", format)))
+            .chain(max_bit_rate.map(|kbps| ("maxBitRate", kbps.to_string())))
+            .chain((options.time_offset > 0).then(|| ("timeOffset", options.time_offset.to_string())))
+            .collect()
+    }
 
-        url.query_pairs_mut()
-            .append_pair("id", id)
-            .append_pair("u", &self.username)
-            .append_pair("t", &token)
-            .append_pair("s", &salt)
-            .append_pair("v", VERSION)
-            .append_pair("c", CLIENT_NAME)
-            .append_pair("format", "mp3");
+    fn stream_url(&self, id: &str, options: &StreamOptions) -> Result<Url> {
+        let params = self.stream_params(id, options);
+        let params: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        self.authed_url("stream", &params)
+    }
 
+    async fn try_fetch_binary(
+        &self,
+        endpoint: &str,
+        params: &[(&str, &str)],
+    ) -> Result<(Vec<u8>, reqwest::header::HeaderMap), FetchError> {
+        let url = self.authed_url(endpoint, params)?;
         let response = self
             .client
             .get(url.as_str())
             .send()
             .await
-            .context("Failed to send stream request")?;
+            .map_err(|e| anyhow::Error::from(TransportError::from(e)))?;
 
-        if !response.status().is_success() {
-            anyhow::bail!("Server returned error: {}", response.status());
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return Err(FetchError::Auth(TransportError::Http(status).into()));
         }
+        if !status.is_success() {
+            return Err(anyhow::Error::from(TransportError::Http(status)).into());
+        }
+
+        // Errors on binary endpoints come back as a normal API response
+        // instead of the data
+        let is_api_error = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("json") || v.contains("xml"));
+        let headers = response.headers().clone();
 
         let bytes = response
             .bytes()
             .await
-            .context("Failed to read audio data")?
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to read response data")?
             .to_vec();
 
+        if is_api_error {
+            let error = serde_json::from_slice::<Value>(&bytes)
+                .ok()
+                .and_then(|json| json.get("subsonic-response")?.get("error").cloned())
+                .unwrap_or(Value::Null);
+            let error = SubsonicError::parse(&error);
+            return Err(if error.is_auth() {
+                FetchError::Auth(error.into())
+            } else {
+                FetchError::Other(error.into())
+            });
+        }
+
         if bytes.is_empty() {
-            anyhow::bail!("Server returned empty audio data");
+            return Err(anyhow::anyhow!("Server returned empty data").into());
+        }
+        Ok((bytes, headers))
+    }
+
+    async fn fetch_binary(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<Vec<u8>> {
+        Ok(self.fetch_binary_with_headers(endpoint, params).await?.0)
+    }
+
+    /// Fetches a binary endpoint. Auth failures get one retry with a fresh
+    /// token, since they are usually transient (clock skew, a server
+    /// restart) rather than a wrong password.
+    async fn fetch_binary_with_headers(
+        &self,
+        endpoint: &str,
+        params: &[(&str, &str)],
+    ) -> Result<(Vec<u8>, reqwest::header::HeaderMap)> {
+        match self.try_fetch_binary(endpoint, params).await {
+            Ok(fetched) => Ok(fetched),
+            Err(FetchError::Other(e)) => Err(e),
+            Err(FetchError::Auth(e)) => {
+                if !self.fall_back_to_legacy(&e) {
+                    log::warn!("{} authentication failed ({}), retrying with a new token", endpoint, e);
+                }
+                match self.try_fetch_binary(endpoint, params).await {
+                    Ok(fetched) => Ok(fetched),
+                    Err(FetchError::Other(e) | FetchError::Auth(e)) => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Downloads the image for a `coverArt` id, scaled by the server to
+    /// `size` pixels on its longest side if given.
+    pub async fn get_cover_art(&self, id: &str, size: Option<u32>) -> Result<Vec<u8>> {
+        match size {
+            Some(size) => {
+                let size = size.to_string();
+                self.fetch_binary("getCoverArt", &[("id", id), ("size", &size)]).await
+            }
+            None => self.fetch_binary("getCoverArt", &[("id", id)]).await,
         }
+    }
 
-        Ok(bytes)
+    /// Downloads a whole song in the client's stream format, ready for
+    /// [`AudioPlayer::play_bytes`](crate::audio::AudioPlayer::play_bytes).
+    pub async fn stream_song(&self, id: &str) -> Result<Vec<u8>> {
+        let params = self.stream_params(id, &self.stream_options());
+        let params: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let bytes = self.fetch_binary("stream", &params).await?;
+        match non_audio_error(None, &bytes) {
+            Some(error) => Err(error),
+            None => Ok(bytes),
+        }
+    }
+
+    /// Downloads a song's original file, untouched by transcoding, with
+    /// what the server says about its name and type.
+    pub async fn download(&self, id: &str) -> Result<Download> {
+        let (bytes, headers) = self.fetch_binary_with_headers("download", &[("id", id)]).await?;
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        Ok(Download {
+            bytes,
+            file_name: header(reqwest::header::CONTENT_DISPOSITION).and_then(disposition_file_name),
+            content_type: header(reqwest::header::CONTENT_TYPE).map(str::to_string),
+        })
+    }
+
+    /// Starts downloading a song as [`SubsonicClient::stream_song`] would,
+    /// from byte `offset` on. Also returns whether the server honoured the
+    /// range; when it didn't, the body starts from the beginning.
+    pub async fn stream_song_from(
+        &self,
+        id: &str,
+        offset: u64,
+    ) -> Result<(reqwest::Response, bool)> {
+        self.open_stream(id, &self.stream_options(), offset).await
+    }
+
+    /// Starts downloading a song as [`SubsonicClient::stream_song`] would,
+    /// but `start` into it, rounded down to the second, for seeking past
+    /// what has downloaded. Only transcoded streams are cut; see
+    /// [`SubsonicClient::transcodes`].
+    pub async fn stream_song_at(&self, id: &str, start: Duration) -> Result<reqwest::Response> {
+        let options = StreamOptions {
+            time_offset: start.as_secs(),
+            ..self.stream_options()
+        };
+        Ok(self.open_stream(id, &options, 0).await?.0)
+    }
+
+    /// Starts downloading a song transcoded as `options` say, for when the
+    /// usual stream won't decode.
+    pub async fn stream_transcoded(&self, id: &str, options: &StreamOptions) -> Result<reqwest::Response> {
+        Ok(self.open_stream(id, options, 0).await?.0)
+    }
+
+    /// Opens a stream, with the same one retry on an auth failure as
+    /// [`fetch_binary_with_headers`](Self::fetch_binary_with_headers).
+    async fn open_stream(
+        &self,
+        id: &str,
+        options: &StreamOptions,
+        offset: u64,
+    ) -> Result<(reqwest::Response, bool)> {
+        match self.try_open_stream(id, options, offset).await {
+            Ok(opened) => Ok(opened),
+            Err(FetchError::Other(e)) => Err(e),
+            Err(FetchError::Auth(e)) => {
+                if !self.fall_back_to_legacy(&e) {
+                    log::warn!("stream authentication failed ({}), retrying with a new token", e);
+                }
+                match self.try_open_stream(id, options, offset).await {
+                    Ok(opened) => Ok(opened),
+                    Err(FetchError::Other(e) | FetchError::Auth(e)) => Err(e),
+                }
+            }
+        }
+    }
+
+    async fn try_open_stream(
+        &self,
+        id: &str,
+        options: &StreamOptions,
+        offset: u64,
+    ) -> Result<(reqwest::Response, bool), FetchError> {
+        // Built afresh each time for a new token
+        let url = self.stream_url(id, options)?;
+        let mut request = self.client.get(url.as_str());
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow::Error::from(TransportError::from(e)))?;
+
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return Err(FetchError::Auth(TransportError::Http(status).into()));
+        }
+        if !status.is_success() {
+            return Err(anyhow::Error::from(TransportError::Http(status)).into());
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        // Answers typed as audio are only found out once they fail to decode
+        if content_type.as_deref().is_some_and(is_text_type) {
+            let bytes = response
+                .bytes()
+                .await
+                .map_err(reqwest::Error::without_url)
+                .context("Failed to read response data")?;
+            let error = non_audio_error(content_type.as_deref(), &bytes)
+                .unwrap_or_else(|| anyhow::anyhow!("Server returned an error instead of the song"));
+            let auth = error.downcast_ref::<SubsonicError>().is_some_and(SubsonicError::is_auth);
+            return Err(if auth { FetchError::Auth(error) } else { FetchError::Other(error) });
+        }
+        Ok((response, status == StatusCode::PARTIAL_CONTENT))
     }
 }
@@ -1,29 +1,138 @@
-use crate::types::Config;
+//! The `~/.config/danavi` files.
+
+use crate::types::{
+    default_album_progress_days, default_bookmark_after_minutes, default_metadata_cache_minutes,
+    default_normalize_target_lufs, default_pause_fade_ms, default_share_expiry_days,
+    default_scrobble_horizon_days, default_scroll_acceleration, default_status_format, default_stream_cache_mb,
+    AlbumSelectMode, Config,
+};
 use anyhow::{Context, Result};
 use serde::Deserialize;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
 const CONFIG_DIR_NAME: &str = "danavi";
 const CONFIG_FILE_NAME: &str = "config.json";
+const SMART_PLAYLISTS_FILE_NAME: &str = "smart_playlists.json";
 
-pub fn get_config_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir()
+// Stands in for the cache and state directories once set
+static SCRATCH_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keeps everything danavi would cache or remember under `dir` instead,
+/// for a session that shouldn't leave traces, like the demo. Only the first
+/// call counts.
+pub fn use_scratch_dir(dir: PathBuf) {
+    let _ = SCRATCH_DIR.set(dir);
+}
+
+fn get_config_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
         .context("Could not find config directory")?
-        .join(CONFIG_DIR_NAME);
+        .join(CONFIG_DIR_NAME))
+}
 
-    Ok(config_dir.join(CONFIG_FILE_NAME))
+/// Path of `config.json` in the platform config directory.
+pub fn get_config_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join(CONFIG_FILE_NAME))
+}
+
+/// danavi's directory under the platform cache directory.
+pub fn get_cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = SCRATCH_DIR.get() {
+        return Ok(dir.join("cache"));
+    }
+    Ok(dirs::cache_dir()
+        .context("Could not find cache directory")?
+        .join(CONFIG_DIR_NAME))
+}
+
+/// danavi's directory for state that should survive restarts, falling back
+/// to the local data directory where there is no state directory.
+pub fn get_state_dir() -> Result<PathBuf> {
+    if let Some(dir) = SCRATCH_DIR.get() {
+        return Ok(dir.join("state"));
+    }
+    Ok(dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .context("Could not find state directory")?
+        .join(CONFIG_DIR_NAME))
+}
+
+/// Path of the smart playlist definitions next to the config file.
+pub fn get_smart_playlists_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join(SMART_PLAYLISTS_FILE_NAME))
+}
+
+/// Expands a leading `~/` to the home directory.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/")
+        && let Some(home) = dirs::home_dir()
+    {
+        return home.join(rest);
+    }
+    PathBuf::from(path)
 }
 
+/// The config written on first run.
+///
+/// ```
+/// use danavi::config::get_default_config;
+/// use danavi::types::{Config, StreamFormat};
+///
+/// let written = serde_json::to_string_pretty(&get_default_config())?;
+/// assert!(written.contains(r#""//stream_format": "raw: the original files"#));
+/// let read: Config = serde_json::from_str(&written)?;
+/// assert_eq!(read.stream_format, StreamFormat::Raw);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
 pub fn get_default_config() -> Config {
     Config {
         base_url: "http://localhost:4533".to_string(),
         username: String::new(),
         password: String::new(),
         show_easter_eggs: true,
+        on_album_select: AlbumSelectMode::default(),
+        mpd_listen: None,
+        mpris_instance: None,
+        global_hotkeys: Default::default(),
+        pause_on_output_change: false,
+        status_file: None,
+        status_format: default_status_format(),
+        album_progress_days: default_album_progress_days(),
+        queue_dedupe: false,
+        search_as_you_type: false,
+        key_hints: true,
+        download_dir: None,
+        scrobble: false,
+        scrobble_horizon_days: default_scrobble_horizon_days(),
+        report_now_playing: None,
+        bookmark_after_minutes: default_bookmark_after_minutes(),
+        share_expiry_days: default_share_expiry_days(),
+        stream_cache: true,
+        stream_cache_mb: default_stream_cache_mb(),
+        metadata_cache_minutes: default_metadata_cache_minutes(),
+        sync_play_queue: true,
+        continue_artist: false,
+        theme: Default::default(),
+        replaygain: Default::default(),
+        replaygain_preamp: 0.0,
+        normalize_loudness: false,
+        normalize_target_lufs: default_normalize_target_lufs(),
+        pause_fade_ms: default_pause_fade_ms(),
+        long_jump: Default::default(),
+        max_bitrate: 0,
+        stream_format_help: (),
+        stream_format: Default::default(),
+        auth_method: Default::default(),
+        tls_ca_file: None,
+        tls_accept_invalid_certs: false,
+        scroll_acceleration: default_scroll_acceleration(),
     }
 }
 
+/// Loads the config file, creating it with defaults if it doesn't exist and
+/// migrating the old camelCase format in place.
 pub fn load_config() -> Result<Config> {
     let config_path = get_config_path()?;
 
@@ -55,6 +164,7 @@ pub fn load_config() -> Result<Config> {
                     username: old.username.unwrap_or_default(),
                     password: old.password.unwrap_or_default(),
                     show_easter_eggs: old.show_easter_eggs.unwrap_or(true),
+                    ..get_default_config()
                 };
 
                 // Save in new format
@@ -69,6 +179,7 @@ pub fn load_config() -> Result<Config> {
     }
 }
 
+/// Writes the config file, creating its directory if needed.
 pub fn save_config(config: &Config) -> Result<()> {
     let config_path = get_config_path()?;
     let config_dir = config_path.parent().context("Invalid config path")?;
@@ -82,6 +193,7 @@ pub fn save_config(config: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Whether the server details are still the defaults.
 pub fn config_needs_edit(config: &Config) -> bool {
     let default = get_default_config();
     config.base_url == default.base_url
@@ -0,0 +1,988 @@
+//! A made-up library served by an in-process Subsonic server, for trying
+//! danavi without a real one (`danavi --demo`) and for testing against.
+//!
+//! The server speaks just enough of the API for browsing (by tags and by
+//! folder), search, stars, ratings, playlists, podcasts, bookmarks, shares, the
+//! play queue, scans, lyrics, cover art and playback. Every track is a few seconds of
+//! generated tone, and every cover a square of one color.
+//!
+//! ```
+//! use danavi::demo::DemoServer;
+//!
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! let demo = DemoServer::start().await?;
+//! let client = demo.client()?;
+//!
+//! let capabilities = client.capabilities().await?;
+//! assert!(capabilities.open_subsonic && capabilities.synced_lyrics() && !capabilities.api_key_auth());
+//! assert_eq!(capabilities.server.as_deref(), Some("danavi-demo"));
+//!
+//! assert!(client.start_scan().await?.scan_status.scanning);
+//! assert!(!client.get_scan_status().await?.scan_status.scanning);
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! # Browsing
+//!
+//! Artists, their albums and songs, and what the server knows about them.
+//!
+//! ```
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! # let demo = danavi::demo::DemoServer::start().await?;
+//! # let client = demo.client()?;
+//! let artists = client.get_artists().await?;
+//! let articles = danavi::sorting::IgnoredArticles::parse(&artists.artists.ignored_articles);
+//! assert_eq!(articles.sort_key("The Sine Waves"), "sine waves");
+//! let first = &artists.artists.index[0].artist[0];
+//! let albums = client.get_artist(&first.id).await?.artist.album;
+//! let songs = client.get_album(&albums[0].id).await?.album.song;
+//! assert!(!songs.is_empty());
+//! assert_eq!((songs[0].track, songs[0].disc_number), (Some(1), Some(1)));
+//!
+//! let similar = client.get_similar_songs2(&songs[0].id, 10).await?.similar_songs2.song;
+//! assert!(!similar.is_empty() && similar.iter().all(|s| s.id != songs[0].id));
+//! let info = client.get_artist_info2(&first.id).await?.artist_info2;
+//! assert!(info.biography_text().unwrap().starts_with(&first.name));
+//! assert_eq!(info.similar_artist.iter().filter(|a| a.library_id().is_none()).count(), 1);
+//! let notes = client.get_album_info2(&albums[0].id).await?.album_info;
+//! assert!(notes.notes_text().unwrap().contains(&albums[0].name));
+//! assert!(notes.large_image_url.is_some());
+//! assert!(client.get_album_info2(&albums[1].id).await?.album_info.notes_text().is_none());
+//! let top = client.get_top_songs(&first.name, 10).await?.top_songs.song;
+//! assert!(top.iter().all(|s| s.album.is_some()));
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! By folder, as the files are laid out:
+//!
+//! ```
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! # let demo = danavi::demo::DemoServer::start().await?;
+//! # let client = demo.client()?;
+//! let folders = client.get_indexes().await?.indexes.index;
+//! let dirs = client.get_music_directory(&folders[0].artist[0].id).await?.directory.child;
+//! assert!(dirs.iter().all(|d| d.is_dir));
+//! let files = client.get_music_directory(&dirs[0].song.id).await?.directory.child;
+//! assert!(!files.is_empty() && files.iter().all(|f| !f.is_dir));
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! # Album lists and search
+//!
+//! ```
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! # let demo = danavi::demo::DemoServer::start().await?;
+//! # let client = demo.client()?;
+//! let newest = client.get_album_list2("newest", 4, 4).await?.album_list2.album;
+//! assert_eq!(newest.len(), 2);
+//! assert!(newest[0].artist.is_some());
+//! let picked = client.get_album_list2("random", 1, 0).await?.album_list2.album;
+//! assert_eq!(picked.len(), 1);
+//! assert!(picked[0].artist_id.is_some());
+//!
+//! let found = client.search3("tide", 0, 0, 10).await?;
+//! assert!(found.search_result3.unwrap().song.unwrap().iter().any(|s| s.title == "Low Tide"));
+//! // An empty query pages through every song
+//! let page = client.search3_page("", 0, 0, 3, 0).await?.search_result3.unwrap().song.unwrap();
+//! let next = client.search3_page("", 0, 0, 3, 3).await?.search_result3.unwrap().song.unwrap();
+//! assert_eq!(next.len(), 3);
+//! assert!(next.iter().all(|s| page.iter().all(|p| p.id != s.id)));
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! # Stars, ratings and playlists
+//!
+//! ```
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! # let demo = danavi::demo::DemoServer::start().await?;
+//! # let client = demo.client()?;
+//! # let artists = client.get_artists().await?;
+//! # let first = &artists.artists.index[0].artist[0];
+//! # let albums = client.get_artist(&first.id).await?.artist.album;
+//! # let songs = client.get_album(&albums[0].id).await?.album.song;
+//! client.star(&[songs[0].id.clone()], &[]).await?;
+//! assert_eq!(client.get_starred2().await?.starred2.song.len(), 1);
+//!
+//! client.set_rating(&albums[0].id, 4).await?;
+//! assert_eq!(client.get_album(&albums[0].id).await?.album.user_rating, Some(4));
+//! // Only songs and albums take a rating
+//! assert!(client.set_rating(&first.id, 4).await.is_err());
+//!
+//! let playlists = client.get_playlists().await?.playlists.playlist;
+//! let openers = client.get_playlist(&playlists[0].id).await?.playlist;
+//! assert_eq!(openers.entry.len() as u64, openers.song_count.unwrap());
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! # Cover art and podcasts
+//!
+//! ```
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! # let demo = danavi::demo::DemoServer::start().await?;
+//! # let client = demo.client()?;
+//! # let artists = client.get_artists().await?;
+//! # let albums = client.get_artist(&artists.artists.index[0].artist[0].id).await?.artist.album;
+//! # let songs = client.get_album(&albums[0].id).await?.album.song;
+//! let covers = danavi::cache::CoverArtCache::new(std::env::temp_dir().join("danavi-demo-covers"));
+//! let cover_art = songs[0].cover_art.as_deref().unwrap();
+//! let path = covers.fetch(&client, cover_art, Some(16)).await.unwrap();
+//! assert!(danavi::cache::file_url(&path).unwrap().starts_with("file://"));
+//! assert!(covers.get(cover_art, Some(16)).unwrap().starts_with(b"BM"));
+//! assert!(covers.fetch(&client, "missing", None).await.is_none());
+//!
+//! let channels = client.get_podcasts().await?.podcasts.channel;
+//! let episodes = client.get_podcast_episodes(&channels[0].id).await?;
+//! assert_eq!(episodes.iter().filter(|e| e.playable_id().is_some()).count(), 2);
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! # Bookmarks and shares
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! # let demo = danavi::demo::DemoServer::start().await?;
+//! # let client = demo.client()?;
+//! # let artists = client.get_artists().await?;
+//! # let albums = client.get_artist(&artists.artists.index[0].artist[0].id).await?.artist.album;
+//! # let songs = client.get_album(&albums[0].id).await?.album.song;
+//! client.create_bookmark(&songs[0].id, Duration::from_secs(3)).await?;
+//! let bookmarks = client.get_bookmarks().await?.bookmarks.bookmark;
+//! assert_eq!((bookmarks[0].entry.id.as_str(), bookmarks[0].position), (songs[0].id.as_str(), 3000));
+//! client.delete_bookmark(&songs[0].id).await?;
+//! assert!(client.get_bookmarks().await?.bookmarks.bookmark.is_empty());
+//!
+//! let week = Duration::from_secs(7 * 24 * 3600);
+//! let share = client.create_share(&albums[0].id, Some("For you"), Some(week)).await?;
+//! assert!(share.url.ends_with(&albums[0].id));
+//! assert_eq!(share.description.as_deref(), Some("For you"));
+//! assert!(client.create_share("missing", None, None).await.is_err());
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! # The play queue and the jukebox
+//!
+//! ```
+//! use danavi::client::JukeboxAction;
+//!
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! # let demo = danavi::demo::DemoServer::start().await?;
+//! # let client = demo.client()?;
+//! # let artists = client.get_artists().await?;
+//! # let albums = client.get_artist(&artists.artists.index[0].artist[0].id).await?.artist.album;
+//! # let songs = client.get_album(&albums[0].id).await?.album.song;
+//! let ids: Vec<String> = songs.iter().map(|s| s.id.clone()).collect();
+//! client.save_play_queue(&ids, Some(&ids[1]), std::time::Duration::from_secs(2)).await?;
+//! let queue = client.get_play_queue().await?.play_queue.unwrap();
+//! assert_eq!(queue.songs().0.len(), songs.len());
+//! assert_eq!((queue.current.as_deref(), queue.position), (Some(ids[1].as_str()), Some(2000)));
+//! assert!(queue.changed_ms().is_some());
+//!
+//! client.jukebox_control(JukeboxAction::Set(&ids[..1])).await?;
+//! client.jukebox_control(JukeboxAction::Add(&ids[1..])).await?;
+//! client.jukebox_control(JukeboxAction::Start).await?;
+//! let status = client.jukebox_control(JukeboxAction::Skip { index: 1, offset: 5 }).await?;
+//! assert_eq!((status.current_index, status.playing, status.position), (1, true, Some(5)));
+//! client.jukebox_control(JukeboxAction::Stop).await?;
+//! assert!(!client.jukebox_control(JukeboxAction::Status).await?.playing);
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! # Streaming and downloads
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! # tokio::runtime::Runtime::new()?.block_on(async {
+//! # let demo = danavi::demo::DemoServer::start().await?;
+//! # let client = demo.client()?;
+//! # let artists = client.get_artists().await?;
+//! # let albums = client.get_artist(&artists.artists.index[0].artist[0].id).await?.artist.album;
+//! # let songs = client.get_album(&albums[0].id).await?.album.song;
+//! let audio = client.stream_song(&songs[0].id).await?;
+//! assert!(audio.starts_with(b"RIFF"));
+//! // Streams carry the same credentials as API calls; the server turns
+//! // away requests without them
+//! let (response, _) = client.stream_song_from(&songs[0].id, 0).await?;
+//! assert_eq!(response.bytes().await?, audio);
+//! let bare = reqwest::get(format!("{}/rest/stream?id={}", demo.base_url(), songs[0].id)).await?;
+//! assert!(bare.text().await?.contains("Required parameter is missing"));
+//! // Starting partway only works when transcoding; raw files come whole
+//! let raw = client.stream_song_at(&songs[0].id, Duration::from_secs(2)).await?;
+//! assert_eq!(raw.bytes().await?, audio);
+//! let mut transcoding = client.clone();
+//! transcoding.set_stream_format(danavi::types::StreamFormat::Mp3);
+//! let cut = transcoding.stream_song_at(&songs[0].id, Duration::from_secs(2)).await?;
+//! assert!(cut.bytes().await?.len() < audio.len());
+//!
+//! let download = client.download(&songs[0].id).await?;
+//! assert_eq!(download.extension().as_deref(), Some("wav"));
+//! assert_eq!(download.bytes, audio);
+//! # Ok::<(), anyhow::Error>(())
+//! # })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::client::SubsonicClient;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::TAU;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use url::Url;
+
+const SAMPLE_RATE: u32 = 22050;
+const TRACK_SECONDS: u32 = 6;
+// Pixels on a side; a multiple of 4 so BMP rows need no padding
+const COVER_SIZE: u32 = 16;
+
+/// Artist, genre, then albums with their year and tracks.
+type CatalogEntry = (&'static str, &'static str, &'static [(&'static str, i32, &'static [&'static str])]);
+
+// As Navidrome has them by default
+const IGNORED_ARTICLES: &str = "The El La Los Las Le Les Os As O A";
+
+const CATALOG: &[CatalogEntry] = &[
+    (
+        "The Sine Waves",
+        "Synthpop",
+        &[
+            ("Pure Tones", 1984, &["Carrier", "Low Tide", "Harmonic", "Overtone"]),
+            ("Phase Shift", 1987, &["Quadrature", "Beat Frequency", "Null Point"]),
+        ],
+    ),
+    (
+        "Square Roots",
+        "Chiptune",
+        &[
+            ("Eight Bit Garden", 1991, &["Pulse Width", "Duty Cycle", "Arpeggio", "Noise Channel"]),
+            ("Aliasing", 1993, &["Nyquist", "Fold Back", "Low Pass"]),
+        ],
+    ),
+    (
+        "Triangle Club",
+        "Ambient",
+        &[
+            ("Slow Attack", 2001, &["Envelope", "Long Release", "Sustain"]),
+            ("Room Tone", 2004, &["Reverb Tail", "Early Reflections", "Dry Signal", "Wet Mix"]),
+        ],
+    ),
+];
+
+struct Track {
+    id: String,
+    title: &'static str,
+    artist_id: String,
+    artist: &'static str,
+    album_id: String,
+    album: &'static str,
+    year: i32,
+    genre: &'static str,
+    number: usize,
+    frequency: f32,
+}
+
+impl Track {
+    fn to_json(&self, starred: bool) -> Value {
+        let mut song = json!({
+            "id": self.id,
+            "title": self.title,
+            "artist": self.artist,
+            "artistId": self.artist_id,
+            "album": self.album,
+            "albumId": self.album_id,
+            "year": self.year,
+            "genre": self.genre,
+            "track": self.number,
+            "discNumber": 1,
+            "duration": TRACK_SECONDS,
+            "path": format!("{}/{}/{:02} - {}.wav", self.artist, self.album, self.number, self.title),
+            "coverArt": self.album_id,
+            "suffix": "wav",
+        });
+        if starred {
+            song["starred"] = json!("2024-01-01T00:00:00Z");
+        }
+        song
+    }
+}
+
+fn tracks() -> Vec<Track> {
+    let mut tracks = Vec::new();
+    for (a, (artist, genre, albums)) in CATALOG.iter().enumerate() {
+        for (b, (album, year, titles)) in albums.iter().enumerate() {
+            for (t, title) in titles.iter().enumerate() {
+                tracks.push(Track {
+                    id: format!("tr-{}-{}-{}", a + 1, b + 1, t + 1),
+                    title,
+                    artist_id: format!("ar-{}", a + 1),
+                    artist,
+                    album_id: format!("al-{}-{}", a + 1, b + 1),
+                    album,
+                    year: *year,
+                    genre,
+                    number: t + 1,
+                    // A pentatonic-ish step per track, an octave per artist
+                    frequency: 220.0 * (a + 1) as f32 * [1.0, 1.125, 1.25, 1.5, 1.667][t % 5],
+                });
+            }
+        }
+    }
+    tracks
+}
+
+/// A mono 16-bit WAV of a soft tone with a fade in and out, from `start`
+/// seconds in.
+fn tone(frequency: f32, start: u32) -> Vec<u8> {
+    let samples = SAMPLE_RATE * TRACK_SECONDS;
+    let first = (SAMPLE_RATE * start).min(samples);
+    let data_len = (samples - first) * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    let fade = SAMPLE_RATE as f32 / 4.0;
+    for i in first..samples {
+        let envelope = (i as f32 / fade).min((samples - i) as f32 / fade).min(1.0);
+        let t = i as f32 / SAMPLE_RATE as f32;
+        let value = (t * frequency * TAU).sin() * 0.25 * envelope;
+        wav.extend_from_slice(&((value * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    wav
+}
+
+/// A square of one color as a 24-bit BMP, which needs no encoder.
+fn cover(seed: f32) -> Vec<u8> {
+    let row = COVER_SIZE * 3;
+    let data_len = row * COVER_SIZE;
+    let mut bmp = Vec::with_capacity(54 + data_len as usize);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&(54 + data_len).to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+    bmp.extend_from_slice(&54u32.to_le_bytes());
+    bmp.extend_from_slice(&40u32.to_le_bytes());
+    bmp.extend_from_slice(&COVER_SIZE.to_le_bytes());
+    bmp.extend_from_slice(&COVER_SIZE.to_le_bytes());
+    bmp.extend_from_slice(&1u16.to_le_bytes()); // planes
+    bmp.extend_from_slice(&24u16.to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes()); // uncompressed
+    bmp.extend_from_slice(&data_len.to_le_bytes());
+    bmp.extend_from_slice(&[0; 16]); // resolution and palette
+    let seed = seed as u32;
+    let bgr = [(seed * 3 % 256) as u8, (seed * 5 % 256) as u8, (seed * 7 % 256) as u8];
+    for _ in 0..COVER_SIZE * COVER_SIZE {
+        bmp.extend_from_slice(&bgr);
+    }
+    bmp
+}
+
+struct Library {
+    tracks: Vec<Track>,
+    starred: Mutex<HashSet<String>>,
+    // Song and album ids to ratings from 1 to 5
+    ratings: Mutex<HashMap<String, u8>>,
+    // Song id and position in milliseconds
+    bookmarks: Mutex<Vec<(String, u64)>>,
+    play_queue: Mutex<Option<SavedQueue>>,
+    jukebox: Mutex<Jukebox>,
+}
+
+// The jukebox has no speakers; its position only moves when skipped
+#[derive(Default)]
+struct Jukebox {
+    playlist: Vec<String>,
+    index: usize,
+    playing: bool,
+    gain: f32,
+    position: u64,
+}
+
+struct SavedQueue {
+    ids: Vec<String>,
+    current: Option<String>,
+    position: u64,
+    changed_by: String,
+    // Saves are stamped a second apart from a fixed date, which keeps them
+    // in order without a clock
+    serial: u64,
+}
+
+impl Library {
+    fn track(&self, id: &str) -> Option<&Track> {
+        self.tracks.iter().find(|t| t.id == id)
+    }
+
+    fn songs<'a>(&'a self, tracks: impl Iterator<Item = &'a Track>) -> Vec<Value> {
+        let starred = self.starred.lock().unwrap();
+        let ratings = self.ratings.lock().unwrap();
+        tracks
+            .map(|t| {
+                let mut song = t.to_json(starred.contains(&t.id));
+                if let Some(rating) = ratings.get(&t.id) {
+                    song["userRating"] = json!(rating);
+                }
+                song
+            })
+            .collect()
+    }
+
+    fn album_json(&self, track: &Track) -> Value {
+        let starred = self.starred.lock().unwrap().contains(&track.album_id);
+        let mut album = json!({
+            "id": track.album_id,
+            "name": track.album,
+            "artist": track.artist,
+            "artistId": track.artist_id,
+            "year": track.year,
+            "genre": track.genre,
+            "songCount": self.tracks.iter().filter(|t| t.album_id == track.album_id).count(),
+            "coverArt": track.album_id,
+        });
+        if starred {
+            album["starred"] = json!("2024-01-01T00:00:00Z");
+        }
+        if let Some(rating) = self.ratings.lock().unwrap().get(&track.album_id) {
+            album["userRating"] = json!(rating);
+        }
+        album
+    }
+
+    // One track per album, in catalog order
+    fn album_heads(&self) -> impl Iterator<Item = &Track> {
+        self.tracks.iter().filter(|t| t.number == 1)
+    }
+
+    /// The payload for an API call, or an error message.
+    fn respond(&self, endpoint: &str, params: &[(String, String)]) -> Result<Value, String> {
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+        };
+        let id = || param("id").ok_or("Required parameter is missing: id".to_string());
+        let count = |name: &str, default: usize| param(name).and_then(|v| v.parse().ok()).unwrap_or(default);
+
+        Ok(match endpoint {
+            "ping" => json!({}),
+            "getOpenSubsonicExtensions" => json!({
+                "openSubsonicExtensions": [{ "name": "songLyrics", "versions": [1] }],
+            }),
+            "getUser" => json!({ "user": { "username": "demo", "streamRole": true } }),
+            // Scans finish at once; the library never changes
+            "startScan" => json!({ "scanStatus": { "scanning": true, "count": 0 } }),
+            "getScanStatus" => json!({ "scanStatus": { "scanning": false, "count": self.tracks.len() } }),
+            "getArtists" => {
+                let starred = self.starred.lock().unwrap();
+                let artists: Vec<Value> = CATALOG
+                    .iter()
+                    .enumerate()
+                    .map(|(a, (name, _, albums))| {
+                        let id = format!("ar-{}", a + 1);
+                        let mut artist = json!({ "id": id, "name": name, "albumCount": albums.len() });
+                        if starred.contains(&id) {
+                            artist["starred"] = json!("2024-01-01T00:00:00Z");
+                        }
+                        artist
+                    })
+                    .collect();
+                json!({ "artists": { "ignoredArticles": IGNORED_ARTICLES, "index": [{ "name": "#", "artist": artists }] } })
+            }
+            // Folders go artist, then album, then files
+            "getIndexes" => {
+                let folders: Vec<Value> = CATALOG
+                    .iter()
+                    .enumerate()
+                    .map(|(a, (name, _, _))| json!({ "id": format!("ar-{}", a + 1), "name": name }))
+                    .collect();
+                json!({ "indexes": { "ignoredArticles": IGNORED_ARTICLES, "index": [{ "name": "#", "artist": folders }] } })
+            }
+            "getMusicDirectory" => {
+                let id = id()?;
+                if let Some(head) = self.album_heads().find(|t| t.album_id == id) {
+                    let mut files = self.songs(self.tracks.iter().filter(|t| t.album_id == id));
+                    for file in &mut files {
+                        file["isDir"] = json!(false);
+                        file["parent"] = json!(id);
+                    }
+                    json!({ "directory": { "id": id, "name": head.album, "parent": head.artist_id, "child": files } })
+                } else {
+                    let albums: Vec<Value> = self
+                        .album_heads()
+                        .filter(|t| t.artist_id == id)
+                        .map(|t| json!({ "id": t.album_id, "title": t.album, "isDir": true, "parent": id }))
+                        .collect();
+                    let name = self
+                        .tracks
+                        .iter()
+                        .find(|t| t.artist_id == id)
+                        .ok_or("Directory not found")?
+                        .artist;
+                    json!({ "directory": { "id": id, "name": name, "child": albums } })
+                }
+            }
+            "getArtist" => {
+                let id = id()?;
+                let albums: Vec<Value> = self
+                    .album_heads()
+                    .filter(|t| t.artist_id == id)
+                    .map(|t| self.album_json(t))
+                    .collect();
+                let name = self
+                    .tracks
+                    .iter()
+                    .find(|t| t.artist_id == id)
+                    .ok_or("Artist not found")?
+                    .artist;
+                json!({ "artist": { "id": id, "name": name, "album": albums } })
+            }
+            // Every other artist counts as similar, plus one not in the library
+            "getArtistInfo2" => {
+                let id = id()?;
+                let artist = self.tracks.iter().find(|t| t.artist_id == id).ok_or("Artist not found")?;
+                let mut similar: Vec<Value> = Vec::new();
+                for track in self.album_heads() {
+                    if track.artist_id != id && similar.iter().all(|a| a["id"] != track.artist_id) {
+                        similar.push(json!({ "id": track.artist_id, "name": track.artist }));
+                    }
+                }
+                similar.push(json!({ "id": "", "name": "The Noise Floor" }));
+                json!({ "artistInfo2": {
+                    "biography": format!("{} make music out of pure tones.<br/><a href=\"http://demo\">Read more</a>", artist.artist),
+                    "lastFmUrl": "http://demo/artist",
+                    "similarArtist": similar,
+                } })
+            }
+            // Notes for each artist's first album only, like a server that
+            // finds nothing for the rest
+            "getAlbumInfo2" => {
+                let id = id()?;
+                let head = self.album_heads().find(|t| t.album_id == id).ok_or("Album not found")?;
+                if id.ends_with("-1") {
+                    json!({ "albumInfo": {
+                        "notes": format!("<p>{} is the first album by {}.</p>", head.album, head.artist),
+                        "lastFmUrl": "http://demo/album",
+                        "largeImageUrl": "http://demo/album.jpg",
+                    } })
+                } else {
+                    json!({ "albumInfo": {} })
+                }
+            }
+            "getAlbum" => {
+                let id = id()?;
+                let head = self
+                    .album_heads()
+                    .find(|t| t.album_id == id)
+                    .ok_or("Album not found")?;
+                let mut album = self.album_json(head);
+                album["song"] = json!(self.songs(self.tracks.iter().filter(|t| t.album_id == id)));
+                json!({ "album": album })
+            }
+            "getSong" => {
+                let track = self.track(id()?).ok_or("Song not found")?;
+                json!({ "song": self.songs(std::iter::once(track))[0] })
+            }
+            "getLyricsBySongId" => {
+                let track = self.track(id()?).ok_or("Song not found")?;
+                let lines: Vec<Value> = (0..TRACK_SECONDS)
+                    .map(|second| {
+                        json!({ "start": second * 1000, "value": format!("{} ({} of {})", track.title, second + 1, TRACK_SECONDS) })
+                    })
+                    .collect();
+                json!({ "lyricsList": { "structuredLyrics": [{ "synced": true, "line": lines }] } })
+            }
+            "getLyrics" => json!({ "lyrics": {} }),
+            "getRandomSongs" => {
+                let genre = param("genre");
+                let tracks = self
+                    .tracks
+                    .iter()
+                    .filter(|t| genre.is_none_or(|g| g.eq_ignore_ascii_case(t.genre)))
+                    .take(count("size", 10));
+                json!({ "randomSongs": { "song": self.songs(tracks) } })
+            }
+            // Every list type gets catalog order
+            "getAlbumList2" => {
+                let albums: Vec<Value> = self
+                    .album_heads()
+                    .skip(count("offset", 0))
+                    .take(count("size", 10))
+                    .map(|t| self.album_json(t))
+                    .collect();
+                json!({ "albumList2": { "album": albums } })
+            }
+            // The artist's album openers stand in for the popular ones
+            "getTopSongs" => {
+                let artist = param("artist").unwrap_or_default();
+                let tracks = self
+                    .album_heads()
+                    .filter(|t| t.artist.eq_ignore_ascii_case(artist))
+                    .take(count("count", 50));
+                json!({ "topSongs": { "song": self.songs(tracks) } })
+            }
+            // Songs of the same genre count as similar
+            "getSimilarSongs2" => {
+                let track = self.track(id()?).ok_or("Song not found")?;
+                let tracks = self
+                    .tracks
+                    .iter()
+                    .filter(|t| t.genre == track.genre && t.id != track.id)
+                    .take(count("count", 50));
+                json!({ "similarSongs2": { "song": self.songs(tracks) } })
+            }
+            // One channel, whose episodes reuse the first album's tracks
+            "getPodcasts" => {
+                let mut channel = json!({ "id": "pc-1", "title": "Tone Talk", "url": "http://demo/feed.xml" });
+                if param("includeEpisodes") != Some("false") {
+                    let mut episodes: Vec<Value> = self
+                        .tracks
+                        .iter()
+                        .take(2)
+                        .enumerate()
+                        .map(|(i, t)| {
+                            json!({
+                                "id": format!("ep-{}", i + 1),
+                                "streamId": t.id,
+                                "title": format!("Episode {}: {}", i + 1, t.title),
+                                "status": "completed",
+                                "duration": TRACK_SECONDS,
+                                "publishDate": format!("2024-0{}-01T00:00:00Z", i + 1),
+                            })
+                        })
+                        .collect();
+                    episodes.push(json!({ "id": "ep-3", "title": "Episode 3: Coming Soon", "status": "new" }));
+                    episodes.reverse();
+                    channel["episode"] = json!(episodes);
+                }
+                json!({ "podcasts": { "channel": [channel] } })
+            }
+            "getSongsByGenre" => {
+                let genre = param("genre").unwrap_or_default();
+                let tracks = self
+                    .tracks
+                    .iter()
+                    .filter(|t| t.genre.eq_ignore_ascii_case(genre))
+                    .skip(count("offset", 0))
+                    .take(count("count", 10));
+                json!({ "songsByGenre": { "song": self.songs(tracks) } })
+            }
+            "getStarred2" => {
+                let starred = self.starred.lock().unwrap().clone();
+                let tracks = self.tracks.iter().filter(|t| starred.contains(&t.id));
+                json!({ "starred2": { "song": self.songs(tracks) } })
+            }
+            // One playlist: the opening track of every album
+            "getPlaylists" => json!({
+                "playlists": { "playlist": [{ "id": "pl-1", "name": "Openers", "songCount": self.album_heads().count(), "owner": "demo" }] },
+            }),
+            "getPlaylist" => {
+                if id()? != "pl-1" {
+                    return Err("Playlist not found".to_string());
+                }
+                json!({
+                    "playlist": { "id": "pl-1", "name": "Openers", "songCount": self.album_heads().count(), "entry": self.songs(self.album_heads()) },
+                })
+            }
+            "star" | "unstar" => {
+                let mut starred = self.starred.lock().unwrap();
+                for (key, value) in params {
+                    if key == "id" || key == "albumId" || key == "artistId" {
+                        if endpoint == "star" {
+                            starred.insert(value.clone());
+                        } else {
+                            starred.remove(value);
+                        }
+                    }
+                }
+                json!({})
+            }
+            "setRating" => {
+                let id = id()?;
+                // Like some real servers, only songs and albums take ratings
+                if !self.tracks.iter().any(|t| t.id == id || t.album_id == id) {
+                    return Err("Rating is not supported for this item".to_string());
+                }
+                let mut ratings = self.ratings.lock().unwrap();
+                match count("rating", 0) {
+                    0 => ratings.remove(id),
+                    rating => ratings.insert(id.to_string(), rating.min(5) as u8),
+                };
+                json!({})
+            }
+            "scrobble" => json!({}),
+            "createBookmark" => {
+                let track = self.track(id()?).ok_or("Song not found")?;
+                let position = count("position", 0) as u64;
+                let mut bookmarks = self.bookmarks.lock().unwrap();
+                bookmarks.retain(|(id, _)| *id != track.id);
+                bookmarks.push((track.id.clone(), position));
+                json!({})
+            }
+            "deleteBookmark" => {
+                let id = id()?;
+                self.bookmarks.lock().unwrap().retain(|(bookmarked, _)| bookmarked != id);
+                json!({})
+            }
+            "getBookmarks" => {
+                let bookmarks: Vec<Value> = self
+                    .bookmarks
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter_map(|(id, position)| {
+                        let entry = self.songs(std::iter::once(self.track(id)?)).pop()?;
+                        Some(json!({ "position": position, "entry": entry }))
+                    })
+                    .collect();
+                json!({ "bookmarks": { "bookmark": bookmarks } })
+            }
+            "savePlayQueue" => {
+                let ids: Vec<String> = params
+                    .iter()
+                    .filter(|(k, _)| k == "id")
+                    .map(|(_, id)| id.clone())
+                    .collect();
+                let mut saved = self.play_queue.lock().unwrap();
+                let serial = saved.as_ref().map_or(0, |queue| queue.serial + 1);
+                *saved = Some(SavedQueue {
+                    ids,
+                    current: param("current").map(str::to_string),
+                    position: count("position", 0) as u64,
+                    changed_by: param("c").unwrap_or_default().to_string(),
+                    serial,
+                });
+                json!({})
+            }
+            "getPlayQueue" => match self.play_queue.lock().unwrap().as_ref() {
+                Some(queue) => json!({ "playQueue": {
+                    "entry": self.songs(queue.ids.iter().filter_map(|id| self.track(id))),
+                    "current": queue.current,
+                    "position": queue.position,
+                    "changed": format!(
+                        "2024-01-01T{:02}:{:02}:{:02}Z",
+                        queue.serial / 3600 % 24,
+                        queue.serial / 60 % 60,
+                        queue.serial % 60
+                    ),
+                    "changedBy": queue.changed_by,
+                }}),
+                None => json!({}),
+            },
+            // Links go nowhere; there's no web player to share to
+            "createShare" => {
+                let id = id()?;
+                if self.track(id).is_none() && self.album_heads().all(|t| t.album_id != id) {
+                    return Err("Nothing to share with that id".to_string());
+                }
+                json!({ "shares": { "share": [{
+                    "id": format!("sh-{}", id),
+                    "url": format!("http://localhost/share/sh-{}", id),
+                    "description": param("description"),
+                    "visitCount": 0,
+                }] } })
+            }
+            "jukeboxControl" => {
+                let mut jukebox = self.jukebox.lock().unwrap();
+                let ids = params.iter().filter(|(k, _)| k == "id").map(|(_, id)| id.clone());
+                match param("action").ok_or("Required parameter is missing: action")? {
+                    "status" => {}
+                    "set" => {
+                        jukebox.playlist = ids.collect();
+                        jukebox.index = 0;
+                        jukebox.position = 0;
+                    }
+                    "add" => jukebox.playlist.extend(ids),
+                    "start" => jukebox.playing = !jukebox.playlist.is_empty(),
+                    "stop" => jukebox.playing = false,
+                    "skip" => {
+                        jukebox.index = count("index", 0);
+                        jukebox.position = count("offset", 0) as u64;
+                    }
+                    "setGain" => jukebox.gain = param("gain").and_then(|g| g.parse().ok()).unwrap_or(jukebox.gain),
+                    other => return Err(format!("Unknown jukebox action: {}", other)),
+                }
+                let index = if jukebox.index < jukebox.playlist.len() { jukebox.index as i64 } else { -1 };
+                json!({ "jukeboxStatus": {
+                    "currentIndex": index,
+                    "playing": jukebox.playing,
+                    "gain": jukebox.gain,
+                    "position": jukebox.position,
+                }})
+            }
+            "search3" => {
+                let query = param("query").unwrap_or_default().trim_matches('"').to_lowercase();
+                let matches = |text: &str| text.to_lowercase().contains(&query);
+                let albums: Vec<Value> = self
+                    .album_heads()
+                    .filter(|t| matches(t.album) || matches(t.artist))
+                    .skip(count("albumOffset", 0))
+                    .take(count("albumCount", 20))
+                    .map(|t| self.album_json(t))
+                    .collect();
+                let songs = self
+                    .tracks
+                    .iter()
+                    .filter(|t| matches(t.title) || matches(t.artist) || matches(t.album))
+                    .skip(count("songOffset", 0))
+                    .take(count("songCount", 20));
+                json!({ "searchResult3": { "album": albums, "song": self.songs(songs) } })
+            }
+            _ => return Err(format!("{} isn't available in the demo", endpoint)),
+        })
+    }
+}
+
+fn api_body(result: Result<Value, String>) -> Vec<u8> {
+    let mut response = match result {
+        Ok(payload) => payload,
+        Err(message) => json!({ "error": { "code": 0, "message": message } }),
+    };
+    let status = if response.get("error").is_some() { "failed" } else { "ok" };
+    response["status"] = json!(status);
+    response["version"] = json!("1.16.1");
+    response["type"] = json!("danavi-demo");
+    response["openSubsonic"] = json!(true);
+    serde_json::to_vec(&json!({ "subsonic-response": response })).unwrap_or_default()
+}
+
+// The first credential or client parameter a request leaves out
+fn missing_auth(params: &[(String, String)]) -> Option<&'static str> {
+    let has = |key: &str| params.iter().any(|(k, _)| k == key);
+    let password = if has("p") { None } else { ["t", "s"].into_iter().find(|k| !has(k)) };
+    ["u", "v", "c"].into_iter().find(|k| !has(k)).or(password)
+}
+
+async fn serve(mut stream: TcpStream, library: Arc<Library>) -> Result<()> {
+    // Requests are bodiless GETs, so the headers are all there is to read
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let target = request
+        .split_whitespace()
+        .nth(1)
+        .context("Malformed request")?;
+    let url = Url::parse(&format!("http://demo{}", target))?;
+    let endpoint = url.path().trim_start_matches("/rest/").trim_end_matches(".view");
+    let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+    let id = params.iter().find(|(k, _)| k == "id").map(|(_, id)| id.as_str());
+    let (content_type, body) = if let Some(missing) = missing_auth(&params) {
+        let message = format!("Required parameter is missing: {}", missing);
+        ("application/json", api_body(Err(message)))
+    } else if endpoint == "getCoverArt" {
+        let head = library.album_heads().find(|t| Some(t.album_id.as_str()) == id);
+        match head {
+            Some(track) => ("image/bmp", cover(track.frequency)),
+            None => ("application/json", api_body(Err("Cover art not found".to_string()))),
+        }
+    } else if endpoint == "stream" || endpoint == "download" {
+        // Like real servers, only a transcoded stream is cut
+        let param = |name| params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+        let start = match (endpoint, param("format")) {
+            ("stream", Some(format)) if format != "raw" => {
+                param("timeOffset").and_then(|t| t.parse().ok()).unwrap_or(0)
+            }
+            _ => 0,
+        };
+        match id.and_then(|id| library.track(id)) {
+            Some(track) => ("audio/wav", tone(track.frequency, start)),
+            None => ("application/json", api_body(Err("Song not found".to_string()))),
+        }
+    } else {
+        ("application/json", api_body(library.respond(endpoint, &params)))
+    };
+
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// The demo server, listening on a random local port until dropped.
+pub struct DemoServer {
+    pub addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl DemoServer {
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .context("Failed to start the demo server")?;
+        let addr = listener.local_addr()?;
+        let library = Arc::new(Library {
+            tracks: tracks(),
+            starred: Mutex::new(HashSet::new()),
+            ratings: Mutex::new(HashMap::new()),
+            bookmarks: Mutex::new(Vec::new()),
+            play_queue: Mutex::new(None),
+            jukebox: Mutex::new(Jukebox {
+                gain: 0.5,
+                ..Default::default()
+            }),
+        });
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, library.clone()));
+            }
+        });
+        Ok(Self { addr, handle })
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A client for the demo library. Any username and password work.
+    pub fn client(&self) -> Result<SubsonicClient> {
+        SubsonicClient::new(self.base_url(), "demo".to_string(), "demo".to_string())
+    }
+}
+
+impl Drop for DemoServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
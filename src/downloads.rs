@@ -0,0 +1,358 @@
+use crate::about::format_size;
+use anyhow::{Context, Result};
+use danavi::cache::SongCache;
+use danavi::client::SubsonicClient;
+use danavi::config::get_state_dir;
+use danavi::types::Song;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const FILE_NAME: &str = "downloads.json";
+// Songs downloading at once, across all albums
+const CONCURRENCY: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pending,
+    Active,
+    Paused,
+    Done,
+    Failed,
+}
+
+impl Status {
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Active => "downloading",
+            Status::Paused => "paused",
+            Status::Done => "done",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+/// An album marked for offline use. Only what's needed to carry on is
+/// saved; progress is worked out again from the cache on load.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadItem {
+    pub album_id: String,
+    /// "Album - Artist"
+    pub title: String,
+    songs: Vec<String>,
+    #[serde(default)]
+    paused: bool,
+    #[serde(skip)]
+    remaining: VecDeque<String>,
+    #[serde(skip)]
+    active: usize,
+    #[serde(skip)]
+    pub done: usize,
+    #[serde(skip)]
+    pub failed: Vec<String>,
+    /// Downloaded this session
+    #[serde(skip)]
+    pub bytes: u64,
+}
+
+impl DownloadItem {
+    pub fn status(&self) -> Status {
+        if self.paused {
+            Status::Paused
+        } else if self.active > 0 {
+            Status::Active
+        } else if !self.remaining.is_empty() {
+            Status::Pending
+        } else if !self.failed.is_empty() {
+            Status::Failed
+        } else {
+            Status::Done
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.songs.len()
+    }
+
+    // Rebuilds the progress from what's already cached
+    fn reset(&mut self, cache: &SongCache) {
+        self.remaining = self
+            .songs
+            .iter()
+            .filter(|id| !cache.contains(id))
+            .cloned()
+            .collect();
+        self.done = self.songs.len() - self.remaining.len();
+        self.failed.clear();
+    }
+}
+
+enum Event {
+    Progress(u64),
+    Done,
+    Failed(String),
+}
+
+// Album id, song id and what happened
+type Message = (String, String, Event);
+
+struct Transfer {
+    album_id: String,
+    song_id: String,
+    handle: JoinHandle<()>,
+}
+
+/// Albums queued for download into the song cache, worked through a couple
+/// of songs at a time. The unfinished ones are saved under the state dir so
+/// an interrupted batch carries on next launch.
+#[derive(Default)]
+pub struct Downloads {
+    // None when there's no state dir
+    path: Option<PathBuf>,
+    pub items: Vec<DownloadItem>,
+    pub selected: usize,
+    transfers: Vec<Transfer>,
+    channel: Option<(mpsc::UnboundedSender<Message>, mpsc::UnboundedReceiver<Message>)>,
+}
+
+impl Downloads {
+    pub fn load(cache: Option<&SongCache>) -> Self {
+        let path = get_state_dir().ok().map(|dir| dir.join(FILE_NAME));
+        let mut items: Vec<DownloadItem> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        if let Some(cache) = cache {
+            for item in &mut items {
+                item.reset(cache);
+            }
+        }
+        Self {
+            path,
+            items,
+            ..Default::default()
+        }
+    }
+
+    /// Marks an album for download. Returns false if it's already listed
+    /// and not finished.
+    pub fn add(&mut self, cache: &SongCache, album_id: &str, title: String, songs: &[Song]) -> bool {
+        if let Some(index) = self.items.iter().position(|item| item.album_id == album_id) {
+            if self.items[index].status() != Status::Done {
+                return false;
+            }
+            self.items.remove(index);
+        }
+        let mut item = DownloadItem {
+            album_id: album_id.to_string(),
+            title,
+            songs: songs.iter().map(|s| s.id.clone()).collect(),
+            paused: false,
+            remaining: VecDeque::new(),
+            active: 0,
+            done: 0,
+            failed: Vec::new(),
+            bytes: 0,
+        };
+        item.reset(cache);
+        self.items.push(item);
+        self.save_logged();
+        true
+    }
+
+    /// Picks up finished chunks and songs and starts the next transfers.
+    pub fn poll(&mut self, client: &SubsonicClient, cache: &SongCache) {
+        let mut changed = false;
+        if let Some((_, rx)) = self.channel.as_mut() {
+            while let Ok((album_id, song_id, event)) = rx.try_recv() {
+                let Some(item) = self.items.iter_mut().find(|i| i.album_id == album_id) else {
+                    continue;
+                };
+                match event {
+                    Event::Progress(bytes) => item.bytes += bytes,
+                    Event::Done | Event::Failed(_) => {
+                        self.transfers.retain(|t| t.song_id != song_id || t.album_id != album_id);
+                        item.active = item.active.saturating_sub(1);
+                        match event {
+                            Event::Failed(reason) => {
+                                log::warn!("Download of {} failed: {}", item.title, reason);
+                                item.failed.push(song_id);
+                            }
+                            _ => item.done += 1,
+                        }
+                        match item.status() {
+                            Status::Done => log::info!("Downloaded {}", item.title),
+                            Status::Failed => log::info!(
+                                "Downloaded {} with {} songs missing",
+                                item.title,
+                                item.failed.len()
+                            ),
+                            _ => {}
+                        }
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let (tx, _) = self.channel.get_or_insert_with(mpsc::unbounded_channel);
+        for item in &mut self.items {
+            if item.paused {
+                continue;
+            }
+            while self.transfers.len() < CONCURRENCY
+                && let Some(song_id) = item.remaining.pop_front()
+            {
+                item.active += 1;
+                let handle = tokio::spawn({
+                    let (client, cache, tx) = (client.clone(), cache.clone(), tx.clone());
+                    let (album_id, song_id) = (item.album_id.clone(), song_id.clone());
+                    async move {
+                        let progress_tx = tx.clone();
+                        let result = cache
+                            .download(&client, &song_id, |bytes| {
+                                let _ = progress_tx.send((
+                                    album_id.clone(),
+                                    song_id.clone(),
+                                    Event::Progress(bytes),
+                                ));
+                            })
+                            .await;
+                        let event = match result {
+                            Ok(_) => Event::Done,
+                            Err(e) => Event::Failed(e.to_string()),
+                        };
+                        let _ = tx.send((album_id, song_id, event));
+                    }
+                });
+                self.transfers.push(Transfer {
+                    album_id: item.album_id.clone(),
+                    song_id,
+                    handle,
+                });
+            }
+        }
+
+        if changed {
+            self.save_logged();
+        }
+    }
+
+    // Stops an item's transfers, putting the songs back to be resumed later
+    fn stop_transfers(&mut self, index: usize) -> Vec<String> {
+        let item = &mut self.items[index];
+        let mut stopped = Vec::new();
+        self.transfers.retain(|transfer| {
+            if transfer.album_id != item.album_id {
+                return true;
+            }
+            transfer.handle.abort();
+            stopped.push(transfer.song_id.clone());
+            false
+        });
+        item.active = 0;
+        for song_id in stopped.iter().rev() {
+            item.remaining.push_front(song_id.clone());
+        }
+        stopped
+    }
+
+    /// Pauses or resumes the selected item. A failed one retries the songs
+    /// that didn't make it.
+    pub fn toggle_pause(&mut self) {
+        let index = self.selected;
+        let Some(item) = self.items.get_mut(index) else {
+            return;
+        };
+        match item.status() {
+            Status::Done => return,
+            Status::Failed => {
+                let failed = std::mem::take(&mut item.failed);
+                item.remaining.extend(failed);
+            }
+            Status::Paused => item.paused = false,
+            Status::Pending | Status::Active => {
+                item.paused = true;
+                self.stop_transfers(index);
+            }
+        }
+        self.save_logged();
+    }
+
+    /// Drops the selected item, discarding its partly downloaded songs.
+    /// Songs already finished stay cached.
+    pub fn cancel(&mut self, cache: Option<&SongCache>) {
+        let index = self.selected;
+        if index >= self.items.len() {
+            return;
+        }
+        let stopped = self.stop_transfers(index);
+        if let Some(cache) = cache {
+            for song_id in &stopped {
+                cache.discard_partial(song_id);
+            }
+        }
+        self.items.remove(index);
+        self.selected = self.selected.min(self.items.len().saturating_sub(1));
+        self.save_logged();
+    }
+
+    pub fn move_selection(&mut self, down: bool) {
+        if down {
+            self.selected = (self.selected + 1).min(self.items.len().saturating_sub(1));
+        } else {
+            self.selected = self.selected.saturating_sub(1);
+        }
+    }
+
+    /// "2 pending · 1 downloading · 5 done · 1 failed · 412.0 MiB"
+    pub fn stats(&self) -> String {
+        let count = |status| self.items.iter().filter(|i| i.status() == status).count();
+        let mut parts: Vec<String> = [
+            Status::Pending,
+            Status::Active,
+            Status::Paused,
+            Status::Done,
+            Status::Failed,
+        ]
+        .into_iter()
+        .map(|status| (count(status), status))
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, status)| format!("{} {}", n, status.label()))
+        .collect();
+        let bytes: u64 = self.items.iter().map(|i| i.bytes).sum();
+        if bytes > 0 {
+            parts.push(format_size(bytes));
+        }
+        parts.join(" · ")
+    }
+
+    fn save_logged(&self) {
+        if let Err(e) = self.save() {
+            log::warn!("Failed to save downloads: {}", e);
+        }
+    }
+
+    // Finished albums aren't worth carrying over
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create state directory")?;
+        }
+        let unfinished: Vec<&DownloadItem> = self
+            .items
+            .iter()
+            .filter(|item| item.status() != Status::Done)
+            .collect();
+        let content = serde_json::to_string(&unfinished)?;
+        fs::write(path, content).context("Failed to write downloads")?;
+        Ok(())
+    }
+}
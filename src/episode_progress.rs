@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use danavi::config::get_cache_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const FILE_NAME: &str = "episode_progress.json";
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+// Stopping this close to the start isn't worth coming back to
+const MIN_POSITION: Duration = Duration::from_secs(30);
+// Stopping this close to the end counts as having finished
+const END_MARGIN: Duration = Duration::from_secs(30);
+
+/// Where an episode was left off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Progress {
+    /// Seconds into the episode
+    position: u64,
+    /// Unix time of the last update
+    updated: u64,
+}
+
+// The episode being listened to
+struct Listen {
+    id: String,
+    duration: Option<Duration>,
+    position: Duration,
+}
+
+/// Partly played podcast episodes by the id they stream from, kept in the
+/// cache dir so opening one again can offer to continue it.
+#[derive(Default)]
+pub struct EpisodeProgress {
+    // None when disabled or there's no cache dir
+    path: Option<PathBuf>,
+    entries: HashMap<String, Progress>,
+    listen: Option<Listen>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl EpisodeProgress {
+    /// Loads the saved entries, pruning those older than `max_age_days`.
+    /// Zero days turns remembering off.
+    pub fn load(max_age_days: u64) -> Self {
+        if max_age_days == 0 {
+            return Self::default();
+        }
+        let path = get_cache_dir().ok().map(|dir| dir.join(FILE_NAME));
+        let mut entries: HashMap<String, Progress> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        let cutoff = now().saturating_sub(max_age_days * SECS_PER_DAY);
+        let count = entries.len();
+        entries.retain(|_, progress| progress.updated >= cutoff);
+        let progress = Self {
+            path,
+            entries,
+            listen: None,
+        };
+        if progress.entries.len() != count {
+            let _ = progress.save();
+        }
+        progress
+    }
+
+    /// Where the episode was left, if part way through.
+    pub fn get(&self, id: &str) -> Option<Duration> {
+        self.entries.get(id).map(|progress| Duration::from_secs(progress.position))
+    }
+
+    /// Called with the playing episode and position. Moving off an episode,
+    /// to anything else or to nothing, saves where it was left.
+    pub fn update(&mut self, episode: Option<(&str, Option<i64>)>, position: Duration) -> Result<()> {
+        if self.listen.as_ref().map(|l| l.id.as_str()) == episode.map(|(id, _)| id) {
+            if let Some(listen) = self.listen.as_mut() {
+                listen.position = position;
+            }
+            return Ok(());
+        }
+        let settled = self.flush();
+        self.listen = episode.map(|(id, duration)| Listen {
+            id: id.to_string(),
+            duration: duration.filter(|&secs| secs > 0).map(|secs| Duration::from_secs(secs as u64)),
+            position,
+        });
+        settled
+    }
+
+    /// Saves where the playing episode is, for use on quitting.
+    pub fn flush(&mut self) -> Result<()> {
+        let Some(Listen { id, duration, position }) = self.listen.take() else {
+            return Ok(());
+        };
+        if self.path.is_none() {
+            return Ok(());
+        }
+        if duration.is_some_and(|duration| position + END_MARGIN >= duration) {
+            if self.entries.remove(&id).is_some() {
+                self.save()?;
+            }
+            return Ok(());
+        }
+        if position < MIN_POSITION {
+            return Ok(());
+        }
+        self.entries.insert(
+            id,
+            Progress {
+                position: position.as_secs(),
+                updated: now(),
+            },
+        );
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create cache directory")?;
+        }
+        let content = serde_json::to_string(&self.entries)?;
+        fs::write(path, content).context("Failed to write episode progress")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+//! Telling responses to superseded requests apart.
+
+/// Numbers requests as they go out so that only the response to the newest
+/// one is used, whatever order the responses arrive in.
+///
+/// ```
+/// use danavi::generation::Generations;
+///
+/// let mut searches = Generations::default();
+/// let mut shown = Vec::new();
+/// let mut receive = |searches: &Generations, generation, results| {
+///     if searches.is_current(generation) {
+///         shown.push(results);
+///     }
+/// };
+///
+/// // Typing "beat" then "beatles": the first answer is stale whenever it
+/// // comes, before the second or after
+/// let beat = searches.start();
+/// let beatles = searches.start();
+/// receive(&searches, beat, "beat");
+/// receive(&searches, beatles, "beatles");
+/// receive(&searches, beat, "beat");
+///
+/// // Leaving the search before it answers leaves nothing current
+/// let live = searches.start();
+/// searches.stop();
+/// receive(&searches, live, "live");
+/// assert!(!searches.is_current(live));
+///
+/// let lives = searches.start();
+/// receive(&searches, lives, "lives");
+/// assert_eq!(shown, ["beatles", "lives"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct Generations {
+    current: u64,
+}
+
+impl Generations {
+    /// Tags a new request, making every earlier one stale.
+    pub fn start(&mut self) -> u64 {
+        self.current += 1;
+        self.current
+    }
+
+    /// Makes every request so far stale, for when none of the answers are
+    /// wanted any more.
+    pub fn stop(&mut self) {
+        self.current += 1;
+    }
+
+    /// Whether an answer to request `generation` is still wanted.
+    pub fn is_current(&self, generation: u64) -> bool {
+        generation == self.current
+    }
+}
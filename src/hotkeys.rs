@@ -0,0 +1,170 @@
+use crate::mpris::MprisCommand;
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::mpsc;
+use zbus::Connection;
+use zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SHORTCUTS_INTERFACE: &str = "org.freedesktop.portal.GlobalShortcuts";
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+const SESSION_INTERFACE: &str = "org.freedesktop.portal.Session";
+
+pub fn parse_action(name: &str) -> Option<MprisCommand> {
+    match name {
+        "play" => Some(MprisCommand::Play),
+        "pause" => Some(MprisCommand::Pause),
+        "play_pause" => Some(MprisCommand::PlayPause),
+        "stop" => Some(MprisCommand::Stop),
+        "next" => Some(MprisCommand::Next),
+        "previous" => Some(MprisCommand::Previous),
+        _ => None,
+    }
+}
+
+/// A GlobalShortcuts portal session. Call `close` on exit so the shortcuts
+/// don't stay bound for the rest of the desktop session.
+pub struct GlobalHotkeys {
+    connection: Connection,
+    session: OwnedObjectPath,
+}
+
+fn random_token() -> String {
+    let mut rng = rand::thread_rng();
+    let suffix: String = (0..8)
+        .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
+        .collect();
+    format!("danavi_{}", suffix)
+}
+
+// Portal methods answer through the Response signal of a Request object. Its
+// path is predictable from the token, so we subscribe before calling to not
+// miss the answer
+async fn portal_request<B>(
+    connection: &Connection,
+    method: &str,
+    body: &B,
+    token: &str,
+) -> Result<HashMap<String, OwnedValue>>
+where
+    B: serde::Serialize + zvariant::DynamicType,
+{
+    let sender = connection
+        .unique_name()
+        .context("No unique bus name")?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    let request_path = format!("{}/request/{}/{}", PORTAL_PATH, sender, token);
+    let request =
+        zbus::Proxy::new(connection, PORTAL_DESTINATION, request_path, REQUEST_INTERFACE).await?;
+    let mut responses = request.receive_signal("Response").await?;
+
+    let shortcuts =
+        zbus::Proxy::new(connection, PORTAL_DESTINATION, PORTAL_PATH, SHORTCUTS_INTERFACE).await?;
+    shortcuts.call_method(method, body).await?;
+
+    let response = responses
+        .next()
+        .await
+        .context("Portal closed the request")?;
+    let (code, results): (u32, HashMap<String, OwnedValue>) = response.body().deserialize()?;
+    if code != 0 {
+        anyhow::bail!("{} was denied by the desktop portal", method);
+    }
+    Ok(results)
+}
+
+/// Binds `bindings` (trigger -> action name) through the desktop portal and
+/// forwards activations as MPRIS commands. Returns the session and warnings
+/// for bindings that were skipped.
+pub async fn register(
+    bindings: &HashMap<String, String>,
+    commands: mpsc::UnboundedSender<MprisCommand>,
+) -> Result<(GlobalHotkeys, Vec<String>)> {
+    let mut warnings = Vec::new();
+    let mut actions = BTreeMap::new();
+    for (trigger, action) in bindings {
+        match parse_action(action) {
+            Some(command) => {
+                actions.insert(trigger.clone(), (action.clone(), command));
+            }
+            None => warnings.push(format!("Unknown hotkey action '{}' for {}", action, trigger)),
+        }
+    }
+    if actions.is_empty() {
+        anyhow::bail!("No valid global hotkeys configured");
+    }
+
+    let connection = Connection::session().await?;
+
+    let token = random_token();
+    let options = HashMap::from([
+        ("handle_token", Value::from(token.as_str())),
+        ("session_handle_token", Value::from(random_token())),
+    ]);
+    let results = portal_request(&connection, "CreateSession", &(options,), &token).await?;
+    let session: OwnedObjectPath = match results.get("session_handle").map(|v| &**v) {
+        Some(Value::Str(handle)) => ObjectPath::try_from(handle.to_string())?.into(),
+        Some(Value::ObjectPath(handle)) => handle.to_owned().into(),
+        _ => anyhow::bail!("Portal did not return a session handle"),
+    };
+
+    // The trigger doubles as the shortcut id, which keeps it stable across runs
+    let shortcuts: Vec<(String, HashMap<&str, Value>)> = actions
+        .iter()
+        .map(|(trigger, (action, _))| {
+            (
+                trigger.clone(),
+                HashMap::from([
+                    ("description", Value::from(format!("danavi: {}", action))),
+                    ("preferred_trigger", Value::from(trigger.as_str())),
+                ]),
+            )
+        })
+        .collect();
+    let token = random_token();
+    let options = HashMap::from([("handle_token", Value::from(token.as_str()))]);
+    let body = (&session, shortcuts, "", options);
+    portal_request(&connection, "BindShortcuts", &body, &token).await?;
+
+    let proxy =
+        zbus::Proxy::new(&connection, PORTAL_DESTINATION, PORTAL_PATH, SHORTCUTS_INTERFACE).await?;
+    let mut activations = proxy.receive_signal("Activated").await?;
+    let session_path = session.clone();
+    tokio::spawn(async move {
+        while let Some(message) = activations.next().await {
+            let Ok((activated_session, id, _, _)) = message
+                .body()
+                .deserialize::<(OwnedObjectPath, String, u64, HashMap<String, OwnedValue>)>()
+            else {
+                continue;
+            };
+            if activated_session != session_path {
+                continue;
+            }
+            if let Some((_, command)) = actions.get(&id) {
+                let _ = commands.send(command.clone());
+            }
+        }
+    });
+
+    Ok((GlobalHotkeys { connection, session }, warnings))
+}
+
+impl GlobalHotkeys {
+    pub async fn close(self) {
+        if let Ok(proxy) = zbus::Proxy::new(
+            &self.connection,
+            PORTAL_DESTINATION,
+            &self.session,
+            SESSION_INTERFACE,
+        )
+        .await
+        {
+            let _ = proxy.call_method("Close", &()).await;
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use danavi::client::{SubsonicClient, SubsonicError};
+use danavi::types::SimilarArtist;
+use std::future::Future;
+use tokio::sync::oneshot;
+
+/// What the overlay shows, whether about an artist or an album.
+#[derive(Debug, Default)]
+pub struct Info {
+    pub text: Option<String>,
+    pub link: Option<String>,
+    pub similar: Vec<SimilarArtist>,
+}
+
+impl Info {
+    fn is_empty(&self) -> bool {
+        self.text.is_none() && self.link.is_none() && self.similar.is_empty()
+    }
+}
+
+type Fetched = Result<Option<Info>, String>;
+
+/// The artist or album info overlay: biography or notes, the Last.fm link,
+/// and for an artist, similar artists.
+pub struct InfoPanel {
+    pub title: String,
+    /// `None` while loading, `Ok(None)` when the server has nothing
+    pub info: Option<Fetched>,
+    pending: Option<oneshot::Receiver<Fetched>>,
+    /// Selected similar artist
+    pub selected: usize,
+    /// First line of text shown
+    pub scroll: usize,
+    /// Lines of text that don't fit, as of the last draw
+    pub overflow: usize,
+}
+
+impl InfoPanel {
+    /// Starts loading an artist's biography and similar artists.
+    pub fn artist(client: &SubsonicClient, artist_id: &str, name: &str) -> Self {
+        let client = client.clone();
+        let id = artist_id.to_string();
+        Self::open(format!("About {}", name), async move {
+            let info = client.get_artist_info2(&id).await?.artist_info2;
+            Ok(Info {
+                text: info.biography_text(),
+                link: info.last_fm_url,
+                similar: info.similar_artist,
+            })
+        })
+    }
+
+    /// Starts loading an album's notes.
+    pub fn album(client: &SubsonicClient, album_id: &str, name: &str) -> Self {
+        let client = client.clone();
+        let id = album_id.to_string();
+        Self::open(format!("Notes on {}", name), async move {
+            let info = client.get_album_info2(&id).await?.album_info;
+            Ok(Info {
+                text: info.notes_text(),
+                link: info.last_fm_url,
+                similar: Vec::new(),
+            })
+        })
+    }
+
+    fn open<F>(title: String, fetch: F) -> Self
+    where
+        F: Future<Output = anyhow::Result<Info>> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let fetched = match fetch.await {
+                Ok(info) => Ok((!info.is_empty()).then_some(info)),
+                // Servers without the info answer with an API error
+                Err(e) if e.downcast_ref::<SubsonicError>().is_some() => {
+                    log::debug!("No info available: {}", e);
+                    Ok(None)
+                }
+                Err(e) => Err(e.to_string()),
+            };
+            let _ = tx.send(fetched);
+        });
+        Self {
+            title,
+            info: None,
+            pending: Some(rx),
+            selected: 0,
+            scroll: 0,
+            overflow: 0,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        if let Some(rx) = self.pending.as_mut()
+            && let Ok(result) = rx.try_recv()
+        {
+            self.info = Some(result);
+            self.pending = None;
+        }
+    }
+
+    fn similar_count(&self) -> usize {
+        match &self.info {
+            Some(Ok(Some(info))) => info.similar.len(),
+            _ => 0,
+        }
+    }
+
+    pub fn move_selection(&mut self, down: bool) {
+        let last = self.similar_count().saturating_sub(1);
+        self.selected = if down {
+            (self.selected + 1).min(last)
+        } else {
+            self.selected.saturating_sub(1)
+        };
+    }
+
+    pub fn scroll_by(&mut self, lines: isize) {
+        self.scroll = self.scroll.saturating_add_signed(lines).min(self.overflow);
+    }
+
+    /// The selected similar artist's id and name; no id when they aren't
+    /// in the library.
+    pub fn selected_artist(&self) -> Option<(Option<String>, String)> {
+        let Some(Ok(Some(info))) = &self.info else {
+            return None;
+        };
+        let artist = info.similar.get(self.selected)?;
+        Some((artist.library_id().map(str::to_string), artist.name.clone()))
+    }
+}
+
+/// Breaks `text` into lines of at most `width` columns, at spaces where
+/// possible. Blank lines are kept.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word = word;
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            // Words too long for a line of their own are cut
+            while word.chars().count() > width {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                let cut = word.char_indices().nth(width).map_or(word.len(), |(at, _)| at);
+                lines.push(word[..cut].to_string());
+                word = &word[cut..];
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
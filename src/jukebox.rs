@@ -0,0 +1,288 @@
+use anyhow::Result;
+use danavi::client::{JukeboxAction, SubsonicClient};
+use danavi::types::{JukeboxStatus, Song};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+// How often the server's player is asked where it is
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// The server takes at most this many songs, the playing one included
+const MAX_SONGS: usize = 200;
+// Waits between tries at updating the playlist after one fails
+const MIN_SYNC_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_SYNC_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Something the server's player did on its own, found by polling.
+pub enum JukeboxEvent {
+    /// Moved on this many songs into the queue
+    Advanced(usize),
+    /// Played out the last song
+    Finished,
+    /// Paused, or resumed, from another client
+    Paused(bool),
+}
+
+/// Playback on the server's own audio output instead of the local player.
+/// The server's playlist is the playing song followed by the queue, kept in
+/// step as the queue changes.
+pub struct Jukebox {
+    client: SubsonicClient,
+    // Ids on the server's playlist and where it's at in them
+    playlist: Vec<String>,
+    index: usize,
+    paused: bool,
+    // Seen playing since the last `play`, so that stopping means the end
+    started: bool,
+    status: Option<(JukeboxStatus, Instant)>,
+    // Bumped by every action, so a poll sent before it is ignored
+    generation: Arc<AtomicU64>,
+    statuses: mpsc::UnboundedReceiver<(u64, Result<JukeboxStatus, String>)>,
+    poller: JoinHandle<()>,
+    // Set while updating the playlist is failing
+    sync_retry: Option<(Instant, Duration)>,
+    /// Why the last poll failed, until one works again
+    pub error: Option<String>,
+}
+
+impl Jukebox {
+    /// Checks that the account may use the jukebox, then starts following it.
+    pub async fn start(client: &SubsonicClient) -> Result<Self> {
+        let status = client.jukebox_control(JukeboxAction::Status).await?;
+        let generation = Arc::new(AtomicU64::new(0));
+        let (tx, statuses) = mpsc::unbounded_channel();
+        let poller = {
+            let client = client.clone();
+            let generation = generation.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                    let sent = generation.load(Ordering::SeqCst);
+                    let status = client
+                        .jukebox_control(JukeboxAction::Status)
+                        .await
+                        .map_err(|e| e.to_string());
+                    if tx.send((sent, status)).is_err() {
+                        break;
+                    }
+                }
+            })
+        };
+        Ok(Self {
+            client: client.clone(),
+            playlist: Vec::new(),
+            index: 0,
+            paused: false,
+            started: false,
+            status: Some((status, Instant::now())),
+            generation,
+            statuses,
+            poller,
+            sync_retry: None,
+            error: None,
+        })
+    }
+
+    async fn act(&mut self, action: JukeboxAction<'_>) -> Result<()> {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        let status = self.client.jukebox_control(action).await?;
+        self.status = Some((status, Instant::now()));
+        Ok(())
+    }
+
+    /// Plays `song` on the server, with the queue lined up after it.
+    pub async fn play(&mut self, song: &Song, queue: &[Song]) -> Result<()> {
+        let ids: Vec<String> = std::iter::once(song)
+            .chain(queue)
+            .take(MAX_SONGS)
+            .map(|s| s.id.clone())
+            .collect();
+        self.act(JukeboxAction::Set(&ids)).await?;
+        self.act(JukeboxAction::Skip { index: 0, offset: 0 }).await?;
+        self.act(JukeboxAction::Start).await?;
+        self.playlist = ids;
+        self.index = 0;
+        self.paused = false;
+        self.started = false;
+        Ok(())
+    }
+
+    /// Brings the server's playlist after the playing song in line with
+    /// the queue: added songs go on the end, anything else replaces it.
+    /// After a failure it waits longer between tries, and only the first
+    /// failure in a row is returned.
+    pub async fn sync_queue(&mut self, queue: &[Song]) -> Result<()> {
+        if self.sync_retry.is_some_and(|(at, _)| Instant::now() < at) {
+            return Ok(());
+        }
+        match self.update_playlist(queue).await {
+            Ok(()) => {
+                self.sync_retry = None;
+                Ok(())
+            }
+            Err(e) => {
+                let failing = self.sync_retry.is_some();
+                let backoff = self
+                    .sync_retry
+                    .map_or(MIN_SYNC_BACKOFF, |(_, backoff)| (backoff * 2).min(MAX_SYNC_BACKOFF));
+                self.sync_retry = Some((Instant::now() + backoff, backoff));
+                if failing {
+                    log::debug!("Jukebox playlist still not updated: {}", e);
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    async fn update_playlist(&mut self, queue: &[Song]) -> Result<()> {
+        if !self.has_song() {
+            return Ok(());
+        }
+        let wanted: Vec<String> = queue
+            .iter()
+            .take(MAX_SONGS - 1)
+            .map(|s| s.id.clone())
+            .collect();
+        let upcoming = &self.playlist[self.index + 1..];
+        if wanted == upcoming {
+            return Ok(());
+        }
+        if wanted.starts_with(upcoming) {
+            let added = wanted[upcoming.len()..].to_vec();
+            self.act(JukeboxAction::Add(&added)).await?;
+            self.playlist.extend(added);
+            return Ok(());
+        }
+
+        let current = self.playlist[self.index].clone();
+        let position = self.position().as_secs();
+        let ids: Vec<String> = std::iter::once(current).chain(wanted).collect();
+        self.act(JukeboxAction::Set(&ids)).await?;
+        self.playlist = ids;
+        self.index = 0;
+        // Setting the playlist may start the song over, so it's put back
+        self.act(JukeboxAction::Skip { index: 0, offset: position }).await?;
+        if self.paused {
+            self.act(JukeboxAction::Stop).await?;
+        }
+        Ok(())
+    }
+
+    /// Pauses or resumes. Returns whether it's paused now.
+    pub async fn toggle_pause(&mut self) -> Result<bool> {
+        if self.paused {
+            self.act(JukeboxAction::Start).await?;
+        } else {
+            self.act(JukeboxAction::Stop).await?;
+        }
+        self.paused = !self.paused;
+        Ok(self.paused)
+    }
+
+    /// Stops the server's player, leaving nothing playing.
+    pub async fn stop(&mut self) -> Result<()> {
+        self.playlist.clear();
+        self.index = 0;
+        self.act(JukeboxAction::Stop).await
+    }
+
+    pub async fn seek(&mut self, position: Duration) -> Result<()> {
+        let index = self.index;
+        self.act(JukeboxAction::Skip {
+            index,
+            offset: position.as_secs(),
+        })
+        .await
+    }
+
+    pub async fn set_gain(&mut self, volume: f64) -> Result<()> {
+        self.act(JukeboxAction::SetGain(volume as f32)).await
+    }
+
+    /// For the status bar.
+    pub fn label(&self) -> String {
+        match &self.error {
+            Some(e) => format!("jukebox ({})", e),
+            None => "jukebox".to_string(),
+        }
+    }
+
+    pub fn has_song(&self) -> bool {
+        self.index < self.playlist.len()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Where the server is in the playing song, moved on by the time since
+    /// it last said.
+    pub fn position(&self) -> Duration {
+        let Some((status, at)) = &self.status else {
+            return Duration::ZERO;
+        };
+        let reported = Duration::from_secs(status.position.unwrap_or(0) as u64);
+        if status.playing && !self.paused {
+            reported + at.elapsed()
+        } else {
+            reported
+        }
+    }
+
+    /// Picks up the server's player moving on, stopping, or being paused or
+    /// resumed elsewhere.
+    pub fn poll(&mut self) -> Vec<JukeboxEvent> {
+        let mut events = Vec::new();
+        while let Ok((sent, status)) = self.statuses.try_recv() {
+            let status = match status {
+                Ok(status) => status,
+                Err(e) => {
+                    self.error = Some(e);
+                    continue;
+                }
+            };
+            self.error = None;
+            if sent != self.generation.load(Ordering::SeqCst) {
+                continue;
+            }
+            self.status = Some((status, Instant::now()));
+            if !self.has_song() {
+                continue;
+            }
+            if let Ok(index) = usize::try_from(status.current_index)
+                && index > self.index
+                && index < self.playlist.len()
+            {
+                events.push(JukeboxEvent::Advanced(index - self.index));
+                self.index = index;
+            }
+            if status.playing {
+                self.started = true;
+                if self.paused {
+                    self.paused = false;
+                    events.push(JukeboxEvent::Paused(false));
+                }
+            } else if self.started && !self.paused {
+                if self.index + 1 >= self.playlist.len() || status.current_index < 0 {
+                    self.playlist.clear();
+                    self.index = 0;
+                    events.push(JukeboxEvent::Finished);
+                } else {
+                    self.paused = true;
+                    events.push(JukeboxEvent::Paused(true));
+                }
+            }
+        }
+        events
+    }
+}
+
+impl Drop for Jukebox {
+    fn drop(&mut self) {
+        self.poller.abort();
+    }
+}
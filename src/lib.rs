@@ -0,0 +1,39 @@
+//! Subsonic/Navidrome client and playback building blocks used by the
+//! danavi terminal player.
+//!
+//! The TUI, MPRIS, MPD and hotkey integrations live in the binary; this
+//! crate exposes the parts that are useful on their own:
+//!
+//! - [`client`]: an async Subsonic API client
+//! - `demo`: a sample library served in-process (with the `demo` feature)
+//! - [`types`]: the library model ([`types::Song`] etc.) and API responses
+//! - [`generation`]: dropping responses to superseded requests
+//! - [`config`]: loading and saving `~/.config/danavi/config.json`
+//! - [`audio`]: a rodio-backed player for tracks as they stream in, gapless
+//!   and with fades
+//! - [`queue`]: what plays next, in order or shuffled
+//! - [`bus_name`]: picking a unique MPRIS bus name per instance
+//! - [`cache`]: songs downloaded for playing without the server, and cover art
+//! - [`loudness`]: evening out loudness between tracks
+//! - [`lyrics`]: synced and plain lyrics, including LRC
+//! - [`m3u`]: reading and writing M3U playlists
+//! - [`mpd_protocol`]: the MPD protocol's command lines, songs and status
+//! - [`smart`]: rule-based smart playlists
+//! - [`sorting`]: sorting artists without their leading articles
+
+pub mod audio;
+pub mod bus_name;
+pub mod cache;
+pub mod client;
+pub mod config;
+#[cfg(feature = "demo")]
+pub mod demo;
+pub mod generation;
+pub mod loudness;
+pub mod lyrics;
+pub mod m3u;
+pub mod mpd_protocol;
+pub mod queue;
+pub mod smart;
+pub mod sorting;
+pub mod types;
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use danavi::cache::MetadataCache;
+use danavi::types::{Album, Artist, Song};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use tokio::sync::mpsc;
+
+/// A listing fetched again in the background after the cached copy was
+/// shown, to be swapped in if it's still on screen.
+pub enum Refreshed {
+    Artists(Vec<Artist>),
+    Albums { artist_id: String, albums: Vec<Album> },
+    Songs { album_id: String, songs: Vec<Song> },
+}
+
+/// The metadata cache in front of the artist, album and song lists.
+pub struct Listings {
+    cache: MetadataCache,
+    tx: mpsc::UnboundedSender<Refreshed>,
+    rx: mpsc::UnboundedReceiver<Refreshed>,
+}
+
+fn store<T: Serialize>(cache: &MetadataCache, endpoint: &str, id: &str, value: &T) {
+    if let Err(e) = cache.store(endpoint, id, value) {
+        log::warn!("Failed to cache {} {}: {:#}", endpoint, id, e);
+    }
+}
+
+impl Listings {
+    pub fn new(cache: MetadataCache) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self { cache, tx, rx }
+    }
+
+    /// The cached listing for `endpoint` and `id`, fetched again in the
+    /// background when it's stale, or else `fetch`ed now and kept.
+    pub async fn load<T, F>(
+        &self,
+        endpoint: &'static str,
+        id: &str,
+        fetch: F,
+        refreshed: impl FnOnce(T) -> Refreshed + Send + 'static,
+    ) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        match self.cache.get(endpoint, id) {
+            Some((value, true)) => Ok(value),
+            Some((value, false)) => {
+                let cache = self.cache.clone();
+                let tx = self.tx.clone();
+                let id = id.to_string();
+                tokio::spawn(async move {
+                    match fetch.await {
+                        Ok(fetched) => {
+                            store(&cache, endpoint, &id, &fetched);
+                            let _ = tx.send(refreshed(fetched));
+                        }
+                        Err(e) => log::debug!("Failed to refresh {} {}: {}", endpoint, id, e),
+                    }
+                });
+                Ok(value)
+            }
+            None => {
+                let value = fetch.await?;
+                store(&self.cache, endpoint, id, &value);
+                Ok(value)
+            }
+        }
+    }
+
+    pub fn refreshed(&mut self) -> Option<Refreshed> {
+        self.rx.try_recv().ok()
+    }
+
+    pub fn store<T: Serialize>(&self, endpoint: &str, id: &str, value: &T) {
+        store(&self.cache, endpoint, id, value);
+    }
+
+    /// Drops one listing, so the next load goes to the server.
+    pub fn forget(&self, endpoint: &str, id: &str) {
+        self.cache.remove(endpoint, id);
+    }
+
+    /// Drops every listing from `endpoint`, for a change that may show up in
+    /// any of them.
+    pub fn forget_all(&self, endpoint: &str) {
+        self.cache.remove_all(endpoint);
+    }
+
+    /// Drops every listing, for when the library has changed under them.
+    pub fn clear(&self) {
+        if let Err(e) = self.cache.clear() {
+            log::warn!("{:#}", e);
+        }
+    }
+}
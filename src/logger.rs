@@ -0,0 +1,31 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use tokio::sync::mpsc;
+
+/// Sends records from our own crates to the message log. Dependencies are
+/// left out; they are far too chatty for it.
+struct MessageLogger {
+    tx: mpsc::UnboundedSender<String>,
+}
+
+impl Log for MessageLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info && metadata.target().starts_with("danavi")
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let _ = self.tx.send(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+pub fn init() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let logger: &'static MessageLogger = Box::leak(Box::new(MessageLogger { tx }));
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+    rx
+}
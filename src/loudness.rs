@@ -0,0 +1,174 @@
+//! Loudness normalization: evening out the volume between tracks from the
+//! audio itself, for libraries without ReplayGain tags.
+
+use rodio::Source;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+// Quiet tracks are boosted by no more than this, +12 dB
+const MAX_GAIN: f32 = 4.0;
+// How much of a track is heard before a running estimate is acted on
+const SETTLE: Duration = Duration::from_secs(2);
+// Samples between updates of a running estimate
+const UPDATE_EVERY: u64 = 1024;
+// Share of the way to the wanted gain each update moves, so the level
+// glides instead of jumping
+const GLIDE: f32 = 0.02;
+
+/// How loud some audio is: the mean square and peak of its samples.
+///
+/// The level is plain RMS in dBFS standing in for LUFS. There's no
+/// K-weighting or gating, but that's close enough to even out a queue.
+///
+/// ```
+/// use danavi::loudness::Loudness;
+/// use rodio::Source;
+/// use rodio::source::SineWave;
+/// use std::time::Duration;
+///
+/// let sine = SineWave::new(440.0).amplify(0.5).take_duration(Duration::from_secs(1));
+/// let loudness = Loudness::measure(sine);
+/// // A sine's RMS is 3 dB under its peak
+/// assert!((loudness.level().unwrap() + 9.03).abs() < 0.1);
+/// assert!((loudness.gain_to(-14.0) - 0.56).abs() < 0.01);
+/// // Brought no further than the loudest sample allows
+/// assert!((loudness.gain_to(0.0) - 2.0).abs() < 0.01);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Loudness {
+    sum_squares: f64,
+    samples: u64,
+    peak: f32,
+}
+
+impl Loudness {
+    /// Reads `samples` to the end.
+    pub fn measure(samples: impl IntoIterator<Item = f32>) -> Self {
+        let mut loudness = Self::default();
+        for sample in samples {
+            loudness.add(sample);
+        }
+        loudness
+    }
+
+    fn add(&mut self, sample: f32) {
+        self.sum_squares += (sample as f64).powi(2);
+        self.samples += 1;
+        self.peak = self.peak.max(sample.abs());
+    }
+
+    /// RMS level in dBFS, if there was anything to measure.
+    pub fn level(&self) -> Option<f32> {
+        (self.samples > 0).then(|| (10.0 * (self.sum_squares / self.samples as f64).log10()) as f32)
+    }
+
+    /// The gain that brings the level to `target`, held down so the peak
+    /// doesn't clip and quiet tracks aren't boosted into noise.
+    pub fn gain_to(&self, target: f32) -> f32 {
+        let Some(level) = self.level() else {
+            return 1.0;
+        };
+        let gain = 10f32.powf((target - level) / 20.0).min(MAX_GAIN);
+        if self.peak > 0.0 { gain.min(1.0 / self.peak) } else { gain }
+    }
+}
+
+/// A whole track's loudness, filled in once measuring it is done.
+pub type Measurement = Arc<OnceLock<Loudness>>;
+
+/// A source played at the gain that brings it to a target level. Given a
+/// measurement of the whole track the gain is fixed; until one arrives it
+/// follows a running estimate of what has played so far.
+///
+/// ```
+/// use danavi::loudness::{Leveler, Loudness, Measurement};
+/// use rodio::Source;
+/// use rodio::source::SineWave;
+/// use std::time::Duration;
+///
+/// let sine = || SineWave::new(440.0).amplify(0.5).take_duration(Duration::from_secs(4));
+/// let measured = Measurement::default();
+/// let mut leveler = Leveler::new(sine(), Some(-14.0), measured.clone());
+/// // Nothing to go on yet
+/// assert_eq!(leveler.next(), sine().next());
+///
+/// // A measurement arriving mid-track is glided to
+/// measured.set(Loudness::measure(sine())).unwrap();
+/// let played: Vec<f32> = leveler.collect();
+/// let tail = played[played.len() - 1000..].iter().map(|s| s.abs()).fold(0.0, f32::max);
+/// assert!((tail / 0.5 - 0.56).abs() < 0.03);
+/// ```
+pub struct Leveler<S> {
+    inner: S,
+    target: Option<f32>,
+    measured: Measurement,
+    running: Loudness,
+    settle: u64,
+    played: u64,
+    gain: f32,
+}
+
+impl<S: Source<Item = f32>> Leveler<S> {
+    /// Levels `inner` to `target` dBFS, or passes it through untouched
+    /// without one.
+    pub fn new(inner: S, target: Option<f32>, measured: Measurement) -> Self {
+        let settle = SETTLE.as_secs() * inner.sample_rate() as u64 * inner.channels() as u64;
+        let gain = match (target, measured.get()) {
+            (Some(target), Some(measured)) => measured.gain_to(target),
+            _ => 1.0,
+        };
+        Self {
+            inner,
+            target,
+            measured,
+            running: Loudness::default(),
+            settle,
+            played: 0,
+            gain,
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Leveler<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        if let Some(target) = self.target {
+            self.played += 1;
+            let wanted = match self.measured.get() {
+                Some(measured) => Some(measured.gain_to(target)),
+                None => {
+                    self.running.add(sample);
+                    // Never let a new peak clip, however far the glide has to go
+                    self.gain = self.gain.min(1.0 / self.running.peak.max(f32::EPSILON));
+                    (self.running.samples >= self.settle).then(|| self.running.gain_to(target))
+                }
+            };
+            if let Some(wanted) = wanted
+                && self.played.is_multiple_of(UPDATE_EVERY)
+            {
+                self.gain += (wanted - self.gain) * GLIDE;
+            }
+        }
+        Some(sample * self.gain)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Leveler<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
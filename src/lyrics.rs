@@ -0,0 +1,200 @@
+//! Lyrics as shown next to the playing song, synced or not.
+
+use crate::types::StructuredLyrics;
+use std::time::Duration;
+
+/// Lyrics ready to display.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lyrics {
+    /// Lines with the time each one starts, in order
+    Synced(Vec<(Duration, String)>),
+    /// Lines without times
+    Plain(Vec<String>),
+}
+
+impl Lyrics {
+    /// Picks the best of the server's lyrics: synced over unsynced. Unsynced
+    /// text that is really LRC gets parsed as such, and "synced" lyrics
+    /// without start times are taken as plain.
+    ///
+    /// ```
+    /// use danavi::lyrics::Lyrics;
+    /// use danavi::types::StructuredLyrics;
+    ///
+    /// let all: Vec<StructuredLyrics> = serde_json::from_str(
+    ///     r#"[{ "synced": true, "line": [{ "value": "No times" }, { "value": "here" }] }]"#,
+    /// )?;
+    /// let lyrics = Lyrics::from_structured(&all).unwrap();
+    /// assert_eq!(lyrics, Lyrics::Plain(vec!["No times".to_string(), "here".to_string()]));
+    /// # Ok::<(), serde_json::Error>(())
+    /// ```
+    pub fn from_structured(all: &[StructuredLyrics]) -> Option<Lyrics> {
+        let timed = |l: &&StructuredLyrics| l.synced && l.line.iter().all(|line| line.start.is_some());
+        let best = all.iter().find(timed).or_else(|| all.first())?;
+        let offset = best.offset.unwrap_or(0);
+        if timed(&best) {
+            let lines = best
+                .line
+                .iter()
+                .map(|line| (shifted(line.start.unwrap_or(0), offset), decode_entities(&line.value)))
+                .collect();
+            return Some(Lyrics::Synced(sorted(lines)));
+        }
+        let text: Vec<&str> = best.line.iter().map(|l| l.value.as_str()).collect();
+        Some(Lyrics::from_text(&text.join("\n")))
+    }
+
+    /// Plain lyrics text, which may be LRC and may have HTML entities.
+    pub fn from_text(text: &str) -> Lyrics {
+        let text = decode_entities(text);
+        match parse_lrc(&text) {
+            Some(lines) => Lyrics::Synced(lines),
+            None => Lyrics::Plain(text.lines().map(str::to_string).collect()),
+        }
+    }
+
+    /// Index of the line being sung at `position`, for synced lyrics.
+    pub fn current_line(&self, position: Duration) -> Option<usize> {
+        let Lyrics::Synced(lines) = self else {
+            return None;
+        };
+        lines.iter().rposition(|(start, _)| *start <= position)
+    }
+
+    /// The number of lines.
+    pub fn len(&self) -> usize {
+        match self {
+            Lyrics::Synced(lines) => lines.len(),
+            Lyrics::Plain(lines) => lines.len(),
+        }
+    }
+
+    /// Whether there are no lines at all.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Replaces the HTML entities some servers leave in lyrics scraped from the
+/// web. Unknown ones are kept as they are.
+///
+/// ```
+/// use danavi::lyrics::decode_entities;
+///
+/// assert_eq!(decode_entities("Rock &amp; roll, &quot;baby&quot;"), "Rock & roll, \"baby\"");
+/// assert_eq!(decode_entities("It&#39;s &#x263A; &nbsp;&bogus; &"), "It's \u{263A} \u{a0}&bogus; &");
+/// ```
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        decoded.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 8)
+            .and_then(|end| Some((entity(&rest[1..=end])?, end + 2)));
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// The character for an entity's name, without the & and ;
+fn entity(name: &str) -> Option<char> {
+    let code = match name {
+        "amp" => return Some('&'),
+        "lt" => return Some('<'),
+        "gt" => return Some('>'),
+        "quot" => return Some('"'),
+        "apos" => return Some('\''),
+        "nbsp" => return Some('\u{a0}'),
+        _ => name.strip_prefix('#')?,
+    };
+    let value = match code.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => code.parse().ok()?,
+    };
+    char::from_u32(value)
+}
+
+// Offsets are positive when lines should show sooner
+fn shifted(start_ms: i64, offset_ms: i64) -> Duration {
+    Duration::from_millis((start_ms - offset_ms).max(0) as u64)
+}
+
+fn sorted(mut lines: Vec<(Duration, String)>) -> Vec<(Duration, String)> {
+    lines.sort_by_key(|(start, _)| *start);
+    lines
+}
+
+// "mm:ss", "mm:ss.xx" or "mm:ss.xxx" in milliseconds
+fn parse_timestamp(tag: &str) -> Option<i64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: i64 = minutes.trim().parse().ok()?;
+    let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+    let whole: i64 = whole.trim().parse().ok()?;
+    if !fraction.chars().all(|c| c.is_ascii_digit()) || fraction.is_empty() {
+        return None;
+    }
+    // Pad or cut to milliseconds: ".5" is 500, ".05" is 50
+    let millis: i64 = format!("{:0<3}", &fraction[..fraction.len().min(3)]).parse().ok()?;
+    Some((minutes * 60 + whole) * 1000 + millis)
+}
+
+/// Parses LRC text into timed lines, or `None` if it has no timestamps.
+/// Lines with several timestamps are repeated at each, `[offset:±ms]`
+/// is applied and other tags (`[ar:...]` etc.) are skipped.
+///
+/// ```
+/// use danavi::lyrics::parse_lrc;
+/// use std::time::Duration;
+///
+/// let lines = parse_lrc("[offset:+500]\n[00:01.50]Hello\n[00:03.00][00:05.00]Again").unwrap();
+/// assert_eq!(
+///     lines,
+///     vec![
+///         (Duration::from_millis(1000), "Hello".to_string()),
+///         (Duration::from_millis(2500), "Again".to_string()),
+///         (Duration::from_millis(4500), "Again".to_string()),
+///     ]
+/// );
+/// ```
+pub fn parse_lrc(text: &str) -> Option<Vec<(Duration, String)>> {
+    let mut offset = 0;
+    let mut timed = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut starts = Vec::new();
+        while let Some(tag) = rest.strip_prefix('[')
+            && let Some((tag, after)) = tag.split_once(']')
+        {
+            if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().trim_start_matches('+').parse().unwrap_or(0);
+            } else if let Some(start) = parse_timestamp(tag) {
+                starts.push(start);
+            }
+            rest = after;
+        }
+        for start in starts {
+            timed.push((start, rest.trim().to_string()));
+        }
+    }
+    if timed.is_empty() {
+        return None;
+    }
+    let lines = timed
+        .into_iter()
+        .map(|(start, text)| (shifted(start, offset), text))
+        .collect();
+    Some(sorted(lines))
+}
@@ -0,0 +1,131 @@
+use crate::mpris;
+use danavi::client::SubsonicClient;
+use danavi::lyrics::Lyrics;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// How far one press of [ or ] moves the lyrics
+const NUDGE_MS: i64 = 500;
+
+type Fetched = Result<Option<Lyrics>, String>;
+
+/// The lyrics overlay, following whatever is playing.
+pub struct LyricsPanel {
+    pub title: Option<String>,
+    song_id: Option<String>,
+    /// `None` while loading, `Ok(None)` when the song has no lyrics
+    pub lyrics: Option<Fetched>,
+    pending: Option<oneshot::Receiver<Fetched>>,
+    /// Added to every line's start time, for lyrics that run early or late
+    pub offset_ms: i64,
+    /// First line shown for unsynced lyrics
+    pub scroll: usize,
+    /// Lines that fit, as of the last draw
+    pub page: usize,
+}
+
+async fn fetch(client: &SubsonicClient, song: &mpris::Song) -> Fetched {
+    let supported = client.capabilities().await.is_ok_and(|c| c.synced_lyrics());
+
+    // Songs the extension has nothing for may still have plain lyrics
+    if supported {
+        let response = client
+            .get_lyrics_by_song_id(&song.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let lyrics = Lyrics::from_structured(&response.lyrics_list.structured_lyrics)
+            .filter(|lyrics| !lyrics.is_empty());
+        if lyrics.is_some() {
+            return Ok(lyrics);
+        }
+    }
+    let Some(artist) = &song.artist else {
+        return Ok(None);
+    };
+    let response = client
+        .get_lyrics(artist, &song.title)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(response
+        .lyrics
+        .value
+        .filter(|text| !text.trim().is_empty())
+        .map(|text| Lyrics::from_text(&text)))
+}
+
+impl LyricsPanel {
+    pub fn new() -> Self {
+        Self {
+            title: None,
+            song_id: None,
+            lyrics: None,
+            pending: None,
+            offset_ms: 0,
+            scroll: 0,
+            page: 0,
+        }
+    }
+
+    /// Starts loading the lyrics of `song` if it isn't the one shown.
+    pub fn follow(&mut self, client: &SubsonicClient, song: Option<&mpris::Song>) {
+        if self.song_id.as_deref() == song.map(|s| s.id.as_str()) {
+            return;
+        }
+        self.song_id = song.map(|s| s.id.clone());
+        self.title = song.map(|s| s.title.clone());
+        self.lyrics = None;
+        self.offset_ms = 0;
+        self.scroll = 0;
+        self.pending = None;
+
+        let Some(song) = song.cloned() else {
+            return;
+        };
+        let (tx, rx) = oneshot::channel();
+        let client = client.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(fetch(&client, &song).await);
+        });
+        self.pending = Some(rx);
+    }
+
+    pub fn poll(&mut self) {
+        if let Some(rx) = self.pending.as_mut()
+            && let Ok(result) = rx.try_recv()
+        {
+            self.lyrics = Some(result);
+            self.pending = None;
+        }
+    }
+
+    pub fn playing(&self) -> bool {
+        self.song_id.is_some()
+    }
+
+    /// Scrolls unsynced lyrics by `lines`, stopping with the last line at
+    /// the bottom.
+    pub fn scroll_by(&mut self, lines: isize) {
+        let Some(Ok(Some(lyrics))) = &self.lyrics else {
+            return;
+        };
+        let last = lyrics.len().saturating_sub(self.page);
+        self.scroll = self.scroll.saturating_add_signed(lines).min(last);
+    }
+
+    /// Shifts synced lyrics half a second later, or earlier.
+    pub fn nudge(&mut self, later: bool) {
+        self.offset_ms += if later { NUDGE_MS } else { -NUDGE_MS };
+    }
+
+    /// The line being sung at `position`, with the nudge applied.
+    pub fn current_line(&self, position: Duration) -> Option<usize> {
+        let Some(Ok(Some(lyrics))) = &self.lyrics else {
+            return None;
+        };
+        let position = position.as_millis() as i64 - self.offset_ms;
+        if position < 0 {
+            return None;
+        }
+        lyrics.current_line(Duration::from_millis(position as u64))
+    }
+}
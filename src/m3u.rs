@@ -0,0 +1,213 @@
+//! Reading and writing M3U/M3U8 playlists.
+
+use crate::types::Song;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = "#EXTM3U";
+
+// Titles end up on a single #EXTINF line, so anything that would start a new
+// line has to go
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn display_name(song: &Song) -> String {
+    match &song.artist {
+        Some(artist) => format!("{} - {}", sanitize(artist), sanitize(&song.title)),
+        None => sanitize(&song.title),
+    }
+}
+
+/// Builds an extended M3U playlist. `locate` returns the location line for a
+/// song; songs it returns `None` for are left out and counted as skipped.
+///
+/// What's written reads back the same with [`parse_m3u`]:
+///
+/// ```
+/// use danavi::m3u::{format_m3u, parse_m3u};
+/// use danavi::types::Song;
+///
+/// let songs = vec![
+///     Song {
+///         id: "1".to_string(),
+///         title: "Teardrop".to_string(),
+///         artist: Some("Massive Attack".to_string()),
+///         duration: Some(329),
+///         ..Default::default()
+///     },
+///     Song {
+///         id: "2".to_string(),
+///         title: "Untitled\nNo. 3".to_string(),
+///         ..Default::default()
+///     },
+///     Song { id: "3".to_string(), title: "Elsewhere".to_string(), ..Default::default() },
+/// ];
+/// let (content, skipped) = format_m3u(&songs, |song| {
+///     (song.id != "3").then(|| format!("https://music.example/rest/stream?id={}", song.id))
+/// });
+/// assert_eq!(skipped, 1);
+///
+/// let entries = parse_m3u(&content);
+/// assert_eq!(entries.len(), 2);
+/// assert_eq!(entries[0].duration, Some(329));
+/// assert_eq!(entries[0].artist.as_deref(), Some("Massive Attack"));
+/// assert_eq!(entries[0].title.as_deref(), Some("Teardrop"));
+/// assert_eq!(entries[0].location, "https://music.example/rest/stream?id=1");
+/// // No length is written as -1 and read as unknown; control characters
+/// // can't break the title onto another line
+/// assert_eq!(entries[1].duration, None);
+/// assert_eq!(entries[1].artist, None);
+/// assert_eq!(entries[1].title.as_deref(), Some("Untitled No. 3"));
+/// assert_eq!(danavi::m3u::stream_song_id(&entries[1].location).as_deref(), Some("2"));
+/// ```
+pub fn format_m3u<F>(songs: &[Song], mut locate: F) -> (String, usize)
+where
+    F: FnMut(&Song) -> Option<String>,
+{
+    let mut content = format!("{}\n", HEADER);
+    let mut skipped = 0;
+
+    for song in songs {
+        let Some(location) = locate(song) else {
+            skipped += 1;
+            continue;
+        };
+        content.push_str(&format!(
+            "#EXTINF:{},{}\n{}\n",
+            song.duration.unwrap_or(-1),
+            display_name(song),
+            sanitize(&location)
+        ));
+    }
+
+    (content, skipped)
+}
+
+/// Writes playlist content, creating parent directories as needed.
+pub fn write_m3u(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent).context("Failed to create playlist directory")?;
+    }
+    fs::write(path, content.as_bytes()).context("Failed to write playlist file")?;
+    Ok(())
+}
+
+/// One track in an M3U playlist, with whatever `#EXTINF` told us about it.
+#[derive(Debug, Clone)]
+pub struct M3uEntry {
+    /// 1-based, of the location line
+    pub line: usize,
+    /// Seconds, when not given as -1
+    pub duration: Option<i64>,
+    /// Before the " - " in the #EXTINF name, when there is one
+    pub artist: Option<String>,
+    /// The rest of the #EXTINF name
+    pub title: Option<String>,
+    /// A path or URL, as written
+    pub location: String,
+}
+
+fn parse_extinf(info: &str) -> (Option<i64>, Option<String>, Option<String>) {
+    let (duration, name) = info.split_once(',').unwrap_or((info, ""));
+    // Some writers put attributes after the duration (`-1 tvg-id="..."`)
+    let duration = duration
+        .split_whitespace()
+        .next()
+        .and_then(|d| d.parse::<i64>().ok())
+        .filter(|d| *d >= 0);
+    let name = name.trim();
+    if name.is_empty() {
+        return (duration, None, None);
+    }
+    match name.split_once(" - ") {
+        Some((artist, title)) => (
+            duration,
+            Some(artist.trim().to_string()),
+            Some(title.trim().to_string()),
+        ),
+        None => (duration, None, Some(name.to_string())),
+    }
+}
+
+/// Parses M3U/M3U8 content into entries in file order. Line numbers are
+/// 1-based and point at the location line of each entry.
+///
+/// ```
+/// use danavi::m3u::parse_m3u;
+///
+/// // A byte order mark, Windows line endings, comments and blank lines
+/// let content = "\u{feff}#EXTM3U\r\n# Saved by hand\r\n\r\n\
+///     #EXTINF:-1 tvg-id=\"x\",Nina Simone - Sinnerman\r\n\
+///     Music/Nina Simone/Sinnerman.flac\r\n\
+///     #EXTVLCOPT:start-time=10\r\n\
+///     other.mp3\r\n";
+/// let entries = parse_m3u(content);
+/// assert_eq!(entries.len(), 2);
+/// assert_eq!(entries[0].line, 5);
+/// assert_eq!(entries[0].duration, None);
+/// assert_eq!(entries[0].artist.as_deref(), Some("Nina Simone"));
+/// assert_eq!(entries[0].title.as_deref(), Some("Sinnerman"));
+/// assert_eq!(entries[0].location, "Music/Nina Simone/Sinnerman.flac");
+/// // An #EXTINF only describes the line right after it
+/// assert_eq!(entries[1].line, 7);
+/// assert_eq!(entries[1].title, None);
+/// assert_eq!(entries[1].location, "other.mp3");
+/// ```
+pub fn parse_m3u(content: &str) -> Vec<M3uEntry> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut entries = Vec::new();
+    let mut pending: Option<(Option<i64>, Option<String>, Option<String>)> = None;
+
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            pending = Some(parse_extinf(info));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let (duration, artist, title) = pending.take().unwrap_or((None, None, None));
+        entries.push(M3uEntry {
+            line: idx + 1,
+            duration,
+            artist,
+            title,
+            location: line.to_string(),
+        });
+    }
+
+    entries
+}
+
+/// Reads and parses a playlist file. Invalid UTF-8 is replaced rather than
+/// rejected, since old M3U files are often Latin-1.
+pub fn read_m3u(path: &Path) -> Result<Vec<M3uEntry>> {
+    let bytes = fs::read(path).context("Failed to read playlist file")?;
+    Ok(parse_m3u(&String::from_utf8_lossy(&bytes)))
+}
+
+/// Returns the song id if `location` is a Subsonic stream URL, like the ones
+/// `format_m3u` writes for exported queues.
+pub fn stream_song_id(location: &str) -> Option<String> {
+    let url = url::Url::parse(location).ok()?;
+    let path = url.path();
+    if !(path.ends_with("/rest/stream") || path.ends_with("/rest/stream.view")) {
+        return None;
+    }
+    url.query_pairs()
+        .find(|(key, _)| key == "id")
+        .map(|(_, value)| value.into_owned())
+}
@@ -1,22 +1,98 @@
-mod audio;
-mod client;
-mod config;
+mod about;
+mod album_progress;
+mod artist_shuffle;
+mod bookmarks;
+mod downloads;
+mod episode_progress;
+mod hotkeys;
+mod info_panel;
+mod jukebox;
+mod listings;
+mod logger;
+mod lyrics_panel;
+mod mpd;
 mod mpris;
+mod now_playing;
+mod output_monitor;
+mod play_queue;
+mod preflight;
+mod prefetch;
+mod radio;
+mod saving;
+mod scrobble;
+mod theme;
 mod tui;
-mod types;
 
 use anyhow::{Context, Result};
-use audio::AudioPlayer;
-use client::SubsonicClient;
-use config::{config_needs_edit, get_config_path, load_config};
-use mpris::{MprisCommand, MprisServer, PlaybackStatus};
-use std::io;
-use std::time::Instant;
+use danavi::audio::{self, AudioPlayer, StreamBuffer, TrackEvent};
+use danavi::client::{self, StreamOptions, SubsonicClient, TlsOptions};
+use danavi::config::{self, expand_tilde, get_smart_playlists_path, load_config};
+use danavi::mpd_protocol::{self, PlaylistIds};
+use danavi::queue::{PlaybackSource, ShuffleOrder};
+use danavi::sorting::IgnoredArticles;
+use danavi::types::{self, *};
+use danavi::{m3u, smart};
+use futures_util::StreamExt;
+use info_panel::InfoPanel;
+use jukebox::{Jukebox, JukeboxEvent};
+use listings::Refreshed;
+use mpris::{MprisCommand, MprisServer, PlaybackStatus, VolumeBurst};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
-use tui::{Action, App, Tui};
-use types::*;
+use radio::Radio;
+use tui::{
+    Action, AlbumListMode, AlbumListing, App, ConfirmAction, Folder, FolderEntry, OpenPlaylist, PlaylistPicker, SearchPaging,
+    SearchResultItem, Tui, ViewType,
+};
 
 const EASTER_EGG_PROBABILITY: f64 = 0.05;
+// Consecutive failed tracks before a queue or album gives up
+const MAX_PLAYBACK_FAILURES: u32 = 5;
+const VOLUME_STEP: f64 = 0.05;
+// Downloaded before a streamed track starts, so it doesn't stall right away
+const STREAM_HEAD_START: usize = 128 * 1024;
+// How far past the downloaded part a seek has the server start the stream
+// there, rather than waiting for the download to get there
+const SERVER_SEEK_AHEAD: Duration = Duration::from_secs(30);
+// How long before an album track ends the next one starts loading
+const GAPLESS_LEAD: Duration = Duration::from_secs(20);
+// What the server is asked for when a track won't decode as it is
+const FALLBACK_FORMAT: &str = "mp3";
+const FALLBACK_BIT_RATE: u32 = 320;
+// How long the server gets to answer before the TUI starts
+const STARTUP_PING_TIMEOUT: Duration = Duration::from_secs(10);
+// Pixels on a side of the cover art asked for
+const COVER_ART_SIZE: u32 = 512;
+// Where listings go in the metadata cache, by endpoint; the id goes with
+// it, blank for the artists
+const ARTISTS_LISTING: &str = "getArtists";
+const ARTIST_LISTING: &str = "getArtist";
+const ALBUM_LISTING: &str = "getAlbum";
+// Albums asked for at a time when listing the whole library
+const ALBUM_PAGE: u32 = 50;
+// The next page is fetched once the selection is this close to the end
+const ALBUM_PAGE_AHEAD: usize = 10;
+// Search results asked for at a time
+const SEARCH_PAGE: u32 = 20;
+// More results are fetched once the selection is this close to the end
+const SEARCH_PAGE_AHEAD: usize = 5;
+// How often a running library scan is checked on
+const SCAN_POLL: Duration = Duration::from_secs(3);
+// Songs fetched at a time for shuffling the whole library
+const SHUFFLE_ALL_BATCH: u32 = 50;
+// Another batch is fetched once the queue is down to this
+const SHUFFLE_ALL_REFILL: usize = 10;
+// Artists with more albums than this are only shuffled once confirmed
+const ARTIST_SHUFFLE_CONFIRM: usize = 100;
+// Songs fetched at a time when evaluating a smart playlist
+const SMART_PLAYLIST_PAGE: u32 = 500;
+// An artist's top songs asked for
+const TOP_SONGS_COUNT: u32 = 50;
+// Similar songs the radio asks for at a time
+const RADIO_BATCH: u32 = 20;
+// Seconds a song found by file name may be off the #EXTINF length
+const M3U_DURATION_SLACK: i64 = 2;
 const EASTER_EGGS: &[&str] = &[
     " - made with coffee",
     " - made with tea",
@@ -34,13 +110,59 @@ fn get_random_easter_egg(show_easter_eggs: bool) -> String {
     EASTER_EGGS[idx].to_string()
 }
 
-async fn load_artists(
-    client: &SubsonicClient,
-    app: &mut App,
-    config: &types::Config,
-) -> Result<()> {
+// The status line for a failed action, spelling out what to do about the
+// server errors a user can fix
+fn error_message(e: &anyhow::Error) -> String {
+    let Some(error) = e.downcast_ref::<client::SubsonicError>() else {
+        return format!("Error: {}", e);
+    };
+    match error {
+        client::SubsonicError::WrongCredentials(_) => {
+            let path = config::get_config_path()
+                .map_or_else(|_| "config.json".to_string(), |path| path.display().to_string());
+            format!("Login failed: check username and password in {}", path)
+        }
+        client::SubsonicError::AuthUnsupported { code: 41, .. } => {
+            "The server can't check tokens: set \"auth_method\": \"legacy\" to send the password".to_string()
+        }
+        client::SubsonicError::AuthUnsupported { .. } => {
+            format!("The server won't take this login: {}", error.message())
+        }
+        client::SubsonicError::ServerTooOld(_) => "The server is too old for danavi".to_string(),
+        client::SubsonicError::ClientTooOld(_) => "The server needs a newer danavi".to_string(),
+        client::SubsonicError::NotAuthorized(message) => format!("Not allowed for this account: {}", message),
+        client::SubsonicError::NotFound(message) => format!("Not found on the server: {}", message),
+        _ => format!("Server said: {}", error.message()),
+    }
+}
+
+/// Says why the server couldn't be reached and asks whether to start
+/// anyway, for downloaded and cached songs.
+fn offer_offline(config: &Config, e: &anyhow::Error) -> Result<bool> {
+    use std::io::Write;
+    let reason = if e.downcast_ref::<client::SubsonicError>().is_some() {
+        error_message(e)
+    } else {
+        format!("{:#}", e)
+    };
+    let path = config::get_config_path()
+        .map_or_else(|_| "config.json".to_string(), |path| path.display().to_string());
+    eprintln!("Couldn't connect to {}", config.base_url);
+    eprintln!("  {}", reason);
+    eprintln!("Check base_url, username and password in {}", path);
+    eprint!("Start anyway, for downloaded and cached songs? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// All artists in one list, sorted as the server's index is but without
+/// its letter buckets, which put accented names apart.
+async fn fetch_artists(client: &SubsonicClient) -> Result<Vec<Artist>> {
     let response = client.get_artists().await?;
-    app.artists = response
+    let articles = IgnoredArticles::parse(&response.artists.ignored_articles);
+    let mut artists: Vec<Artist> = response
         .artists
         .index
         .into_iter()
@@ -48,8 +170,26 @@ async fn load_artists(
         .map(|a| Artist {
             id: a.id,
             name: a.name,
+            starred: a.starred.is_some(),
         })
         .collect();
+    artists.sort_by_cached_key(|a| articles.sort_key(&a.name));
+    Ok(artists)
+}
+
+async fn load_artists(
+    client: &SubsonicClient,
+    app: &mut App,
+    config: &types::Config,
+) -> Result<()> {
+    let fetch = {
+        let client = client.clone();
+        async move { fetch_artists(&client).await }
+    };
+    app.artists = match &app.listings {
+        Some(listings) => listings.load(ARTISTS_LISTING, "", fetch, Refreshed::Artists).await?,
+        None => fetch.await?,
+    };
 
     let items: Vec<String> = app.artists.iter().map(|a| a.name.clone()).collect();
     app.set_items(items);
@@ -63,36 +203,204 @@ async fn load_albums(
     artist_id: &str,
     config: &types::Config,
 ) -> Result<()> {
+    let fetch = {
+        let client = client.clone();
+        let artist_id = artist_id.to_string();
+        async move { fetch_artist_albums(&client, &artist_id).await }
+    };
+    let refreshed = {
+        let artist_id = artist_id.to_string();
+        move |(_, albums)| Refreshed::Albums { artist_id, albums }
+    };
+    let (artist_name, albums) = match &app.listings {
+        Some(listings) => listings.load(ARTIST_LISTING, artist_id, fetch, refreshed).await?,
+        None => fetch.await?,
+    };
+    app.albums = albums;
+
+    let items: Vec<String> = app
+        .albums
+        .iter()
+        .map(|a| tui::album_label(&a.name, a.year))
+        .collect();
+    app.set_items(items);
+    // Artists only credited on others' albums have none of their own
+    let heading = if app.albums.is_empty() { "No albums for" } else { "Albums for" };
+    app.current_base_content = format!(
+        "{} {}{}",
+        heading,
+        artist_name,
+        get_random_easter_egg(config.show_easter_eggs)
+    );
+    Ok(())
+}
+
+/// Shows the biography and similar artists of the selected artist, or the
+/// one whose albums are open, or the notes on the open album.
+fn open_info(client: &SubsonicClient, app: &mut App) {
+    if app.current_view == ViewType::Songs {
+        let album = app.current_album_id.clone().filter(|_| app.playlist_open.is_none() && !app.starred_open);
+        let Some(album_id) = album else {
+            app.show_message("Open an album first".to_string(), 1500);
+            return;
+        };
+        let name = app.songs.first().and_then(|s| s.album.clone()).unwrap_or_else(|| "this album".to_string());
+        app.info_panel = Some(InfoPanel::album(client, &album_id, &name));
+        return;
+    }
+    let artist_id = match app.current_view {
+        ViewType::Artists => app
+            .get_selected_index()
+            .and_then(|idx| app.artists.get(idx))
+            .map(|a| a.id.clone()),
+        ViewType::Albums if app.album_listing.is_none() => app.current_artist_id.clone(),
+        _ => None,
+    };
+    let Some(artist_id) = artist_id else {
+        app.show_message("Select an artist first".to_string(), 1500);
+        return;
+    };
+    let name = app
+        .artists
+        .iter()
+        .find(|a| a.id == artist_id)
+        .map(|a| a.name.clone())
+        .or_else(|| app.albums.first().and_then(|a| a.artist.clone()))
+        .unwrap_or_else(|| "this artist".to_string());
+    app.info_panel = Some(InfoPanel::artist(client, &artist_id, &name));
+}
+
+/// Opens the albums of the similar artist picked in the artist info, in
+/// place of any artist's albums already open.
+async fn open_similar_artist(client: &SubsonicClient, app: &mut App, config: &types::Config) -> Result<()> {
+    let Some((artist_id, name)) = app.info_panel.as_ref().and_then(InfoPanel::selected_artist) else {
+        return Ok(());
+    };
+    let Some(artist_id) = artist_id else {
+        app.show_message(format!("{} isn't in your library", name), 2000);
+        return Ok(());
+    };
+    app.info_panel = None;
+    if app.current_view != ViewType::Albums {
+        app.push_view(ViewType::Albums);
+    }
+    app.album_listing = None;
+    app.current_artist_id = Some(artist_id.clone());
+    load_albums(client, app, &artist_id, config).await
+}
+
+/// Returns the artist's name and albums.
+async fn fetch_artist_albums(client: &SubsonicClient, artist_id: &str) -> Result<(String, Vec<Album>)> {
     let response = client.get_artist(artist_id).await?;
-    app.albums = response
+    let albums = response
         .artist
         .album
         .into_iter()
         .map(|a| Album {
             id: a.id,
             name: a.name,
+            year: a.year,
+            starred: a.starred.is_some(),
+            artist: None,
+            rating: a.user_rating,
+            genre: a.genre,
         })
         .collect();
+    Ok((response.artist.name, albums))
+}
 
-    let items: Vec<String> = app.albums.iter().map(|a| a.name.clone()).collect();
-    app.set_items(items);
-    app.current_base_content = format!(
-        "Albums for {}{}",
-        response.artist.name,
-        get_random_easter_egg(config.show_easter_eggs)
-    );
-    Ok(())
+async fn fetch_album_page(client: &SubsonicClient, mode: AlbumListMode, offset: usize) -> Result<Vec<Album>> {
+    let response = client.get_album_list2(mode.api_name(), ALBUM_PAGE, offset).await?;
+    Ok(response
+        .album_list2
+        .album
+        .into_iter()
+        .map(|a| Album {
+            id: a.id,
+            name: a.name,
+            year: a.year,
+            starred: a.starred.is_some(),
+            artist: a.artist,
+            rating: a.user_rating,
+            genre: a.genre,
+        })
+        .collect())
 }
 
-async fn load_songs(
+/// Lists the whole library's albums in the Albums view, or switches the
+/// list already open there to `mode`.
+async fn browse_albums(
     client: &SubsonicClient,
     app: &mut App,
-    album_id: &str,
     config: &types::Config,
+    mode: AlbumListMode,
 ) -> Result<()> {
+    let albums = fetch_album_page(client, mode, 0).await?;
+    if app.current_view != ViewType::Albums || app.album_listing.is_none() {
+        app.push_view(ViewType::Albums);
+    }
+    app.current_artist_id = None;
+    app.album_listing = Some(AlbumListing {
+        mode,
+        complete: albums.len() < ALBUM_PAGE as usize,
+        failed_at: None,
+    });
+    app.albums = albums;
+    app.set_items(app.albums.iter().map(|a| a.name.clone()).collect());
+    app.current_base_content = format!(
+        "Albums: {}{}",
+        mode.label(),
+        get_random_easter_egg(config.show_easter_eggs)
+    );
+    Ok(())
+}
+
+/// Fetches the album list's next page once the selection nears the end.
+async fn load_more_albums(client: &SubsonicClient, app: &mut App) {
+    let Some(listing) = app.album_listing else {
+        return;
+    };
+    if app.current_view != ViewType::Albums || listing.complete {
+        return;
+    }
+    let Some(selected) = app
+        .get_selected_index()
+        .filter(|idx| idx + ALBUM_PAGE_AHEAD >= app.albums.len() && listing.failed_at != Some(*idx))
+    else {
+        return;
+    };
+    let listing = match fetch_album_page(client, listing.mode, app.albums.len()).await {
+        Ok(page) => {
+            let last = page.len() < ALBUM_PAGE as usize;
+            // Random lists can repeat themselves; a page of nothing new ends it
+            let known: HashSet<String> = app.albums.iter().map(|a| a.id.clone()).collect();
+            let before = app.albums.len();
+            app.albums.extend(page.into_iter().filter(|a| !known.contains(&a.id)));
+            AlbumListing {
+                complete: last || app.albums.len() == before,
+                failed_at: None,
+                ..listing
+            }
+        }
+        Err(e) => {
+            app.show_message(format!("Couldn't load more albums: {}", e), 3000);
+            AlbumListing {
+                failed_at: Some(selected),
+                ..listing
+            }
+        }
+    };
+    app.album_listing = Some(listing);
+}
+
+/// Returns the album's display name (with its year) and its songs.
+async fn fetch_album_songs(client: &SubsonicClient, album_id: &str) -> Result<(String, Vec<Song>)> {
     let response = client.get_album(album_id).await?;
     let album_artist = response.album.artist.clone();
-    app.songs = response
+    let album_year = response.album.year;
+    let album_cover_art = response.album.cover_art.clone();
+    let album_genre = response.album.genre.clone();
+    let mut songs: Vec<Song> = response
         .album
         .song
         .into_iter()
@@ -103,257 +411,3525 @@ async fn load_songs(
             album: Some(response.album.name.clone()),
             album_artist: album_artist.clone(),
             duration: s.duration,
+            path: s.path,
+            genre: s.genre.or_else(|| album_genre.clone()),
+            year: s.year.or(album_year),
+            starred: s.starred.is_some(),
+            rating: s.user_rating,
+            replay_gain: s.replay_gain,
+            cover_art: s.cover_art.or_else(|| album_cover_art.clone()),
+            suffix: s.suffix,
+            track: s.track,
+            disc_number: s.disc_number,
+            podcast: false,
         })
         .collect();
+    Song::sort_album_tracks(&mut songs);
+    Ok((tui::album_label(&response.album.name, album_year), songs))
+}
+
+async fn load_songs(
+    client: &SubsonicClient,
+    app: &mut App,
+    album_id: &str,
+    config: &types::Config,
+) -> Result<()> {
+    let fetch = {
+        let client = client.clone();
+        let album_id = album_id.to_string();
+        async move { fetch_album_songs(&client, &album_id).await }
+    };
+    let refreshed = {
+        let album_id = album_id.to_string();
+        move |(_, songs)| Refreshed::Songs { album_id, songs }
+    };
+    let (album_name, songs) = match &app.listings {
+        Some(listings) => listings.load(ALBUM_LISTING, album_id, fetch, refreshed).await?,
+        None => fetch.await?,
+    };
+    app.songs = songs;
+    app.resume_offered = None;
+    app.starred_open = false;
+    app.playlist_open = None;
+    app.top_songs_open = None;
 
     let items: Vec<String> = app.songs.iter().map(|s| s.title.clone()).collect();
     app.set_items(items);
+    let heading = if app.songs.is_empty() { "No songs in" } else { "Songs in" };
     app.current_base_content = format!(
-        "Songs in {}{}",
-        response.album.name,
+        "{} {}{}",
+        heading,
+        album_name,
         get_random_easter_egg(config.show_easter_eggs)
     );
     Ok(())
 }
 
-async fn handle_select(
+/// The album under the cursor in the Albums view, or the open album in the
+/// Songs view.
+fn selected_album_id(app: &App) -> Option<String> {
+    match app.current_view {
+        ViewType::Albums => app
+            .get_selected_index()
+            .and_then(|idx| app.albums.get(idx))
+            .map(|a| a.id.clone()),
+        ViewType::Songs => app.current_album_id.clone(),
+        _ => None,
+    }
+}
+
+async fn append_album(client: &SubsonicClient, app: &mut App, album_id: &str) -> Result<()> {
+    let (album_name, songs) = if app.current_view == ViewType::Songs {
+        let name = app.songs.first().and_then(|s| s.album.clone()).unwrap_or_default();
+        (name, app.songs.clone())
+    } else {
+        fetch_album_songs(client, album_id).await?
+    };
+    let skipped = app.enqueue(songs.clone());
+    app.show_message(
+        format!(
+            "Added {} songs from {} (Queue: {}){}",
+            songs.len() - skipped,
+            album_name,
+            app.queue.len(),
+            already_queued(skipped)
+        ),
+        1500,
+    );
+    Ok(())
+}
+
+type SearchReply = (u64, String, Result<SearchResponse>);
+
+/// Runs a search in the background. Starting another one cancels it, and
+/// its results are dropped if they arrive anyway.
+fn start_search(
     client: &SubsonicClient,
     app: &mut App,
-    config: &types::Config,
-    audio_player: &AudioPlayer,
-    mpris_server: &MprisServer,
-) -> Result<()> {
-    let selected = app.get_selected_index();
-    if selected.is_none() {
+    search_tx: &mpsc::UnboundedSender<SearchReply>,
+    query: String,
+) {
+    app.search_due = None;
+    if let Some(task) = app.search_task.take() {
+        task.abort();
+    }
+    let generation = app.search_generations.start();
+    if app.current_view != ViewType::Search {
+        app.push_view(ViewType::Search);
+    }
+    app.current_base_content = format!("Searching for \"{}\"...", query);
+
+    let client = client.clone();
+    let search_tx = search_tx.clone();
+    app.search_task = Some(tokio::spawn(async move {
+        let response = client.search3(&query, 20, SEARCH_PAGE, SEARCH_PAGE).await;
+        let _ = search_tx.send((generation, query, response));
+    }));
+}
+
+// The albums and songs of a search response, as listed
+fn search_items(response: SearchResponse) -> (Vec<SearchResultItem>, Vec<SearchResultItem>) {
+    let Some(search_result) = response.into_result() else {
+        return (Vec::new(), Vec::new());
+    };
+    let albums = search_result
+        .album
+        .unwrap_or_default()
+        .into_iter()
+        .map(|album| SearchResultItem::Album {
+            id: album.id,
+            name: album.name,
+            artist: album.artist,
+            artist_id: album.artist_id,
+            year: album.year,
+            song_count: album.song_count,
+            starred: album.starred.is_some(),
+            rating: album.user_rating,
+            folder: album.folder,
+        })
+        .collect();
+    let songs = search_result
+        .song
+        .unwrap_or_default()
+        .into_iter()
+        .map(|song| SearchResultItem::Song {
+            id: song.id,
+            title: song.title,
+            artist: song.artist,
+            album: song.album,
+            duration: song.duration,
+            path: song.path,
+            replay_gain: song.replay_gain,
+            starred: song.starred.is_some(),
+            cover_art: song.cover_art,
+            rating: song.user_rating,
+        })
+        .collect();
+    (albums, songs)
+}
+
+// "Search: live (40+ results)", the + while more may load
+fn search_title(app: &App, query: &str) -> String {
+    let more = app.search_paging.as_ref().is_some_and(|paging| !paging.complete());
+    format!(
+        "Search: {} ({}{} results)",
+        query,
+        app.search_results.len(),
+        if more { "+" } else { "" }
+    )
+}
+
+fn show_search_results(app: &mut App, config: &Config, query: &str, response: SearchResponse) {
+    let (albums, songs) = search_items(response);
+    app.search_paging = Some(SearchPaging {
+        query: query.to_string(),
+        album_offset: albums.len(),
+        song_offset: songs.len(),
+        albums_done: albums.len() < SEARCH_PAGE as usize,
+        songs_done: songs.len() < SEARCH_PAGE as usize,
+    });
+    app.search_results = albums.into_iter().chain(songs).collect();
+    app.search_terms = query.split_whitespace().map(str::to_lowercase).collect();
+    let search_items: Vec<String> = app
+        .search_results
+        .iter()
+        .map(|r| tui::search_result_label(r, None, &[]))
+        .collect();
+    app.set_items(search_items);
+    app.current_base_content = format!(
+        "{}{}",
+        search_title(app, query),
+        get_random_easter_egg(config.show_easter_eggs)
+    );
+}
+
+/// Fetches the search's next albums and songs once the selection nears the
+/// end. New albums go after the ones listed, new songs at the end.
+async fn load_more_search(client: &SubsonicClient, app: &mut App) {
+    let Some(paging) = app.search_paging.clone() else {
+        return;
+    };
+    if app.current_view != ViewType::Search || paging.complete() {
+        return;
+    }
+    let Some(selected) = app
+        .get_selected_index()
+        .filter(|idx| idx + SEARCH_PAGE_AHEAD >= app.search_results.len())
+    else {
+        return;
+    };
+    let count = |done: bool| if done { 0 } else { SEARCH_PAGE };
+    let page = client
+        .search3_page(
+            &paging.query,
+            count(paging.albums_done),
+            paging.album_offset,
+            count(paging.songs_done),
+            paging.song_offset,
+        )
+        .await;
+    let (albums, songs) = match page {
+        Ok(response) => search_items(response),
+        Err(e) => {
+            app.show_message(format!("Couldn't load more results: {}", e), 3000);
+            app.search_paging = None;
+            return;
+        }
+    };
+    let paging = SearchPaging {
+        album_offset: paging.album_offset + albums.len(),
+        song_offset: paging.song_offset + songs.len(),
+        albums_done: paging.albums_done || albums.len() < SEARCH_PAGE as usize,
+        songs_done: paging.songs_done || songs.len() < SEARCH_PAGE as usize,
+        ..paging
+    };
+    // Pages can overlap when the library changes in between
+    let mut known: HashSet<String> = app.search_results.iter().map(|r| r.id().to_string()).collect();
+    let albums: Vec<SearchResultItem> = albums.into_iter().filter(|a| known.insert(a.id().to_string())).collect();
+    let songs = songs.into_iter().filter(|s| known.insert(s.id().to_string()));
+    let album_end = app
+        .search_results
+        .iter()
+        .position(|r| matches!(r, SearchResultItem::Song { .. }))
+        .unwrap_or(app.search_results.len());
+    let added_albums = albums.len();
+    app.search_results.splice(album_end..album_end, albums);
+    app.search_results.extend(songs);
+    if selected >= album_end {
+        app.list_state.select(Some(selected + added_albums));
+    }
+    app.current_base_content = search_title(app, &paging.query);
+    app.search_paging = Some(paging);
+}
+
+/// Adds an album to the downloads, which keep its songs in the song cache
+/// for playing offline.
+async fn download_album(client: &SubsonicClient, app: &mut App, album_id: &str) -> Result<()> {
+    let Some(cache) = app.song_cache.clone() else {
+        app.show_message("No cache directory to download into".to_string(), 3000);
         return Ok(());
+    };
+    let (label, songs) = fetch_album_songs(client, album_id).await?;
+    let title = match songs.first().and_then(|s| s.album_artist.clone()) {
+        Some(artist) => format!("{} - {}", label, artist),
+        None => label,
+    };
+    if app.downloads.add(&cache, album_id, title.clone(), &songs) {
+        app.show_message(format!("Downloading {} (O shows progress)", title), 2000);
+    } else {
+        app.show_message(format!("Already downloading {}", title), 1500);
     }
-    let idx = selected.unwrap();
+    Ok(())
+}
 
-    match app.current_view {
-        ViewType::Artists => {
-            if let Some(artist) = app.artists.get(idx) {
-                let artist_id = artist.id.clone();
-                app.current_artist_id = Some(artist_id.clone());
-                app.current_view = ViewType::Albums;
-                load_albums(client, app, &artist_id, config).await?;
-            }
+/// Saves the selected song, or every song of the selected album, as files
+/// in the background.
+async fn save_files(client: &SubsonicClient, app: &mut App) -> Result<()> {
+    let Some(idx) = app.get_selected_index() else {
+        return Ok(());
+    };
+    let songs = match app.current_view {
+        ViewType::Albums => match selected_album_id(app) {
+            Some(album_id) => fetch_album_songs(client, &album_id).await?.1,
+            None => Vec::new(),
+        },
+        ViewType::Songs => app.songs.get(idx).cloned().into_iter().collect(),
+        // Search results leave out the album artist and the file type
+        ViewType::Search => match app.search_results.get(idx) {
+            Some(SearchResultItem::Album { id, folder: true, .. }) => fetch_folder_files(client, id).await?.1,
+            Some(SearchResultItem::Album { id, .. }) => fetch_album_songs(client, id).await?.1,
+            Some(SearchResultItem::Song { id, .. }) => vec![song_from_data(client.get_song(id).await?.song)],
+            None => Vec::new(),
+        },
+        ViewType::Folders => match app.folders.last().and_then(|f| f.entries.get(idx)) {
+            Some(FolderEntry::File(song)) => vec![song.as_ref().clone()],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+    if songs.is_empty() {
+        app.show_message("Select a song or an album to save".to_string(), 1500);
+        return Ok(());
+    }
+    let saving = match app.saving.as_mut() {
+        Some(saving) => saving,
+        None => app.saving.insert(saving::Saving::start(client)?),
+    };
+    let count = songs.len();
+    saving.add(songs);
+    let message = format!("Saving {} songs to {}", count, saving.dir.display());
+    app.show_message(message, 2000);
+    Ok(())
+}
+
+// Why the server refused a share, when it's one it won't make at all: the
+// account lacks the share role, or sharing is off, which Navidrome reports
+// as not implemented
+fn share_refusal(e: &anyhow::Error) -> Option<&'static str> {
+    match e.downcast_ref::<client::SubsonicError>()? {
+        client::SubsonicError::NotAuthorized(_) => Some("Your account isn't allowed to share"),
+        client::SubsonicError::Generic(message) if message.to_lowercase().contains("not implemented") => {
+            Some("Sharing disabled on server")
         }
-        ViewType::Albums => {
-            if let Some(album) = app.albums.get(idx) {
-                let album_id = album.id.clone();
-                app.current_album_id = Some(album_id.clone());
-                app.current_view = ViewType::Songs;
-                load_songs(client, app, &album_id, config).await?;
-            }
+        _ => None,
+    }
+}
+
+/// Makes a share link for the selected song or album, shows it and copies
+/// it to the clipboard.
+async fn share_selected(client: &SubsonicClient, app: &mut App, config: &Config) -> Result<()> {
+    let Some(idx) = app.get_selected_index() else {
+        return Ok(());
+    };
+    let target = match app.current_view {
+        ViewType::Albums => app.albums.get(idx).map(|a| (a.id.clone(), a.name.clone())),
+        ViewType::Songs => app.songs.get(idx).map(|s| (s.id.clone(), s.title.clone())),
+        ViewType::Search => match app.search_results.get(idx) {
+            Some(SearchResultItem::Album { id, name, .. }) => Some((id.clone(), name.clone())),
+            Some(SearchResultItem::Song { id, title, .. }) => Some((id.clone(), title.clone())),
+            None => None,
+        },
+        _ => None,
+    };
+    let Some((id, name)) = target else {
+        app.show_message("Select a song or an album to share".to_string(), 1500);
+        return Ok(());
+    };
+    // Too many days to count is as good as none
+    let expires = (config.share_expiry_days > 0)
+        .then(|| config.share_expiry_days.checked_mul(24 * 60 * 60))
+        .flatten()
+        .map(Duration::from_secs);
+    let share = match client.create_share(&id, Some(&name), expires).await {
+        Err(e) if let Some(refusal) = share_refusal(&e) => {
+            app.show_message(refusal.to_string(), 3000);
+            return Ok(());
         }
-        ViewType::Songs => {
-            if let Some(song) = app.songs.get(idx) {
-                let song = song.clone();
-                // When playing from Songs view, set up album continuation
-                let source = PlaybackSource::Album {
-                    album_songs: app.songs.clone(),
-                    current_index: idx,
-                };
-                play_song(client, app, song, audio_player, mpris_server, source).await?;
+        share => share?,
+    };
+    let copied = if tui::copy_to_clipboard(&share.url).is_ok() { " (copied)" } else { "" };
+    app.show_message(format!("Share link{}: {}", copied, share.url), 10000);
+    Ok(())
+}
+
+/// Starts a library scan and follows it in the background until it ends.
+async fn start_scan(client: &SubsonicClient, app: &mut App) -> Result<()> {
+    if app.scan.is_some() {
+        app.show_message("Already scanning".to_string(), 1500);
+        return Ok(());
+    }
+    // Left as it is, so a missing admin role reads as such
+    let status = client.start_scan().await?.scan_status;
+    let (tx, updates) = mpsc::unbounded_channel();
+    let client = client.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(SCAN_POLL).await;
+            let status = client.get_scan_status().await.map(|r| r.scan_status);
+            let running = matches!(status, Ok(ScanStatus { scanning: true, .. }));
+            if tx.send(status).is_err() || !running {
+                break;
             }
         }
-        ViewType::Search => {
-            if let Some(result) = app.search_results.get(idx) {
-                match result {
-                    SearchResultItem::Album { id, artist_id, .. } => {
-                        let id_clone = id.clone();
-                        let artist_id_clone = artist_id.clone();
-                        app.current_artist_id = Some(artist_id_clone);
-                        app.current_album_id = Some(id_clone.clone());
-                        app.current_view = ViewType::Songs;
-                        load_songs(client, app, &id_clone, config).await?;
-                    }
-                    SearchResultItem::Song { id, title, artist, album, .. } => {
-                        let song = Song {
-                            id: id.clone(),
-                            title: title.clone(),
-                            artist: Some(artist.clone()),
-                            album: album.clone(),
-                            album_artist: None,
-                            duration: None,
-                        };
-                        play_song(client, app, song, audio_player, mpris_server, PlaybackSource::Search).await?;
-                    }
-                }
+    });
+    app.scan = Some(tui::LibraryScan {
+        updates,
+        count: status.count,
+    });
+    Ok(())
+}
+
+// Puts the selection back where it was after a list is reloaded
+fn reselect(app: &mut App, selected: Option<usize>, len: usize) {
+    if let Some(i) = selected.filter(|_| len > 0) {
+        app.list_state.select(Some(i.min(len - 1)));
+    }
+}
+
+/// Swaps in a listing refreshed in the background, if it's still the one
+/// on screen and it changed.
+fn poll_refreshed(app: &mut App) {
+    let Some(refreshed) = app.listings.as_mut().and_then(|listings| listings.refreshed()) else {
+        return;
+    };
+    let selected = app.get_selected_index();
+    match refreshed {
+        Refreshed::Artists(artists) if artists != app.artists => {
+            app.artists = artists;
+            if app.current_view == ViewType::Artists {
+                app.set_items(app.artists.iter().map(|a| a.name.clone()).collect());
+                reselect(app, selected, app.artists.len());
             }
         }
+        Refreshed::Albums { artist_id, albums }
+            if app.current_view == ViewType::Albums
+                && app.album_listing.is_none()
+                && app.current_artist_id.as_deref() == Some(artist_id.as_str())
+                && albums != app.albums =>
+        {
+            app.albums = albums;
+            app.set_items(app.albums.iter().map(|a| tui::album_label(&a.name, a.year)).collect());
+            reselect(app, selected, app.albums.len());
+        }
+        Refreshed::Songs { album_id, songs }
+            if app.current_view == ViewType::Songs
+                && app.playlist_open.is_none()
+                && !app.starred_open
+                && app.current_album_id.as_deref() == Some(album_id.as_str())
+                && songs != app.songs =>
+        {
+            app.songs = songs;
+            app.set_items(app.songs.iter().map(|s| s.title.clone()).collect());
+            reselect(app, selected, app.songs.len());
+        }
+        _ => {}
+    }
+}
+
+/// Fetches the open artist, album or song list again, skipping the
+/// metadata cache.
+async fn refresh_view(client: &SubsonicClient, app: &mut App, config: &types::Config) -> Result<()> {
+    let selected = app.get_selected_index();
+    let forget = |app: &App, endpoint: &str, id: &str| {
+        if let Some(listings) = &app.listings {
+            listings.forget(endpoint, id);
+        }
+    };
+    match (app.current_view.clone(), app.current_artist_id.clone(), app.current_album_id.clone()) {
+        (ViewType::Artists, _, _) => {
+            forget(app, ARTISTS_LISTING, "");
+            load_artists(client, app, config).await?;
+            reselect(app, selected, app.artists.len());
+        }
+        (ViewType::Albums, Some(artist_id), _) if app.album_listing.is_none() => {
+            forget(app, ARTIST_LISTING, &artist_id);
+            load_albums(client, app, &artist_id, config).await?;
+            reselect(app, selected, app.albums.len());
+        }
+        (ViewType::Songs, _, Some(album_id)) if app.playlist_open.is_none() && !app.starred_open => {
+            forget(app, ALBUM_LISTING, &album_id);
+            load_songs(client, app, &album_id, config).await?;
+            reselect(app, selected, app.songs.len());
+        }
+        _ => {
+            app.show_message("Only artists, albums and songs can be refreshed".to_string(), 2000);
+            return Ok(());
+        }
     }
+    app.show_message("Refreshed".to_string(), 1500);
     Ok(())
 }
 
-async fn play_song(
+/// Follows a running scan; once it's done, reloads the artists so new ones
+/// show up.
+async fn poll_scan(client: &SubsonicClient, app: &mut App) {
+    let Some(scan) = app.scan.as_mut() else {
+        return;
+    };
+    let status = match scan.updates.try_recv() {
+        Ok(Ok(status)) if status.scanning => {
+            scan.count = status.count.or(scan.count);
+            return;
+        }
+        Ok(status) => status,
+        Err(mpsc::error::TryRecvError::Empty) => return,
+        Err(mpsc::error::TryRecvError::Disconnected) => {
+            app.scan = None;
+            return;
+        }
+    };
+    app.scan = None;
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            app.show_message(format!("Lost track of the scan: {}", e), 3000);
+            return;
+        }
+    };
+    // Whatever was cached may have changed
+    if let Some(listings) = &app.listings {
+        listings.clear();
+    }
+    match fetch_artists(client).await {
+        Ok(artists) => {
+            if let Some(listings) = &app.listings {
+                listings.store(ARTISTS_LISTING, "", &artists);
+            }
+            app.artists = artists;
+            if app.current_view == ViewType::Artists {
+                let selected = app.get_selected_index();
+                app.set_items(app.artists.iter().map(|a| a.name.clone()).collect());
+                reselect(app, selected, app.artists.len());
+            }
+            let items = status.count.map_or(String::new(), |count| format!(": {} items", count));
+            app.show_message(format!("Scan finished{}", items), 3000);
+        }
+        Err(e) => app.show_message(format!("Scan finished, but reloading artists failed: {}", e), 3000),
+    }
+}
+
+/// Starts downloading the queue into the song cache.
+fn start_prefetch(client: &SubsonicClient, app: &mut App) {
+    if app.prefetch.is_some() {
+        app.show_message("Already prefetching (Esc to stop)".to_string(), 1500);
+        return;
+    }
+    let Some(cache) = app.song_cache.as_ref() else {
+        app.show_message("No cache directory to download into".to_string(), 3000);
+        return;
+    };
+    if app.queue.is_empty() {
+        app.show_message("Queue is empty".to_string(), 1500);
+        return;
+    }
+    app.prefetch = Some(prefetch::Prefetch::start(client, cache, &app.queue));
+}
+
+/// Summary suffix for songs `enqueue` left out.
+fn already_queued(skipped: usize) -> String {
+    if skipped > 0 {
+        format!(", {} already queued", skipped)
+    } else {
+        String::new()
+    }
+}
+
+fn add_to_queue(app: &mut App, song: Song) {
+    let title = song.title.clone();
+    if app.enqueue(vec![song]) > 0 {
+        app.show_message(format!("Already queued: {}", title), 1500);
+    } else {
+        app.show_message(
+            format!("Added to queue: {} (Queue: {})", title, app.queue.len()),
+            1500,
+        );
+    }
+}
+
+/// Stars or unstars an album along with every song on it. Everything goes in
+/// one request; if that fails, songs are retried one at a time so a partial
+/// result can be reported.
+async fn set_album_starred(
     client: &SubsonicClient,
     app: &mut App,
-    song: Song,
-    audio_player: &AudioPlayer,
-    mpris_server: &MprisServer,
-    source: PlaybackSource,
+    album_id: &str,
+    starred: bool,
 ) -> Result<()> {
-    app.show_message(format!("Playing: {}", song.title), 2000);
+    let (album_name, songs) = if app.current_view == ViewType::Songs
+        && app.current_album_id.as_deref() == Some(album_id)
+    {
+        let name = app.songs.first().and_then(|s| s.album.clone()).unwrap_or_default();
+        (name, app.songs.clone())
+    } else {
+        fetch_album_songs(client, album_id).await?
+    };
+    let ids: Vec<String> = songs.iter().map(|s| s.id.clone()).collect();
+    let album_ids = vec![album_id.to_string()];
+
+    let set = |ids: Vec<String>, album_ids: Vec<String>| async move {
+        if starred {
+            client.star(&ids, &album_ids).await
+        } else {
+            client.unstar(&ids, &album_ids).await
+        }
+    };
 
-    let bytes = client.stream_song(&song.id).await?;
+    let changed: Vec<String> = if set(ids.clone(), album_ids.clone()).await.is_ok() {
+        ids.clone()
+    } else {
+        let album_ok = set(Vec::new(), album_ids).await.is_ok();
+        let mut changed = Vec::new();
+        for id in &ids {
+            if set(vec![id.clone()], Vec::new()).await.is_ok() {
+                changed.push(id.clone());
+            }
+        }
+        if !album_ok && changed.is_empty() {
+            anyhow::bail!("Failed to {} {}", if starred { "star" } else { "unstar" }, album_name);
+        }
+        changed
+    };
 
-    audio_player
-        .play_bytes(bytes)
-        .context("Failed to play audio")?;
+    forget_listings_of(app, &StarTarget::Album);
+    forget_listings_of(app, &StarTarget::Song);
+    for song in app.songs.iter_mut().chain(app.queue.iter_mut()) {
+        if changed.contains(&song.id) {
+            song.starred = starred;
+        }
+    }
+    if let Some(album) = app.albums.iter_mut().find(|a| a.id == album_id) {
+        album.starred = starred;
+    }
 
-    // Update MPRIS state and emit PropertiesChanged signal
-    let stream_url = format!("{}/rest/stream?id={}", client.base_url, song.id);
-    mpris_server.update_current_song(
-        Some(mpris::Song {
-            id: song.id.clone(),
-            title: song.title.clone(),
-            artist: song.artist.clone(),
-            album: song.album.clone(),
-            album_artist: song.album_artist.clone(),
-            duration: song.duration,
-        }),
-        Some(stream_url),
-    ).await?;
-    mpris_server.update_playback_status(PlaybackStatus::Playing).await?;
+    let verb = if starred { "Starred" } else { "Unstarred" };
+    if changed.len() == ids.len() {
+        app.show_message(format!("{} {} ({} songs)", verb, album_name, ids.len()), 2000);
+    } else {
+        app.show_message(
+            format!("{} {} of {} songs from {}", verb, changed.len(), ids.len(), album_name),
+            5000,
+        );
+    }
+    Ok(())
+}
 
-    // Track the playback source
-    app.current_playback_source = Some(source);
+enum StarTarget {
+    Artist,
+    Album,
+    Song,
+}
 
+/// Drops the cached listings a star or rating on `target` shows up in, so
+/// they aren't served stale until the TTL runs out. Which album a song is on
+/// or whose an album is isn't always known, so it's all of that kind.
+fn forget_listings_of(app: &App, target: &StarTarget) {
+    let Some(listings) = &app.listings else {
+        return;
+    };
+    match target {
+        StarTarget::Artist => listings.forget(ARTISTS_LISTING, ""),
+        StarTarget::Album => listings.forget_all(ARTIST_LISTING),
+        StarTarget::Song => listings.forget_all(ALBUM_LISTING),
+    }
+}
+
+/// Stars whatever is selected, or unstars it if it already is. Only that
+/// one item changes; `F` does an album together with its songs.
+async fn toggle_star(client: &SubsonicClient, app: &mut App) -> Result<()> {
+    let Some(idx) = app.get_selected_index() else {
+        return Ok(());
+    };
+    let (target, id, name, starred) = match app.current_view {
+        ViewType::Artists => {
+            let Some(artist) = app.artists.get(idx) else {
+                return Ok(());
+            };
+            (StarTarget::Artist, artist.id.clone(), artist.name.clone(), artist.starred)
+        }
+        ViewType::Albums => {
+            let Some(album) = app.albums.get(idx) else {
+                return Ok(());
+            };
+            (StarTarget::Album, album.id.clone(), album.name.clone(), album.starred)
+        }
+        ViewType::Songs => {
+            let Some(song) = app.songs.get(idx) else {
+                return Ok(());
+            };
+            (StarTarget::Song, song.id.clone(), song.title.clone(), song.starred)
+        }
+        ViewType::Search => match app.search_results.get(idx) {
+            // A folder is starred by plain id, as songs are
+            Some(SearchResultItem::Album { id, name, starred, folder: true, .. }) => {
+                (StarTarget::Song, id.clone(), name.clone(), *starred)
+            }
+            Some(SearchResultItem::Album { id, name, starred, .. }) => {
+                (StarTarget::Album, id.clone(), name.clone(), *starred)
+            }
+            Some(SearchResultItem::Song { id, title, starred, .. }) => {
+                (StarTarget::Song, id.clone(), title.clone(), *starred)
+            }
+            None => return Ok(()),
+        },
+        ViewType::Playlists | ViewType::Podcasts | ViewType::Episodes | ViewType::Folders => return Ok(()),
+    };
+
+    let star = !starred;
+    let ids = std::slice::from_ref(&id);
+    let result = match (&target, star) {
+        (StarTarget::Artist, true) => client.star_artist(&id).await,
+        (StarTarget::Artist, false) => client.unstar_artist(&id).await,
+        (StarTarget::Album, true) => client.star(&[], ids).await,
+        (StarTarget::Album, false) => client.unstar(&[], ids).await,
+        (StarTarget::Song, true) => client.star(ids, &[]).await,
+        (StarTarget::Song, false) => client.unstar(ids, &[]).await,
+    };
+    if let Err(e) = result {
+        let verb = if star { "star" } else { "unstar" };
+        anyhow::bail!("The server wouldn't {} {}: {}", verb, name, e);
+    }
+    forget_listings_of(app, &target);
+
+    match target {
+        StarTarget::Artist => {
+            for artist in app.artists.iter_mut().filter(|a| a.id == id) {
+                artist.starred = star;
+            }
+        }
+        StarTarget::Album => {
+            for album in app.albums.iter_mut().filter(|a| a.id == id) {
+                album.starred = star;
+            }
+        }
+        StarTarget::Song => {
+            for song in app.songs.iter_mut().chain(app.queue.iter_mut()).filter(|s| s.id == id) {
+                song.starred = star;
+            }
+        }
+    }
+    for result in app.search_results.iter_mut() {
+        match result {
+            SearchResultItem::Album { id: result_id, starred, .. }
+            | SearchResultItem::Song { id: result_id, starred, .. }
+                if *result_id == id =>
+            {
+                *starred = star;
+            }
+            _ => {}
+        }
+    }
+    let verb = if star { "Starred" } else { "Unstarred" };
+    app.show_message(format!("{}: {}", verb, name), 2000);
+    Ok(())
+}
+
+/// Rates the selected song or album, or clears its rating with 0.
+async fn rate_selected(client: &SubsonicClient, app: &mut App, rating: u8) -> Result<()> {
+    let Some(idx) = app.get_selected_index() else {
+        return Ok(());
+    };
+    let (target, id, name, rated) = match app.current_view {
+        ViewType::Albums => {
+            let Some(album) = app.albums.get(idx) else {
+                return Ok(());
+            };
+            (StarTarget::Album, album.id.clone(), album.name.clone(), album.rating)
+        }
+        ViewType::Songs => {
+            let Some(song) = app.songs.get(idx) else {
+                return Ok(());
+            };
+            (StarTarget::Song, song.id.clone(), song.title.clone(), song.rating)
+        }
+        ViewType::Search => match app.search_results.get(idx) {
+            Some(SearchResultItem::Album { id, name, rating, .. }) => {
+                (StarTarget::Album, id.clone(), name.clone(), *rating)
+            }
+            Some(SearchResultItem::Song { id, title, rating, .. }) => {
+                (StarTarget::Song, id.clone(), title.clone(), *rating)
+            }
+            None => return Ok(()),
+        },
+        _ => return Ok(()),
+    };
+    // Album lists don't always carry the rating, but the album itself does
+    let rated = match target {
+        StarTarget::Album => match client.get_album(&id).await {
+            Ok(response) => response.album.user_rating,
+            Err(_) => rated,
+        },
+        _ => rated,
+    };
+    set_rating(client, app, target, &id, &name, rated, rating).await
+}
+
+/// Rates the playing song, or clears its rating with 0.
+async fn rate_playing(client: &SubsonicClient, app: &mut App, playing: Option<(String, String)>, rating: u8) -> Result<()> {
+    let Some((id, title)) = playing else {
+        app.show_message("Nothing is playing".to_string(), 1500);
+        return Ok(());
+    };
+    let rated = match app.songs.iter().chain(app.queue.iter()).find(|s| s.id == id) {
+        Some(song) => song.rating,
+        None => client.get_song(&id).await.ok().and_then(|response| response.song.user_rating),
+    };
+    set_rating(client, app, StarTarget::Song, &id, &title, rated, rating).await
+}
+
+async fn set_rating(
+    client: &SubsonicClient,
+    app: &mut App,
+    target: StarTarget,
+    id: &str,
+    name: &str,
+    rated: Option<u8>,
+    rating: u8,
+) -> Result<()> {
+    let rated = rated.filter(|&r| r > 0);
+    let new = (rating > 0).then_some(rating);
+    if rated == new {
+        let message = match rated {
+            Some(r) => format!("{} is already rated {}", name, tui::rating_stars(r)),
+            None => format!("{} has no rating", name),
+        };
+        app.show_message(message, 2000);
+        return Ok(());
+    }
+    if let Err(e) = client.set_rating(id, rating).await {
+        let kind = match target {
+            StarTarget::Album => "albums",
+            _ => "songs",
+        };
+        anyhow::bail!("The server wouldn't rate {}, and may not take ratings for {}: {}", name, kind, e);
+    }
+    forget_listings_of(app, &target);
+
+    match target {
+        StarTarget::Album => {
+            for album in app.albums.iter_mut().filter(|a| a.id == id) {
+                album.rating = new;
+            }
+        }
+        _ => {
+            for song in app.songs.iter_mut().chain(app.queue.iter_mut()).filter(|s| s.id == id) {
+                song.rating = new;
+            }
+        }
+    }
+    for result in app.search_results.iter_mut() {
+        match result {
+            SearchResultItem::Album { id: result_id, rating, .. }
+            | SearchResultItem::Song { id: result_id, rating, .. }
+                if *result_id == id =>
+            {
+                *rating = new;
+            }
+            _ => {}
+        }
+    }
+    let was = rated.map_or(String::new(), |r| format!(" (was {})", tui::rating_stars(r)));
+    let message = match new {
+        Some(r) => format!("Rated {}: {}{}", name, tui::rating_stars(r), was),
+        None => format!("Cleared the rating of {}{}", name, was),
+    };
+    app.show_message(message, 2000);
+    Ok(())
+}
+
+/// Replaces the queue with an album from `start_index` on and plays it. The
+/// old queue is kept for `u`, and a synced play queue follows on the next
+/// tick, as after any other queue change.
+async fn replace_queue_with_album(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    album_id: &str,
+    start_index: usize,
+) -> Result<()> {
+    let songs = if app.current_view == ViewType::Songs {
+        app.songs.clone()
+    } else {
+        fetch_album_songs(client, album_id).await?.1
+    };
+    if songs.is_empty() {
+        app.show_message("Album has no songs".to_string(), 1500);
+        return Ok(());
+    }
+
+    let start_index = start_index.min(songs.len() - 1);
+    app.replace_queue(songs[start_index..].to_vec());
+    let count = app.queue.len();
+    audio_player.stop();
+    play_next_in_queue(client, app, audio_player, mpris_server).await?;
+    app.show_message(format!("Replaced the queue with {} songs (u brings back the old one)", count), 2000);
+    Ok(())
+}
+
+async fn random_songs(client: &SubsonicClient) -> Result<Vec<Song>> {
+    let songs = client
+        .get_random_songs(SHUFFLE_ALL_BATCH, None, None, None)
+        .await?
+        .random_songs
+        .song;
+    Ok(songs.into_iter().map(song_from_data).collect())
+}
+
+/// Replaces the queue with random songs from the whole library and plays.
+async fn shuffle_all(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+) -> Result<()> {
+    let songs = random_songs(client).await?;
+    if songs.is_empty() {
+        app.show_message("The library has no songs".to_string(), 1500);
+        return Ok(());
+    }
+    app.replace_queue(songs);
+    app.shuffle_all = true;
+    audio_player.stop();
+    play_next_in_queue(client, app, audio_player, mpris_server).await
+}
+
+/// Tops up a shuffle-all queue running low, and lets it go once something
+/// else is playing.
+async fn refill_shuffle_all(client: &SubsonicClient, app: &mut App) {
+    if !app.shuffle_all {
+        return;
+    }
+    if !matches!(app.current_playback_source, Some(PlaybackSource::Queue)) {
+        app.shuffle_all = false;
+        return;
+    }
+    if app.queue.len() > SHUFFLE_ALL_REFILL {
+        return;
+    }
+    match random_songs(client).await {
+        Ok(songs) => {
+            let count = songs.len();
+            // A library this small, or all of it queued already, has nothing more to give
+            if app.enqueue(songs) == count {
+                app.shuffle_all = false;
+            }
+        }
+        Err(e) => {
+            // Stop asking rather than retry every frame; the queue plays out
+            app.shuffle_all = false;
+            app.show_message(format!("Shuffle all stopped: {}", e), 3000);
+        }
+    }
+}
+
+/// Plays an album the server picks at random from its first track, in
+/// place of whatever is playing. The queue is left alone and comes after
+/// the album, as it would after any other.
+async fn play_random_album(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+) -> Result<()> {
+    let picked = client.get_album_list2("random", 1, 0).await?.album_list2.album;
+    let Some(album) = picked.into_iter().next() else {
+        app.show_message("The server has no albums to pick from".to_string(), 2000);
+        return Ok(());
+    };
+    let songs = fetch_album_songs(client, &album.id).await?.1;
+    let Some(first) = songs.first().cloned() else {
+        app.show_message(format!("Random album {} has no songs", album.name), 2000);
+        return Ok(());
+    };
+    let source = PlaybackSource::Album {
+        album_songs: songs,
+        current_index: 0,
+        album_id: Some(album.id),
+        artist_id: album.artist_id,
+        shuffle: None,
+    };
+    play_song(client, app, first, audio_player, mpris_server, source).await?;
+    let message = match album.artist {
+        Some(artist) => format!("Random album: {} by {}", album.name, artist),
+        None => format!("Random album: {}", album.name),
+    };
+    app.show_message(message, 3000);
+    Ok(())
+}
+
+/// Starts gathering every song by the open artist, asking first when
+/// they have a great many albums.
+fn shuffle_artist(client: &SubsonicClient, app: &mut App) {
+    if app.artist_shuffle.is_some() {
+        app.show_message("Already gathering an artist's songs (Esc to stop)".to_string(), 1500);
+        return;
+    }
+    let Some(artist) = app
+        .current_artist_id
+        .as_ref()
+        .and_then(|id| app.artists.iter().find(|a| &a.id == id))
+        .map(|a| a.name.clone())
+    else {
+        return;
+    };
+    let album_ids: Vec<String> = app.albums.iter().map(|a| a.id.clone()).collect();
+    if album_ids.is_empty() {
+        app.show_message(format!("{} has no albums to shuffle", artist), 2000);
+    } else if album_ids.len() > ARTIST_SHUFFLE_CONFIRM {
+        let prompt = format!("Fetch all {} albums by {} to shuffle?", album_ids.len(), artist);
+        app.ask_confirm(prompt, ConfirmAction::ShuffleArtist { artist, album_ids });
+    } else {
+        app.artist_shuffle = Some(artist_shuffle::ArtistShuffle::start(client, artist, album_ids));
+    }
+}
+
+/// Plays the songs of a finished artist shuffle.
+async fn play_artist_shuffle(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    shuffle: artist_shuffle::ArtistShuffle,
+) -> Result<()> {
+    for failure in &shuffle.failures {
+        app.log(format!("Couldn't fetch an album to shuffle: {}", failure));
+    }
+    let (artist, failed) = (shuffle.artist.clone(), shuffle.failures.len());
+    let songs = shuffle.into_songs();
+    let Some(first) = songs.first().cloned() else {
+        app.show_message(format!("Found no songs by {}", artist), 3000);
+        return Ok(());
+    };
+    let count = songs.len();
+    let source = PlaybackSource::Artist {
+        songs,
+        current_index: 0,
+    };
+    play_song(client, app, first, audio_player, mpris_server, source).await?;
+    let mut message = format!("Shuffling {} songs by {}", count, artist);
+    if failed > 0 {
+        message += &format!(" ({} albums failed, see :messages)", failed);
+    }
+    app.show_message(message, 3000);
+    Ok(())
+}
+
+async fn handle_select(
+    client: &SubsonicClient,
+    app: &mut App,
+    config: &types::Config,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+) -> Result<()> {
+    let selected = app.get_selected_index();
+    if selected.is_none() {
+        return Ok(());
+    }
+    let idx = selected.unwrap();
+
+    match app.current_view {
+        ViewType::Artists => {
+            if let Some(artist) = app.artists.get(idx) {
+                let artist_id = artist.id.clone();
+                app.current_artist_id = Some(artist_id.clone());
+                app.push_view(ViewType::Albums);
+                load_albums(client, app, &artist_id, config).await?;
+            }
+        }
+        ViewType::Albums => {
+            if let Some(album) = app.albums.get(idx) {
+                let album_id = album.id.clone();
+                app.current_album_id = Some(album_id.clone());
+                app.push_view(ViewType::Songs);
+                load_songs(client, app, &album_id, config).await?;
+            }
+        }
+        ViewType::Songs => {
+            if let Some(song) = app.songs.get(idx) {
+                let song = song.clone();
+                if let Some(playlist_id) = app.playlist_open.as_ref().map(|p| p.id.clone()) {
+                    let source = PlaybackSource::Playlist {
+                        songs: app.songs.clone(),
+                        current_index: idx,
+                        playlist_id,
+                        shuffle: None,
+                    };
+                    return play_song(client, app, song, audio_player, mpris_server, source).await;
+                }
+                if app.starred_open {
+                    let source = PlaybackSource::Starred {
+                        songs: app.songs.clone(),
+                        current_index: idx,
+                        shuffle: None,
+                    };
+                    return play_song(client, app, song, audio_player, mpris_server, source).await;
+                }
+                if let Some(artist) = app.top_songs_open.clone() {
+                    return select_top_song(client, app, config, audio_player, mpris_server, &artist, idx).await;
+                }
+                match config.on_album_select {
+                    AlbumSelectMode::Play => {
+                        // Offer to pick up where the album was left, once
+                        if let Some(album_id) = app.current_album_id.clone()
+                            && app.resume_offered.as_ref() != Some(&album_id)
+                            && let Some(progress) = app.album_progress.get(&album_id)
+                            && progress.track_index < app.songs.len()
+                        {
+                            let prompt = format!(
+                                "Resume from track {} at {}?",
+                                progress.track_index + 1,
+                                tui::format_duration(progress.position as i64)
+                            );
+                            let action = ConfirmAction::ResumeAlbum {
+                                album_id: album_id.clone(),
+                                track_index: progress.track_index,
+                                position: progress.position,
+                            };
+                            app.resume_offered = Some(album_id);
+                            app.ask_confirm(prompt, action);
+                            return Ok(());
+                        }
+                        // When playing from Songs view, set up album continuation
+                        let source = PlaybackSource::Album {
+                            album_songs: app.songs.clone(),
+                            current_index: idx,
+                            album_id: app.current_album_id.clone(),
+                            artist_id: app.current_artist_id.clone(),
+                            shuffle: None,
+                        };
+                        play_song(client, app, song, audio_player, mpris_server, source).await?;
+                    }
+                    AlbumSelectMode::Append => {
+                        let album_id = app.current_album_id.clone().unwrap_or_default();
+                        append_album(client, app, &album_id).await?;
+                    }
+                    AlbumSelectMode::ReplaceQueue => {
+                        let album_id = app.current_album_id.clone().unwrap_or_default();
+                        replace_queue_with_album(client, app, audio_player, mpris_server, &album_id, idx)
+                            .await?;
+                    }
+                }
+            }
+        }
+        ViewType::Search => {
+            if let Some(result) = app.search_results.get(idx) {
+                match result {
+                    SearchResultItem::Album { id, folder: true, .. } => {
+                        let id = id.clone();
+                        open_folder(client, app, &id).await?;
+                    }
+                    SearchResultItem::Album { id, artist_id, .. } => {
+                        let id_clone = id.clone();
+                        let artist_id_clone = artist_id.clone();
+                        app.current_artist_id = Some(artist_id_clone);
+                        app.current_album_id = Some(id_clone.clone());
+                        app.push_view(ViewType::Songs);
+                        load_songs(client, app, &id_clone, config).await?;
+                    }
+                    SearchResultItem::Song { .. } => {
+                        // Album results are skipped when walking the list
+                        let songs: Vec<Song> = app
+                            .search_results
+                            .iter()
+                            .filter_map(SearchResultItem::to_song)
+                            .collect();
+                        let current_index = app.search_results[..idx]
+                            .iter()
+                            .filter(|r| matches!(r, SearchResultItem::Song { .. }))
+                            .count();
+                        let song = songs[current_index].clone();
+                        let source = PlaybackSource::Search { songs, current_index };
+                        play_song(client, app, song, audio_player, mpris_server, source).await?;
+                    }
+                }
+            }
+        }
+        ViewType::Folders => {
+            let Some(folder) = app.folders.last() else {
+                return Ok(());
+            };
+            match folder.entries.get(idx) {
+                Some(FolderEntry::Dir { id, .. }) => {
+                    let id = id.clone();
+                    open_folder(client, app, &id).await?;
+                }
+                Some(FolderEntry::File(song)) => {
+                    // Play on through the rest of the directory's files
+                    let song = song.as_ref().clone();
+                    let files = folder_files(folder);
+                    let current_index = folder.entries[..idx]
+                        .iter()
+                        .filter(|e| matches!(e, FolderEntry::File(_)))
+                        .count();
+                    let source = PlaybackSource::Album {
+                        album_songs: files,
+                        current_index,
+                        album_id: None,
+                        artist_id: None,
+                        shuffle: None,
+                    };
+                    play_song(client, app, song, audio_player, mpris_server, source).await?;
+                }
+                None => {}
+            }
+        }
+        ViewType::Podcasts => {
+            if let Some(channel) = app.podcasts.get(idx).cloned() {
+                open_episodes(client, app, &channel).await?;
+            }
+        }
+        ViewType::Episodes => {
+            let Some(episode) = app.episodes.get(idx) else {
+                return Ok(());
+            };
+            match episode_song(&app.podcast_title, episode) {
+                // Offer to carry on where it was left, once
+                Some(song)
+                    if app.resume_offered.as_ref() != Some(&song.id)
+                        && let Some(position) = app.episode_progress.get(&song.id) =>
+                {
+                    let prompt = format!("Continue from {}?", tui::format_duration(position.as_secs() as i64));
+                    app.resume_offered = Some(song.id.clone());
+                    app.ask_confirm(prompt, ConfirmAction::ResumeEpisode { id: song.id, position });
+                }
+                Some(song) => play_song(client, app, song, audio_player, mpris_server, PlaybackSource::Queue).await?,
+                None => app.show_message(
+                    format!("Not on the server yet: {}", tui::episode_label(episode)),
+                    3000,
+                ),
+            }
+        }
+        ViewType::Playlists => {
+            // The server's playlists come after the smart ones
+            if let Some(server_idx) = idx.checked_sub(app.smart_playlists.len()) {
+                if let Some(playlist) = app.playlists.get(server_idx) {
+                    let id = playlist.id.clone();
+                    open_server_playlist(client, app, config, &id).await?;
+                }
+            } else if let Some(playlist) = app.smart_playlists.get(idx).cloned() {
+                let songs = evaluate_smart_playlist(client, &playlist).await?;
+                app.current_album_id = None;
+                app.push_view(ViewType::Songs);
+                app.songs = songs;
+                app.starred_open = false;
+                app.playlist_open = None;
+                app.top_songs_open = None;
+                let items: Vec<String> = app.songs.iter().map(|s| s.title.clone()).collect();
+                app.set_items(items);
+                app.current_base_content = format!(
+                    "Smart playlist: {} ({} songs){}",
+                    playlist.name,
+                    app.songs.len(),
+                    get_random_easter_egg(config.show_easter_eggs)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Pages through the starred songs, the genre or else the whole library
+/// and applies the full rule set to what comes back, so the same library
+/// always gives the same playlist.
+async fn evaluate_smart_playlist(
+    client: &SubsonicClient,
+    playlist: &smart::SmartPlaylist,
+) -> Result<Vec<Song>> {
+    let articles = if playlist.order == smart::SmartOrder::Artist {
+        IgnoredArticles::parse(&client.get_artists().await?.artists.ignored_articles)
+    } else {
+        IgnoredArticles::default()
+    };
+    if playlist.starred == Some(true) {
+        let starred = client.get_starred2().await?.starred2.song;
+        return Ok(playlist.apply(starred.into_iter().map(song_from_data).collect(), &articles));
+    }
+
+    let mut candidates: Vec<Song> = Vec::new();
+    let mut offset = 0;
+    loop {
+        let page: Vec<Song> = match &playlist.genre {
+            Some(genre) => client
+                .get_songs_by_genre(genre, SMART_PLAYLIST_PAGE, offset)
+                .await?
+                .songs_by_genre
+                .song
+                .into_iter()
+                .map(song_from_data)
+                .collect(),
+            None => client
+                .search3_page("", 0, 0, SMART_PLAYLIST_PAGE, offset)
+                .await?
+                .into_result()
+                .and_then(|result| result.song)
+                .unwrap_or_default()
+                .into_iter()
+                .map(song_from_search)
+                .collect(),
+        };
+        let last = page.len() < SMART_PLAYLIST_PAGE as usize;
+        offset += page.len();
+        candidates.extend(page.into_iter().filter(|song| playlist.matches(song)));
+        // In server order the first matches are the ones kept anyway
+        let enough = playlist.order == smart::SmartOrder::Server
+            && playlist.limit.is_some_and(|limit| candidates.len() >= limit);
+        if last || enough {
+            break;
+        }
+    }
+    Ok(playlist.apply(candidates, &articles))
+}
+
+/// Lists the open artist's top songs in the Songs view. Like a smart
+/// playlist, playing one carries on through the rest.
+async fn open_top_songs(client: &SubsonicClient, app: &mut App, config: &types::Config) -> Result<()> {
+    let Some(artist) = app
+        .current_artist_id
+        .as_ref()
+        .and_then(|id| app.artists.iter().find(|a| &a.id == id))
+        .map(|a| a.name.clone())
+    else {
+        return Ok(());
+    };
+    // Not every server has the endpoint, or data behind it
+    let songs: Vec<Song> = match client.get_top_songs(&artist, TOP_SONGS_COUNT).await {
+        Ok(response) => response.top_songs.song.into_iter().map(song_from_data).collect(),
+        Err(e) => {
+            log::info!("No top songs for {}: {:#}", artist, e);
+            Vec::new()
+        }
+    };
+    if songs.is_empty() {
+        app.show_message(format!("The server has no top songs for {}", artist), 3000);
+        return Ok(());
+    }
+    app.current_album_id = None;
+    app.push_view(ViewType::Songs);
+    app.songs = songs;
+    app.starred_open = false;
+    app.playlist_open = None;
+    app.top_songs_open = Some(artist.clone());
+    app.set_items(app.songs.iter().map(|s| s.title.clone()).collect());
+    app.current_base_content = format!(
+        "Top songs by {}{}",
+        artist,
+        get_random_easter_egg(config.show_easter_eggs)
+    );
+    Ok(())
+}
+
+/// Plays, queues or replaces the queue with the open top songs from `idx`
+/// on, as `on_album_select` says.
+async fn select_top_song(
+    client: &SubsonicClient,
+    app: &mut App,
+    config: &types::Config,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    artist: &str,
+    idx: usize,
+) -> Result<()> {
+    let songs = app.songs[idx..].to_vec();
+    match config.on_album_select {
+        AlbumSelectMode::Play => {
+            let source = PlaybackSource::TopSongs {
+                songs: app.songs.clone(),
+                current_index: idx,
+            };
+            play_song(client, app, songs[0].clone(), audio_player, mpris_server, source).await
+        }
+        AlbumSelectMode::Append => {
+            let count = songs.len();
+            let skipped = app.enqueue(songs);
+            app.show_message(
+                format!(
+                    "Added {} top songs by {} (Queue: {}){}",
+                    count - skipped,
+                    artist,
+                    app.queue.len(),
+                    already_queued(skipped)
+                ),
+                1500,
+            );
+            Ok(())
+        }
+        AlbumSelectMode::ReplaceQueue => {
+            app.replace_queue(songs);
+            audio_player.stop();
+            play_next_in_queue(client, app, audio_player, mpris_server).await
+        }
+    }
+}
+
+/// Lists the podcast channels the server follows.
+async fn open_podcasts(client: &SubsonicClient, app: &mut App) -> Result<()> {
+    app.podcasts = client.get_podcasts().await?.podcasts.channel;
+    if app.current_view != ViewType::Podcasts {
+        app.push_view(ViewType::Podcasts);
+    }
+    app.set_items(app.podcasts.iter().map(tui::podcast_label).collect());
+    app.current_base_content = "Podcasts".to_string();
+    if app.podcasts.is_empty() {
+        app.show_message("The server doesn't follow any podcasts".to_string(), 3000);
+    }
+    Ok(())
+}
+
+async fn open_episodes(client: &SubsonicClient, app: &mut App, channel: &PodcastChannel) -> Result<()> {
+    app.episodes = client.get_podcast_episodes(&channel.id).await?;
+    app.podcast_title = tui::podcast_label(channel);
+    app.resume_offered = None;
+    app.push_view(ViewType::Episodes);
+    app.set_items(app.episodes.iter().map(tui::episode_label).collect());
+    app.current_base_content = format!("{} ({} episodes)", app.podcast_title, app.episodes.len());
+    Ok(())
+}
+
+/// An episode as a song to play, or None if the server doesn't have it yet.
+fn episode_song(channel: &str, episode: &PodcastEpisode) -> Option<Song> {
+    Some(Song {
+        id: episode.playable_id()?.to_string(),
+        title: episode.title.clone().unwrap_or_else(|| episode.id.clone()),
+        artist: Some(channel.to_string()),
+        album: Some(channel.to_string()),
+        duration: episode.duration,
+        podcast: true,
+        ..Default::default()
+    })
+}
+
+/// Opens the top of the library's folder tree, leaving any folders that
+/// were open.
+async fn open_folders(client: &SubsonicClient, app: &mut App) -> Result<()> {
+    let indexes = client.get_indexes().await?.indexes;
+    let articles = IgnoredArticles::parse(&indexes.ignored_articles);
+    let mut folders: Vec<_> = indexes.index.into_iter().flat_map(|index| index.artist).collect();
+    folders.sort_by_cached_key(|folder| articles.sort_key(&folder.name));
+    let entries: Vec<FolderEntry> = folders
+        .into_iter()
+        .map(|folder| FolderEntry::Dir {
+            id: folder.id,
+            name: folder.name,
+        })
+        .chain(indexes.child.into_iter().map(folder_entry))
+        .collect();
+    while app.current_view == ViewType::Folders && app.go_back() {}
+    app.push_view(ViewType::Folders);
+    show_folder(
+        app,
+        Folder {
+            name: "Folders".to_string(),
+            entries,
+        },
+    );
+    Ok(())
+}
+
+async fn open_folder(client: &SubsonicClient, app: &mut App, id: &str) -> Result<()> {
+    let directory = client.get_music_directory(id).await?.directory;
+    let entries = directory.child.into_iter().map(folder_entry).collect();
+    app.push_view(ViewType::Folders);
+    show_folder(
+        app,
+        Folder {
+            name: directory.name,
+            entries,
+        },
+    );
+    Ok(())
+}
+
+fn show_folder(app: &mut App, folder: Folder) {
+    let items = folder.entries.iter().map(tui::folder_entry_label).collect();
+    app.folders.push(folder);
+    app.set_items(items);
+    app.current_base_content = tui::folder_path(&app.folders);
+}
+
+fn folder_entry(child: DirectoryChild) -> FolderEntry {
+    if child.is_dir {
+        FolderEntry::Dir {
+            id: child.song.id,
+            name: child.song.title,
+        }
+    } else {
+        FolderEntry::File(Box::new(song_from_data(child.song)))
+    }
+}
+
+fn folder_files(folder: &Folder) -> Vec<Song> {
+    folder
+        .entries
+        .iter()
+        .filter_map(|entry| match entry {
+            FolderEntry::File(song) => Some(song.as_ref().clone()),
+            FolderEntry::Dir { .. } => None,
+        })
+        .collect()
+}
+
+/// A directory's name and the files directly in it.
+async fn fetch_folder_files(client: &SubsonicClient, id: &str) -> Result<(String, Vec<Song>)> {
+    let directory = client.get_music_directory(id).await?.directory;
+    let folder = Folder {
+        name: directory.name,
+        entries: directory.child.into_iter().map(folder_entry).collect(),
+    };
+    let songs = folder_files(&folder);
+    Ok((folder.name, songs))
+}
+
+/// Queues the selected file, or the files directly in the selected directory.
+async fn queue_folder_entry(client: &SubsonicClient, app: &mut App, idx: usize) -> Result<()> {
+    let Some(entry) = app.folders.last().and_then(|f| f.entries.get(idx)).cloned() else {
+        return Ok(());
+    };
+    match entry {
+        FolderEntry::File(song) => add_to_queue(app, *song),
+        FolderEntry::Dir { id, name } => {
+            let songs = fetch_folder_files(client, &id).await?.1;
+            if songs.is_empty() {
+                app.show_message(format!("No files directly in {}", name), 2000);
+                return Ok(());
+            }
+            let count = songs.len();
+            let skipped = app.enqueue(songs);
+            app.show_message(
+                format!(
+                    "Added {} songs from {} (Queue: {}){}",
+                    count - skipped,
+                    name,
+                    app.queue.len(),
+                    already_queued(skipped)
+                ),
+                2000,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Lists the starred songs in the Songs view.
+async fn open_starred(client: &SubsonicClient, app: &mut App, config: &types::Config) -> Result<()> {
+    let songs: Vec<Song> = client
+        .get_starred2()
+        .await?
+        .starred2
+        .song
+        .into_iter()
+        .map(song_from_data)
+        .collect();
+    if app.current_view != ViewType::Songs || !app.starred_open {
+        app.push_view(ViewType::Songs);
+    }
+    app.current_album_id = None;
+    app.songs = songs;
+    app.starred_open = true;
+    app.playlist_open = None;
+    app.top_songs_open = None;
+    app.set_items(app.songs.iter().map(|s| s.title.clone()).collect());
+    app.current_base_content = format!(
+        "Starred songs ({}){}",
+        app.songs.len(),
+        get_random_easter_egg(config.show_easter_eggs)
+    );
+    Ok(())
+}
+
+/// Lists the smart playlists followed by the server's own. Either can fail
+/// without keeping the other from showing.
+async fn open_playlists(client: &SubsonicClient, app: &mut App) -> Result<()> {
+    let (playlists, mut errors) = smart::load_smart_playlists(&get_smart_playlists_path()?);
+    app.smart_playlists = playlists;
+    match client.get_playlists().await {
+        Ok(response) => app.playlists = response.playlists.playlist,
+        Err(e) => {
+            app.playlists.clear();
+            errors.insert(0, format!("Couldn't load the server's playlists: {}", e));
+        }
+    }
+    if app.current_view != ViewType::Playlists {
+        app.push_view(ViewType::Playlists);
+    }
+    let names = app.smart_playlists.iter().map(|p| p.name.clone());
+    app.set_items(names.chain(app.playlists.iter().map(|p| p.name.clone())).collect());
+    app.current_base_content = "Playlists".to_string();
+
+    if let Some(first) = errors.first() {
+        let more = if errors.len() > 1 {
+            format!(" (+{} more)", errors.len() - 1)
+        } else {
+            String::new()
+        };
+        app.show_message(format!("{}{}", first, more), 5000);
+    } else if app.smart_playlists.is_empty() && app.playlists.is_empty() {
+        app.show_message(
+            format!(
+                "No playlists on the server, and no smart playlists defined in {:?}",
+                get_smart_playlists_path()?
+            ),
+            3000,
+        );
+    }
+    Ok(())
+}
+
+/// Lists a server playlist's songs in the Songs view.
+async fn open_server_playlist(
+    client: &SubsonicClient,
+    app: &mut App,
+    config: &types::Config,
+    id: &str,
+) -> Result<()> {
+    let playlist = client.get_playlist(id).await?.playlist;
+    let missing = playlist
+        .song_count
+        .map_or(0, |count| count.saturating_sub(playlist.entry.len() as u64));
+    app.current_album_id = None;
+    app.push_view(ViewType::Songs);
+    app.songs = playlist.entry.into_iter().map(song_from_data).collect();
+    app.starred_open = false;
+    app.playlist_open = Some(OpenPlaylist {
+        id: playlist.id,
+        name: playlist.name.clone(),
+        missing,
+    });
+    app.top_songs_open = None;
+    app.set_items(app.songs.iter().map(|s| s.title.clone()).collect());
+    app.current_base_content = format!(
+        "Playlist: {} ({} songs){}",
+        playlist.name,
+        app.songs.len(),
+        get_random_easter_egg(config.show_easter_eggs)
+    );
+    if missing > 0 {
+        app.show_message(format!("{} songs in this playlist are no longer on the server", missing), 3000);
+    } else if app.songs.is_empty() {
+        app.show_message("Playlist is empty".to_string(), 1500);
+    }
+    Ok(())
+}
+
+/// Opens the picker for saving the queue, listing the server playlists the
+/// user owns and so may add to.
+async fn open_playlist_picker(client: &SubsonicClient, app: &mut App, username: &str) -> Result<()> {
+    let mut playlists = client.get_playlists().await?.playlists.playlist;
+    playlists.retain(|p| p.owner.as_deref().is_none_or(|owner| owner == username));
+    app.playlist_picker = Some(PlaylistPicker::new(playlists));
+    Ok(())
+}
+
+/// Saves the queue as the playlist picker says and closes it.
+async fn save_queue_to_playlist(client: &SubsonicClient, app: &mut App) {
+    let Some(picker) = app.playlist_picker.take() else {
+        return;
+    };
+    let ids: Vec<String> = app.queue.iter().map(|s| s.id.clone()).collect();
+    let (result, name) = match picker.target() {
+        Some(playlist) => (
+            client.update_playlist(&playlist.id, &ids).await,
+            playlist.name.clone(),
+        ),
+        None => {
+            let name = picker.name.trim().to_string();
+            (client.create_playlist(&name, &ids).await.map(|_| ()), name)
+        }
+    };
+    match result {
+        Ok(()) if picker.target().is_some() => {
+            app.show_message(format!("Added {} songs to playlist {}", ids.len(), name), 3000)
+        }
+        Ok(()) => app.show_message(format!("Saved {} songs as playlist {}", ids.len(), name), 3000),
+        Err(e) => app.show_message(format!("Couldn't save playlist {}: {}", name, e), 5000),
+    }
+}
+
+/// "Playlist: Road trip (12 songs)", for the open playlist after a change.
+fn show_playlist_heading(app: &mut App) {
+    if let Some(playlist) = &app.playlist_open {
+        app.current_base_content = format!("Playlist: {} ({} songs)", playlist.name, app.songs.len());
+    }
+}
+
+/// Takes the selected song out of the open server playlist.
+async fn remove_from_playlist(client: &SubsonicClient, app: &mut App) -> Result<()> {
+    let Some(playlist) = app.open_playlist().cloned() else {
+        return Ok(());
+    };
+    let Some(idx) = app.get_selected_index().filter(|&i| i < app.songs.len()) else {
+        return Ok(());
+    };
+    if playlist.missing == 0 {
+        client.remove_from_playlist(&playlist.id, &[idx]).await?;
+    } else {
+        // Our indices don't match the server's, so write out what's left,
+        // which also clears the entries it couldn't resolve
+        let ids: Vec<String> = app
+            .songs
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != idx)
+            .map(|(_, s)| s.id.clone())
+            .collect();
+        client.set_playlist_songs(&playlist.id, &ids).await?;
+        if let Some(open) = app.playlist_open.as_mut() {
+            open.missing = 0;
+        }
+    }
+    let song = app.songs.remove(idx);
+    let selected = (!app.songs.is_empty()).then(|| idx.min(app.songs.len() - 1));
+    app.list_state.select(selected);
+    show_playlist_heading(app);
+    app.show_message(format!("Removed {} from {}", song.title, playlist.name), 2000);
+    Ok(())
+}
+
+/// Moves the selected song of the open server playlist `offset` rows.
+async fn move_in_playlist(client: &SubsonicClient, app: &mut App, offset: isize) -> Result<()> {
+    let Some(playlist) = app.open_playlist().cloned() else {
+        return Ok(());
+    };
+    let Some(idx) = app.get_selected_index() else {
+        return Ok(());
+    };
+    let Some(target) = idx.checked_add_signed(offset).filter(|&t| t < app.songs.len()) else {
+        return Ok(());
+    };
+    app.songs.swap(idx, target);
+    app.list_state.select(Some(target));
+    let ids: Vec<String> = app.songs.iter().map(|s| s.id.clone()).collect();
+    if let Err(e) = client.set_playlist_songs(&playlist.id, &ids).await {
+        app.songs.swap(idx, target);
+        app.list_state.select(Some(idx));
+        return Err(e);
+    }
+    if let Some(open) = app.playlist_open.as_mut() {
+        open.missing = 0;
+    }
+    Ok(())
+}
+
+/// Renames the open server playlist to what was typed.
+async fn rename_playlist(client: &SubsonicClient, app: &mut App) -> Result<()> {
+    let Some(name) = app.playlist_rename.take() else {
+        return Ok(());
+    };
+    let name = name.trim().to_string();
+    let Some(id) = app.open_playlist().map(|p| p.id.clone()) else {
+        return Ok(());
+    };
+    client.rename_playlist(&id, &name).await?;
+    if let Some(open) = app.playlist_open.as_mut() {
+        open.name = name.clone();
+    }
+    if let Some(listed) = app.playlists.iter_mut().find(|p| p.id == id) {
+        listed.name = name.clone();
+    }
+    show_playlist_heading(app);
+    app.show_message(format!("Renamed playlist to {}", name), 2000);
+    Ok(())
+}
+
+/// Deletes a server playlist, leaving it first if it's open.
+async fn delete_playlist(client: &SubsonicClient, app: &mut App, id: &str, name: &str) -> Result<()> {
+    client.delete_playlist(id).await?;
+    if app.open_playlist().is_some_and(|p| p.id == id) {
+        app.go_back();
+    }
+    app.playlists.retain(|p| p.id != id);
+    if app.current_view == ViewType::Playlists {
+        let count = app.smart_playlists.len() + app.playlists.len();
+        let selected = app.get_selected_index().map(|i| i.min(count.saturating_sub(1)));
+        app.list_state.select(selected.filter(|_| count > 0));
+    }
+    app.show_message(format!("Deleted playlist {}", name), 2000);
+    Ok(())
+}
+
+async fn play_song(
+    client: &SubsonicClient,
+    app: &mut App,
+    song: Song,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    source: PlaybackSource,
+) -> Result<()> {
+    app.show_message(format!("Playing: {}", song.title), 2000);
+
+    if let Some(jukebox) = app.jukebox.as_mut() {
+        jukebox.play(&song, &app.queue).await?;
+        return song_started(client, app, song, audio_player, mpris_server, source).await;
+    }
+
+    let track = match cached_song(client, app, &song.id).await {
+        Some(bytes) => StreamBuffer::complete(bytes),
+        None => start_stream(client, app, &song.id).await?,
+    };
+
+    let mut result = start_playing(audio_player, &track);
+    let mut fallback = None;
+    // A codec rodio doesn't know gets one more go, converted by the server
+    if let Err(e) = &result
+        && danavi::audio::is_unsupported_format(e)
+    {
+        track.cancel();
+        app.show_message("transcoding on server (unsupported codec)".to_string(), 3000);
+        let options = StreamOptions {
+            format: Some(FALLBACK_FORMAT.to_string()),
+            max_bit_rate: FALLBACK_BIT_RATE,
+            time_offset: 0,
+        };
+        let response = client
+            .stream_transcoded(&song.id, &options)
+            .await?;
+        let transcoded = feed_stream(response, None);
+        result = start_playing(audio_player, &transcoded);
+        if result.is_err() {
+            transcoded.cancel();
+        }
+        fallback = Some(options);
+    }
+    if let Err(e) = result {
+        track.cancel();
+        // A server error is spelled out by error_message; anything else gets
+        // its cause in the one message the status line shows
+        if e.downcast_ref::<client::SubsonicError>().is_some() {
+            return Err(e);
+        }
+        return Err(anyhow::anyhow!("Failed to play audio: {:#}", e));
+    }
+    app.fallback_stream = fallback;
+    song_started(client, app, song, audio_player, mpris_server, source).await
+}
+
+/// Waits on the first chunks and reads the header block on this thread.
+fn start_playing(audio_player: &AudioPlayer, track: &std::sync::Arc<StreamBuffer>) -> Result<()> {
+    tokio::task::block_in_place(|| {
+        track.wait_for(STREAM_HEAD_START);
+        audio_player.play_stream(track.clone())
+    })
+}
+
+/// Everything that follows a song starting, whether by [`play_song`] or by
+/// playback moving on to a lined up track.
+async fn song_started(
+    client: &SubsonicClient,
+    app: &mut App,
+    song: Song,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    source: PlaybackSource,
+) -> Result<()> {
+    app.track_starts = app.track_starts.wrapping_add(1);
+    let gain = song.replay_gain.unwrap_or_default();
+    audio_player.set_gain(gain.multiplier(app.replaygain, app.replaygain_preamp));
+
+    // Some servers leave the length out; the file may know it
+    let duration = song
+        .duration
+        .or_else(|| audio_player.track_duration().map(|d| d.as_secs() as i64));
+
+    // Update MPRIS state and emit PropertiesChanged signal
+    let stream_url = client.song_url(&song.id).ok();
+    mpris_server.update_current_song(
+        Some(mpris::Song {
+            id: song.id.clone(),
+            title: song.title.clone(),
+            artist: song.artist.clone(),
+            album: song.album.clone(),
+            album_artist: song.album_artist.clone(),
+            duration,
+            art_url: None,
+            genre: song.genre.clone(),
+            year: song.year,
+            track: song.track,
+            disc_number: song.disc_number,
+            podcast: song.podcast,
+        }),
+        stream_url,
+    ).await?;
+    fetch_cover_art(client, app, &song);
+    mpris_server.update_playback_status(PlaybackStatus::Playing).await?;
+
+    // Track the playback source
+    let same_album = matches!(
+        (&app.current_playback_source, &source),
+        (Some(PlaybackSource::Album { album_id: Some(old), .. }), PlaybackSource::Album { album_id: Some(new), .. })
+            if old == new
+    );
+    if !same_album {
+        remember_album_progress(app, audio_player);
+    }
+    let mut source = source;
+    source.set_shuffle(app.shuffle);
+    if matches!(source, PlaybackSource::Queue) {
+        app.queue_played = match app.current_playback_source {
+            Some(PlaybackSource::Queue) => app.queue_played + 1,
+            _ => 1,
+        };
+    }
+    if let Some(radio) = app.radio.as_mut() {
+        radio.played(&song.id);
+    }
+    app.current_playback_source = Some(source);
+    app.playback_failures = 0;
+
+    Ok(())
+}
+
+/// Looks up the song's cover art in the background, downloading it if it
+/// isn't cached, for MPRIS once it's there.
+fn fetch_cover_art(client: &SubsonicClient, app: &mut App, song: &Song) {
+    app.cover_art = None;
+    let (Some(covers), Some(cover_art)) = (app.covers.clone(), song.cover_art.clone()) else {
+        return;
+    };
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let client = client.clone();
+    let id = song.id.clone();
+    tokio::spawn(async move {
+        let path = covers.fetch(&client, &cover_art, Some(COVER_ART_SIZE)).await;
+        let _ = tx.send((id, path));
+    });
+    app.cover_art = Some(rx);
+}
+
+/// The album track to line up after the current one, if any. The queue
+/// comes first, so nothing is lined up while it has songs.
+fn gapless_target(app: &App) -> Option<(Song, PlaybackSource)> {
+    if !app.queue.is_empty() {
+        return None;
+    }
+    let source @ PlaybackSource::Album { .. } = app.current_playback_source.as_ref()? else {
+        return None;
+    };
+    let next_index = *source.upcoming().first()?;
+    let (songs, _) = source.list()?;
+    Some((songs.get(next_index)?.clone(), source.with_index(next_index)))
+}
+
+/// Keeps the next album track lined up in the player so it follows without
+/// a gap.
+async fn update_gapless(client: &SubsonicClient, app: &mut App, audio_player: &AudioPlayer) {
+    // The server's player goes from song to song on its own
+    if app.jukebox.is_some() {
+        return;
+    }
+    let target = gapless_target(app);
+    // Shuffle, the queue or another song got in the way
+    if let Some((song, _)) = &app.preloaded
+        && target.as_ref().is_none_or(|(next, _)| next.id != song.id)
+    {
+        audio_player.drop_queued();
+        app.preloaded = None;
+    }
+
+    if app.preloaded.is_some() {
+        if !audio_player.has_queued() {
+            app.preloaded = None;
+        }
+        return;
+    }
+
+    let Some((song, source)) = target else {
+        return;
+    };
+    let near_end = app
+        .track_time
+        .and_then(|(elapsed, duration)| Some(duration? - elapsed.as_secs() as i64))
+        .is_some_and(|left| left <= GAPLESS_LEAD.as_secs() as i64);
+    if !near_end || audio_player.is_paused() || app.gapless_tried == app.playing_id {
+        return;
+    }
+    // One try per track; a failure falls back to starting it the usual way
+    app.gapless_tried = app.playing_id.clone();
+    let track = match cached_song(client, app, &song.id).await {
+        Some(bytes) => StreamBuffer::complete(bytes),
+        None => match start_stream(client, app, &song.id).await {
+            Ok(track) => track,
+            Err(e) => {
+                app.log(format!("Couldn't line up {}: {}", song.title, e));
+                return;
+            }
+        },
+    };
+    let result = tokio::task::block_in_place(|| {
+        track.wait_for(STREAM_HEAD_START);
+        audio_player.queue_stream(track.clone())
+    });
+    match result {
+        Ok(()) => app.preloaded = Some((song, source)),
+        Err(e) => {
+            track.cancel();
+            app.log(format!("Couldn't line up {}: {}", song.title, e));
+        }
+    }
+}
+
+/// Starts downloading a song in the background, into a buffer that can be
+/// played while it fills. The download stops when the track is stopped or
+/// replaced, and holds off while it's paused.
+async fn start_stream(client: &SubsonicClient, app: &App, id: &str) -> Result<std::sync::Arc<StreamBuffer>> {
+    let (response, _) = client.stream_song_from(id, 0).await?;
+    Ok(feed_stream(response, stream_keeper(client, app, id)))
+}
+
+// Keeps a streamed song once it has all arrived, to play from disk next time
+fn stream_keeper(client: &SubsonicClient, app: &App, id: &str) -> Option<Box<dyn FnOnce(Vec<u8>) + Send>> {
+    app.stream_cache.clone().map(|cache| {
+        let id = id.to_string();
+        let variant = client.stream_variant();
+        Box::new(move |bytes: Vec<u8>| {
+            if let Err(e) = cache.store(&id, &variant, &bytes) {
+                log::warn!("Failed to cache {}: {:#}", id, e);
+            }
+        }) as Box<dyn FnOnce(Vec<u8>) + Send>
+    })
+}
+
+/// Holds off the playing track's download while paused, and drops the one
+/// lined up behind it, which is lined up again after resuming.
+fn hold_download(app: &mut App, audio_player: &AudioPlayer) {
+    if let Some(track) = audio_player.current_track() {
+        track.suspend();
+    }
+    audio_player.drop_queued();
+    app.preloaded = None;
+    app.gapless_tried = None;
+}
+
+/// Takes the playing track's download back up after a pause held it off.
+/// A raw file carries on from the byte it got to. A conversion can't be
+/// picked up at a byte, so the server starts another from where playback
+/// is. A failure ends the track where its data runs out, with the error
+/// shown.
+async fn resume_download(client: &SubsonicClient, app: &App, audio_player: &AudioPlayer) {
+    let (Some(track), Some(id)) = (audio_player.current_track(), app.playing_id.as_deref()) else {
+        return;
+    };
+    let Some(offset) = track.resume() else {
+        return;
+    };
+    let options = match &app.fallback_stream {
+        Some(options) => Some(options.clone()),
+        None => client.transcodes().then(|| client.stream_options()),
+    };
+    let result = match options {
+        None => match client.stream_song_from(id, offset as u64).await {
+            // A server ignoring the range sends it all again
+            Ok((response, resumed)) => {
+                let skip = if resumed { 0 } else { offset };
+                feed_into(&track, response, skip, stream_keeper(client, app, id));
+                Ok(())
+            }
+            Err(e) => Err(e),
+        },
+        Some(options) => {
+            let start = Duration::from_secs(playback_position(app, audio_player).as_secs());
+            let options = StreamOptions {
+                time_offset: start.as_secs(),
+                ..options
+            };
+            match client.stream_transcoded(id, &options).await {
+                Ok(response) => {
+                    let resumed = feed_stream(response, None);
+                    let result = tokio::task::block_in_place(|| {
+                        resumed.wait_for(STREAM_HEAD_START);
+                        audio_player.seek_stream(resumed.clone(), start)
+                    });
+                    if result.is_err() {
+                        resumed.cancel();
+                    }
+                    result
+                }
+                Err(e) => Err(e),
+            }
+        }
+    };
+    if let Err(e) = result {
+        track.fail(format!("{:#}", e));
+    }
+}
+
+/// A song kept on disk, downloaded or from streaming it before. Read off
+/// the UI loop, since a whole song is read and checksummed.
+async fn cached_song(client: &SubsonicClient, app: &App, id: &str) -> Option<Vec<u8>> {
+    let (song_cache, stream_cache) = (app.song_cache.clone(), app.stream_cache.clone());
+    let (id, variant) = (id.to_string(), client.stream_variant());
+    tokio::task::spawn_blocking(move || {
+        song_cache
+            .and_then(|cache| cache.get(&id))
+            .or_else(|| stream_cache?.get(&id, &variant))
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+fn feed_stream(
+    response: reqwest::Response,
+    keep: Option<Box<dyn FnOnce(Vec<u8>) + Send>>,
+) -> std::sync::Arc<StreamBuffer> {
+    let track = StreamBuffer::new(response.content_length());
+    feed_into(&track, response, 0, keep);
+    track
+}
+
+/// Downloads `response` into `track` in the background, leaving out its
+/// first `skip` bytes. Stops when the track is cancelled or suspended.
+fn feed_into(
+    track: &std::sync::Arc<StreamBuffer>,
+    response: reqwest::Response,
+    mut skip: usize,
+    keep: Option<Box<dyn FnOnce(Vec<u8>) + Send>>,
+) {
+    let feed = track.feed();
+    let track = track.clone();
+    tokio::spawn(async move {
+        let mut chunks = response.bytes_stream();
+        while let Some(chunk) = chunks.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    let skipped = skip.min(chunk.len());
+                    skip -= skipped;
+                    if !feed.push(&chunk[skipped..]) {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    feed.fail(e.without_url().to_string());
+                    return;
+                }
+            }
+        }
+        feed.finish();
+        if let Some(keep) = keep
+            && let Some(bytes) = track.completed()
+        {
+            tokio::task::spawn_blocking(move || keep(bytes));
+        }
+    });
+}
+
+/// Records a track that failed while continuing through a queue or album.
+/// Returns false once too many failed in a row and playback should stop.
+fn note_playback_failure(app: &mut App, song: &Song, error: &anyhow::Error) -> bool {
+    app.playback_failures += 1;
+    let name = match &song.artist {
+        Some(artist) => format!("{} - {}", artist, song.title),
+        None => song.title.clone(),
+    };
+    app.show_message(
+        format!(
+            "Skipped {}: {:#} ({}/{})",
+            name, error, app.playback_failures, MAX_PLAYBACK_FAILURES
+        ),
+        3000,
+    );
+    if app.playback_failures < MAX_PLAYBACK_FAILURES {
+        return true;
+    }
+    app.playback_failures = 0;
+    app.show_message(
+        format!(
+            "Stopped after {} consecutive playback failures",
+            MAX_PLAYBACK_FAILURES
+        ),
+        5000,
+    );
+    false
+}
+
+/// Saves how far into the playing album we got, to offer resuming it later.
+/// Call before the track stops.
+fn remember_album_progress(app: &mut App, audio_player: &AudioPlayer) {
+    let Some(PlaybackSource::Album {
+        album_id: Some(album_id),
+        current_index,
+        ..
+    }) = &app.current_playback_source
+    else {
+        return;
+    };
+    if playback_finished(app, audio_player) {
+        return;
+    }
+    let (album_id, current_index) = (album_id.clone(), *current_index);
+    let position = playback_position(app, audio_player);
+    if let Err(e) = app
+        .album_progress
+        .record(&album_id, current_index, position)
+    {
+        app.log(format!("Couldn't save album progress: {}", e));
+    }
+}
+
+/// Jumps within the playing track and tells MPRIS clients.
+async fn seek_to(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    position: Duration,
+) -> Result<()> {
+    match app.jukebox.as_mut() {
+        Some(jukebox) => jukebox.seek(position).await?,
+        None => seek_player(client, app, audio_player, position).await?,
+    }
+    mpris_server.seeked(position).await?;
+    Ok(())
+}
+
+/// Seeks the local player. Well past what has downloaded, or before where
+/// a stream cut by the server begins, a transcoding server is asked for the
+/// song from `position`, to the second, instead. Raw files always come from
+/// the top, so those wait for the download to get there.
+async fn seek_player(
+    client: &SubsonicClient,
+    app: &App,
+    audio_player: &AudioPlayer,
+    position: Duration,
+) -> Result<()> {
+    let start = audio_player.stream_start();
+    let duration = app
+        .track_time
+        .and_then(|(_, duration)| duration)
+        .filter(|&d| d > 0)
+        .map(|d| Duration::from_secs(d as u64));
+    let share = audio_player.current_track().and_then(|track| track.downloaded_share());
+    // Without a length to go by, the download is taken to be about where
+    // playback is
+    let downloaded = match (share, duration) {
+        (Some(share), _) if share >= 1.0 => Duration::MAX,
+        (Some(share), Some(duration)) => start + duration.saturating_sub(start).mul_f64(share),
+        _ => audio_player.position(),
+    };
+    let far = position < start || position > downloaded.saturating_add(SERVER_SEEK_AHEAD);
+    if !client.transcodes() && position > downloaded {
+        let broken = audio_player.current_track().is_some_and(|track| track.is_done());
+        if broken {
+            anyhow::bail!("Can't seek here: the rest of the file didn't download");
+        }
+        // Decoding up to it would wait on the download with the UI stuck
+        if share.is_some() {
+            anyhow::bail!("Can't seek here: not downloaded yet");
+        }
+    }
+    if !far || !client.transcodes() {
+        // Decodes from the top of the track up to the position
+        return tokio::task::block_in_place(|| audio_player.seek(position));
+    }
+    let Some(id) = app.playing_id.as_deref() else {
+        anyhow::bail!("Nothing is playing");
+    };
+    let start = Duration::from_secs(position.as_secs());
+    let track = feed_stream(client.stream_song_at(id, start).await?, None);
+    let result = tokio::task::block_in_place(|| {
+        track.wait_for(STREAM_HEAD_START);
+        audio_player.seek_stream(track.clone(), start)
+    });
+    if result.is_err() {
+        track.cancel();
+    } else if audio_player.is_paused() {
+        track.suspend();
+    }
+    result.context("Can't seek here: the server couldn't start the song there")
+}
+
+/// Seeks `offset` microseconds from where playback is, as MPRIS Seek does.
+/// Seeking before the start goes to the start; seeking past the end leaves
+/// nothing to play, so the track finishes as it would have anyway.
+async fn seek_by(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    offset: i64,
+) -> Result<()> {
+    let position = playback_position(app, audio_player).as_micros() as i64 + offset;
+    let position = Duration::from_micros(position.max(0) as u64);
+    seek_to(client, app, audio_player, mpris_server, position).await?;
+    if let Some((_, duration)) = app.track_time {
+        app.track_time = Some((position, duration));
+    }
+    Ok(())
+}
+
+/// Plays the current track again from the top. The track is decoded again
+/// from what was already downloaded, so it works the same whatever it was
+/// started from, and the queue is left alone.
+async fn restart_track(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+) -> Result<()> {
+    let Some((_, duration)) = app.track_time else {
+        app.show_message("Nothing is playing".to_string(), 1500);
+        return Ok(());
+    };
+    let finished = playback_finished(app, audio_player);
+    seek_to(client, app, audio_player, mpris_server, Duration::ZERO).await?;
+    if finished && !playback_paused(app, audio_player) {
+        mpris_server.update_playback_status(PlaybackStatus::Playing).await?;
+    }
+    app.track_time = Some((Duration::ZERO, duration));
+    Ok(())
+}
+
+/// Seeks to `tenths` of the playing track's length.
+async fn seek_percent(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    tenths: u8,
+) -> Result<()> {
+    let Some((_, duration)) = app.track_time else {
+        app.show_message("Nothing is playing".to_string(), 1500);
+        return Ok(());
+    };
+    let Some(duration) = duration.filter(|&d| d > 0) else {
+        app.show_message("Track length unknown, can't seek".to_string(), 2000);
+        return Ok(());
+    };
+    let position = Duration::from_secs(duration as u64) * tenths as u32 / 10;
+    seek_to(client, app, audio_player, mpris_server, position).await?;
+    app.track_time = Some((position, Some(duration)));
+    Ok(())
+}
+
+/// Skips `offset` seconds ahead or back, stopping short of either end, and
+/// flashes where that landed.
+async fn jump(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    offset: i64,
+) -> Result<()> {
+    if app.track_time.is_none() || playback_finished(app, audio_player) {
+        app.show_message("Nothing is playing".to_string(), 1500);
+        return Ok(());
+    }
+    let duration = app.track_time.and_then(|(_, duration)| duration);
+    let current = playback_position(app, audio_player).as_secs() as i64;
+    let mut target = (current + offset).max(0);
+    // Landing right on the end would skip the track, so leave a moment
+    if let Some(duration) = duration.filter(|&d| d > 0) {
+        target = target.min((duration - 1).max(0));
+    }
+    let position = Duration::from_secs(target as u64);
+    seek_to(client, app, audio_player, mpris_server, position).await?;
+    app.track_time = Some((position, duration));
+    let sign = if offset < 0 { '-' } else { '+' };
+    app.flash(
+        format!("{}{}s → {}", sign, offset.unsigned_abs(), tui::format_duration(target)),
+        1500,
+    );
+    Ok(())
+}
+
+/// Switches album shuffle, applying it to the album playing right now too.
+async fn set_shuffle(app: &mut App, mpris_server: &MprisServer, shuffle: bool) {
+    app.shuffle = shuffle;
+    if let Some(source) = app.current_playback_source.as_mut() {
+        source.set_shuffle(shuffle);
+    }
+    let _ = mpris_server.update_shuffle(shuffle).await;
+    app.show_message(
+        format!("Shuffle {}", if shuffle { "on" } else { "off" }),
+        1500,
+    );
+}
+
+async fn stop_playback(app: &mut App, audio_player: &AudioPlayer, mpris_server: &MprisServer) -> Result<()> {
+    audio_player.stop();
+    if let Some(jukebox) = app.jukebox.as_mut() {
+        jukebox.stop().await?;
+    }
+    mpris_server.update_current_song(None, None).await?;
+    mpris_server.update_playback_status(PlaybackStatus::Stopped).await?;
+    Ok(())
+}
+
+/// Where playback is in the playing song, on the server in jukebox mode.
+fn playback_position(app: &App, audio_player: &AudioPlayer) -> Duration {
+    match &app.jukebox {
+        Some(jukebox) => jukebox.position(),
+        None => audio_player.position(),
+    }
+}
+
+fn playback_paused(app: &App, audio_player: &AudioPlayer) -> bool {
+    match &app.jukebox {
+        Some(jukebox) => jukebox.is_paused(),
+        None => audio_player.is_paused(),
+    }
+}
+
+fn playback_finished(app: &App, audio_player: &AudioPlayer) -> bool {
+    match &app.jukebox {
+        Some(jukebox) => !jukebox.has_song(),
+        None => audio_player.is_finished(),
+    }
+}
+
+/// Pauses or resumes, here or on the server. Returns whether it's paused now.
+/// Pausing here holds off the download until playback resumes.
+async fn toggle_pause(client: &SubsonicClient, app: &mut App, audio_player: &AudioPlayer) -> Result<bool> {
+    match app.jukebox.as_mut() {
+        Some(jukebox) => jukebox.toggle_pause().await,
+        None => {
+            audio_player.toggle_pause();
+            let paused = audio_player.is_paused();
+            if paused {
+                hold_download(app, audio_player);
+            } else {
+                resume_download(client, app, audio_player).await;
+            }
+            Ok(paused)
+        }
+    }
+}
+
+/// Passes the volume on to the server's player in jukebox mode.
+async fn update_jukebox_gain(app: &mut App, audio_player: &AudioPlayer) {
+    let Some(jukebox) = app.jukebox.as_mut() else {
+        return;
+    };
+    if let Err(e) = jukebox.set_gain(audio_player.get_volume()).await {
+        app.log(format!("Couldn't set the jukebox volume: {}", e));
+    }
+}
+
+/// Moves playback between here and the server's jukebox. The playing song
+/// carries on from the same spot on the other side, which is stopped.
+async fn toggle_jukebox(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+) -> Result<()> {
+    let resume = app
+        .playing_id
+        .clone()
+        .filter(|_| !playback_finished(app, audio_player))
+        .map(|id| {
+            let position = playback_position(app, audio_player);
+            (id, position, playback_paused(app, audio_player))
+        });
+
+    if let Some(jukebox) = app.jukebox.as_mut() {
+        jukebox.stop().await?;
+        app.jukebox = None;
+        app.show_message("Jukebox off".to_string(), 2000);
+    } else {
+        let jukebox = Jukebox::start(client)
+            .await
+            .context("The server won't play as a jukebox")?;
+        audio_player.stop();
+        app.preloaded = None;
+        app.jukebox = Some(jukebox);
+        update_jukebox_gain(app, audio_player).await;
+        app.show_message("Jukebox on: playing on the server".to_string(), 2000);
+    }
+
+    let Some((id, position, paused)) = resume else {
+        return Ok(());
+    };
+    let song = song_from_data(client.get_song(&id).await?.song);
+    let source = app.current_playback_source.clone().unwrap_or(PlaybackSource::Queue);
+    play_song(client, app, song, audio_player, mpris_server, source).await?;
+    if !position.is_zero() {
+        seek_to(client, app, audio_player, mpris_server, position).await?;
+    }
+    if paused {
+        toggle_pause(client, app, audio_player).await?;
+        mpris_server.update_playback_status(PlaybackStatus::Paused).await?;
+    }
+    Ok(())
+}
+
+/// Carries on after the last song played out: the queue, the list it came
+/// from, or the radio.
+async fn song_finished(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+) {
+    let list_ended = app
+        .current_playback_source
+        .as_ref()
+        .is_none_or(|source| source.upcoming().is_empty());
+    if !app.queue.is_empty() || (app.radio.is_some() && list_ended) {
+        // Queue takes priority, then the radio once nothing else is left
+        let _ = play_next_in_queue(client, app, audio_player, mpris_server).await;
+    } else if let Some(source) = app.current_playback_source.take() {
+        // Check if we should continue based on playback source
+        if source.list().is_some() {
+            let _ = play_next_in_list(client, app, audio_player, mpris_server, &source).await;
+        } else {
+            // Queue source with an empty queue - stop playback
+            let _ = mpris_server.update_playback_status(PlaybackStatus::Stopped).await;
+        }
+    } else {
+        let _ = mpris_server.update_playback_status(PlaybackStatus::Stopped).await;
+    }
+}
+
+/// Follows the server's player in jukebox mode, and keeps its playlist in
+/// step with the queue.
+async fn poll_jukebox(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+) {
+    let Some(jukebox) = app.jukebox.as_mut() else {
+        return;
+    };
+    for event in jukebox.poll() {
+        match event {
+            JukeboxEvent::Advanced(songs) => {
+                // The server's playlist is the queue, so it's used up as
+                // the server gets through it
+                let Some(song) = app.queue.drain(..songs.min(app.queue.len())).next_back() else {
+                    continue;
+                };
+                app.show_message(format!("Playing: {}", song.title), 2000);
+                if let Err(e) = song_started(client, app, song, audio_player, mpris_server, PlaybackSource::Queue).await {
+                    app.log(format!("Couldn't update MPRIS: {}", e));
+                }
+            }
+            JukeboxEvent::Finished => song_finished(client, app, audio_player, mpris_server).await,
+            JukeboxEvent::Paused(paused) => {
+                let status = if paused { PlaybackStatus::Paused } else { PlaybackStatus::Playing };
+                let _ = mpris_server.update_playback_status(status).await;
+            }
+        }
+    }
+    if let Some(jukebox) = app.jukebox.as_mut()
+        && let Err(e) = jukebox.sync_queue(&app.queue).await
+    {
+        app.log(format!("Couldn't update the jukebox playlist: {}", e));
+    }
+}
+
+async fn play_next_in_queue(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+) -> Result<()> {
+    // The radio's songs come once the queue is empty
+    while let Some(song) = next_in_queue(app) {
+        match play_song(client, app, song.clone(), audio_player, mpris_server, PlaybackSource::Queue).await {
+            Ok(()) => return Ok(()),
+            Err(e) if note_playback_failure(app, &song, &e) => {}
+            Err(_) => return stop_playback(app, audio_player, mpris_server).await,
+        }
+    }
+    // No more songs in queue - update MPRIS state to stopped
+    mpris_server.update_current_song(None, None).await?;
+    mpris_server.update_playback_status(PlaybackStatus::Stopped).await?;
+    Ok(())
+}
+
+fn next_in_queue(app: &mut App) -> Option<Song> {
+    if app.queue.is_empty() {
+        return app.radio.as_mut().and_then(Radio::next);
+    }
+    Some(app.queue.remove(0))
+}
+
+/// Turns similar-songs radio on, seeded by the playing song, or off.
+fn toggle_radio(app: &mut App) {
+    if app.radio.take().is_some() {
+        app.show_message("Radio off".to_string(), 1500);
+        return;
+    }
+    let Some(playing) = app.playing_id.clone() else {
+        app.show_message("Play something first, then start the radio".to_string(), 2000);
+        return;
+    };
+    if app.playing_podcast {
+        app.show_message("The radio can't start from a podcast episode".to_string(), 2000);
+        return;
+    }
+    let mut radio = Radio::default();
+    radio.played(&playing);
+    app.radio = Some(radio);
+    app.show_message("Radio on: similar songs follow when the queue runs out".to_string(), 2000);
+}
+
+/// Looks up songs like the playing one when the radio is running low.
+async fn refill_radio(client: &SubsonicClient, app: &mut App) {
+    let Some(seed) = app
+        .radio
+        .as_ref()
+        .and_then(|radio| radio.wants_more(app.playing_id.as_deref().filter(|_| !app.playing_podcast)))
+    else {
+        return;
+    };
+    match client.get_similar_songs2(&seed, RADIO_BATCH).await {
+        Ok(response) => {
+            let songs = response.similar_songs2.song.into_iter().map(song_from_data).collect();
+            if let Some(radio) = app.radio.as_mut() {
+                radio.add(seed, songs, &app.queue);
+            }
+        }
+        Err(e) => {
+            app.radio = None;
+            app.show_message(format!("Radio off: the server can't find similar songs ({})", e), 5000);
+        }
+    }
+}
+
+/// The album after `album_id` in the artist's discography, ordered by year
+/// and then name, with its songs.
+async fn next_artist_album(
+    client: &SubsonicClient,
+    artist_id: &str,
+    album_id: &str,
+) -> Result<Option<(String, String, Vec<Song>)>> {
+    let mut albums = client.get_artist(artist_id).await?.artist.album;
+    albums.sort_by_key(|a| (a.year.is_none(), a.year, a.name.to_lowercase()));
+    let Some(position) = albums.iter().position(|a| a.id == album_id) else {
+        return Ok(None);
+    };
+    let Some(next) = albums.get(position + 1) else {
+        return Ok(None);
+    };
+    let (name, songs) = fetch_album_songs(client, &next.id).await?;
+    Ok(Some((next.id.clone(), name, songs)))
+}
+
+/// Moves on to the next song of an album or search results source. With
+/// `continue_artist` set, a finished album rolls into the artist's next one.
+async fn play_next_in_list(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    source: &PlaybackSource,
+) -> Result<()> {
+    let mut source = source.clone();
+    let mut upcoming = source.upcoming();
+    let mut continued_with = None;
+    loop {
+        let songs = source.list().map(|(songs, _)| songs.to_vec()).unwrap_or_default();
+        for next_index in upcoming {
+            let Some(next_song) = songs.get(next_index).cloned() else {
+                continue;
+            };
+            match play_song(client, app, next_song.clone(), audio_player, mpris_server, source.with_index(next_index)).await {
+                Ok(()) => {
+                    if let Some(name) = continued_with {
+                        app.show_message(format!("Continuing with {}", name), 3000);
+                    }
+                    return Ok(());
+                }
+                Err(e) if note_playback_failure(app, &next_song, &e) => {}
+                Err(_) => {
+                    app.current_playback_source = None;
+                    return stop_playback(app, audio_player, mpris_server).await;
+                }
+            }
+        }
+
+        // Played to the end, nothing left to resume
+        if let PlaybackSource::Album {
+            album_id: Some(album_id),
+            ..
+        } = &source
+            && let Err(e) = app.album_progress.clear(album_id)
+        {
+            app.log(format!("Couldn't save album progress: {}", e));
+        }
+
+        let PlaybackSource::Album {
+            album_id: Some(album_id),
+            artist_id: Some(artist_id),
+            ..
+        } = &source
+        else {
+            break;
+        };
+        if !app.continue_artist {
+            break;
+        }
+        match next_artist_album(client, artist_id, album_id).await {
+            Ok(Some((next_id, name, songs))) => {
+                let shuffle = app.shuffle.then(|| ShuffleOrder::new(songs.len(), None));
+                upcoming = match &shuffle {
+                    Some(shuffle) => shuffle.order.clone(),
+                    None => (0..songs.len()).collect(),
+                };
+                source = PlaybackSource::Album {
+                    album_songs: songs,
+                    current_index: 0,
+                    album_id: Some(next_id),
+                    artist_id: Some(artist_id.clone()),
+                    shuffle,
+                };
+                continued_with = Some(name);
+            }
+            Ok(None) => break,
+            Err(e) => {
+                app.show_message(format!("Couldn't load the next album: {}", e), 3000);
+                break;
+            }
+        }
+    }
+    // List finished - clear playback source and stop
+    app.current_playback_source = None;
+    mpris_server.update_current_song(None, None).await?;
+    mpris_server.update_playback_status(PlaybackStatus::Stopped).await?;
+    Ok(())
+}
+
+/// Goes back one song (through the play history when shuffled), or restarts
+/// the first one.
+async fn play_previous_in_list(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    source: &PlaybackSource,
+) -> Result<()> {
+    let (songs, _) = source.list().unwrap_or_default();
+    let Some(prev_index) = source.previous_index() else {
+        return Ok(());
+    };
+    let Some(prev_song) = songs.get(prev_index).cloned() else {
+        return Ok(());
+    };
+    play_song(client, app, prev_song, audio_player, mpris_server, source.with_index(prev_index)).await
+}
+
+fn export_queue(
+    client: &SubsonicClient,
+    app: &mut App,
+    path: &str,
+    use_paths: bool,
+) -> Result<()> {
+    let (content, skipped) = m3u::format_m3u(&app.queue, |song| {
+        if use_paths {
+            song.path.clone()
+        } else {
+            client.get_stream_url(&song.id, &StreamOptions::default()).ok()
+        }
+    });
+    m3u::write_m3u(&expand_tilde(path), &content)?;
+
+    let exported = app.queue.len() - skipped;
+    if skipped > 0 {
+        app.show_message(
+            format!("Exported {} songs to {} ({} without a library path)", exported, path, skipped),
+            3000,
+        );
+    } else {
+        app.show_message(format!("Exported {} songs to {}", exported, path), 2000);
+    }
+    Ok(())
+}
+
+fn song_from_data(s: SongData) -> Song {
+    Song {
+        id: s.id,
+        title: s.title,
+        artist: s.artist,
+        album: s.album,
+        album_artist: None,
+        duration: s.duration,
+        path: s.path,
+        genre: s.genre,
+        year: s.year,
+        starred: s.starred.is_some(),
+        rating: s.user_rating,
+        replay_gain: s.replay_gain,
+        cover_art: s.cover_art,
+        suffix: s.suffix,
+        track: s.track,
+        disc_number: s.disc_number,
+        podcast: false,
+    }
+}
+
+fn song_from_search(s: SearchSong) -> Song {
+    Song {
+        id: s.id,
+        title: s.title,
+        artist: Some(s.artist),
+        album: s.album,
+        duration: s.duration,
+        path: s.path,
+        genre: s.genre,
+        year: s.year,
+        starred: s.starred.is_some(),
+        rating: s.user_rating,
+        replay_gain: s.replay_gain,
+        cover_art: s.cover_art,
+        ..Default::default()
+    }
+}
+
+async fn resolve_m3u_entry(client: &SubsonicClient, entry: &m3u::M3uEntry) -> Option<Song> {
+    if let Some(id) = m3u::stream_song_id(&entry.location)
+        && let Ok(response) = client.get_song(&id).await
+    {
+        return Some(song_from_data(response.song));
+    }
+
+    // Without an #EXTINF title the best we have is the file name
+    let (title, exact) = match &entry.title {
+        Some(title) => (title.clone(), true),
+        None => (
+            std::path::Path::new(&entry.location)
+                .file_stem()?
+                .to_string_lossy()
+                .to_string(),
+            false,
+        ),
+    };
+
+    let response = client.search3(&title, 0, 0, 20).await.ok()?;
+    let songs = response.into_result()?.song?;
+    // Several candidates can share a title (live cuts, remasters), so the
+    // #EXTINF duration picks the closest one
+    songs
+        .into_iter()
+        .filter(|s| {
+            if !exact {
+                // A file name often has a track number in front, and the
+                // search matches loosely, so it has to end in the title or
+                // be about as long
+                let named = !s.title.is_empty() && title.to_lowercase().ends_with(&s.title.to_lowercase());
+                let timed = entry
+                    .duration
+                    .zip(s.duration)
+                    .is_some_and(|(wanted, actual)| (wanted - actual).abs() <= M3U_DURATION_SLACK);
+                return named || timed;
+            }
+            let artist_matches = entry
+                .artist
+                .as_ref()
+                .is_none_or(|a| s.artist.to_lowercase() == a.to_lowercase());
+            s.title.to_lowercase() == title.to_lowercase() && artist_matches
+        })
+        .min_by_key(|s| match (entry.duration, s.duration) {
+            (Some(wanted), Some(actual)) => (wanted - actual).abs(),
+            _ => 0,
+        })
+        .map(|s| Song {
+            id: s.id,
+            title: s.title,
+            artist: Some(s.artist),
+            album: s.album,
+            album_artist: None,
+            duration: s.duration,
+            path: s.path,
+            replay_gain: s.replay_gain,
+            cover_art: s.cover_art,
+            ..Default::default()
+        })
+}
+
+async fn import_playlist(client: &SubsonicClient, app: &mut App, path: &str) -> Result<()> {
+    let entries = m3u::read_m3u(&expand_tilde(path))?;
+    let mut matched = Vec::new();
+    let mut unmatched_lines = Vec::new();
+
+    for entry in &entries {
+        match resolve_m3u_entry(client, entry).await {
+            Some(song) => matched.push(song),
+            None => unmatched_lines.push(entry.line.to_string()),
+        }
+    }
+    let skipped_duplicates = app.enqueue(matched.clone());
+    let matched = format!("{} matched{}", matched.len(), already_queued(skipped_duplicates));
+
+    if unmatched_lines.is_empty() {
+        app.show_message(format!("Imported {}: {}", path, matched), 3000);
+    } else {
+        let shown = unmatched_lines.len().min(5);
+        let more = if unmatched_lines.len() > shown { ", ..." } else { "" };
+        app.show_message(
+            format!(
+                "Imported {}: {}, {} skipped (lines {}{})",
+                path,
+                matched,
+                unmatched_lines.len(),
+                unmatched_lines[..shown].join(", "),
+                more
+            ),
+            5000,
+        );
+    }
+    Ok(())
+}
+
+fn open_about(client: &SubsonicClient, app: &mut App, config: &Config, mpris_server: &MprisServer) {
+    let mut features = Vec::new();
+    if let Some(addr) = &config.mpd_listen {
+        features.push(format!("MPD server ({})", addr));
+    }
+    if !config.global_hotkeys.is_empty() {
+        features.push(format!("global hotkeys ({})", config.global_hotkeys.len()));
+    }
+    if !app.smart_playlists.is_empty() {
+        features.push(format!("smart playlists ({})", app.smart_playlists.len()));
+    }
+    if config.pause_on_output_change {
+        features.push("pause on output change".to_string());
+    }
+    if let Some(path) = &config.status_file {
+        features.push(format!("status file ({})", path));
+    }
+    if config.show_easter_eggs {
+        features.push("easter eggs".to_string());
+    }
+    let scrobbles = app.scrobbler.as_ref().map(|s| s.depth());
+    app.about = Some(about::AboutInfo::new(
+        client,
+        mpris_server.bus_name(),
+        features,
+        scrobbles,
+    ));
+}
+
+async fn handle_command(
+    client: &SubsonicClient,
+    app: &mut App,
+    config: &Config,
+    mpris_server: &MprisServer,
+) -> Result<()> {
+    let command = app.command_string.trim().to_string();
+    app.command_string.clear();
+    let (verb, args) = command.split_once(' ').unwrap_or((command.as_str(), ""));
+    let args = args.trim();
+
+    match verb {
+        "" => {}
+        "export" => {
+            let (use_paths, path) = match args.split_once(' ') {
+                Some(("-p", path)) => (true, path.trim()),
+                _ => (false, args),
+            };
+            if path.is_empty() {
+                anyhow::bail!("Usage: export [-p] <file.m3u8>");
+            }
+            if app.queue.is_empty() {
+                app.show_message("Queue is empty".to_string(), 1500);
+                return Ok(());
+            }
+            if expand_tilde(path).exists() {
+                app.ask_confirm(
+                    format!("Overwrite {}?", path),
+                    ConfirmAction::ExportQueue {
+                        path: path.to_string(),
+                        use_paths,
+                    },
+                );
+            } else {
+                export_queue(client, app, path, use_paths)?;
+            }
+        }
+        "import" => {
+            if args.is_empty() {
+                anyhow::bail!("Usage: import <file.m3u>");
+            }
+            import_playlist(client, app, args).await?;
+        }
+        "about" => open_about(client, app, config, mpris_server),
+        "messages" => app.log_open = true,
+        "prefetch" => start_prefetch(client, app),
+        "scan" => start_scan(client, app).await?,
+        "queue" => match args {
+            "dedupe" => {
+                let removed = app.dedupe_queue();
+                app.show_message(format!("Removed {} duplicates from the queue", removed), 2000);
+            }
+            _ => anyhow::bail!("Usage: queue dedupe"),
+        },
+        "starred" => open_starred(client, app, config).await?,
+        "star-album" => {
+            let Some(album_id) = selected_album_id(app) else {
+                anyhow::bail!("Select an album or open one first");
+            };
+            set_album_starred(client, app, &album_id, true).await?;
+        }
+        "unstar-album" => {
+            let Some(album_id) = selected_album_id(app) else {
+                anyhow::bail!("Select an album or open one first");
+            };
+            app.ask_confirm(
+                "Unstar this album and all its songs?".to_string(),
+                ConfirmAction::UnstarAlbum { album_id },
+            );
+        }
+        other => anyhow::bail!("Unknown command: {}", other),
+    }
     Ok(())
 }
 
-async fn play_next_in_queue(
+async fn handle_confirm(
     client: &SubsonicClient,
     app: &mut App,
     audio_player: &AudioPlayer,
     mpris_server: &MprisServer,
 ) -> Result<()> {
-    if !app.queue.is_empty() {
-        let song = app.queue.remove(0);
-        play_song(client, app, song, audio_player, mpris_server, PlaybackSource::Queue).await?;
-    } else {
-        // No more songs in queue - update MPRIS state to stopped
-        mpris_server.update_current_song(None, None).await?;
-        mpris_server.update_playback_status(PlaybackStatus::Stopped).await?;
+    let Some(confirm) = app.pending_confirm.take() else {
+        return Ok(());
+    };
+    match confirm.action {
+        ConfirmAction::ExportQueue { path, use_paths } => {
+            export_queue(client, app, &path, use_paths)?;
+        }
+        ConfirmAction::UnstarAlbum { album_id } => {
+            set_album_starred(client, app, &album_id, false).await?;
+        }
+        ConfirmAction::DeletePlaylist { id, name } => {
+            delete_playlist(client, app, &id, &name).await?;
+        }
+        ConfirmAction::ResumeAlbum {
+            album_id,
+            track_index,
+            position,
+        } => {
+            if app.current_album_id.as_ref() == Some(&album_id)
+                && let Some(song) = app.songs.get(track_index).cloned()
+            {
+                let source = PlaybackSource::Album {
+                    album_songs: app.songs.clone(),
+                    current_index: track_index,
+                    album_id: Some(album_id),
+                    artist_id: app.current_artist_id.clone(),
+                    shuffle: None,
+                };
+                play_song(client, app, song, audio_player, mpris_server, source).await?;
+                seek_to(client, app, audio_player, mpris_server, Duration::from_secs(position)).await?;
+            }
+        }
+        ConfirmAction::ResumeEpisode { id, position } => {
+            let song = app
+                .episodes
+                .iter()
+                .filter_map(|episode| episode_song(&app.podcast_title, episode))
+                .find(|song| song.id == id);
+            if let Some(song) = song {
+                play_song(client, app, song, audio_player, mpris_server, PlaybackSource::Queue).await?;
+                seek_to(client, app, audio_player, mpris_server, position).await?;
+            }
+        }
+        ConfirmAction::RestorePlayQueue {
+            songs,
+            current,
+            position,
+            unresolved,
+        } => {
+            restore_play_queue(client, app, audio_player, mpris_server, songs, current, position).await?;
+            if unresolved > 0 {
+                app.show_message(
+                    format!("Restored the queue; {} songs no longer on the server were skipped", unresolved),
+                    3000,
+                );
+            }
+        }
+        ConfirmAction::ShuffleArtist { artist, album_ids } => {
+            app.artist_shuffle = Some(artist_shuffle::ArtistShuffle::start(client, artist, album_ids));
+        }
     }
     Ok(())
 }
 
-async fn play_next_in_album(
+/// Asks whether to take up a queue saved on the server, by this or another
+/// client.
+fn offer_play_queue(app: &mut App, queue: types::PlayQueue) {
+    let (songs, unresolved) = queue.songs();
+    let songs: Vec<Song> = songs.into_iter().map(song_from_data).collect();
+    if songs.is_empty() {
+        return;
+    }
+    let current = queue
+        .current
+        .as_ref()
+        .and_then(|id| songs.iter().position(|s| &s.id == id));
+    let position = queue.position.unwrap_or(0);
+    let at = match current {
+        Some(index) => format!(
+            ", at {} {}",
+            songs[index].title,
+            tui::format_duration((position / 1000) as i64)
+        ),
+        None => String::new(),
+    };
+    let prompt = format!(
+        "Restore the queue saved by {} ({} songs{})?",
+        queue.changed_by.as_deref().unwrap_or("another client"),
+        songs.len(),
+        at
+    );
+    let action = ConfirmAction::RestorePlayQueue {
+        songs,
+        current,
+        position,
+        unresolved,
+    };
+    app.ask_confirm(prompt, action);
+}
+
+/// Replaces the queue with a saved one and plays its current song from
+/// where it was left. Songs before the current one have been heard, so
+/// they're left out.
+async fn restore_play_queue(
     client: &SubsonicClient,
     app: &mut App,
     audio_player: &AudioPlayer,
     mpris_server: &MprisServer,
-    album_songs: &[Song],
-    current_index: usize,
+    mut songs: Vec<Song>,
+    current: Option<usize>,
+    position: u64,
 ) -> Result<()> {
-    let next_index = current_index + 1;
-    if next_index < album_songs.len() {
-        let next_song = album_songs[next_index].clone();
-        play_song(
-            client,
-            app,
-            next_song,
-            audio_player,
-            mpris_server,
-            PlaybackSource::Album {
-                album_songs: album_songs.to_vec(),
-                current_index: next_index,
-            },
-        )
-        .await?;
-    } else {
-        // Album finished - clear playback source and stop
-        app.current_playback_source = None;
-        mpris_server.update_current_song(None, None).await?;
-        mpris_server.update_playback_status(PlaybackStatus::Stopped).await?;
+    let Some(index) = current else {
+        app.replace_queue(songs);
+        app.show_message(format!("Restored the queue ({} songs)", app.queue.len()), 2000);
+        return Ok(());
+    };
+    let mut upcoming = songs.split_off(index);
+    let song = upcoming.remove(0);
+    app.replace_queue(upcoming);
+    play_song(client, app, song, audio_player, mpris_server, PlaybackSource::Queue).await?;
+    if position > 0 {
+        seek_to(client, app, audio_player, mpris_server, Duration::from_millis(position)).await?;
     }
     Ok(())
 }
 
-async fn play_previous_in_album(
+/// Answers an MPD client. Its playlist is the song playing, then the queue.
+async fn handle_mpd_query(
     client: &SubsonicClient,
     app: &mut App,
     audio_player: &AudioPlayer,
     mpris_server: &MprisServer,
-    album_songs: &[Song],
-    current_index: usize,
-) -> Result<()> {
-    if current_index > 0 {
-        let prev_index = current_index - 1;
-        let prev_song = album_songs[prev_index].clone();
-        play_song(
-            client,
-            app,
-            prev_song,
-            audio_player,
-            mpris_server,
-            PlaybackSource::Album {
-                album_songs: album_songs.to_vec(),
-                current_index: prev_index,
-            },
-        )
-        .await?;
-    } else {
-        // At first song - restart it
-        let current_song = album_songs[current_index].clone();
-        play_song(
-            client,
-            app,
-            current_song,
-            audio_player,
-            mpris_server,
-            PlaybackSource::Album {
-                album_songs: album_songs.to_vec(),
-                current_index,
-            },
-        )
-        .await?;
+    mpris_state: &std::sync::Arc<tokio::sync::RwLock<mpris::MprisState>>,
+    mpd_ids: &mut PlaylistIds,
+    query: mpd::MpdQuery,
+) -> Result<String, mpd::MpdError> {
+    let (current, playback) = {
+        let state = mpris_state.read().await;
+        let current = state
+            .current_song
+            .as_ref()
+            .filter(|_| state.playback_status != PlaybackStatus::Stopped)
+            .map(|current| Song {
+                id: current.id.clone(),
+                title: current.title.clone(),
+                artist: current.artist.clone(),
+                album: current.album.clone(),
+                album_artist: current.album_artist.clone(),
+                duration: current.duration,
+                ..Default::default()
+            });
+        (current, state.playback_status)
+    };
+    let playlist: Vec<Song> = current.iter().chain(&app.queue).cloned().collect();
+    let ids = mpd_ids.assign(&playlist);
+    // Where the queue starts in the playlist
+    let queued = current.is_some() as usize;
+    let position = |entry: mpd::Entry| match entry {
+        mpd::Entry::Pos(pos) if pos < ids.len() => Ok(pos),
+        mpd::Entry::Pos(_) => Err(mpd::MpdError::new(mpd::ACK_ERROR_ARG, "Bad song index")),
+        mpd::Entry::Id(id) => ids
+            .iter()
+            .position(|&i| i == id)
+            .ok_or_else(|| mpd::MpdError::new(mpd::ACK_ERROR_NO_EXIST, "No such song")),
+    };
+    let system = |e: anyhow::Error| mpd::MpdError::new(mpd::ACK_ERROR_SYSTEM, e.to_string());
+
+    match query {
+        mpd::MpdQuery::Status => {
+            // The playlist version only has to change when the playlist does
+            let mut hasher = std::hash::DefaultHasher::new();
+            std::hash::Hash::hash(&ids, &mut hasher);
+            let status = mpd_protocol::Status {
+                volume: (audio_player.get_volume() * 100.0).round() as i64,
+                random: app.shuffle,
+                playlist: std::hash::Hasher::finish(&hasher) as u32,
+                playlist_length: playlist.len(),
+                state: match playback {
+                    PlaybackStatus::Playing => "play",
+                    PlaybackStatus::Paused => "pause",
+                    PlaybackStatus::Stopped => "stop",
+                },
+                current: current.as_ref().map(|song| mpd_protocol::Current {
+                    id: ids[0],
+                    elapsed: playback_position(app, audio_player),
+                    duration: song.duration,
+                }),
+                next_id: ids.get(1).copied().filter(|_| current.is_some()),
+            };
+            Ok(status.format())
+        }
+        mpd::MpdQuery::CurrentSong => Ok(current
+            .as_ref()
+            .map(|song| mpd_protocol::format_song(song, Some((0, ids[0]))))
+            .unwrap_or_default()),
+        mpd::MpdQuery::PlaylistInfo => Ok(playlist
+            .iter()
+            .zip(&ids)
+            .enumerate()
+            .map(|(pos, (song, &id))| mpd_protocol::format_song(song, Some((pos, id))))
+            .collect()),
+        mpd::MpdQuery::Add(id) => {
+            let response = client
+                .get_song(&id)
+                .await
+                .map_err(|e| mpd::MpdError::new(mpd::ACK_ERROR_NO_EXIST, e.to_string()))?;
+            app.enqueue(vec![song_from_data(response.song)]);
+            Ok(String::new())
+        }
+        mpd::MpdQuery::Delete(entry) => {
+            let pos = position(entry)?;
+            match pos.checked_sub(queued) {
+                Some(pos) => {
+                    app.queue.remove(pos);
+                }
+                // Taking away the song playing moves on to the next
+                None => {
+                    audio_player.stop();
+                    play_next_in_queue(client, app, audio_player, mpris_server).await.map_err(system)?;
+                }
+            }
+            Ok(String::new())
+        }
+        mpd::MpdQuery::Clear => {
+            app.queue.clear();
+            stop_playback(app, audio_player, mpris_server).await.map_err(system)?;
+            Ok(String::new())
+        }
+        mpd::MpdQuery::Play(entry) => {
+            let pos = position(entry)?;
+            play_mpd_entry(client, app, audio_player, mpris_server, pos, queued).await.map_err(system)?;
+            Ok(String::new())
+        }
+        mpd::MpdQuery::Seek(entry, time) => {
+            let pos = position(entry)?;
+            if pos >= queued {
+                play_mpd_entry(client, app, audio_player, mpris_server, pos, queued).await.map_err(system)?;
+            }
+            seek_to(client, app, audio_player, mpris_server, time).await.map_err(system)?;
+            Ok(String::new())
+        }
     }
-    Ok(())
+}
+
+// Plays the MPD playlist entry at `pos`, dropping the queue before it. The
+// song playing starts over.
+async fn play_mpd_entry(
+    client: &SubsonicClient,
+    app: &mut App,
+    audio_player: &AudioPlayer,
+    mpris_server: &MprisServer,
+    pos: usize,
+    queued: usize,
+) -> Result<()> {
+    let Some(pos) = pos.checked_sub(queued) else {
+        return restart_track(client, app, audio_player, mpris_server).await;
+    };
+    app.queue.drain(..pos);
+    audio_player.stop();
+    play_next_in_queue(client, app, audio_player, mpris_server).await
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let mut import_path = None;
+    let mut mpris_instance = None;
+    let mut check = false;
+    let mut demo = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--import" {
+            import_path = args.next();
+        } else if arg == "--mpris-instance" {
+            mpris_instance = args.next();
+        } else if arg == "--check" {
+            check = true;
+        } else if arg == "--demo" {
+            demo = true;
+        }
+    }
+
+    let mut log_rx = logger::init();
+
     let config = load_config().context("Failed to load config")?;
 
-    if config_needs_edit(&config) {
-        eprintln!("Config not found or using defaults!");
-        eprintln!("Please edit the config file at: {:?}", get_config_path()?);
-        eprintln!("Press Enter to continue anyway...");
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+    // The server is kept alive for as long as main runs
+    #[cfg(feature = "demo")]
+    let (config, _demo_server) = if demo {
+        let server = danavi::demo::DemoServer::start().await?;
+        let mut config = config;
+        config.base_url = server.base_url();
+        config.username = "demo".to_string();
+        config.password = "demo".to_string();
+        // Queued scrobbles outlive the session and would go to the real server
+        config.scrobble = false;
+        // Nothing from the demo library belongs among the real one's
+        // downloads, caches and progress
+        config.download_dir = None;
+        config::use_scratch_dir(std::env::temp_dir().join(format!("danavi-demo-{}", std::process::id())));
+        (config, Some(server))
+    } else {
+        (config, None)
+    };
+    #[cfg(not(feature = "demo"))]
+    if demo {
+        anyhow::bail!("This build has no demo mode; rebuild with --features demo");
     }
 
-    let client = SubsonicClient::new(
+    let mut client = SubsonicClient::new(
         config.base_url.clone(),
         config.username.clone(),
         config.password.clone(),
     )?;
+    client.set_max_bit_rate(config.max_bitrate);
+    client.set_stream_format(config.stream_format);
+    client.set_auth_method(config.auth_method);
+    client.set_tls(&TlsOptions {
+        ca_file: config.tls_ca_file.as_deref().map(expand_tilde),
+        accept_invalid_certs: config.tls_accept_invalid_certs,
+    })?;
+
+    // Checked before the screen is taken over, so what went wrong can be
+    // read and the config fixed
+    let ping = tokio::time::timeout(STARTUP_PING_TIMEOUT, client.capabilities()).await;
+    let offline = match ping.unwrap_or_else(|_| Err(client::TransportError::TimedOut.into())) {
+        Ok(_) => false,
+        Err(e) if offer_offline(&config, &e)? => true,
+        // Why was already said, so only the exit status is left to tell
+        Err(_) => std::process::exit(1),
+    };
+
+    let mut tui = Tui::new()?;
+    // Raw mode is on by now, so the terminal's answer isn't echoed
+    let palette = theme::palette(config.theme.background);
+
+    // The checks screen only shows up when asked for or something's wrong
+    let cache_dir = match &config.download_dir {
+        Some(dir) => Some(expand_tilde(dir)),
+        None => config::get_cache_dir().ok(),
+    };
+    let mut preflight = preflight::Preflight::start(&client, &config, cache_dir);
+    // Carrying on offline was already agreed to, failed checks and all
+    if check || (!preflight.settle().await && !offline) {
+        loop {
+            preflight.poll();
+            tui.draw_preflight(&preflight, &palette)?;
+            match tui.handle_preflight_event(&mut preflight)? {
+                Some(true) => break,
+                Some(false) => return Ok(()),
+                None => {}
+            }
+        }
+    }
 
     // Initialize audio player
-    let audio_player = AudioPlayer::new()
+    let (track_end_tx, mut track_end_rx) = mpsc::unbounded_channel();
+    let audio_player = AudioPlayer::new(track_end_tx)
         .context("Failed to initialize audio player. Make sure audio output is available.")?;
+    audio_player.set_fade(Duration::from_millis(config.pause_fade_ms));
+    audio_player.set_normalization(config.normalize_loudness.then_some(config.normalize_target_lufs));
 
     // Initialize MPRIS server
     let (mpris_command_tx, mut mpris_command_rx) = mpsc::unbounded_channel::<MprisCommand>();
-    let (mpris_server, mpris_state) = MprisServer::new(mpris_command_tx).await?;
+    let mpd_command_tx = mpris_command_tx.clone();
+    let hotkey_command_tx = mpris_command_tx.clone();
+    let (mpris_server, mpris_state) = MprisServer::new(
+        mpris_command_tx,
+        mpris_instance.as_deref().or(config.mpris_instance.as_deref()),
+    )
+    .await?;
     
     // Set initial volume in MPRIS state
     mpris_server.update_volume(audio_player.get_volume()).await?;
 
     let mut app = App::new();
-    let mut tui = Tui::new()?;
+    app.scroll_steps = config.scroll_acceleration.clone();
+    app.continue_artist = config.continue_artist;
+    app.queue_dedupe = config.queue_dedupe;
+    app.search_as_you_type = config.search_as_you_type;
+    app.key_hints = config.key_hints;
+    app.long_jump = config.long_jump.clone();
+    app.replaygain = config.replaygain;
+    app.replaygain_preamp = config.replaygain_preamp;
+    app.song_cache = match &config.download_dir {
+        Some(dir) => Some(danavi::cache::SongCache::new(expand_tilde(dir))),
+        None => danavi::cache::SongCache::open().ok(),
+    };
+    app.covers = danavi::cache::CoverArtCache::open().ok();
+    // The demo library has no business in the real one's caches
+    if config.metadata_cache_minutes > 0 && !demo {
+        let ttl = Duration::from_secs(config.metadata_cache_minutes * 60);
+        app.listings = danavi::cache::MetadataCache::open(&client.server_key(), ttl)
+            .ok()
+            .map(listings::Listings::new);
+    }
+    if config.stream_cache && !demo {
+        app.stream_cache =
+            danavi::cache::StreamCache::open(&client.server_key(), config.stream_cache_mb * 1024 * 1024).ok();
+    }
+    app.downloads = downloads::Downloads::load(app.song_cache.as_ref());
+    app.album_progress = album_progress::AlbumProgress::load(config.album_progress_days);
+    app.episode_progress = episode_progress::EpisodeProgress::load(config.album_progress_days);
+    if config.scrobble {
+        app.scrobbler = Some(scrobble::Scrobbler::start(&client, config.scrobble_horizon_days));
+    }
+    app.palette = palette;
+    app.insecure_tls = config.tls_accept_invalid_certs;
+    if app.insecure_tls {
+        app.show_message(
+            "WARNING: tls_accept_invalid_certs is on, the server's identity isn't checked".to_string(),
+            10000,
+        );
+    }
 
     // Initial load
     if let Err(e) = load_artists(&client, &mut app, &config).await {
-        app.show_message(format!("Error: {}", e), 3000);
+        app.show_message(error_message(&e), 3000);
+    }
+
+    if let Some(path) = import_path
+        && let Err(e) = import_playlist(&client, &mut app, &path).await
+    {
+        app.show_message(error_message(&e), 3000);
+    }
+
+    // MPD clients share the MPRIS command channel; state queries come back
+    // through their own channel since they need the App
+    let (mpd_request_tx, mut mpd_request_rx) = mpsc::unbounded_channel::<mpd::MpdRequest>();
+    let mut mpd_ids = PlaylistIds::default();
+    if let Some(addr) = &config.mpd_listen
+        && let Err(e) = mpd::spawn(addr, mpd_command_tx, mpd_request_tx).await
+    {
+        app.show_message(format!("Failed to start MPD server on {}: {}", addr, e), 5000);
     }
 
+    // Portal registration can wait on a user dialog, so it must not hold up
+    // startup
+    let mut hotkeys_rx = None;
+    if !config.global_hotkeys.is_empty() {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let bindings = config.global_hotkeys.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(hotkeys::register(&bindings, hotkey_command_tx).await);
+        });
+        hotkeys_rx = Some(rx);
+    }
+    let mut global_hotkeys = None;
+
+    let (output_tx, mut output_rx) = mpsc::unbounded_channel::<String>();
+    if config.pause_on_output_change {
+        output_monitor::spawn(output_tx);
+    }
+
+    let mut status_file = config
+        .status_file
+        .as_deref()
+        .map(|path| now_playing::StatusFile::new(expand_tilde(path), config.status_format.clone()));
+
+    let (search_tx, mut search_rx) = mpsc::unbounded_channel::<SearchReply>();
+
     let mut last_message_check = Instant::now();
+    let mut volume_burst = VolumeBurst::default();
+    let mut now_playing_report = config
+        .report_now_playing
+        .unwrap_or(config.scrobble)
+        .then(|| scrobble::NowPlaying::new(&client));
+    let mut bookmarks = (config.bookmark_after_minutes > 0)
+        .then(|| bookmarks::Bookmarks::start(&client, Duration::from_secs(config.bookmark_after_minutes * 60)));
+    let mut play_queue_sync = config.sync_play_queue.then(|| play_queue::PlayQueueSync::start(&client));
 
     loop {
+        let position = playback_position(&app, &audio_player);
+        let mut resume = None;
+        {
+            let state = mpris_state.read().await;
+            let song = state
+                .current_song
+                .as_ref()
+                .filter(|_| state.playback_status != PlaybackStatus::Stopped);
+            app.track_time = song.map(|s| (position, s.duration));
+            app.playing_id = song.map(|s| s.id.clone());
+            app.playing_podcast = song.is_some_and(|s| s.podcast);
+            app.muted = audio_player.is_muted();
+            let listened = song.filter(|s| !s.podcast);
+            if let Some(scrobbler) = app.scrobbler.as_mut() {
+                scrobbler.update(listened.map(|s| (s.id.as_str(), s.duration)), app.track_starts, position);
+            }
+            if let Some(report) = now_playing_report.as_mut() {
+                report.update(listened.map(|s| s.id.as_str()), state.playback_status == PlaybackStatus::Paused);
+            }
+            // Episodes offer to continue instead, when opened again
+            if let Some(bookmarks) = bookmarks.as_mut() {
+                resume = bookmarks.update(listened.map(|s| (s.id.as_str(), s.duration)), position);
+            }
+            let episode = song.filter(|s| s.podcast);
+            if let Err(e) = app.episode_progress.update(episode.map(|s| (s.id.as_str(), s.duration)), position) {
+                app.log(format!("Couldn't save episode progress: {}", e));
+            }
+            if let Some(sync) = play_queue_sync.as_mut() {
+                sync.update(song.map(|s| s.id.as_str()), &app.queue, position);
+            }
+            if let Some(lyrics) = app.lyrics.as_mut() {
+                lyrics.follow(&client, song);
+                lyrics.poll();
+            }
+            // Found out at startup, or later when starting offline
+            if app.capabilities.is_none() {
+                app.capabilities = client.known_capabilities().cloned();
+            }
+            if client.take_legacy_fallback() {
+                app.show_message("The server can't check tokens, so the password is sent (over HTTPS)".to_string(), 5000);
+            }
+        }
+        if let Some(at) = resume {
+            match seek_to(&client, &mut app, &audio_player, &mpris_server, at).await {
+                Ok(()) => app.show_message(
+                    format!("Resumed from bookmark at {}", tui::format_duration(at.as_secs() as i64)),
+                    3000,
+                ),
+                Err(e) => log::warn!("Failed to resume from bookmark: {}", e),
+            }
+        }
+        if app.pending_confirm.is_none()
+            && let Some(queue) = play_queue_sync.as_mut().and_then(|sync| sync.offer())
+        {
+            offer_play_queue(&mut app, queue);
+        }
+        mpris_server.update_position(position).await;
         tui.draw(&mut app)?;
 
         // Check message timeout
@@ -366,53 +3942,185 @@ async fn main() -> Result<()> {
             last_message_check = Instant::now();
         }
 
+        if let Some(rx) = hotkeys_rx.as_mut()
+            && let Ok(result) = rx.try_recv()
+        {
+            hotkeys_rx = None;
+            match result {
+                Ok((hotkeys, warnings)) => {
+                    global_hotkeys = Some(hotkeys);
+                    if let Some(warning) = warnings.first() {
+                        app.show_message(warning.clone(), 5000);
+                    }
+                }
+                Err(e) => {
+                    app.show_message(format!("Global hotkeys unavailable: {}", e), 5000);
+                }
+            }
+        }
+
+        if let Some(rx) = app.cover_art.as_mut()
+            && let Ok((id, path)) = rx.try_recv()
+        {
+            app.cover_art = None;
+            if let Some(url) = path.as_deref().and_then(danavi::cache::file_url) {
+                let _ = mpris_server.update_art_url(&id, url).await;
+            }
+        }
+
+        while let Ok(message) = log_rx.try_recv() {
+            app.log(message);
+        }
+
+        // Never auto-resume; the user decides when the new output is ready
+        while let Ok(reason) = output_rx.try_recv() {
+            if !audio_player.is_paused() && !audio_player.is_finished() {
+                audio_player.toggle_pause();
+                hold_download(&mut app, &audio_player);
+                let _ = mpris_server.update_playback_status(PlaybackStatus::Paused).await;
+                app.show_message(format!("Paused: {}", reason), 5000);
+            }
+        }
+
+        if let Some(about) = app.about.as_mut() {
+            about.poll();
+        }
+        if let Some(panel) = app.info_panel.as_mut() {
+            panel.poll();
+        }
+
+        if let Some(due) = app.search_due
+            && Instant::now() >= due
+        {
+            let query = app.search_string.clone();
+            start_search(&client, &mut app, &search_tx, query);
+        }
+        while let Ok((generation, query, response)) = search_rx.try_recv() {
+            // Only the newest search gets to fill in the results and title,
+            // and only while they're on screen
+            if !app.search_generations.is_current(generation) {
+                continue;
+            }
+            app.search_task = None;
+            if app.current_view != ViewType::Search {
+                continue;
+            }
+            match response {
+                Ok(response) => show_search_results(&mut app, &config, &query, response),
+                Err(e) => app.show_message(format!("Search error: {}", e), 3000),
+            }
+        }
+        if let Some(cache) = app.song_cache.as_ref() {
+            app.downloads.poll(&client, cache);
+        }
+
+        if let Some(prefetch) = app.prefetch.as_mut()
+            && prefetch.poll()
+            && let Some(prefetch) = app.prefetch.take()
+        {
+            for failure in &prefetch.failures {
+                app.log(format!("Prefetch failed: {}", failure));
+            }
+            app.show_message(prefetch.summary(), 5000);
+        }
+        if let Some(shuffle) = app.artist_shuffle.as_mut()
+            && shuffle.poll()
+            && let Some(shuffle) = app.artist_shuffle.take()
+            && let Err(e) = play_artist_shuffle(&client, &mut app, &audio_player, &mpris_server, shuffle).await
+        {
+            app.show_message(error_message(&e), 3000);
+        }
+        if let Some(saving) = app.saving.as_mut()
+            && saving.poll()
+            && let Some(saving) = app.saving.take()
+        {
+            for failure in &saving.failures {
+                app.log(format!("Save failed: {}", failure));
+            }
+            app.show_message(saving.summary(), 5000);
+        }
+
+        refill_shuffle_all(&client, &mut app).await;
+        poll_scan(&client, &mut app).await;
+        poll_refreshed(&mut app);
+        refill_radio(&client, &mut app).await;
+        load_more_albums(&client, &mut app).await;
+        load_more_search(&client, &mut app).await;
+
+        // Answer MPD state queries
+        while let Ok(request) = mpd_request_rx.try_recv() {
+            let result = handle_mpd_query(
+                &client,
+                &mut app,
+                &audio_player,
+                &mpris_server,
+                &mpris_state,
+                &mut mpd_ids,
+                request.query,
+            )
+            .await;
+            let _ = request.reply.send(result);
+        }
+
         // Handle MPRIS commands
         while let Ok(command) = mpris_command_rx.try_recv() {
             match command {
                 MprisCommand::Play => {
-                    if audio_player.is_paused() {
-                        audio_player.toggle_pause();
-                        let _ = mpris_server.update_playback_status(PlaybackStatus::Playing).await;
+                    if playback_paused(&app, &audio_player) {
+                        if toggle_pause(&client, &mut app, &audio_player).await.is_ok() {
+                            let _ = mpris_server.update_playback_status(PlaybackStatus::Playing).await;
+                        }
                     } else if !app.queue.is_empty() {
                         let _ = play_next_in_queue(&client, &mut app, &audio_player, &mpris_server).await;
                     }
                 }
-                MprisCommand::Pause if !audio_player.is_paused() && !audio_player.is_finished() => {
-                    audio_player.toggle_pause();
-                    let _ = mpris_server.update_playback_status(PlaybackStatus::Paused).await;
+                MprisCommand::Pause
+                    if !playback_paused(&app, &audio_player) && !playback_finished(&app, &audio_player) =>
+                {
+                    let paused = toggle_pause(&client, &mut app, &audio_player).await;
+                    if paused.is_ok() {
+                        let _ = mpris_server.update_playback_status(PlaybackStatus::Paused).await;
+                    }
                 }
-                MprisCommand::PlayPause => {
-                    audio_player.toggle_pause();
-                    if audio_player.is_paused() {
+                MprisCommand::PlayPause => match toggle_pause(&client, &mut app, &audio_player).await {
+                    Ok(true) => {
                         let _ = mpris_server.update_playback_status(PlaybackStatus::Paused).await;
-                    } else if !audio_player.is_finished() {
+                    }
+                    Ok(false) if !playback_finished(&app, &audio_player) => {
                         let _ = mpris_server.update_playback_status(PlaybackStatus::Playing).await;
                     }
-                }
+                    _ => {}
+                },
                 MprisCommand::Stop => {
+                    remember_album_progress(&mut app, &audio_player);
                     audio_player.stop();
+                    if let Some(jukebox) = app.jukebox.as_mut() {
+                        let _ = jukebox.stop().await;
+                    }
                     let _ = mpris_server.update_playback_status(PlaybackStatus::Stopped).await;
                 }
                 MprisCommand::Next => {
                     if !app.queue.is_empty() {
                         let _ = play_next_in_queue(&client, &mut app, &audio_player, &mpris_server).await;
                     } else if let Some(source) = app.current_playback_source.take() {
-                        match source {
-                            PlaybackSource::Album { album_songs, current_index } => {
-                                let _ = play_next_in_album(&client, &mut app, &audio_player, &mpris_server, &album_songs, current_index).await;
-                            }
-                            _ => {
-                                // For other sources, just stop
-                                let _ = mpris_server.update_playback_status(PlaybackStatus::Stopped).await;
-                            }
+                        if source.list().is_some() {
+                            let _ = play_next_in_list(&client, &mut app, &audio_player, &mpris_server, &source).await;
+                        } else {
+                            // For other sources, just stop
+                            let _ = mpris_server.update_playback_status(PlaybackStatus::Stopped).await;
                         }
                     }
                 }
                 MprisCommand::Previous => {
                     if let Some(source) = app.current_playback_source.take() {
                         match source {
-                            PlaybackSource::Album { album_songs, current_index } => {
-                                let _ = play_previous_in_album(&client, &mut app, &audio_player, &mpris_server, &album_songs, current_index).await;
+                            PlaybackSource::Album { .. }
+                            | PlaybackSource::Search { .. }
+                            | PlaybackSource::Artist { .. }
+                            | PlaybackSource::TopSongs { .. }
+                            | PlaybackSource::Starred { .. }
+                            | PlaybackSource::Playlist { .. } => {
+                                let _ = play_previous_in_list(&client, &mut app, &audio_player, &mpris_server, &source).await;
                             }
                             _ => {
                                 // For other sources, restart current song if available
@@ -425,6 +4133,7 @@ async fn main() -> Result<()> {
                                         album: current_song.album.clone(),
                                         album_artist: current_song.album_artist.clone(),
                                         duration: current_song.duration,
+                                        ..Default::default()
                                     };
                                     drop(state);
                                     let _ = play_song(&client, &mut app, song, &audio_player, &mpris_server, source).await;
@@ -433,37 +4142,90 @@ async fn main() -> Result<()> {
                         }
                     }
                 }
-                MprisCommand::SetVolume(volume) => {
-                    let volume = volume.clamp(0.0, 1.0);
-                    audio_player.set_volume(volume);
-                    let _ = mpris_server.update_volume(volume).await;
+                MprisCommand::Seek(offset) if !playback_finished(&app, &audio_player) => {
+                    let _ = seek_by(&client, &mut app, &audio_player, &mpris_server, offset).await;
+                }
+                MprisCommand::SetPosition(position) if !playback_finished(&app, &audio_player) => {
+                    // Out of range positions are ignored, as the spec asks
+                    let duration = app.track_time.and_then(|(_, duration)| duration);
+                    if position >= 0 && duration.is_none_or(|d| position <= d * 1_000_000) {
+                        let position = Duration::from_micros(position as u64);
+                        let _ = seek_to(&client, &mut app, &audio_player, &mpris_server, position).await;
+                    }
+                }
+                MprisCommand::SetShuffle(shuffle) => {
+                    set_shuffle(&mut app, &mpris_server, shuffle).await;
                 }
+                MprisCommand::SetVolume(volume) => volume_burst.request(volume),
                 _ => {}
             }
         }
+        if let Some(volume) = volume_burst.due_apply() {
+            audio_player.set_volume(volume);
+            update_jukebox_gain(&mut app, &audio_player).await;
+        }
+        if let Some(volume) = volume_burst.due_announce() {
+            let _ = mpris_server.update_volume(volume).await;
+        }
 
-        // Check if audio finished playing
-        if !audio_player.is_paused() && audio_player.is_finished() {
+        if let Some(file) = status_file.as_mut() {
             let state = mpris_state.read().await;
-            if state.playback_status == PlaybackStatus::Playing {
+            if let Err(e) = file.update(
+                state.current_song.as_ref(),
+                state.playback_status,
+                position,
+            ) {
                 drop(state);
-                if !app.queue.is_empty() {
-                    // Queue takes priority - play next in queue
-                    let _ = play_next_in_queue(&client, &mut app, &audio_player, &mpris_server).await;
-                } else if let Some(source) = app.current_playback_source.take() {
-                    // Check if we should continue based on playback source
-                    match source {
-                        PlaybackSource::Album { album_songs, current_index } => {
-                            let _ = play_next_in_album(&client, &mut app, &audio_player, &mpris_server, &album_songs, current_index).await;
-                        }
-                        _ => {
-                            // For Search or Queue (already handled) sources - stop playback
-                            let _ = mpris_server.update_playback_status(PlaybackStatus::Stopped).await;
+                app.show_message(format!("Failed to write status file: {}", e), 5000);
+                status_file = None;
+            }
+        }
+
+        update_gapless(&client, &mut app, &audio_player).await;
+        poll_jukebox(&client, &mut app, &audio_player, &mpris_server).await;
+
+        // An unplugged device or a restarted sound server leaves the stream
+        // dead without any error reaching us
+        if audio_player.is_stalled() {
+            let _ = mpris_server.update_playback_status(PlaybackStatus::Paused).await;
+            match tokio::task::block_in_place(|| audio_player.reconnect()) {
+                Ok(()) => {
+                    log::info!("Reconnected to the audio output");
+                    app.show_message("Audio output lost; reconnected".to_string(), 3000);
+                    let _ = mpris_server.update_playback_status(PlaybackStatus::Playing).await;
+                }
+                Err(e) => {
+                    audio_player.toggle_pause();
+                    hold_download(&mut app, &audio_player);
+                    // Stays up until something else is shown; space retries
+                    app.show_message(
+                        format!("Audio output lost ({}). Paused; press space to try again", e),
+                        u64::MAX,
+                    );
+                }
+            }
+        }
+
+        if let Some(error) = audio_player.take_stream_error() {
+            app.show_message(format!("Stream interrupted: {}", error), 5000);
+        }
+
+        // Tracks that played out; stopping or skipping doesn't send these
+        while let Ok(end) = track_end_rx.try_recv() {
+            match audio_player.track_ended(end) {
+                Some(TrackEvent::Advanced) => {
+                    if let Some((song, source)) = app.preloaded.take() {
+                        app.fallback_stream = None;
+                        app.show_message(format!("Playing: {}", song.title), 2000);
+                        if let Err(e) = song_started(&client, &mut app, song, &audio_player, &mpris_server, source).await {
+                            app.log(format!("Couldn't update MPRIS: {}", e));
                         }
                     }
-                } else {
-                    let _ = mpris_server.update_playback_status(PlaybackStatus::Stopped).await;
                 }
+                Some(TrackEvent::Finished) => {
+                    song_finished(&client, &mut app, &audio_player, &mpris_server).await;
+                }
+                None => {}
             }
         }
 
@@ -472,134 +4234,320 @@ async fn main() -> Result<()> {
                 tui::Action::Quit => break,
                 Action::Select => {
                     if let Err(e) = handle_select(&client, &mut app, &config, &audio_player, &mpris_server).await {
-                        app.show_message(format!("Error: {}", e), 3000);
+                        app.show_message(error_message(&e), 3000);
                     }
                 }
                 Action::AddToQueue => {
                     if let Some(idx) = app.get_selected_index() {
                         match app.current_view {
+                            ViewType::Albums => {
+                                if let Some(album_id) = selected_album_id(&app)
+                                    && let Err(e) = append_album(&client, &mut app, &album_id).await
+                                {
+                                    app.show_message(error_message(&e), 3000);
+                                }
+                            }
                             ViewType::Songs => {
-                                if let Some(song) = app.songs.get(idx) {
-                                    app.queue.push(song.clone());
-                                    app.show_message(
-                                        format!(
-                                            "Added to queue: {} (Queue: {})",
-                                            song.title,
-                                            app.queue.len()
-                                        ),
-                                        1500,
-                                    );
+                                if let Some(song) = app.songs.get(idx).cloned() {
+                                    add_to_queue(&mut app, song);
                                 }
                             }
                             ViewType::Search => {
-                                if let Some(SearchResultItem::Song { id, title, artist, album, duration, .. }) = app.search_results.get(idx) {
-                                    app.queue.push(Song {
-                                        id: id.clone(),
-                                        title: title.clone(),
-                                        artist: Some(artist.clone()),
-                                        album: album.clone(),
-                                        album_artist: None,
-                                        duration: *duration,
-                                    });
-                                    app.show_message(
-                                        format!(
-                                            "Added to queue: {} (Queue: {})",
-                                            title,
-                                            app.queue.len()
-                                        ),
-                                        1500,
-                                    );
+                                if let Some(song) = app.search_results.get(idx).and_then(SearchResultItem::to_song) {
+                                    add_to_queue(&mut app, song);
+                                }
+                            }
+                            ViewType::Folders => {
+                                if let Err(e) = queue_folder_entry(&client, &mut app, idx).await {
+                                    app.show_message(error_message(&e), 3000);
+                                }
+                            }
+                            ViewType::Episodes => {
+                                match app.episodes.get(idx).and_then(|e| episode_song(&app.podcast_title, e)) {
+                                    Some(song) => add_to_queue(&mut app, song),
+                                    None => app.show_message("That episode isn't on the server yet".to_string(), 2000),
                                 }
                             }
                             _ => {}
                         }
                     }
                 }
+                Action::OpenStarred => {
+                    if let Err(e) = open_starred(&client, &mut app, &config).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::Refresh => {
+                    if let Err(e) = refresh_view(&client, &mut app, &config).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::OpenFolders => {
+                    if let Err(e) = open_folders(&client, &mut app).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::OpenPodcasts => {
+                    if let Err(e) = open_podcasts(&client, &mut app).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::OpenPlaylists => {
+                    if let Err(e) = open_playlists(&client, &mut app).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::AppendAlbum => {
+                    if let Some(album_id) = selected_album_id(&app)
+                        && let Err(e) = append_album(&client, &mut app, &album_id).await
+                    {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::ReplaceQueue => {
+                    if let Some(album_id) = selected_album_id(&app) {
+                        let start_index = if app.current_view == ViewType::Songs {
+                            app.get_selected_index().unwrap_or(0)
+                        } else {
+                            0
+                        };
+                        if let Err(e) = replace_queue_with_album(
+                            &client,
+                            &mut app,
+                            &audio_player,
+                            &mpris_server,
+                            &album_id,
+                            start_index,
+                        )
+                        .await
+                        {
+                            app.show_message(error_message(&e), 3000);
+                        }
+                    }
+                }
+                Action::BrowseAlbums(mode) => {
+                    if let Err(e) = browse_albums(&client, &mut app, &config, mode).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::ToggleRadio => toggle_radio(&mut app),
+                Action::ToggleJukebox => {
+                    if let Err(e) = toggle_jukebox(&client, &mut app, &audio_player, &mpris_server).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::TopSongs => {
+                    if let Err(e) = open_top_songs(&client, &mut app, &config).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::ShuffleAll => {
+                    if let Err(e) = shuffle_all(&client, &mut app, &audio_player, &mpris_server).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::ShuffleArtist => shuffle_artist(&client, &mut app),
+                Action::CancelArtistShuffle => {
+                    if let Some(shuffle) = app.artist_shuffle.take() {
+                        shuffle.cancel();
+                        app.show_message("Stopped gathering songs".to_string(), 2000);
+                    }
+                }
+                Action::PlayRandomAlbum => {
+                    if let Err(e) = play_random_album(&client, &mut app, &audio_player, &mpris_server).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
                 Action::PlayNext => {
                     if let Err(e) = play_next_in_queue(&client, &mut app, &audio_player, &mpris_server).await {
-                        app.show_message(format!("Error: {}", e), 3000);
+                        app.show_message(error_message(&e), 3000);
                     }
                 }
                 Action::RestartQueue => {
                     if !app.queue.is_empty() {
-                        // Stop current playback
+                        // Stop current playback and start counting from here
                         audio_player.stop();
+                        app.current_playback_source = None;
                         if let Err(e) = play_next_in_queue(&client, &mut app, &audio_player, &mpris_server).await {
-                            app.show_message(format!("Error: {}", e), 3000);
+                            app.show_message(error_message(&e), 3000);
                         }
                     }
                 }
-                Action::TogglePause => {
-                    audio_player.toggle_pause();
-                    if audio_player.is_paused() {
+                Action::TogglePause => match toggle_pause(&client, &mut app, &audio_player).await {
+                    Ok(true) => {
                         app.show_message("Paused".to_string(), 1500);
                         let _ = mpris_server.update_playback_status(PlaybackStatus::Paused).await;
-                    } else {
+                    }
+                    Ok(false) => {
                         app.show_message("Resumed".to_string(), 1500);
                         let _ = mpris_server.update_playback_status(PlaybackStatus::Playing).await;
                     }
+                    Err(e) => app.show_message(error_message(&e), 3000),
+                },
+                Action::Command => {
+                    if let Err(e) = handle_command(&client, &mut app, &config, &mpris_server).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::Prefetch => start_prefetch(&client, &mut app),
+                Action::CancelPrefetch => {
+                    if let Some(prefetch) = app.prefetch.take() {
+                        let summary = prefetch.summary();
+                        prefetch.cancel();
+                        app.show_message(format!("Prefetch stopped. {}", summary), 3000);
+                    }
+                }
+                Action::SeekPercent(tenths) => {
+                    if let Err(e) = seek_percent(&client, &mut app, &audio_player, &mpris_server, tenths).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::Seek(seconds) => {
+                    if playback_finished(&app, &audio_player) {
+                        app.show_message("Nothing is playing".to_string(), 1500);
+                    } else if let Err(e) = seek_by(&client, &mut app, &audio_player, &mpris_server, seconds * 1_000_000).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::VolumeUp | Action::VolumeDown => {
+                    let step = if matches!(action, Action::VolumeUp) { VOLUME_STEP } else { -VOLUME_STEP };
+                    // Rounded to whole steps so repeated presses land on 0% and 100%
+                    let volume = ((audio_player.get_volume() + step) / VOLUME_STEP).round() * VOLUME_STEP;
+                    let volume = volume.clamp(0.0, 1.0);
+                    audio_player.set_volume(volume);
+                    update_jukebox_gain(&mut app, &audio_player).await;
+                    let _ = mpris_server.update_volume(volume).await;
+                    app.flash(format!("Volume {}%", (volume * 100.0).round()), 1500);
+                }
+                Action::ToggleMute => {
+                    audio_player.toggle_mute();
+                    update_jukebox_gain(&mut app, &audio_player).await;
+                    let _ = mpris_server.update_volume(audio_player.get_volume()).await;
+                }
+                Action::Jump(offset) => {
+                    if let Err(e) = jump(&client, &mut app, &audio_player, &mpris_server, offset).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::Restart => {
+                    if let Err(e) = restart_track(&client, &mut app, &audio_player, &mpris_server).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::ToggleShuffle => {
+                    let shuffle = !app.shuffle;
+                    set_shuffle(&mut app, &mpris_server, shuffle).await;
+                }
+                Action::ShowInfo => open_info(&client, &mut app),
+                Action::OpenSimilarArtist => {
+                    if let Err(e) = open_similar_artist(&client, &mut app, &config).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::About => open_about(&client, &mut app, &config, &mpris_server),
+                Action::SaveQueue => {
+                    if let Err(e) = open_playlist_picker(&client, &mut app, &config.username).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::SaveToPlaylist => save_queue_to_playlist(&client, &mut app).await,
+                Action::RemoveFromPlaylist => {
+                    if let Err(e) = remove_from_playlist(&client, &mut app).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::MoveInPlaylist(offset) => {
+                    if let Err(e) = move_in_playlist(&client, &mut app, offset).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::RenamePlaylist => {
+                    if let Err(e) = rename_playlist(&client, &mut app).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::DeletePlaylist => {
+                    if let Some(playlist) = app.open_playlist().cloned() {
+                        let prompt = format!("Delete playlist {}?", playlist.name);
+                        let action = ConfirmAction::DeletePlaylist {
+                            id: playlist.id,
+                            name: playlist.name,
+                        };
+                        app.ask_confirm(prompt, action);
+                    }
+                }
+                Action::DownloadAlbum => {
+                    if let Some(album_id) = selected_album_id(&app)
+                        && let Err(e) = download_album(&client, &mut app, &album_id).await
+                    {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::SaveFiles => {
+                    if let Err(e) = save_files(&client, &mut app).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::Share => {
+                    if let Err(e) = share_selected(&client, &mut app, &config).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::ToggleStar => {
+                    if let Err(e) = toggle_star(&client, &mut app).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::Rate(rating) => {
+                    if let Err(e) = rate_selected(&client, &mut app, rating).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::RatePlaying(rating) => {
+                    let playing = mpris_state
+                        .read()
+                        .await
+                        .current_song
+                        .as_ref()
+                        .map(|song| (song.id.clone(), song.title.clone()));
+                    if let Err(e) = rate_playing(&client, &mut app, playing, rating).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::StarAlbum => {
+                    if let Some(album_id) = selected_album_id(&app)
+                        && let Err(e) = set_album_starred(&client, &mut app, &album_id, true).await
+                    {
+                        app.show_message(error_message(&e), 3000);
+                    }
+                }
+                Action::Confirm => {
+                    if let Err(e) = handle_confirm(&client, &mut app, &audio_player, &mpris_server).await {
+                        app.show_message(error_message(&e), 3000);
+                    }
                 }
                 Action::Search => {
                     let query = app.search_string.clone();
-                    app.current_view = ViewType::Search;
-                    app.current_base_content = format!("Searching for \"{}\"...", query);
-                    tui.draw(&mut app)?;
-
-                    match client.search3(&query, 20, 20, 20).await {
-                        Ok(response) => {
-                            let mut items = Vec::new();
-                            if let Some(search_result) = response.search_result3 {
-                                if let Some(albums) = search_result.album {
-                                    for album in albums {
-                                        items.push(SearchResultItem::Album {
-                                            id: album.id,
-                                            name: album.name,
-                                            artist: album.artist,
-                                            artist_id: album.artist_id,
-                                        });
-                                    }
-                                }
-                                if let Some(songs) = search_result.song {
-                                    for song in songs {
-                                        items.push(SearchResultItem::Song {
-                                            id: song.id,
-                                            title: song.title,
-                                            artist: song.artist,
-                                            album: song.album,
-                                            duration: song.duration,
-                                        });
-                                    }
-                                }
-                            }
-                            app.search_results = items;
-                            let search_items: Vec<String> = app
-                                .search_results
-                                .iter()
-                                .map(|r| match r {
-                                    SearchResultItem::Album { name, artist, .. } => {
-                                        format!("[A] {} - {}", name, artist)
-                                    }
-                                    SearchResultItem::Song { title, artist, .. } => {
-                                        format!("[S] {} - {}", title, artist)
-                                    }
-                                })
-                                .collect();
-                            app.set_items(search_items);
-                            app.current_base_content = format!(
-                                "Search: {} ({} results){}",
-                                query,
-                                app.search_results.len(),
-                                get_random_easter_egg(config.show_easter_eggs)
-                            );
-                        }
-                        Err(e) => {
-                            app.show_message(format!("Search error: {}", e), 3000);
-                        }
-                    }
+                    start_search(&client, &mut app, &search_tx, query);
                 }
             }
         }
     }
 
+    remember_album_progress(&mut app, &audio_player);
+    let _ = app.episode_progress.flush();
+    if let Some(bookmarks) = bookmarks.as_mut() {
+        bookmarks.flush().await;
+    }
+    if let Some(sync) = play_queue_sync.as_mut() {
+        sync.flush(playback_position(&app, &audio_player)).await;
+    }
+    if let Some(hotkeys) = global_hotkeys {
+        hotkeys.close().await;
+    }
+    if let Some(file) = status_file {
+        file.remove();
+    }
+
     Ok(())
 }
@@ -0,0 +1,352 @@
+use crate::mpris::MprisCommand;
+use danavi::mpd_protocol::{SeekTime, parse_seek_time, split_args};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+
+const GREETING: &str = "OK MPD 0.23.0\n";
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// ACK error codes from the MPD protocol
+pub const ACK_ERROR_ARG: u32 = 2;
+pub const ACK_ERROR_UNKNOWN: u32 = 5;
+pub const ACK_ERROR_NO_EXIST: u32 = 50;
+pub const ACK_ERROR_SYSTEM: u32 = 52;
+
+#[derive(Debug)]
+pub struct MpdError {
+    pub code: u32,
+    pub message: String,
+}
+
+impl MpdError {
+    pub fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// A playlist entry, by where it is or by its id.
+#[derive(Debug, Clone, Copy)]
+pub enum Entry {
+    Pos(usize),
+    Id(u32),
+}
+
+/// Requests that need the player state owned by the main loop. The playlist
+/// is the song playing followed by the queue.
+#[derive(Debug, Clone)]
+pub enum MpdQuery {
+    Status,
+    CurrentSong,
+    PlaylistInfo,
+    Add(String),
+    Delete(Entry),
+    Clear,
+    Play(Entry),
+    Seek(Entry, Duration),
+}
+
+pub struct MpdRequest {
+    pub query: MpdQuery,
+    pub reply: oneshot::Sender<Result<String, MpdError>>,
+}
+
+type ArgsResult<T> = Result<T, MpdError>;
+
+enum Outcome {
+    /// Forwarded on the same channel MPRIS commands use
+    Command(MprisCommand),
+    /// Answered by the main loop
+    Query(MpdQuery),
+    /// Answered right here
+    Reply(String),
+}
+
+type Handler = fn(&[String]) -> ArgsResult<Outcome>;
+
+// To support another verb, add a row here
+const COMMANDS: &[(&str, Handler)] = &[
+    ("ping", |_| Ok(Outcome::Reply(String::new()))),
+    ("commands", |_| Ok(Outcome::Reply(command_list()))),
+    ("notcommands", |_| Ok(Outcome::Reply(String::new()))),
+    ("tagtypes", |_| Ok(Outcome::Reply(String::new()))),
+    ("outputs", |_| {
+        Ok(Outcome::Reply(
+            "outputid: 0\noutputname: danavi\noutputenabled: 1\n".to_string(),
+        ))
+    }),
+    ("status", |_| Ok(Outcome::Query(MpdQuery::Status))),
+    ("currentsong", |_| Ok(Outcome::Query(MpdQuery::CurrentSong))),
+    ("playlistinfo", |_| Ok(Outcome::Query(MpdQuery::PlaylistInfo))),
+    ("add", |args| Ok(Outcome::Query(MpdQuery::Add(arg(args, 0)?.to_string())))),
+    ("delete", |args| Ok(Outcome::Query(MpdQuery::Delete(Entry::Pos(number(args, 0)?))))),
+    ("deleteid", |args| Ok(Outcome::Query(MpdQuery::Delete(Entry::Id(number(args, 0)?))))),
+    ("clear", |_| Ok(Outcome::Query(MpdQuery::Clear))),
+    ("play", |args| {
+        if args.is_empty() {
+            Ok(Outcome::Command(MprisCommand::Play))
+        } else {
+            Ok(Outcome::Query(MpdQuery::Play(Entry::Pos(number(args, 0)?))))
+        }
+    }),
+    ("playid", |args| {
+        if args.is_empty() {
+            Ok(Outcome::Command(MprisCommand::Play))
+        } else {
+            Ok(Outcome::Query(MpdQuery::Play(Entry::Id(number(args, 0)?))))
+        }
+    }),
+    ("seek", |args| Ok(Outcome::Query(MpdQuery::Seek(Entry::Pos(number(args, 0)?), seconds(args, 1)?)))),
+    ("seekid", |args| Ok(Outcome::Query(MpdQuery::Seek(Entry::Id(number(args, 0)?), seconds(args, 1)?)))),
+    ("seekcur", |args| {
+        let value = arg(args, 0)?;
+        match parse_seek_time(value) {
+            Some(SeekTime::To(seconds)) => Ok(Outcome::Command(MprisCommand::SetPosition((seconds * 1e6) as i64))),
+            Some(SeekTime::By(seconds)) => Ok(Outcome::Command(MprisCommand::Seek((seconds * 1e6) as i64))),
+            None => Err(MpdError::new(ACK_ERROR_ARG, format!("Number expected: {}", value))),
+        }
+    }),
+    ("pause", |args| match args.first().map(|a| a.as_str()) {
+        None => Ok(Outcome::Command(MprisCommand::PlayPause)),
+        Some("1") => Ok(Outcome::Command(MprisCommand::Pause)),
+        Some("0") => Ok(Outcome::Command(MprisCommand::Play)),
+        Some(other) => Err(MpdError::new(ACK_ERROR_ARG, format!("Boolean expected: {}", other))),
+    }),
+    ("stop", |_| Ok(Outcome::Command(MprisCommand::Stop))),
+    ("next", |_| Ok(Outcome::Command(MprisCommand::Next))),
+    ("previous", |_| Ok(Outcome::Command(MprisCommand::Previous))),
+    ("random", |args| match arg(args, 0)? {
+        "1" => Ok(Outcome::Command(MprisCommand::SetShuffle(true))),
+        "0" => Ok(Outcome::Command(MprisCommand::SetShuffle(false))),
+        other => Err(MpdError::new(ACK_ERROR_ARG, format!("Boolean expected: {}", other))),
+    }),
+    ("setvol", |args| {
+        let volume: f64 = number(args, 0)?;
+        Ok(Outcome::Command(MprisCommand::SetVolume(volume / 100.0)))
+    }),
+];
+
+fn command_list() -> String {
+    COMMANDS
+        .iter()
+        .map(|(name, _)| format!("command: {}\n", name))
+        .collect()
+}
+
+fn arg(args: &[String], idx: usize) -> ArgsResult<&str> {
+    args.get(idx)
+        .map(|a| a.as_str())
+        .ok_or_else(|| MpdError::new(ACK_ERROR_ARG, "missing argument"))
+}
+
+fn number<T: std::str::FromStr>(args: &[String], idx: usize) -> ArgsResult<T> {
+    let value = arg(args, idx)?;
+    value
+        .parse()
+        .map_err(|_| MpdError::new(ACK_ERROR_ARG, format!("Number expected: {}", value)))
+}
+
+// A time into a song for `seek` and `seekid`, which only go from the start
+fn seconds(args: &[String], idx: usize) -> ArgsResult<Duration> {
+    match parse_seek_time(arg(args, idx)?) {
+        Some(SeekTime::To(seconds)) => Ok(Duration::from_secs_f64(seconds)),
+        _ => Err(MpdError::new(ACK_ERROR_ARG, format!("Number expected: {}", args[idx]))),
+    }
+}
+
+async fn query(
+    requests: &mpsc::UnboundedSender<MpdRequest>,
+    query: MpdQuery,
+) -> Result<String, MpdError> {
+    let (reply, response) = oneshot::channel();
+    requests
+        .send(MpdRequest { query, reply })
+        .map_err(|_| MpdError::new(ACK_ERROR_SYSTEM, "player is shutting down"))?;
+    response
+        .await
+        .map_err(|_| MpdError::new(ACK_ERROR_SYSTEM, "player did not answer"))?
+}
+
+async fn execute(
+    args: &[String],
+    commands: &mpsc::UnboundedSender<MprisCommand>,
+    requests: &mpsc::UnboundedSender<MpdRequest>,
+) -> Result<String, MpdError> {
+    let name = args[0].as_str();
+    let Some((_, handler)) = COMMANDS.iter().find(|(n, _)| *n == name) else {
+        return Err(MpdError::new(ACK_ERROR_UNKNOWN, format!("unknown command \"{}\"", name)));
+    };
+
+    match handler(&args[1..])? {
+        Outcome::Command(command) => {
+            commands
+                .send(command)
+                .map_err(|_| MpdError::new(ACK_ERROR_SYSTEM, "player is shutting down"))?;
+            Ok(String::new())
+        }
+        Outcome::Query(request) => query(requests, request).await,
+        Outcome::Reply(body) => Ok(body),
+    }
+}
+
+fn ack(error: &MpdError, list_index: usize, command: &str) -> String {
+    format!(
+        "ACK [{}@{}] {{{}}} {}\n",
+        error.code, list_index, command, error.message
+    )
+}
+
+fn parse_fields(response: &str) -> HashMap<String, String> {
+    response
+        .lines()
+        .filter_map(|l| l.split_once(": "))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+// Polls the player until something the client subscribed to changes, or the
+// client sends `noidle`. Returns None when the connection closed.
+async fn idle(
+    subsystems: &[String],
+    lines: &mut Lines<BufReader<OwnedReadHalf>>,
+    requests: &mpsc::UnboundedSender<MpdRequest>,
+) -> std::io::Result<Option<String>> {
+    let snapshot = || async {
+        let status = query(requests, MpdQuery::Status).await.unwrap_or_default();
+        let song = query(requests, MpdQuery::CurrentSong).await.unwrap_or_default();
+        (parse_fields(&status), song)
+    };
+    let (start_status, start_song) = snapshot().await;
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) if line.trim() == "noidle" => return Ok(Some("OK\n".to_string())),
+                Some(_) => {}
+                None => return Ok(None),
+            },
+            _ = tokio::time::sleep(IDLE_POLL_INTERVAL) => {
+                let (status, song) = snapshot().await;
+                let mut changed = Vec::new();
+                if status.get("state") != start_status.get("state") || song != start_song {
+                    changed.push("player");
+                }
+                if status.get("volume") != start_status.get("volume") {
+                    changed.push("mixer");
+                }
+                if status.get("playlist") != start_status.get("playlist") {
+                    changed.push("playlist");
+                }
+                changed.retain(|c| subsystems.is_empty() || subsystems.iter().any(|s| s == c));
+                if !changed.is_empty() {
+                    let out: String = changed.iter().map(|c| format!("changed: {}\n", c)).collect();
+                    return Ok(Some(format!("{}OK\n", out)));
+                }
+            }
+        }
+    }
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    commands: mpsc::UnboundedSender<MprisCommand>,
+    requests: mpsc::UnboundedSender<MpdRequest>,
+) -> std::io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    write.write_all(GREETING.as_bytes()).await?;
+
+    // Some((list_ok, commands)) while inside a command list
+    let mut command_list: Option<(bool, Vec<Vec<String>>)> = None;
+
+    while let Some(line) = lines.next_line().await? {
+        let args = split_args(&line);
+        let Some(name) = args.first() else {
+            continue;
+        };
+
+        let out = match name.as_str() {
+            "close" => return Ok(()),
+            "command_list_begin" => {
+                command_list = Some((false, Vec::new()));
+                continue;
+            }
+            "command_list_ok_begin" => {
+                command_list = Some((true, Vec::new()));
+                continue;
+            }
+            "command_list_end" => {
+                let Some((list_ok, list)) = command_list.take() else {
+                    let error = MpdError::new(ACK_ERROR_ARG, "not in command list");
+                    write.write_all(ack(&error, 0, name).as_bytes()).await?;
+                    continue;
+                };
+                let mut out = String::new();
+                let mut failed = false;
+                for (idx, args) in list.iter().enumerate() {
+                    match execute(args, &commands, &requests).await {
+                        Ok(body) => {
+                            out.push_str(&body);
+                            if list_ok {
+                                out.push_str("list_OK\n");
+                            }
+                        }
+                        Err(error) => {
+                            out.push_str(&ack(&error, idx, &args[0]));
+                            failed = true;
+                            break;
+                        }
+                    }
+                }
+                if !failed {
+                    out.push_str("OK\n");
+                }
+                out
+            }
+            _ if command_list.is_some() => {
+                if let Some((_, list)) = command_list.as_mut() {
+                    list.push(args);
+                }
+                continue;
+            }
+            "idle" => match idle(&args[1..], &mut lines, &requests).await? {
+                Some(out) => out,
+                None => return Ok(()),
+            },
+            _ => match execute(&args, &commands, &requests).await {
+                Ok(body) => format!("{}OK\n", body),
+                Err(error) => ack(&error, 0, name),
+            },
+        };
+        write.write_all(out.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Starts listening for MPD clients on `addr`.
+pub async fn spawn(
+    addr: &str,
+    commands: mpsc::UnboundedSender<MprisCommand>,
+    requests: mpsc::UnboundedSender<MpdRequest>,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let commands = commands.clone();
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                let _ = handle_client(stream, commands, requests).await;
+            });
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,255 @@
+//! The parts of the MPD protocol that don't need a connection: splitting
+//! command lines, and formatting songs and the player status the way MPD
+//! clients read them.
+
+use crate::types::Song;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Splits a command line into words, honouring MPD's double-quoted
+/// arguments.
+///
+/// ```
+/// use danavi::mpd_protocol::split_args;
+///
+/// assert_eq!(split_args("seekcur +5"), ["seekcur", "+5"]);
+/// assert_eq!(split_args(r#"add "a b/\"c\".flac""#), ["add", r#"a b/"c".flac"#]);
+/// assert!(split_args("   ").is_empty());
+/// ```
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut word = String::new();
+        if c == '"' {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            word.push(escaped);
+                        }
+                    }
+                    _ => word.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+        }
+        args.push(word);
+    }
+    args
+}
+
+/// Formats a song the way `currentsong` and `playlistinfo` list them, with
+/// its place in the playlist when it has one.
+///
+/// ```
+/// use danavi::mpd_protocol::format_song;
+/// use danavi::types::Song;
+///
+/// let song = Song {
+///     id: "s1".to_string(),
+///     title: "Intro".to_string(),
+///     duration: Some(61),
+///     ..Default::default()
+/// };
+/// assert_eq!(
+///     format_song(&song, Some((2, 7))),
+///     "file: s1\nTitle: Intro\nTime: 61\nduration: 61\nPos: 2\nId: 7\n"
+/// );
+/// ```
+pub fn format_song(song: &Song, place: Option<(usize, u32)>) -> String {
+    let mut out = format!(
+        "file: {}\nTitle: {}\n",
+        song.path.as_deref().unwrap_or(&song.id),
+        song.title
+    );
+    if let Some(artist) = &song.artist {
+        out.push_str(&format!("Artist: {}\n", artist));
+    }
+    if let Some(album_artist) = &song.album_artist {
+        out.push_str(&format!("AlbumArtist: {}\n", album_artist));
+    }
+    if let Some(album) = &song.album {
+        out.push_str(&format!("Album: {}\n", album));
+    }
+    if let Some(duration) = song.duration {
+        out.push_str(&format!("Time: {}\nduration: {}\n", duration, duration));
+    }
+    if let Some((pos, id)) = place {
+        out.push_str(&format!("Pos: {}\nId: {}\n", pos, id));
+    }
+    out
+}
+
+/// Numbers for the entries of the playlist. MPD ids are numbers that stay
+/// with an entry for as long as it's in the playlist, wherever it moves;
+/// song ids are strings, so each entry gets one the first time it's seen.
+/// A song that's in the playlist twice gets an id per copy.
+///
+/// ```
+/// use danavi::mpd_protocol::PlaylistIds;
+/// use danavi::types::Song;
+///
+/// let song = |id: &str| Song { id: id.to_string(), ..Default::default() };
+/// let mut ids = PlaylistIds::default();
+/// assert_eq!(ids.assign(&[song("a"), song("b"), song("a")]), [1, 2, 3]);
+/// // Entries keep theirs as the ones before them go
+/// assert_eq!(ids.assign(&[song("b"), song("a"), song("c")]), [2, 1, 4]);
+/// // One that left and came back is a new entry
+/// assert_eq!(ids.assign(&[song("c"), song("b")]), [4, 2]);
+/// assert_eq!(ids.assign(&[song("c"), song("b"), song("a")]), [4, 2, 5]);
+/// ```
+#[derive(Debug, Default)]
+pub struct PlaylistIds {
+    ids: HashMap<(String, usize), u32>,
+    last: u32,
+}
+
+impl PlaylistIds {
+    /// The ids of `playlist`'s entries, in order. Entries no longer in it
+    /// lose theirs.
+    pub fn assign(&mut self, playlist: &[Song]) -> Vec<u32> {
+        let mut copies: HashMap<&str, usize> = HashMap::new();
+        let keys: Vec<(String, usize)> = playlist
+            .iter()
+            .map(|song| {
+                let copy = copies.entry(&song.id).or_default();
+                *copy += 1;
+                (song.id.clone(), *copy - 1)
+            })
+            .collect();
+        let present: HashSet<&(String, usize)> = keys.iter().collect();
+        self.ids.retain(|key, _| present.contains(key));
+        keys.iter()
+            .map(|key| {
+                *self.ids.entry(key.clone()).or_insert_with(|| {
+                    self.last += 1;
+                    self.last
+                })
+            })
+            .collect()
+    }
+}
+
+/// Where a `seekcur` goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekTime {
+    /// Seconds from the start of the song
+    To(f64),
+    /// Seconds from where playback is, back when negative
+    By(f64),
+}
+
+/// Reads a `seekcur` time: seconds, with a leading `+` or `-` to seek
+/// relative to where playback is.
+///
+/// ```
+/// use danavi::mpd_protocol::{parse_seek_time, SeekTime};
+///
+/// assert_eq!(parse_seek_time("42.5"), Some(SeekTime::To(42.5)));
+/// assert_eq!(parse_seek_time("+5"), Some(SeekTime::By(5.0)));
+/// assert_eq!(parse_seek_time("-10"), Some(SeekTime::By(-10.0)));
+/// assert_eq!(parse_seek_time("soon"), None);
+/// assert_eq!(parse_seek_time("NaN"), None);
+/// ```
+pub fn parse_seek_time(arg: &str) -> Option<SeekTime> {
+    let seconds: f64 = arg.parse().ok().filter(|s: &f64| s.is_finite())?;
+    if arg.starts_with(['+', '-']) {
+        Some(SeekTime::By(seconds))
+    } else {
+        (seconds >= 0.0).then_some(SeekTime::To(seconds))
+    }
+}
+
+/// The song playing, which is first in the playlist.
+#[derive(Debug, Clone, Copy)]
+pub struct Current {
+    /// Its playlist id
+    pub id: u32,
+    /// How far into it playback is
+    pub elapsed: Duration,
+    /// In seconds, when known
+    pub duration: Option<i64>,
+}
+
+/// What `status` reports.
+#[derive(Debug, Clone)]
+pub struct Status {
+    /// 0 to 100
+    pub volume: i64,
+    /// Whether shuffle is on
+    pub random: bool,
+    /// Changes whenever the playlist does
+    pub playlist: u32,
+    /// Entries in the playlist, the current one included
+    pub playlist_length: usize,
+    /// "play", "pause" or "stop"
+    pub state: &'static str,
+    /// None when stopped
+    pub current: Option<Current>,
+    /// The id of the entry after the current one, if any
+    pub next_id: Option<u32>,
+}
+
+impl Status {
+    /// Formats the status as `key: value` lines.
+    ///
+    /// ```
+    /// use danavi::mpd_protocol::{Current, Status};
+    /// use std::time::Duration;
+    ///
+    /// let status = Status {
+    ///     volume: 80,
+    ///     random: false,
+    ///     playlist: 3,
+    ///     playlist_length: 2,
+    ///     state: "play",
+    ///     current: Some(Current { id: 4, elapsed: Duration::from_millis(12_345), duration: Some(200) }),
+    ///     next_id: Some(5),
+    /// };
+    /// let out = status.format();
+    /// for line in ["song: 0", "songid: 4", "elapsed: 12.345", "time: 12:200", "duration: 200.000", "nextsong: 1", "nextsongid: 5"] {
+    ///     assert!(out.lines().any(|l| l == line), "{} missing from\n{}", line, out);
+    /// }
+    ///
+    /// let stopped = Status { state: "stop", current: None, next_id: None, ..status };
+    /// assert!(!stopped.format().contains("songid"));
+    /// ```
+    pub fn format(&self) -> String {
+        let mut out = format!(
+            "volume: {}\nrepeat: 0\nrandom: {}\nsingle: 0\nconsume: 1\nplaylist: {}\nplaylistlength: {}\nstate: {}\n",
+            self.volume, self.random as u8, self.playlist, self.playlist_length, self.state
+        );
+        if let Some(current) = &self.current {
+            let elapsed = current.elapsed.as_secs_f64();
+            out.push_str(&format!(
+                "song: 0\nsongid: {}\nelapsed: {:.3}\ntime: {}:{}\n",
+                current.id,
+                elapsed,
+                elapsed.round() as u64,
+                current.duration.unwrap_or(0)
+            ));
+            if let Some(duration) = current.duration {
+                out.push_str(&format!("duration: {}.000\n", duration));
+            }
+        }
+        if let Some(next_id) = self.next_id {
+            out.push_str(&format!("nextsong: 1\nnextsongid: {}\n", next_id));
+        }
+        out
+    }
+}
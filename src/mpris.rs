@@ -1,11 +1,16 @@
+use danavi::bus_name;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, RwLock};
 use zbus::{interface, Connection, object_server::SignalContext};
 use zvariant::{ObjectPath, Str, Value};
 
-const MPRIS_BUS_NAME: &str = "org.mpris.MediaPlayer2.danavi";
 const MPRIS_OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+// At most 20 sink volume changes a second while a slider is dragged
+const VOLUME_APPLY_INTERVAL: Duration = Duration::from_millis(50);
+// Quiet needed before a burst counts as over and is announced
+const VOLUME_SETTLE: Duration = Duration::from_millis(200);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlaybackStatus {
@@ -32,6 +37,13 @@ pub struct Song {
     pub album: Option<String>,
     pub album_artist: Option<String>,
     pub duration: Option<i64>,
+    /// `file://` URL of the cover art
+    pub art_url: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<i32>,
+    pub track: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub podcast: bool,
 }
 
 pub struct MprisState {
@@ -39,6 +51,8 @@ pub struct MprisState {
     pub current_song: Option<Song>,
     pub current_song_url: Option<String>,
     pub volume: f64,
+    pub shuffle: bool,
+    pub position: Duration,
 }
 
 impl Default for MprisState {
@@ -48,6 +62,8 @@ impl Default for MprisState {
             current_song: None,
             current_song_url: None,
             volume: 1.0,
+            shuffle: false,
+            position: Duration::ZERO,
         }
     }
 }
@@ -66,9 +82,51 @@ pub enum MprisCommand {
     Stop,
     Next,
     Previous,
-    Seek,
-    SetPosition,
+    /// Relative, in microseconds
+    Seek(i64),
+    /// Absolute, in microseconds
+    SetPosition(i64),
     SetVolume(f64),
+    SetShuffle(bool),
+}
+
+/// Smooths out bursts of SetVolume calls from desktop sliders. Only the
+/// latest value is kept, the sink changes at a limited rate, and the new
+/// volume is announced once the burst is over. The last value always ends
+/// up applied.
+#[derive(Default)]
+pub struct VolumeBurst {
+    pending: Option<f64>,
+    unannounced: Option<f64>,
+    applied_at: Option<Instant>,
+    requested_at: Option<Instant>,
+}
+
+impl VolumeBurst {
+    pub fn request(&mut self, volume: f64) {
+        self.pending = Some(volume.clamp(0.0, 1.0));
+        self.requested_at = Some(Instant::now());
+    }
+
+    /// The volume to set on the sink now, if one is waiting and the last
+    /// change was long enough ago.
+    pub fn due_apply(&mut self) -> Option<f64> {
+        if self.applied_at.is_some_and(|at| at.elapsed() < VOLUME_APPLY_INTERVAL) {
+            return None;
+        }
+        let volume = self.pending.take()?;
+        self.applied_at = Some(Instant::now());
+        self.unannounced = Some(volume);
+        Some(volume)
+    }
+
+    /// The applied volume to announce, once the burst has settled.
+    pub fn due_announce(&mut self) -> Option<f64> {
+        if self.pending.is_some() || self.requested_at.is_some_and(|at| at.elapsed() < VOLUME_SETTLE) {
+            return None;
+        }
+        self.unannounced.take()
+    }
 }
 
 impl PlayerInterface {
@@ -129,6 +187,36 @@ impl PlayerInterface {
                 );
             }
             
+            if let Some(genre) = &song.genre {
+                metadata.insert(
+                    "xesam:genre".to_string(),
+                    Value::Array(zvariant::Array::from(vec![Str::from(genre.clone())])),
+                );
+            }
+
+            // Only the year is known, so it's the first moment of it
+            if let Some(year) = song.year {
+                metadata.insert(
+                    "xesam:contentCreated".to_string(),
+                    Value::Str(Str::from(format!("{:04}-01-01T00:00:00Z", year))),
+                );
+            }
+
+            if let Some(track) = song.track {
+                metadata.insert("xesam:trackNumber".to_string(), Value::I32(track as i32));
+            }
+
+            if let Some(disc) = song.disc_number {
+                metadata.insert("xesam:discNumber".to_string(), Value::I32(disc as i32));
+            }
+
+            if let Some(art_url) = &song.art_url {
+                metadata.insert(
+                    "mpris:artUrl".to_string(),
+                    Value::Str(Str::from(art_url.clone())),
+                );
+            }
+
             if let Some(url) = &state.current_song_url {
                 metadata.insert(
                     "xesam:url".to_string(),
@@ -174,15 +262,19 @@ impl PlayerInterface {
     }
 
     #[zbus(name = "Seek")]
-    async fn seek(&self, _offset: i64) {
-        let _ = self.command_sender.send(MprisCommand::Seek);
+    async fn seek(&self, offset: i64) {
+        let _ = self.command_sender.send(MprisCommand::Seek(offset));
     }
 
+    // There's only ever the one track id, so it isn't checked
     #[zbus(name = "SetPosition")]
-    async fn set_position(&self, _track_id: ObjectPath<'_>, _position: i64) {
-        let _ = self.command_sender.send(MprisCommand::SetPosition);
+    async fn set_position(&self, _track_id: ObjectPath<'_>, position: i64) {
+        let _ = self.command_sender.send(MprisCommand::SetPosition(position));
     }
 
+    #[zbus(signal, name = "Seeked")]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
     #[zbus(name = "OpenUri")]
     async fn open_uri(&self, _uri: &str) {
     }
@@ -205,7 +297,12 @@ impl PlayerInterface {
 
     #[zbus(property, name = "Shuffle")]
     async fn shuffle(&self) -> bool {
-        false
+        self.state.read().await.shuffle
+    }
+
+    #[zbus(property, name = "Shuffle")]
+    async fn set_shuffle(&self, shuffle: bool) {
+        let _ = self.command_sender.send(MprisCommand::SetShuffle(shuffle));
     }
 
     #[zbus(property, name = "Metadata")]
@@ -226,7 +323,7 @@ impl PlayerInterface {
 
     #[zbus(property, name = "Position")]
     async fn position(&self) -> i64 {
-        0
+        self.state.read().await.position.as_micros() as i64
     }
 
     #[zbus(property, name = "MinimumRate")]
@@ -261,7 +358,7 @@ impl PlayerInterface {
 
     #[zbus(property, name = "CanSeek")]
     async fn can_seek(&self) -> bool {
-        false
+        true
     }
 
     #[zbus(property, name = "CanControl")]
@@ -270,7 +367,9 @@ impl PlayerInterface {
     }
 }
 
-pub struct RootInterface;
+pub struct RootInterface {
+    identity: String,
+}
 
 #[interface(name = "org.mpris.MediaPlayer2")]
 impl RootInterface {
@@ -299,7 +398,7 @@ impl RootInterface {
 
     #[zbus(property, name = "Identity")]
     fn identity(&self) -> String {
-        "danavi".to_string()
+        self.identity.clone()
     }
 
     #[zbus(property, name = "DesktopEntry")]
@@ -321,25 +420,57 @@ impl RootInterface {
 pub struct MprisServer {
     state: Arc<RwLock<MprisState>>,
     connection: Connection,
+    bus_name: String,
 }
 
 impl MprisServer {
+    /// Serves MPRIS under the base bus name, or under `instance` if given,
+    /// falling back to a per-process name when that one is taken.
     pub async fn new(
         command_sender: mpsc::UnboundedSender<MprisCommand>,
+        instance: Option<&str>,
     ) -> anyhow::Result<(Self, Arc<RwLock<MprisState>>)> {
         let state = Arc::new(RwLock::new(MprisState::default()));
         
         let player_interface = PlayerInterface::new(state.clone(), command_sender);
-        let root_interface = RootInterface;
+        let root_interface = RootInterface {
+            identity: "danavi".to_string(),
+        };
 
         let connection = zbus::connection::Builder::session()?
-            .name(MPRIS_BUS_NAME)?
             .serve_at(MPRIS_OBJECT_PATH, player_interface)?
             .serve_at(MPRIS_OBJECT_PATH, root_interface)?
             .build()
             .await?;
 
-        Ok((Self { state: state.clone(), connection }, state))
+        let bus_name = bus_name::claim(instance, std::process::id(), |name| {
+            let connection = connection.clone();
+            async move { connection.request_name(name).await }
+        })
+        .await?;
+        log::info!("MPRIS registered as {}", bus_name);
+
+        // Set before anyone can see the interface under the new name
+        if let Ok(iface_ref) = connection
+            .object_server()
+            .interface::<_, RootInterface>(MPRIS_OBJECT_PATH)
+            .await
+        {
+            iface_ref.get_mut().await.identity = bus_name::identity(&bus_name);
+        }
+
+        Ok((
+            Self {
+                state: state.clone(),
+                connection,
+                bus_name,
+            },
+            state,
+        ))
+    }
+
+    pub fn bus_name(&self) -> &str {
+        &self.bus_name
     }
 
     pub async fn update_playback_status(&self, status: PlaybackStatus) -> anyhow::Result<()> {
@@ -376,6 +507,55 @@ impl MprisServer {
         Ok(())
     }
 
+    /// Adds cover art to the current song's metadata, unless another song
+    /// has started since it was asked for.
+    pub async fn update_art_url(&self, song_id: &str, art_url: String) -> anyhow::Result<()> {
+        let mut state = self.state.write().await;
+        let Some(song) = state.current_song.as_mut().filter(|song| song.id == song_id) else {
+            return Ok(());
+        };
+        song.art_url = Some(art_url);
+        drop(state);
+
+        let object_server = self.connection.object_server();
+        if let Ok(iface_ref) = object_server.interface::<_, PlayerInterface>(MPRIS_OBJECT_PATH).await {
+            let ctxt = SignalContext::new(&self.connection, MPRIS_OBJECT_PATH)?;
+            iface_ref.get().await.metadata_changed(&ctxt).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn update_shuffle(&self, shuffle: bool) -> anyhow::Result<()> {
+        let mut state = self.state.write().await;
+        let old_shuffle = state.shuffle;
+        state.shuffle = shuffle;
+        drop(state);
+
+        if old_shuffle != shuffle {
+            let object_server = self.connection.object_server();
+            if let Ok(iface_ref) = object_server.interface::<_, PlayerInterface>(MPRIS_OBJECT_PATH).await {
+                let ctxt = SignalContext::new(&self.connection, MPRIS_OBJECT_PATH)?;
+                iface_ref.get().await.shuffle_changed(&ctxt).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the Position property current. Clients poll it, so there is
+    /// no signal.
+    pub async fn update_position(&self, position: Duration) {
+        self.state.write().await.position = position;
+    }
+
+    /// Records a jump in the track and emits Seeked.
+    pub async fn seeked(&self, position: Duration) -> anyhow::Result<()> {
+        self.update_position(position).await;
+        let ctxt = SignalContext::new(&self.connection, MPRIS_OBJECT_PATH)?;
+        PlayerInterface::seeked(&ctxt, position.as_micros() as i64).await?;
+        Ok(())
+    }
+
     pub async fn update_volume(&self, volume: f64) -> anyhow::Result<()> {
         let mut state = self.state.write().await;
         let old_volume = state.volume;
@@ -0,0 +1,75 @@
+use crate::mpris::{self, PlaybackStatus};
+use crate::tui::format_duration;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Fills in a song template. Placeholders: `{title}`, `{artist}`, `{album}`,
+/// `{duration}` and `{position}`; unknown values become empty.
+pub fn format_template(template: &str, song: &mpris::Song, position: Duration) -> String {
+    template
+        .replace("{title}", &song.title)
+        .replace("{artist}", song.artist.as_deref().unwrap_or(""))
+        .replace("{album}", song.album.as_deref().unwrap_or(""))
+        .replace(
+            "{duration}",
+            &song.duration.map(format_duration).unwrap_or_default(),
+        )
+        .replace("{position}", &format_duration(position.as_secs() as i64))
+}
+
+/// A one-line file for status bars that mirrors what's playing.
+pub struct StatusFile {
+    path: PathBuf,
+    template: String,
+    written: Option<String>,
+}
+
+impl StatusFile {
+    pub fn new(path: PathBuf, template: String) -> Self {
+        Self {
+            path,
+            template,
+            written: None,
+        }
+    }
+
+    /// Rewrites the file if the line changed. Without `{position}` in the
+    /// template that only happens on track changes, pause/resume and stop.
+    pub fn update(
+        &mut self,
+        song: Option<&mpris::Song>,
+        status: PlaybackStatus,
+        position: Duration,
+    ) -> std::io::Result<()> {
+        let line = match (song, status) {
+            (_, PlaybackStatus::Stopped) | (None, _) => String::new(),
+            (Some(song), PlaybackStatus::Paused) => {
+                format!("{} (paused)", format_template(&self.template, song, position))
+            }
+            (Some(song), PlaybackStatus::Playing) => format_template(&self.template, song, position),
+        };
+        if self.written.as_ref() == Some(&line) {
+            return Ok(());
+        }
+
+        // Write then rename so readers never see a half-written line
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        let content = if line.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n", line)
+        };
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.path)?;
+        self.written = Some(line);
+        Ok(())
+    }
+
+    pub fn remove(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
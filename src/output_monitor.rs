@@ -0,0 +1,132 @@
+use rodio::cpal::traits::HostTrait;
+use rodio::DeviceTrait;
+use std::collections::HashSet;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// The default sink's name and index, as sink events only give the index
+async fn default_sink() -> Option<(String, String)> {
+    let name = pactl(&["get-default-sink"]).await?;
+    let name = name.trim().to_string();
+    let sinks = pactl(&["list", "short", "sinks"]).await?;
+    let index = sinks.lines().find_map(|line| {
+        let mut fields = line.split('\t');
+        let index = fields.next()?;
+        (fields.next()? == name).then(|| index.to_string())
+    })?;
+    Some((name, index))
+}
+
+async fn pactl(args: &[&str]) -> Option<String> {
+    let output = Command::new("pactl").args(args).output().await.ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Follows PulseAudio/PipeWire events. Only the default sink going away and
+/// the default switching count; other sinks come and go (a sleeping display,
+/// an unloaded virtual sink) and new streams (sink-input events) are what
+/// every track change produces, so those are ignored. Returns false if
+/// pactl isn't usable, and otherwise only once nobody is listening.
+async fn watch_pactl(tx: &mpsc::UnboundedSender<String>) -> bool {
+    let Some(mut current) = default_sink().await else {
+        return false;
+    };
+    loop {
+        if let Ok(mut child) = Command::new("pactl")
+            .arg("subscribe")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            && let Some(stdout) = child.stdout.take()
+        {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reason = if line.ends_with(&format!("'remove' on sink #{}", current.1)) {
+                    "audio output removed"
+                } else if line.contains("'change' on server") {
+                    "audio output changed"
+                } else {
+                    continue;
+                };
+                // A removed default is followed by the server picking
+                // another, which isn't news by then
+                let sink = default_sink().await;
+                let moved = sink.as_ref().is_some_and(|s| s.0 != current.0);
+                if let Some(sink) = sink {
+                    // The same sink loaded again comes back under a new index
+                    current = sink;
+                }
+                if reason == "audio output changed" && !moved {
+                    continue;
+                }
+                if tx.send(reason.to_string()).is_err() {
+                    return true;
+                }
+            }
+        }
+        // pactl ends when the sound server restarts; follow it again once
+        // it's back
+        if tx.is_closed() {
+            return true;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+        if let Some(sink) = default_sink().await {
+            if sink.0 != current.0 && tx.send("audio output changed".to_string()).is_err() {
+                return true;
+            }
+            current = sink;
+        }
+    }
+}
+
+fn output_devices() -> (Option<String>, HashSet<String>) {
+    let host = rodio::cpal::default_host();
+    let default = host.default_output_device().and_then(|d| d.name().ok());
+    let devices = host
+        .output_devices()
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default();
+    (default, devices)
+}
+
+/// Without a sound server to ask, poll cpal for the default device changing
+/// or disappearing from the list. Other devices leaving don't count.
+fn poll_cpal(tx: mpsc::UnboundedSender<String>) {
+    let (mut default, _) = output_devices();
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let (new_default, new_devices) = output_devices();
+        let reason = if default.as_ref().is_some_and(|d| !new_devices.contains(d)) {
+            Some("audio output removed")
+        } else if new_default != default {
+            Some("audio output changed")
+        } else {
+            None
+        };
+        if let Some(reason) = reason
+            && tx.send(reason.to_string()).is_err()
+        {
+            break;
+        }
+        default = new_default;
+    }
+}
+
+/// Starts watching the audio output in the background. Each message is a
+/// short reason suitable for the status line.
+pub fn spawn(tx: mpsc::UnboundedSender<String>) {
+    tokio::spawn(async move {
+        if !watch_pactl(&tx).await {
+            let _ = tokio::task::spawn_blocking(move || poll_cpal(tx)).await;
+        }
+    });
+}
@@ -0,0 +1,177 @@
+use danavi::client::{SubsonicClient, CLIENT_NAME};
+use danavi::types::{PlayQueue, Song};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+// A changed queue is saved once it's been left alone this long, so a burst
+// of edits goes up as one
+const SETTLE: Duration = Duration::from_secs(2);
+// Quitting waits this long at most for the last save
+const EXIT_TIMEOUT: Duration = Duration::from_secs(3);
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+// What the server is told: the playing song followed by the queue
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Snapshot {
+    current: Option<String>,
+    queue: Vec<String>,
+}
+
+impl Snapshot {
+    // The ids as saved, the playing song first
+    fn ids(&self) -> Vec<String> {
+        self.current.iter().cloned().chain(self.queue.iter().cloned()).collect()
+    }
+}
+
+// Whether `remote` is just what was last saved from here, come back
+fn is_own_save(remote: &PlayQueue, saved: Option<&Snapshot>) -> bool {
+    let Some(saved) = saved else {
+        return false;
+    };
+    let ids: Vec<String> = remote.songs().0.into_iter().map(|song| song.id).collect();
+    remote.changed_by.as_deref() == Some(CLIENT_NAME) && remote.current == saved.current && ids == saved.ids()
+}
+
+struct Save {
+    snapshot: Snapshot,
+    position: Duration,
+    /// Unix time in milliseconds the queue last changed here
+    changed: u64,
+    done: Option<oneshot::Sender<()>>,
+}
+
+// Saves one at a time, holding back when another client saved since the
+// last change here; that queue is offered instead
+async fn run(client: SubsonicClient, mut rx: mpsc::UnboundedReceiver<Save>, offers: mpsc::UnboundedSender<PlayQueue>) {
+    // The `changed` stamp of the server's queue as last seen here
+    let mut known = match client.get_play_queue().await {
+        Ok(response) => response.play_queue.and_then(|queue| {
+            let changed = queue.changed.clone();
+            if !queue.songs().0.is_empty() {
+                let _ = offers.send(queue);
+            }
+            changed
+        }),
+        Err(e) => {
+            log::debug!("Failed to fetch the play queue: {}", e);
+            None
+        }
+    };
+    // What this client last saved
+    let mut saved: Option<Snapshot> = None;
+    while let Some(save) = rx.recv().await {
+        let remote = client.get_play_queue().await.ok().and_then(|r| r.play_queue);
+        if let Some(remote) = &remote
+            && remote.changed != known
+            && is_own_save(remote, saved.as_ref())
+        {
+            known = remote.changed.clone();
+        }
+        if let Some(remote) = remote
+            && remote.changed != known
+            && remote.changed_ms().is_some_and(|at| at > save.changed)
+        {
+            log::info!(
+                "Play queue saved by {} since the last change here, keeping it",
+                remote.changed_by.as_deref().unwrap_or("another client")
+            );
+            known = remote.changed.clone();
+            let _ = offers.send(remote);
+        } else {
+            let ids = save.snapshot.ids();
+            match client.save_play_queue(&ids, save.snapshot.current.as_deref(), save.position).await {
+                Ok(()) => {
+                    saved = Some(save.snapshot);
+                    known = match client.get_play_queue().await {
+                        Ok(response) => response.play_queue.and_then(|queue| queue.changed),
+                        Err(_) => known,
+                    };
+                }
+                Err(e) => log::warn!("Failed to save the play queue: {}", e),
+            }
+        }
+        if let Some(done) = save.done {
+            let _ = done.send(());
+        }
+    }
+}
+
+/// Keeps the server's play queue in step with this one, so listening can
+/// carry on from another device and back.
+pub struct PlayQueueSync {
+    tx: mpsc::UnboundedSender<Save>,
+    offers: mpsc::UnboundedReceiver<PlayQueue>,
+    last: Snapshot,
+    changed: u64,
+    // When the last change is due to be saved
+    due: Option<Instant>,
+}
+
+impl PlayQueueSync {
+    /// Starts by fetching the server's queue, which `offer` then hands out
+    /// for restoring.
+    pub fn start(client: &SubsonicClient) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (offers_tx, offers) = mpsc::unbounded_channel();
+        tokio::spawn(run(client.clone(), rx, offers_tx));
+        Self {
+            tx,
+            offers,
+            last: Snapshot::default(),
+            changed: now_ms(),
+            due: None,
+        }
+    }
+
+    /// Called with the playing song, the queue and the position; saves the
+    /// queue shortly after it changes.
+    pub fn update(&mut self, current: Option<&str>, queue: &[Song], position: Duration) {
+        let unchanged = self.last.current.as_deref() == current
+            && self.last.queue.len() == queue.len()
+            && self.last.queue.iter().zip(queue).all(|(id, song)| *id == song.id);
+        if !unchanged {
+            self.last = Snapshot {
+                current: current.map(str::to_string),
+                queue: queue.iter().map(|s| s.id.clone()).collect(),
+            };
+            self.changed = now_ms();
+            self.due = Some(Instant::now() + SETTLE);
+        }
+        if self.due.is_some_and(|due| due <= Instant::now()) {
+            self.due = None;
+            self.save(position, None);
+        }
+    }
+
+    /// A queue from the server to offer restoring: the one there at startup,
+    /// or one another client saved over changes made here.
+    pub fn offer(&mut self) -> Option<PlayQueue> {
+        self.offers.try_recv().ok()
+    }
+
+    /// Saves the queue with the final position, for use on quitting.
+    pub async fn flush(&mut self, position: Duration) {
+        if self.last == Snapshot::default() && self.due.is_none() {
+            return;
+        }
+        let (done, saved) = oneshot::channel();
+        self.save(position, Some(done));
+        let _ = tokio::time::timeout(EXIT_TIMEOUT, saved).await;
+    }
+
+    fn save(&self, position: Duration, done: Option<oneshot::Sender<()>>) {
+        let _ = self.tx.send(Save {
+            snapshot: self.last.clone(),
+            position,
+            changed: self.changed,
+            done,
+        });
+    }
+}
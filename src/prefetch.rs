@@ -0,0 +1,113 @@
+use crate::about::format_size;
+use danavi::cache::SongCache;
+use danavi::client::SubsonicClient;
+use danavi::types::Song;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+// Downloads running at once
+const CONCURRENCY: usize = 3;
+
+enum Event {
+    Done(u64),
+    Failed(String),
+}
+
+/// Downloads of the queue into the song cache, running in the background.
+pub struct Prefetch {
+    pub total: usize,
+    pub done: usize,
+    pub bytes: u64,
+    pub already_cached: usize,
+    pub failures: Vec<String>,
+    rx: mpsc::UnboundedReceiver<Event>,
+    handle: JoinHandle<()>,
+}
+
+impl Prefetch {
+    /// Starts downloading the songs that aren't cached yet.
+    pub fn start(client: &SubsonicClient, cache: &SongCache, songs: &[Song]) -> Self {
+        let mut seen = std::collections::HashSet::new();
+        let songs: Vec<Song> = songs
+            .iter()
+            .filter(|s| seen.insert(s.id.clone()))
+            .cloned()
+            .collect();
+        let unique = songs.len();
+        let songs: Vec<Song> = songs.into_iter().filter(|s| !cache.contains(&s.id)).collect();
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let total = songs.len();
+        let client = client.clone();
+        let cache = cache.clone();
+        let handle = tokio::spawn(async move {
+            let mut downloads = futures_util::stream::iter(songs)
+                .map(|song| {
+                    let (client, cache) = (&client, &cache);
+                    async move {
+                        match cache.fetch(client, &song.id).await {
+                            Ok(bytes) => Event::Done(bytes),
+                            Err(e) => Event::Failed(format!("{}: {}", song.title, e)),
+                        }
+                    }
+                })
+                .buffer_unordered(CONCURRENCY);
+            while let Some(event) = downloads.next().await {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            total,
+            done: 0,
+            bytes: 0,
+            already_cached: unique - total,
+            failures: Vec::new(),
+            rx,
+            handle,
+        }
+    }
+
+    /// Picks up finished downloads. Returns true once every song is done.
+    pub fn poll(&mut self) -> bool {
+        while let Ok(event) = self.rx.try_recv() {
+            self.done += 1;
+            match event {
+                Event::Done(bytes) => self.bytes += bytes,
+                Event::Failed(reason) => self.failures.push(reason),
+            }
+        }
+        self.done >= self.total
+    }
+
+    pub fn cancel(self) {
+        self.handle.abort();
+    }
+
+    pub fn progress(&self) -> String {
+        format!(
+            "Prefetching {}/{} · {}",
+            self.done,
+            self.total,
+            format_size(self.bytes)
+        )
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Prefetched {} songs ({})",
+            self.done - self.failures.len(),
+            format_size(self.bytes)
+        );
+        if self.already_cached > 0 {
+            summary += &format!(", {} already cached", self.already_cached);
+        }
+        if !self.failures.is_empty() {
+            summary += &format!(", {} failed (see :messages)", self.failures.len());
+        }
+        summary
+    }
+}
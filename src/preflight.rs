@@ -0,0 +1,236 @@
+use danavi::client::SubsonicClient;
+use danavi::config::{config_needs_edit, get_config_path};
+use danavi::types::Config;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use url::Url;
+
+// How long startup waits on the checks before showing their progress
+const QUIET_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Config,
+    Server,
+    Streaming,
+    Audio,
+    Mpris,
+    Cache,
+}
+
+const CHECKS: [Check; 6] = [
+    Check::Config,
+    Check::Server,
+    Check::Streaming,
+    Check::Audio,
+    Check::Mpris,
+    Check::Cache,
+];
+
+impl Check {
+    pub fn label(self) -> &'static str {
+        match self {
+            Check::Config => "Config",
+            Check::Server => "Server and login",
+            Check::Streaming => "Streaming allowed",
+            Check::Audio => "Audio output",
+            Check::Mpris => "D-Bus / MPRIS",
+            Check::Cache => "Cache directory",
+        }
+    }
+
+    /// What to do about a failure.
+    pub fn remedy(self) -> String {
+        match self {
+            Check::Config => match get_config_path() {
+                Ok(path) => format!("Set base_url, username and password in {}", path.display()),
+                Err(_) => "Set base_url, username and password in config.json".to_string(),
+            },
+            Check::Server => {
+                "Check that the server is up, base_url is right and the password is current"
+                    .to_string()
+            }
+            Check::Streaming => "Ask the server admin to give your user the stream role".to_string(),
+            Check::Audio => "Plug in or enable an output device, or start PulseAudio/PipeWire".to_string(),
+            Check::Mpris => "Start a D-Bus session bus (media keys and playerctl need it)".to_string(),
+            Check::Cache => "Make the directory writable, or point download_dir elsewhere".to_string(),
+        }
+    }
+}
+
+pub enum Status {
+    Running,
+    Passed(String),
+    Failed(String),
+}
+
+/// The startup checks, each running in its own task.
+pub struct Preflight {
+    client: SubsonicClient,
+    config: Config,
+    cache_dir: Option<PathBuf>,
+    pub rows: Vec<(Check, Status)>,
+    pub selected: usize,
+    pub started: Instant,
+    tx: mpsc::UnboundedSender<(Check, Result<String, String>)>,
+    rx: mpsc::UnboundedReceiver<(Check, Result<String, String>)>,
+}
+
+async fn run_check(
+    check: Check,
+    client: SubsonicClient,
+    config: Config,
+    cache_dir: Option<PathBuf>,
+) -> Result<String, String> {
+    match check {
+        Check::Config => {
+            if config_needs_edit(&config) {
+                return Err("still the defaults".to_string());
+            }
+            let url = Url::parse(&config.base_url).map_err(|e| format!("base_url: {}", e))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(format!("base_url must be http or https, not {}", url.scheme()));
+            }
+            if config.username.is_empty() {
+                return Err("username is empty".to_string());
+            }
+            Ok("ok".to_string())
+        }
+        Check::Server => {
+            let started = Instant::now();
+            let ping = client.ping().await.map_err(|e| e.to_string())?;
+            Ok(format!(
+                "{} {} ms",
+                ping.server_type.as_deref().unwrap_or("Subsonic"),
+                started.elapsed().as_millis()
+            ))
+        }
+        Check::Streaming => {
+            let user = client.get_user().await.map_err(|e| e.to_string())?.user;
+            if user.stream_role {
+                Ok(format!("{} may stream", user.username))
+            } else {
+                Err(format!("{} isn't allowed to stream", user.username))
+            }
+        }
+        Check::Audio => tokio::task::spawn_blocking(danavi::audio::output_device_name)
+            .await
+            .ok()
+            .flatten()
+            .ok_or_else(|| "no output device".to_string()),
+        Check::Mpris => zbus::Connection::session()
+            .await
+            .map(|_| "session bus reachable".to_string())
+            .map_err(|e| e.to_string()),
+        Check::Cache => {
+            let dir = cache_dir.ok_or("no cache directory on this system")?;
+            let probe = dir.join(".write-test");
+            fs::create_dir_all(&dir)
+                .and_then(|()| fs::write(&probe, b"ok"))
+                .and_then(|()| fs::remove_file(&probe))
+                .map_err(|e| format!("{}: {}", dir.display(), e))?;
+            Ok(dir.display().to_string())
+        }
+    }
+}
+
+impl Preflight {
+    /// Starts every check at once.
+    pub fn start(client: &SubsonicClient, config: &Config, cache_dir: Option<PathBuf>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut preflight = Self {
+            client: client.clone(),
+            config: config.clone(),
+            cache_dir,
+            rows: CHECKS.iter().map(|&check| (check, Status::Running)).collect(),
+            selected: 0,
+            started: Instant::now(),
+            tx,
+            rx,
+        };
+        for check in CHECKS {
+            preflight.spawn(check);
+        }
+        preflight
+    }
+
+    fn spawn(&mut self, check: Check) {
+        if let Some((_, status)) = self.rows.iter_mut().find(|(c, _)| *c == check) {
+            *status = Status::Running;
+        }
+        let tx = self.tx.clone();
+        let (client, config, cache_dir) = (
+            self.client.clone(),
+            self.config.clone(),
+            self.cache_dir.clone(),
+        );
+        tokio::spawn(async move {
+            let _ = tx.send((check, run_check(check, client, config, cache_dir).await));
+        });
+    }
+
+    pub fn poll(&mut self) {
+        while let Ok((check, result)) = self.rx.try_recv() {
+            self.record(check, result);
+        }
+    }
+
+    fn record(&mut self, check: Check, result: Result<String, String>) {
+        if let Some((_, status)) = self.rows.iter_mut().find(|(c, _)| *c == check) {
+            *status = match result {
+                Ok(detail) => Status::Passed(detail),
+                Err(reason) => Status::Failed(reason),
+            };
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        !self.rows.iter().any(|(_, s)| matches!(s, Status::Running))
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.rows.iter().all(|(_, s)| matches!(s, Status::Passed(_)))
+    }
+
+    pub fn retry_selected(&mut self) {
+        if let Some(&(check, _)) = self.rows.get(self.selected) {
+            self.spawn(check);
+        }
+    }
+
+    pub fn retry_failed(&mut self) {
+        let failed: Vec<Check> = self
+            .rows
+            .iter()
+            .filter(|(_, s)| matches!(s, Status::Failed(_)))
+            .map(|(c, _)| *c)
+            .collect();
+        for check in failed {
+            self.spawn(check);
+        }
+    }
+
+    /// Waits quietly for a moment; true if everything passed by then and
+    /// the screen can be skipped. Returns as soon as that's known either
+    /// way: every check passed, or one failed.
+    pub async fn settle(&mut self) -> bool {
+        let deadline = tokio::time::Instant::from_std(self.started + QUIET_WAIT);
+        loop {
+            if self.all_passed() {
+                return true;
+            }
+            if self.rows.iter().any(|(_, s)| matches!(s, Status::Failed(_))) {
+                return false;
+            }
+            match tokio::time::timeout_at(deadline, self.rx.recv()).await {
+                Ok(Some((check, result))) => self.record(check, result),
+                _ => {
+                    self.poll();
+                    return self.all_passed();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! What plays next: the list a song was started from and its shuffled order.
+
+use crate::types::Song;
+use rand::seq::SliceRandom;
+
+/// Where the playing song came from, which decides what plays after it.
+///
+/// ```
+/// use danavi::queue::PlaybackSource;
+/// use danavi::types::Song;
+///
+/// let songs: Vec<Song> = (1..=4)
+///     .map(|i| Song { id: i.to_string(), ..Default::default() })
+///     .collect();
+/// let mut source = PlaybackSource::Starred { songs, current_index: 1, shuffle: None };
+/// assert_eq!(source.upcoming(), [2, 3]);
+/// assert_eq!(source.previous_index(), Some(0));
+///
+/// // Shuffled, what's left plays in some order, and Previous goes back
+/// // through what actually played
+/// source.set_shuffle(true);
+/// let order = source.upcoming();
+/// assert_eq!(order.len(), 3);
+/// let next = source.with_index(order[0]);
+/// assert_eq!(next.list().unwrap().1, order[0]);
+/// assert_eq!(next.previous_index(), Some(1));
+///
+/// // The queue is a source of its own, without a list to walk
+/// assert!(PlaybackSource::Queue.list().is_none());
+/// ```
+#[derive(Debug, Clone)]
+pub enum PlaybackSource {
+    /// The play queue, which songs are taken off as they play
+    Queue,
+    /// An album, or a directory of files
+    Album {
+        album_songs: Vec<Song>,
+        current_index: usize,
+        // Known when the album was opened from the library, for continuing
+        // with the artist's next album
+        album_id: Option<String>,
+        artist_id: Option<String>,
+        shuffle: Option<ShuffleOrder>,
+    },
+    /// The song results of a search, in display order
+    Search {
+        songs: Vec<Song>,
+        current_index: usize,
+    },
+    /// The starred songs as they were when playback started, so unstarring
+    /// along the way doesn't disturb it
+    Starred {
+        songs: Vec<Song>,
+        current_index: usize,
+        shuffle: Option<ShuffleOrder>,
+    },
+    /// A playlist from the server, as it was when playback started
+    Playlist {
+        songs: Vec<Song>,
+        current_index: usize,
+        playlist_id: String,
+        shuffle: Option<ShuffleOrder>,
+    },
+    /// Every song by an artist, already shuffled
+    Artist {
+        songs: Vec<Song>,
+        current_index: usize,
+    },
+    /// An artist's top songs, in the server's order
+    TopSongs {
+        songs: Vec<Song>,
+        current_index: usize,
+    },
+}
+
+impl PlaybackSource {
+    /// The songs and current position of a source that walks through a list.
+    pub fn list(&self) -> Option<(&[Song], usize)> {
+        match self {
+            PlaybackSource::Queue => None,
+            PlaybackSource::Album {
+                album_songs,
+                current_index,
+                ..
+            } => Some((album_songs, *current_index)),
+            PlaybackSource::Search {
+                songs,
+                current_index,
+            }
+            | PlaybackSource::Artist {
+                songs,
+                current_index,
+            }
+            | PlaybackSource::TopSongs {
+                songs,
+                current_index,
+            }
+            | PlaybackSource::Starred {
+                songs,
+                current_index,
+                ..
+            }
+            | PlaybackSource::Playlist {
+                songs,
+                current_index,
+                ..
+            } => Some((songs, *current_index)),
+        }
+    }
+
+    /// The shuffled order of a source that can be shuffled, when it is.
+    pub fn shuffle(&self) -> Option<&ShuffleOrder> {
+        match self {
+            PlaybackSource::Album { shuffle, .. }
+            | PlaybackSource::Starred { shuffle, .. }
+            | PlaybackSource::Playlist { shuffle, .. } => shuffle.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Indices still to come, in the order they will play.
+    pub fn upcoming(&self) -> Vec<usize> {
+        match (self.shuffle(), self.list()) {
+            (Some(shuffle), _) => shuffle.order[shuffle.played..].to_vec(),
+            (None, Some((songs, current_index))) => (current_index + 1..songs.len()).collect(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// The index Previous goes to: one step back in the play history, or the
+    /// current song again at the start.
+    pub fn previous_index(&self) -> Option<usize> {
+        let (_, current_index) = self.list()?;
+        Some(match self.shuffle() {
+            Some(shuffle) => shuffle
+                .played
+                .checked_sub(2)
+                .map_or(current_index, |i| shuffle.order[i]),
+            None => current_index.saturating_sub(1),
+        })
+    }
+
+    /// Turns shuffle on or off for an album, starred or playlist source. Turning it on
+    /// keeps the current song and shuffles the rest; turning it off carries
+    /// on in list order from the current song.
+    pub fn set_shuffle(&mut self, on: bool) {
+        let Some((songs, current_index)) = self.list() else {
+            return;
+        };
+        let len = songs.len();
+        if let PlaybackSource::Album { shuffle, .. }
+        | PlaybackSource::Starred { shuffle, .. }
+        | PlaybackSource::Playlist { shuffle, .. } = self
+            && shuffle.is_some() != on
+        {
+            *shuffle = on.then(|| ShuffleOrder::new(len, Some(current_index)));
+        }
+    }
+
+    /// The same list source, moved to another position.
+    pub fn with_index(&self, index: usize) -> PlaybackSource {
+        match self {
+            PlaybackSource::Queue => PlaybackSource::Queue,
+            PlaybackSource::Album {
+                album_songs,
+                album_id,
+                artist_id,
+                shuffle,
+                ..
+            } => PlaybackSource::Album {
+                album_songs: album_songs.clone(),
+                current_index: index,
+                album_id: album_id.clone(),
+                artist_id: artist_id.clone(),
+                shuffle: shuffle.as_ref().map(|s| s.moved_to(index)),
+            },
+            PlaybackSource::Search { songs, .. } => PlaybackSource::Search {
+                songs: songs.clone(),
+                current_index: index,
+            },
+            PlaybackSource::Starred { songs, shuffle, .. } => PlaybackSource::Starred {
+                songs: songs.clone(),
+                current_index: index,
+                shuffle: shuffle.as_ref().map(|s| s.moved_to(index)),
+            },
+            PlaybackSource::Playlist {
+                songs,
+                playlist_id,
+                shuffle,
+                ..
+            } => PlaybackSource::Playlist {
+                songs: songs.clone(),
+                current_index: index,
+                playlist_id: playlist_id.clone(),
+                shuffle: shuffle.as_ref().map(|s| s.moved_to(index)),
+            },
+            PlaybackSource::Artist { songs, .. } => PlaybackSource::Artist {
+                songs: songs.clone(),
+                current_index: index,
+            },
+            PlaybackSource::TopSongs { songs, .. } => PlaybackSource::TopSongs {
+                songs: songs.clone(),
+                current_index: index,
+            },
+        }
+    }
+}
+
+/// A shuffled list, generated once so Next and Previous agree. The first
+/// `played` entries of `order` are the play history.
+///
+/// ```
+/// use danavi::queue::ShuffleOrder;
+///
+/// let shuffle = ShuffleOrder::new(5, Some(2));
+/// assert_eq!(shuffle.order[0], 2);
+/// assert_eq!(shuffle.played, 1);
+/// let mut sorted = shuffle.order.clone();
+/// sorted.sort();
+/// assert_eq!(sorted, [0, 1, 2, 3, 4]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ShuffleOrder {
+    pub order: Vec<usize>,
+    pub played: usize,
+}
+
+impl ShuffleOrder {
+    /// Shuffles `len` tracks. A `current` track goes first and counts as
+    /// played.
+    pub fn new(len: usize, current: Option<usize>) -> Self {
+        let mut order: Vec<usize> = (0..len).filter(|&i| Some(i) != current).collect();
+        order.shuffle(&mut rand::thread_rng());
+        if let Some(current) = current {
+            order.insert(0, current);
+        }
+        Self {
+            order,
+            played: current.map_or(0, |_| 1),
+        }
+    }
+
+    fn moved_to(&self, index: usize) -> Self {
+        Self {
+            order: self.order.clone(),
+            played: self
+                .order
+                .iter()
+                .position(|&i| i == index)
+                .map_or(self.played, |i| i + 1),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use danavi::types::Song;
+use std::collections::{HashSet, VecDeque};
+
+// Songs this recent aren't picked again
+const MEMORY: usize = 50;
+// Similar songs are looked up again once fewer than this are lined up
+const LOW_WATER: usize = 3;
+
+/// Similar-songs radio: songs like the playing one, lined up to play once
+/// the queue runs dry. They're kept apart from the queue so anything queued
+/// by hand still comes first.
+#[derive(Default)]
+pub struct Radio {
+    upcoming: VecDeque<Song>,
+    recent: VecDeque<String>,
+    // The song similar ones were last looked up for
+    seed: Option<String>,
+}
+
+impl Radio {
+    /// Notes a song that started playing so it isn't picked again soon.
+    pub fn played(&mut self, id: &str) {
+        self.recent.retain(|recent| recent != id);
+        self.recent.push_back(id.to_string());
+        if self.recent.len() > MEMORY {
+            self.recent.pop_front();
+        }
+    }
+
+    /// The song to look up similar ones for, when more are wanted and it
+    /// hasn't been asked about yet.
+    pub fn wants_more(&self, playing: Option<&str>) -> Option<String> {
+        let playing = playing?;
+        (self.upcoming.len() < LOW_WATER && self.seed.as_deref() != Some(playing)).then(|| playing.to_string())
+    }
+
+    /// Lines up the songs similar to `seed` that haven't played lately and
+    /// aren't already coming. Returns how many were added.
+    pub fn add(&mut self, seed: String, songs: Vec<Song>, queued: &[Song]) -> usize {
+        let mut seen: HashSet<String> = self
+            .recent
+            .iter()
+            .cloned()
+            .chain(self.upcoming.iter().map(|s| s.id.clone()))
+            .chain(queued.iter().map(|s| s.id.clone()))
+            .chain(std::iter::once(seed.clone()))
+            .collect();
+        let before = self.upcoming.len();
+        self.upcoming
+            .extend(songs.into_iter().filter(|s| seen.insert(s.id.clone())));
+        self.seed = Some(seed);
+        self.upcoming.len() - before
+    }
+
+    pub fn next(&mut self) -> Option<Song> {
+        self.upcoming.pop_front()
+    }
+}
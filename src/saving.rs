@@ -0,0 +1,168 @@
+use crate::about::format_size;
+use anyhow::{Context, Result};
+use danavi::client::SubsonicClient;
+use danavi::types::Song;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+const DIR_NAME: &str = "danavi";
+
+enum Event {
+    Saved(u64),
+    Skipped,
+    Failed(String),
+}
+
+/// Where saved songs go: `danavi` in the music directory, usually
+/// `~/Music/danavi`.
+pub fn save_dir() -> Result<PathBuf> {
+    let music = dirs::audio_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join("Music")))
+        .context("Could not find a music directory")?;
+    Ok(music.join(DIR_NAME))
+}
+
+// A tag made safe to use as one path component
+fn component(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_matches('.').trim();
+    if name.is_empty() { "_".to_string() } else { name.to_string() }
+}
+
+// The song's file name without the extension: the one on the server if it
+// shows, else the title
+fn stem(song: &Song) -> String {
+    let from_path = song
+        .path
+        .as_deref()
+        .and_then(|path| Path::new(path).file_stem()?.to_str());
+    component(from_path.unwrap_or(&song.title))
+}
+
+async fn save(client: &SubsonicClient, root: &Path, song: &Song) -> Result<Event> {
+    let artist = song.album_artist.as_deref().or(song.artist.as_deref());
+    let dir = root
+        .join(component(artist.unwrap_or("Unknown Artist")))
+        .join(component(song.album.as_deref().unwrap_or("Unknown Album")));
+    let stem = stem(song);
+    // Knowing the extension up front, a saved song isn't downloaded again
+    let suffix = song.suffix.as_deref().map(str::to_lowercase);
+    if let Some(suffix) = &suffix
+        && dir.join(format!("{}.{}", stem, suffix)).exists()
+    {
+        return Ok(Event::Skipped);
+    }
+
+    let download = client.download(&song.id).await?;
+    let path = match suffix.or_else(|| download.extension()) {
+        Some(extension) => dir.join(format!("{}.{}", stem, extension)),
+        None => dir.join(&stem),
+    };
+    if path.exists() {
+        return Ok(Event::Skipped);
+    }
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    // Written aside first so a failed save doesn't look like a saved song
+    let part = path.with_file_name(format!(".{}.part", stem));
+    fs::write(&part, &download.bytes).with_context(|| format!("Failed to write {}", part.display()))?;
+    fs::rename(&part, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(Event::Saved(download.bytes.len() as u64))
+}
+
+/// Songs being saved as files under [`save_dir`], one at a time in the
+/// background.
+pub struct Saving {
+    pub dir: PathBuf,
+    pub total: usize,
+    pub done: usize,
+    pub skipped: usize,
+    pub bytes: u64,
+    pub failures: Vec<String>,
+    songs: mpsc::UnboundedSender<Song>,
+    rx: mpsc::UnboundedReceiver<Event>,
+}
+
+impl Saving {
+    pub fn start(client: &SubsonicClient) -> Result<Self> {
+        let dir = save_dir()?;
+        let (songs, mut queue) = mpsc::unbounded_channel::<Song>();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let client = client.clone();
+        let root = dir.clone();
+        tokio::spawn(async move {
+            while let Some(song) = queue.recv().await {
+                let event = save(&client, &root, &song)
+                    .await
+                    .unwrap_or_else(|e| Event::Failed(format!("{}: {:#}", song.title, e)));
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            dir,
+            total: 0,
+            done: 0,
+            skipped: 0,
+            bytes: 0,
+            failures: Vec::new(),
+            songs,
+            rx,
+        })
+    }
+
+    /// Lines songs up after any still being saved.
+    pub fn add(&mut self, songs: Vec<Song>) {
+        for song in songs {
+            if self.songs.send(song).is_ok() {
+                self.total += 1;
+            }
+        }
+    }
+
+    /// Picks up saved songs. Returns true once every song is done.
+    pub fn poll(&mut self) -> bool {
+        while let Ok(event) = self.rx.try_recv() {
+            self.done += 1;
+            match event {
+                Event::Saved(bytes) => self.bytes += bytes,
+                Event::Skipped => self.skipped += 1,
+                Event::Failed(reason) => self.failures.push(reason),
+            }
+        }
+        self.done >= self.total
+    }
+
+    pub fn progress(&self) -> String {
+        format!(
+            "Saving {}/{} · {}",
+            self.done,
+            self.total,
+            format_size(self.bytes)
+        )
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Saved {} songs ({}) to {}",
+            self.done - self.skipped - self.failures.len(),
+            format_size(self.bytes),
+            self.dir.display()
+        );
+        if self.skipped > 0 {
+            summary += &format!(", {} already there", self.skipped);
+        }
+        if !self.failures.is_empty() {
+            summary += &format!(", {} failed (see :messages)", self.failures.len());
+        }
+        summary
+    }
+}
@@ -0,0 +1,314 @@
+use anyhow::{Context, Result};
+use danavi::client::{SubsonicClient, SubsonicError};
+use danavi::config::get_state_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+const FILE_NAME: &str = "scrobbles.json";
+// Oldest entries are dropped beyond this
+const MAX_QUEUED: usize = 1000;
+const MIN_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+// A play counts after half the song or four minutes, whichever comes first
+const SCROBBLE_AFTER: Duration = Duration::from_secs(4 * 60);
+// Position changes larger than this between updates are seeks, not listening
+const MAX_STEP: Duration = Duration::from_secs(2);
+// Servers drop a now-playing entry after a while, so it's sent again on
+// resuming from a pause this long
+const LONG_PAUSE: Duration = Duration::from_secs(5 * 60);
+const MS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// A play waiting to be submitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Pending {
+    id: String,
+    /// Unix time in milliseconds when the song started
+    time: u64,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Scrobbles not yet accepted by the server, saved under the state dir so
+/// they survive restarts.
+struct ScrobbleQueue {
+    // None when there's no state dir
+    path: Option<PathBuf>,
+    entries: VecDeque<Pending>,
+    horizon_ms: u64,
+}
+
+impl ScrobbleQueue {
+    fn load(horizon_days: u64) -> Self {
+        let path = get_state_dir().ok().map(|dir| dir.join(FILE_NAME));
+        let entries = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let mut queue = Self {
+            path,
+            entries,
+            horizon_ms: horizon_days * MS_PER_DAY,
+        };
+        if queue.prune() > 0 {
+            let _ = queue.save();
+        }
+        queue
+    }
+
+    /// Drops entries past the horizon. Returns how many went.
+    fn prune(&mut self) -> usize {
+        let cutoff = now_ms().saturating_sub(self.horizon_ms);
+        let count = self.entries.len();
+        self.entries.retain(|entry| entry.time >= cutoff);
+        count - self.entries.len()
+    }
+
+    fn push(&mut self, entry: Pending) {
+        self.entries.push_back(entry);
+        if self.entries.len() > MAX_QUEUED {
+            self.entries.pop_front();
+            log::warn!("Scrobble queue full, dropped the oldest play");
+        }
+        if let Err(e) = self.save() {
+            log::warn!("Failed to save scrobble queue: {}", e);
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create state directory")?;
+        }
+        let content = serde_json::to_string(&self.entries)?;
+        fs::write(path, content).context("Failed to write scrobble queue")?;
+        Ok(())
+    }
+
+    /// Submits entries oldest first, stopping at the first failure to get
+    /// through. One the server turns down, say a song since deleted, would
+    /// fail the same way forever, so it's dropped instead; only a failed
+    /// login is kept for another go.
+    async fn flush(&mut self, client: &SubsonicClient) -> Result<usize> {
+        let mut changed = self.prune() > 0;
+        let mut sent = 0;
+        let mut result = Ok(());
+        while let Some(entry) = self.entries.front() {
+            match client.scrobble(&entry.id, true, entry.time).await {
+                Ok(()) => sent += 1,
+                Err(e) if e.downcast_ref::<SubsonicError>().is_some_and(|e| !e.is_auth()) => {
+                    log::warn!("Server turned down the scrobble of {}, dropping it: {}", entry.id, e);
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+            self.entries.pop_front();
+            changed = true;
+        }
+        if changed
+            && let Err(e) = self.save()
+        {
+            log::warn!("Failed to save scrobble queue: {}", e);
+        }
+        result.map(|()| sent)
+    }
+}
+
+// Owns the queue, submitting new plays and retrying failed ones with backoff
+async fn run(
+    client: SubsonicClient,
+    mut queue: ScrobbleQueue,
+    mut rx: mpsc::UnboundedReceiver<Pending>,
+    depth: Arc<AtomicUsize>,
+) {
+    let mut backoff = MIN_BACKOFF;
+    // Set while the server is unreachable
+    let mut retry_at = (!queue.entries.is_empty()).then(Instant::now);
+    loop {
+        let retry = async {
+            match retry_at {
+                Some(at) => tokio::time::sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            entry = rx.recv() => {
+                let Some(entry) = entry else {
+                    break;
+                };
+                queue.push(entry);
+                depth.store(queue.entries.len(), Ordering::Relaxed);
+                // Don't hammer a server that's down; the retry picks it up
+                if retry_at.is_some() {
+                    continue;
+                }
+            }
+            _ = retry => {}
+        }
+
+        let retrying = retry_at.is_some();
+        match queue.flush(&client).await {
+            Ok(sent) => {
+                if retrying && sent > 0 {
+                    log::info!("Submitted {} queued scrobbles", sent);
+                }
+                retry_at = None;
+                backoff = MIN_BACKOFF;
+            }
+            Err(e) => {
+                if !retrying {
+                    log::warn!("Scrobble failed, will retry: {}", e);
+                }
+                retry_at = Some(Instant::now() + backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+        depth.store(queue.entries.len(), Ordering::Relaxed);
+    }
+}
+
+// The song being listened to
+struct Listen {
+    id: String,
+    // Which start of a track this is, so a song played twice counts twice
+    start: u64,
+    started: u64,
+    // Time actually played, so seeking ahead and pausing don't count
+    heard: Duration,
+    position: Duration,
+    submitted: bool,
+}
+
+/// Decides when a play counts and hands it to the background queue.
+pub struct Scrobbler {
+    tx: mpsc::UnboundedSender<Pending>,
+    depth: Arc<AtomicUsize>,
+    listen: Option<Listen>,
+}
+
+impl Scrobbler {
+    /// Loads the saved queue and starts retrying whatever is in it.
+    pub fn start(client: &SubsonicClient, horizon_days: u64) -> Self {
+        let queue = ScrobbleQueue::load(horizon_days);
+        let depth = Arc::new(AtomicUsize::new(queue.entries.len()));
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(client.clone(), queue, rx, depth.clone()));
+        Self {
+            tx,
+            depth,
+            listen: None,
+        }
+    }
+
+    /// Called with the playing song, a number that changes each time a
+    /// track starts, and the position; submits the play once enough of it
+    /// has been heard.
+    pub fn update(&mut self, song: Option<(&str, Option<i64>)>, start: u64, position: Duration) {
+        let Some((id, duration)) = song else {
+            self.listen = None;
+            return;
+        };
+        if self.listen.as_ref().is_none_or(|listen| listen.start != start || listen.id != id) {
+            let started = now_ms().saturating_sub(position.as_millis() as u64);
+            self.listen = Some(Listen {
+                id: id.to_string(),
+                start,
+                started,
+                heard: Duration::ZERO,
+                position,
+                submitted: false,
+            });
+        }
+        let Some(listen) = self.listen.as_mut() else {
+            return;
+        };
+        if let Some(step) = position.checked_sub(listen.position)
+            && step <= MAX_STEP
+        {
+            listen.heard += step;
+        }
+        listen.position = position;
+        let threshold = duration
+            .filter(|&secs| secs > 0)
+            .map_or(SCROBBLE_AFTER, |secs| {
+                Duration::from_secs(secs as u64 / 2).min(SCROBBLE_AFTER)
+            });
+        if !listen.submitted && listen.heard >= threshold {
+            listen.submitted = true;
+            let _ = self.tx.send(Pending {
+                id: listen.id.clone(),
+                time: listen.started,
+            });
+        }
+    }
+
+    /// Plays waiting to be submitted.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps the server's now-playing list showing the current song.
+pub struct NowPlaying {
+    client: SubsonicClient,
+    reported: Option<String>,
+    paused_since: Option<Instant>,
+}
+
+impl NowPlaying {
+    pub fn new(client: &SubsonicClient) -> Self {
+        Self {
+            client: client.clone(),
+            reported: None,
+            paused_since: None,
+        }
+    }
+
+    /// Called with the playing song's id; reports a new song, or the same
+    /// one after a long pause.
+    pub fn update(&mut self, id: Option<&str>, paused: bool) {
+        let Some(id) = id else {
+            self.reported = None;
+            self.paused_since = None;
+            return;
+        };
+        if paused {
+            self.paused_since.get_or_insert_with(Instant::now);
+            return;
+        }
+        let resumed = self
+            .paused_since
+            .take()
+            .is_some_and(|since| since.elapsed() >= LONG_PAUSE);
+        if resumed || self.reported.as_deref() != Some(id) {
+            self.reported = Some(id.to_string());
+            self.report(id);
+        }
+    }
+
+    // Spawned so a slow server never holds up playback, and not retried
+    fn report(&self, id: &str) {
+        let client = self.client.clone();
+        let id = id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = client.scrobble(&id, false, now_ms()).await {
+                log::debug!("Now playing update failed: {}", e);
+            }
+        });
+    }
+}
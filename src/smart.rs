@@ -0,0 +1,235 @@
+//! Rule-based smart playlists, defined in `smart_playlists.json`.
+
+use crate::sorting::IgnoredArticles;
+use crate::types::Song;
+use rand::seq::SliceRandom;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+const FIELDS: &[&str] = &[
+    "name",
+    "genre",
+    "min_year",
+    "max_year",
+    "starred",
+    "min_rating",
+    "artist",
+    "min_duration",
+    "max_duration",
+    "limit",
+    "order",
+];
+
+/// How a smart playlist's songs are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SmartOrder {
+    /// As the server lists them
+    #[default]
+    Server,
+    /// Shuffled
+    Random,
+    /// By title
+    Title,
+    /// By artist
+    Artist,
+    /// Oldest first
+    Year,
+}
+
+/// A named set of rules evaluated against the library. All rules that are
+/// set must match for a song to be included.
+#[derive(Debug, Clone, Default)]
+pub struct SmartPlaylist {
+    /// Shown in the Playlists view
+    pub name: String,
+    /// Exactly this genre, ignoring case
+    pub genre: Option<String>,
+    /// Released in this year or later
+    pub min_year: Option<i32>,
+    /// Released in this year or earlier
+    pub max_year: Option<i32>,
+    /// Starred, or with false not starred
+    pub starred: Option<bool>,
+    /// Rated at least this, from 1 to 5
+    pub min_rating: Option<u8>,
+    /// Part of the artist's name, ignoring case
+    pub artist: Option<String>,
+    /// At least this many seconds long
+    pub min_duration: Option<i64>,
+    /// At most this many seconds long
+    pub max_duration: Option<i64>,
+    /// At most this many songs
+    pub limit: Option<usize>,
+    /// How the songs are ordered
+    pub order: SmartOrder,
+}
+
+fn field<T: DeserializeOwned>(
+    obj: &Map<String, Value>,
+    name: &str,
+    key: &str,
+    expected: &str,
+) -> Result<Option<T>, String> {
+    match obj.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone()).map(Some).map_err(|_| {
+            format!("Smart playlist '{}': field '{}' must be {}", name, key, expected)
+        }),
+    }
+}
+
+fn parse_playlist(index: usize, value: &Value) -> Result<SmartPlaylist, String> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| format!("Smart playlist #{}: expected an object", index + 1))?;
+    let name = obj
+        .get("name")
+        .and_then(|n| n.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| format!("Smart playlist #{}: field 'name' must be a string", index + 1))?;
+
+    if let Some(key) = obj.keys().find(|k| !FIELDS.contains(&k.as_str())) {
+        return Err(format!("Smart playlist '{}': unknown field '{}'", name, key));
+    }
+
+    let order = match field::<String>(obj, &name, "order", "a string")?.as_deref() {
+        None | Some("none") => SmartOrder::Server,
+        Some("random") => SmartOrder::Random,
+        Some("title") => SmartOrder::Title,
+        Some("artist") => SmartOrder::Artist,
+        Some("year") => SmartOrder::Year,
+        Some(_) => {
+            return Err(format!(
+                "Smart playlist '{}': field 'order' must be one of none, random, title, artist, year",
+                name
+            ));
+        }
+    };
+
+    Ok(SmartPlaylist {
+        genre: field(obj, &name, "genre", "a string")?,
+        min_year: field(obj, &name, "min_year", "a year")?,
+        max_year: field(obj, &name, "max_year", "a year")?,
+        starred: field(obj, &name, "starred", "true or false")?,
+        min_rating: field(obj, &name, "min_rating", "a rating from 0 to 5")?,
+        artist: field(obj, &name, "artist", "a string")?,
+        min_duration: field(obj, &name, "min_duration", "a number of seconds")?,
+        max_duration: field(obj, &name, "max_duration", "a number of seconds")?,
+        limit: field(obj, &name, "limit", "a positive number")?,
+        order,
+        name,
+    })
+}
+
+/// Loads smart playlists from a JSON array. Invalid entries are skipped and
+/// reported in the returned error list, so one bad rule doesn't hide the rest.
+pub fn load_smart_playlists(path: &Path) -> (Vec<SmartPlaylist>, Vec<String>) {
+    if !path.exists() {
+        return (Vec::new(), Vec::new());
+    }
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return (Vec::new(), vec![format!("Failed to read {}: {}", path.display(), e)]),
+    };
+    let entries = match serde_json::from_str::<Vec<Value>>(&content) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return (
+                Vec::new(),
+                vec![format!("{} must be a JSON array: {}", path.display(), e)],
+            );
+        }
+    };
+
+    let mut playlists = Vec::new();
+    let mut errors = Vec::new();
+    for (index, value) in entries.iter().enumerate() {
+        match parse_playlist(index, value) {
+            Ok(playlist) => playlists.push(playlist),
+            Err(e) => errors.push(e),
+        }
+    }
+    (playlists, errors)
+}
+
+impl SmartPlaylist {
+    /// Whether the song satisfies every rule that is set.
+    pub fn matches(&self, song: &Song) -> bool {
+        if let Some(genre) = &self.genre
+            && !song
+                .genre
+                .as_ref()
+                .is_some_and(|g| g.eq_ignore_ascii_case(genre))
+        {
+            return false;
+        }
+        if let Some(min_year) = self.min_year
+            && song.year.is_none_or(|y| y < min_year)
+        {
+            return false;
+        }
+        if let Some(max_year) = self.max_year
+            && song.year.is_none_or(|y| y > max_year)
+        {
+            return false;
+        }
+        if let Some(starred) = self.starred
+            && song.starred != starred
+        {
+            return false;
+        }
+        if let Some(min_rating) = self.min_rating
+            && song.rating.unwrap_or(0) < min_rating
+        {
+            return false;
+        }
+        if let Some(artist) = &self.artist
+            && !song
+                .artist
+                .as_ref()
+                .is_some_and(|a| a.to_lowercase().contains(&artist.to_lowercase()))
+        {
+            return false;
+        }
+        if let Some(min_duration) = self.min_duration
+            && song.duration.is_none_or(|d| d < min_duration)
+        {
+            return false;
+        }
+        if let Some(max_duration) = self.max_duration
+            && song.duration.is_none_or(|d| d > max_duration)
+        {
+            return false;
+        }
+        true
+    }
+
+    /// Filters, orders and truncates a candidate pool into the final list.
+    /// Artists sort as the server's index does, leaving out `articles`.
+    pub fn apply(&self, candidates: Vec<Song>, articles: &IgnoredArticles) -> Vec<Song> {
+        let mut seen = HashSet::new();
+        let mut songs: Vec<Song> = candidates
+            .into_iter()
+            .filter(|s| self.matches(s) && seen.insert(s.id.clone()))
+            .collect();
+
+        match self.order {
+            SmartOrder::Server => {}
+            SmartOrder::Random => songs.shuffle(&mut rand::thread_rng()),
+            SmartOrder::Title => songs.sort_by_key(|s| s.title.to_lowercase()),
+            SmartOrder::Artist => {
+                songs.sort_by_cached_key(|s| articles.sort_key(s.artist.as_deref().unwrap_or_default()))
+            }
+            SmartOrder::Year => songs.sort_by_key(|s| s.year),
+        }
+
+        if let Some(limit) = self.limit {
+            songs.truncate(limit);
+        }
+        songs
+    }
+}
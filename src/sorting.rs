@@ -0,0 +1,56 @@
+//! Ordering artist names the way the server's own index does.
+
+use icu_normalizer::DecomposingNormalizerBorrowed;
+
+/// Leading words left out when sorting names, as a server lists them in
+/// `ignoredArticles`. Only the order changes; names are always shown whole.
+///
+/// ```
+/// use danavi::sorting::IgnoredArticles;
+///
+/// let articles = IgnoredArticles::parse("The El La Los Las Le Les");
+/// let mut names = vec!["The Beatles", "Blur", "Étienne Daho", "etta james", "Etienne de Crécy", "Los Lobos"];
+/// names.sort_by_cached_key(|name| articles.sort_key(name));
+/// assert_eq!(names, ["The Beatles", "Blur", "Étienne Daho", "Etienne de Crécy", "etta james", "Los Lobos"]);
+///
+/// // Only a whole leading word counts, and a name that is nothing but one
+/// // keeps it
+/// assert_eq!(articles.sort_key("Theory of a Deadman"), "theory of a deadman");
+/// assert_eq!(articles.sort_key("THE  The"), "the");
+/// assert_eq!(articles.sort_key("The"), "the");
+/// assert_eq!(IgnoredArticles::default().sort_key("The Beatles"), "the beatles");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IgnoredArticles(Vec<String>);
+
+impl IgnoredArticles {
+    /// From the space separated list servers send.
+    pub fn parse(list: &str) -> Self {
+        Self(list.split_whitespace().map(fold).collect())
+    }
+
+    /// What to sort `name` by: without a leading article, lowercase and
+    /// with accents dropped, so "Étienne" sorts with "Etienne".
+    pub fn sort_key(&self, name: &str) -> String {
+        let name = fold(name.trim());
+        for article in &self.0 {
+            if let Some(rest) = name.strip_prefix(article.as_str())
+                && rest.starts_with(' ')
+                && !rest.trim().is_empty()
+            {
+                return rest.trim_start().to_string();
+            }
+        }
+        name
+    }
+}
+
+// Lowercase, without the combining accents decomposition splits off
+fn fold(text: &str) -> String {
+    DecomposingNormalizerBorrowed::new_nfd()
+        .normalize(text)
+        .chars()
+        .filter(|c| !('\u{300}'..='\u{36f}').contains(c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
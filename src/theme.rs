@@ -0,0 +1,152 @@
+use danavi::types::Background;
+use ratatui::style::{Color, Modifier, Style};
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+
+// Terminals that answer neither query get this long
+const QUERY_TIMEOUT: Duration = Duration::from_millis(150);
+
+/// Colors for everything that isn't the terminal's own foreground and
+/// background.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    /// Status bar and overlay panels
+    pub panel: Style,
+    pub selection: Style,
+    /// The line being sung, a download in progress
+    pub accent: Color,
+    pub success: Color,
+    pub error: Color,
+}
+
+impl Palette {
+    pub fn dark() -> Self {
+        Self {
+            panel: Style::default().fg(Color::White).bg(Color::Blue),
+            selection: Style::default().fg(Color::Black).bg(Color::LightBlue),
+            accent: Color::Yellow,
+            success: Color::Green,
+            error: Color::Red,
+        }
+    }
+
+    // Fixed 256-color entries, since light themes tend to remap the basic
+    // sixteen in ways that make them unreadable
+    pub fn light() -> Self {
+        Self {
+            panel: Style::default().fg(Color::Indexed(16)).bg(Color::Indexed(153)),
+            selection: Style::default().fg(Color::Indexed(231)).bg(Color::Indexed(25)),
+            accent: Color::Indexed(130),
+            success: Color::Indexed(28),
+            error: Color::Indexed(124),
+        }
+    }
+
+    pub fn accent_bold(&self) -> Style {
+        Style::default().fg(self.accent).add_modifier(Modifier::BOLD)
+    }
+
+    /// Search matches. The modifiers survive the selection highlight, which
+    /// replaces the colors.
+    pub fn matched(&self) -> Style {
+        self.accent_bold().add_modifier(Modifier::UNDERLINED)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// The palette for `background`, asking the terminal in auto mode. Needs
+/// raw mode so the terminal's reply isn't echoed, and should run before the
+/// first frame.
+pub fn palette(background: Background) -> Palette {
+    let light = match background {
+        Background::Dark => false,
+        Background::Light => true,
+        Background::Auto => query_background()
+            .or_else(colorfgbg_background)
+            .is_some_and(|luminance| luminance > 0.5),
+    };
+    if light { Palette::light() } else { Palette::dark() }
+}
+
+/// Background luminance from `COLORFGBG`, which some terminals set to
+/// "fg;bg" with ANSI color numbers.
+fn colorfgbg_background() -> Option<f64> {
+    let value = std::env::var("COLORFGBG").ok()?;
+    let bg: u8 = value.rsplit(';').next()?.parse().ok()?;
+    // 7 and 9-15 are the light ones; 8 is dark grey
+    Some(if bg == 7 || bg >= 9 { 1.0 } else { 0.0 })
+}
+
+/// Parses an OSC 11 reply like `ESC]11;rgb:ffff/ffff/dddd BEL` into its
+/// luminance from 0 to 1.
+fn parse_osc11(reply: &str) -> Option<f64> {
+    let rgb = reply.split("rgb:").nth(1)?;
+    let channels: Vec<f64> = rgb
+        .splitn(3, '/')
+        .map(|hex| {
+            let hex: String = hex.chars().take_while(char::is_ascii_hexdigit).collect();
+            let max = 16f64.powi(hex.len() as i32) - 1.0;
+            u32::from_str_radix(&hex, 16).ok().map(|v| v as f64 / max)
+        })
+        .collect::<Option<_>>()?;
+    let [r, g, b] = channels[..] else {
+        return None;
+    };
+    Some(0.2126 * r + 0.7152 * g + 0.0722 * b)
+}
+
+/// Asks the terminal for its background color. A device attributes query
+/// goes right after, which every terminal answers, so one that ignores
+/// OSC 11 is found out without waiting for the timeout.
+#[cfg(unix)]
+fn query_background() -> Option<f64> {
+    use std::io::Write;
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return None;
+    }
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+    stdout.flush().ok()?;
+
+    let fd = io::stdin().as_raw_fd();
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+    // The device attributes reply, ESC [ ? ... c, comes last
+    while !reply
+        .rsplit(|&b| b == 0x1b)
+        .next()
+        .is_some_and(|last: &[u8]| last.starts_with(b"[?") && last.ends_with(b"c"))
+    {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: one valid pollfd, and a buffer of the length given
+        let ready = unsafe { libc::poll(&mut pollfd, 1, left.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            break;
+        }
+        let mut buf = [0u8; 64];
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..n as usize]);
+    }
+    parse_osc11(&String::from_utf8_lossy(&reply))
+}
+
+#[cfg(not(unix))]
+fn query_background() -> Option<f64> {
+    None
+}
@@ -1,18 +1,174 @@
+use crate::about::{format_size, AboutInfo};
+use crate::album_progress::AlbumProgress;
+use crate::episode_progress::EpisodeProgress;
+use crate::artist_shuffle::ArtistShuffle;
+use crate::downloads::{Downloads, Status};
+use crate::info_panel::{self, InfoPanel};
+use crate::jukebox::Jukebox;
+use crate::listings::Listings;
+use crate::lyrics_panel::LyricsPanel;
+use crate::preflight::{Preflight, Status as CheckStatus};
+use crate::prefetch::Prefetch;
+use crate::radio::Radio;
+use crate::saving::Saving;
+use crate::scrobble::Scrobbler;
+use crate::smart::SmartPlaylist;
+use crate::theme::Palette;
 use crate::types::*;
+use danavi::cache::{CoverArtCache, SongCache, StreamCache};
+use danavi::client::{ServerCapabilities, StreamOptions};
+use danavi::generation::Generations;
+use danavi::lyrics::Lyrics;
+use danavi::queue::PlaybackSource;
 use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{
+    self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyEventKind,
+    KeyModifiers,
+};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Alignment, Constraint, Direction, Layout, Rect};
-use ratatui::style::{Color, Style};
-use ratatui::text::Line;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
 use ratatui::Frame;
 use ratatui::Terminal;
-use std::io;
+use base64::Engine;
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const MESSAGE_LOG_SIZE: usize = 200;
+const HIGHLIGHT_SYMBOL: &str = ">> ";
+const RESULT_PREFIX_ALBUM: &str = "[A] ";
+const RESULT_PREFIX_SONG: &str = "[S] ";
+// Navigation repeats closer together than this count as a held key
+const KEY_REPEAT_THRESHOLD: Duration = Duration::from_millis(80);
+// Held repeats before moving up to the next acceleration step
+const REPEATS_PER_STEP: usize = 10;
+// Quiet time after a keystroke before searching as you type
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(300);
+// Seconds , and . seek by
+const SEEK_STEP: i64 = 10;
+
+#[derive(Debug, Clone)]
+pub enum SearchResultItem {
+    Album {
+        id: String,
+        name: String,
+        artist: String,
+        artist_id: String,
+        year: Option<i32>,
+        song_count: Option<u32>,
+        starred: bool,
+        rating: Option<u8>,
+        /// A directory from a `search2` server, opened as a folder
+        folder: bool,
+    },
+    Song {
+        id: String,
+        title: String,
+        artist: String,
+        album: Option<String>,
+        duration: Option<i64>,
+        path: Option<String>,
+        replay_gain: Option<ReplayGain>,
+        starred: bool,
+        cover_art: Option<String>,
+        rating: Option<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViewType {
+    Artists,
+    Albums,
+    Songs,
+    Search,
+    Playlists,
+    Podcasts,
+    Episodes,
+    Folders,
+}
+
+impl SearchResultItem {
+    /// The result as a playable song; `None` for albums.
+    pub fn to_song(&self) -> Option<Song> {
+        match self {
+            SearchResultItem::Album { .. } => None,
+            SearchResultItem::Song {
+                id,
+                title,
+                artist,
+                album,
+                duration,
+                path,
+                replay_gain,
+                starred,
+                cover_art,
+                rating,
+            } => Some(Song {
+                id: id.clone(),
+                title: title.clone(),
+                artist: Some(artist.clone()),
+                album: album.clone(),
+                duration: *duration,
+                path: path.clone(),
+                starred: *starred,
+                replay_gain: *replay_gain,
+                cover_art: cover_art.clone(),
+                rating: *rating,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn starred(&self) -> bool {
+        match self {
+            SearchResultItem::Album { starred, .. } | SearchResultItem::Song { starred, .. } => *starred,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        match self {
+            SearchResultItem::Album { id, .. } | SearchResultItem::Song { id, .. } => id,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ConfirmAction {
+    ExportQueue { path: String, use_paths: bool },
+    UnstarAlbum { album_id: String },
+    ResumeAlbum {
+        album_id: String,
+        track_index: usize,
+        /// Seconds into the track
+        position: u64,
+    },
+    ResumeEpisode { id: String, position: Duration },
+    DeletePlaylist { id: String, name: String },
+    RestorePlayQueue {
+        songs: Vec<Song>,
+        current: Option<usize>,
+        /// Milliseconds into the current song
+        position: u64,
+        /// Songs in the saved queue no longer on the server
+        unresolved: usize,
+    },
+    ShuffleArtist { artist: String, album_ids: Vec<String> },
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingConfirm {
+    pub prompt: String,
+    pub action: ConfirmAction,
+}
 
 #[derive(Debug, Clone, Copy)]
 pub enum Action {
@@ -23,6 +179,205 @@ pub enum Action {
     RestartQueue,
     Search,
     TogglePause,
+    Command,
+    Confirm,
+    AppendAlbum,
+    ReplaceQueue,
+    OpenPlaylists,
+    OpenStarred,
+    OpenPodcasts,
+    OpenFolders,
+    Refresh,
+    About,
+    StarAlbum,
+    ToggleShuffle,
+    /// Seek to this many tenths of the track
+    SeekPercent(u8),
+    /// Seek this many seconds ahead, or back if negative
+    Seek(i64),
+    VolumeUp,
+    VolumeDown,
+    ToggleMute,
+    /// Like `Seek`, but stops short of the end and says where it landed
+    Jump(i64),
+    /// Play the current track again from the start
+    Restart,
+    /// Star or unstar whatever is selected
+    ToggleStar,
+    /// Rate the selected song or album from 1 to 5, or clear it with 0
+    Rate(u8),
+    /// Rate the playing song
+    RatePlaying(u8),
+    /// Fill the queue with random songs and keep it topped up
+    ShuffleAll,
+    /// Play every song by the open artist, shuffled
+    ShuffleArtist,
+    CancelArtistShuffle,
+    /// Play an album the server picks at random, from its first track
+    PlayRandomAlbum,
+    /// List the open artist's most played songs
+    TopSongs,
+    /// Show the selected or open artist's biography and similar artists,
+    /// or the open album's notes
+    ShowInfo,
+    /// Open the albums of the artist picked in the artist info
+    OpenSimilarArtist,
+    ToggleRadio,
+    /// Play on the server's own audio output, or back here
+    ToggleJukebox,
+    /// List every album in this order, or switch the open list to it
+    BrowseAlbums(AlbumListMode),
+    /// Pick a playlist to save the queue to
+    SaveQueue,
+    /// Save the queue where the playlist picker says
+    SaveToPlaylist,
+    RemoveFromPlaylist,
+    /// Move the selected song of the open playlist this many rows
+    MoveInPlaylist(isize),
+    RenamePlaylist,
+    DeletePlaylist,
+    Prefetch,
+    CancelPrefetch,
+    DownloadAlbum,
+    /// Save the selected song, or the selected album's songs, as files
+    SaveFiles,
+    /// Make a share link for the selected song or album
+    Share,
+}
+
+/// Where the queue is being saved: a new playlist, named as it's typed, or
+/// one of the server's to append to.
+pub struct PlaylistPicker {
+    pub playlists: Vec<PlaylistData>,
+    // 0 is the new playlist; the rest are `playlists` from 1 on
+    pub selected: usize,
+    pub name: String,
+    pub naming: bool,
+}
+
+impl PlaylistPicker {
+    pub fn new(playlists: Vec<PlaylistData>) -> Self {
+        Self {
+            playlists,
+            selected: 0,
+            name: String::new(),
+            naming: false,
+        }
+    }
+
+    /// The existing playlist picked, or None for a new one.
+    pub fn target(&self) -> Option<&PlaylistData> {
+        self.playlists.get(self.selected.checked_sub(1)?)
+    }
+}
+
+/// The orders the server can list every album in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlbumListMode {
+    Newest,
+    Recent,
+    Frequent,
+    Random,
+    AlphabeticalByName,
+}
+
+impl AlbumListMode {
+    /// The `type` getAlbumList2 takes.
+    pub fn api_name(self) -> &'static str {
+        match self {
+            AlbumListMode::Newest => "newest",
+            AlbumListMode::Recent => "recent",
+            AlbumListMode::Frequent => "frequent",
+            AlbumListMode::Random => "random",
+            AlbumListMode::AlphabeticalByName => "alphabeticalByName",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AlbumListMode::Newest => "Recently added",
+            AlbumListMode::Recent => "Recently played",
+            AlbumListMode::Frequent => "Most played",
+            AlbumListMode::Random => "Random",
+            AlbumListMode::AlphabeticalByName => "By name",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            AlbumListMode::Newest => AlbumListMode::Recent,
+            AlbumListMode::Recent => AlbumListMode::Frequent,
+            AlbumListMode::Frequent => AlbumListMode::Random,
+            AlbumListMode::Random => AlbumListMode::AlphabeticalByName,
+            AlbumListMode::AlphabeticalByName => AlbumListMode::Newest,
+        }
+    }
+}
+
+/// The Albums view listing the whole library rather than one artist,
+/// fetched a page at a time.
+#[derive(Debug, Clone, Copy)]
+pub struct AlbumListing {
+    pub mode: AlbumListMode,
+    // The server has sent the last page
+    pub complete: bool,
+    // Where the selection was when a page failed; it's tried again once
+    // the selection moves
+    pub failed_at: Option<usize>,
+}
+
+/// A search whose results load a page at a time as the selection nears
+/// the end. Offsets count what the server sent, duplicates included.
+#[derive(Debug, Clone)]
+pub struct SearchPaging {
+    pub query: String,
+    pub album_offset: usize,
+    pub song_offset: usize,
+    pub albums_done: bool,
+    pub songs_done: bool,
+}
+
+impl SearchPaging {
+    pub fn complete(&self) -> bool {
+        self.albums_done && self.songs_done
+    }
+}
+
+/// A library scan being followed until it finishes.
+pub struct LibraryScan {
+    pub updates: tokio::sync::mpsc::UnboundedReceiver<Result<ScanStatus>>,
+    /// Items the server has counted so far
+    pub count: Option<u64>,
+}
+
+/// One directory of the library's folder tree.
+#[derive(Debug, Clone)]
+pub struct Folder {
+    pub name: String,
+    pub entries: Vec<FolderEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub enum FolderEntry {
+    Dir { id: String, name: String },
+    File(Box<Song>),
+}
+
+/// A server playlist open in the Songs view.
+#[derive(Debug, Clone)]
+pub struct OpenPlaylist {
+    pub id: String,
+    pub name: String,
+    // Entries the server couldn't resolve to songs, which aren't listed and
+    // so throw its indices out from ours
+    pub missing: u64,
+}
+
+/// A view we drilled down from, restored when going back.
+pub struct ViewState {
+    pub view: ViewType,
+    pub title: String,
+    pub selected: Option<usize>,
 }
 
 pub struct App {
@@ -31,15 +386,115 @@ pub struct App {
     pub albums: Vec<Album>,
     pub songs: Vec<Song>,
     pub search_results: Vec<SearchResultItem>,
+    // Lowercased words of the query the results are for
+    pub search_terms: Vec<String>,
+    pub smart_playlists: Vec<SmartPlaylist>,
+    // The server's playlists, listed after the smart ones
+    pub playlists: Vec<PlaylistData>,
+    pub podcasts: Vec<PodcastChannel>,
+    pub episodes: Vec<PodcastEpisode>,
+    // The channel whose episodes are listed
+    pub podcast_title: String,
     pub list_state: ListState,
+    pub view_stack: Vec<ViewState>,
     pub current_artist_id: Option<String>,
     pub current_album_id: Option<String>,
     pub queue: Vec<Song>,
+    pub queue_undo: Option<Vec<Song>>,
+    /// The queue is random songs, fetched again as it runs low
+    pub shuffle_all: bool,
+    // Similar songs to play when the queue runs dry; None when it's off
+    pub radio: Option<Radio>,
+    // Playing on the server instead of here; None when it's off
+    pub jukebox: Option<Jukebox>,
+    // What the server supports, once known, for the help screen
+    pub capabilities: Option<ServerCapabilities>,
     pub status_message: Option<String>,
     pub status_message_timeout: Option<u64>,
     pub search_string: String,
     pub in_search: bool,
+    pub command_string: String,
+    pub in_command: bool,
+    pub pending_confirm: Option<PendingConfirm>,
     pub help_open: bool,
+    pub message_log: VecDeque<String>,
+    pub log_open: bool,
+    pub playback_failures: u32,
+    // Songs played in the current run through the queue
+    pub queue_played: usize,
+    pub continue_artist: bool,
+    // Shuffle within albums
+    pub shuffle: bool,
+    pub album_progress: AlbumProgress,
+    pub episode_progress: EpisodeProgress,
+    // None when scrobbling is off
+    pub scrobbler: Option<Scrobbler>,
+    // Album or episode the resume prompt was already shown for since
+    // opening it
+    pub resume_offered: Option<String>,
+    // The Songs view holds the starred songs rather than an album
+    pub starred_open: bool,
+    // Or the songs of this server playlist
+    pub playlist_open: Option<OpenPlaylist>,
+    // Or the top songs of this artist
+    pub top_songs_open: Option<String>,
+    // The Albums view holds an album list instead of an artist's albums
+    pub album_listing: Option<AlbumListing>,
+    pub search_paging: Option<SearchPaging>,
+    // The folders opened from the top down; the Folders view shows the last
+    pub folders: Vec<Folder>,
+    // The new name being typed for the open playlist
+    pub playlist_rename: Option<String>,
+    // Elapsed time and length in seconds of the playing track
+    pub track_time: Option<(Duration, Option<i64>)>,
+    pub playing_id: Option<String>,
+    // Podcast episodes aren't scrobbled or used to seed the radio
+    pub playing_podcast: bool,
+    // How the server converts the playing track, when rodio couldn't play
+    // the file as it is
+    pub fallback_stream: Option<StreamOptions>,
+    // Bumped whenever a track starts, the same one over again included
+    pub track_starts: u64,
+    pub muted: bool,
+    // The next album track, lined up in the player for gapless playback
+    pub preloaded: Option<(Song, PlaybackSource)>,
+    // The track a gapless line-up was last tried during
+    pub gapless_tried: Option<String>,
+    pub queue_dedupe: bool,
+    pub search_as_you_type: bool,
+    pub key_hints: bool,
+    pub long_jump: LongJumpConfig,
+    pub replaygain: ReplayGainMode,
+    pub replaygain_preamp: f32,
+    // When the typed query should be searched, pushed back by each key
+    pub search_due: Option<Instant>,
+    pub search_generations: Generations,
+    pub search_task: Option<tokio::task::JoinHandle<()>>,
+    pub song_cache: Option<SongCache>,
+    /// Songs kept as they streamed; none when turned off
+    pub stream_cache: Option<StreamCache>,
+    pub covers: Option<CoverArtCache>,
+    // The playing song's id and its cached art, once fetched
+    pub cover_art: Option<tokio::sync::oneshot::Receiver<(String, Option<std::path::PathBuf>)>>,
+    pub prefetch: Option<Prefetch>,
+    pub artist_shuffle: Option<ArtistShuffle>,
+    /// Songs being saved as files
+    pub saving: Option<Saving>,
+    pub scan: Option<LibraryScan>,
+    /// Cached artist, album and song lists; none when the cache is off
+    pub listings: Option<Listings>,
+    /// Server certificates go unchecked, which the status bar keeps saying
+    pub insecure_tls: bool,
+    pub downloads: Downloads,
+    pub downloads_open: bool,
+    pub playlist_picker: Option<PlaylistPicker>,
+    pub palette: Palette,
+    pub scroll_steps: Vec<usize>,
+    // Direction (down = true), time and count of the current run of repeats
+    nav_repeat: Option<(bool, Instant, usize)>,
+    pub about: Option<AboutInfo>,
+    pub lyrics: Option<LyricsPanel>,
+    pub info_panel: Option<InfoPanel>,
     pub current_base_content: String,
     pub current_playback_source: Option<PlaybackSource>,
 }
@@ -55,20 +510,95 @@ impl App {
             albums: Vec::new(),
             songs: Vec::new(),
             search_results: Vec::new(),
+            search_terms: Vec::new(),
+            smart_playlists: Vec::new(),
+            playlists: Vec::new(),
+            podcasts: Vec::new(),
+            episodes: Vec::new(),
+            podcast_title: String::new(),
             list_state,
+            view_stack: Vec::new(),
             current_artist_id: None,
             current_album_id: None,
             queue: Vec::new(),
+            queue_undo: None,
+            shuffle_all: false,
+            radio: None,
+            jukebox: None,
+            capabilities: None,
             status_message: None,
             status_message_timeout: None,
             search_string: String::new(),
             in_search: false,
+            command_string: String::new(),
+            in_command: false,
+            pending_confirm: None,
             help_open: false,
+            message_log: VecDeque::new(),
+            log_open: false,
+            playback_failures: 0,
+            queue_played: 0,
+            continue_artist: false,
+            shuffle: false,
+            album_progress: AlbumProgress::default(),
+            episode_progress: EpisodeProgress::default(),
+            scrobbler: None,
+            resume_offered: None,
+            starred_open: false,
+            playlist_open: None,
+            top_songs_open: None,
+            album_listing: None,
+            search_paging: None,
+            folders: Vec::new(),
+            playlist_rename: None,
+            track_time: None,
+            muted: false,
+            preloaded: None,
+            gapless_tried: None,
+            playing_id: None,
+            playing_podcast: false,
+            fallback_stream: None,
+            track_starts: 0,
+            queue_dedupe: false,
+            search_as_you_type: false,
+            key_hints: true,
+            long_jump: LongJumpConfig::default(),
+            replaygain: ReplayGainMode::Off,
+            replaygain_preamp: 0.0,
+            search_due: None,
+            search_generations: Generations::default(),
+            search_task: None,
+            song_cache: None,
+            stream_cache: None,
+            covers: None,
+            cover_art: None,
+            prefetch: None,
+            artist_shuffle: None,
+            saving: None,
+            scan: None,
+            listings: None,
+            insecure_tls: false,
+            downloads: Downloads::default(),
+            downloads_open: false,
+            playlist_picker: None,
+            palette: Palette::default(),
+            scroll_steps: vec![1],
+            nav_repeat: None,
+            about: None,
+            lyrics: None,
+            info_panel: None,
             current_base_content: "Artists".to_string(),
             current_playback_source: None,
         }
     }
 
+    /// The server playlist being looked at, if any.
+    pub fn open_playlist(&self) -> Option<&OpenPlaylist> {
+        self.playlist_open
+            .as_ref()
+            .filter(|_| self.current_view == ViewType::Songs)
+    }
+
     pub fn set_items(&mut self, items: Vec<String>) {
         self.list_state = ListState::default();
         if !items.is_empty() {
@@ -76,13 +606,21 @@ impl App {
         }
     }
 
-    pub fn next(&mut self) {
-        let items_count = match self.current_view {
+    fn items_count(&self) -> usize {
+        match self.current_view {
             ViewType::Artists => self.artists.len(),
             ViewType::Albums => self.albums.len(),
             ViewType::Songs => self.songs.len(),
             ViewType::Search => self.search_results.len(),
-        };
+            ViewType::Playlists => self.smart_playlists.len() + self.playlists.len(),
+            ViewType::Podcasts => self.podcasts.len(),
+            ViewType::Episodes => self.episodes.len(),
+            ViewType::Folders => self.folders.last().map_or(0, |f| f.entries.len()),
+        }
+    }
+
+    pub fn next(&mut self) {
+        let items_count = self.items_count();
 
         if items_count == 0 {
             return;
@@ -102,12 +640,7 @@ impl App {
     }
 
     pub fn previous(&mut self) {
-        let items_count = match self.current_view {
-            ViewType::Artists => self.artists.len(),
-            ViewType::Albums => self.albums.len(),
-            ViewType::Songs => self.songs.len(),
-            ViewType::Search => self.search_results.len(),
-        };
+        let items_count = self.items_count();
 
         if items_count == 0 {
             return;
@@ -126,11 +659,108 @@ impl App {
         self.list_state.select(Some(i));
     }
 
+    /// Switches to `view`, remembering the current one for `go_back`.
+    pub fn push_view(&mut self, view: ViewType) {
+        self.view_stack.push(ViewState {
+            view: self.current_view.clone(),
+            title: self.current_base_content.clone(),
+            selected: self.list_state.selected(),
+        });
+        self.current_view = view;
+    }
+
+    /// Returns to the previous view. Returns false when already at the top.
+    pub fn go_back(&mut self) -> bool {
+        let Some(previous) = self.view_stack.pop() else {
+            return false;
+        };
+
+        match self.current_view {
+            ViewType::Albums => {
+                self.current_artist_id = None;
+                self.album_listing = None;
+            }
+            ViewType::Songs => {
+                self.current_album_id = None;
+                self.playlist_open = None;
+            }
+            ViewType::Search => {
+                self.search_results.clear();
+                self.search_paging = None;
+                self.search_generations.stop();
+            }
+            ViewType::Folders => {
+                self.folders.pop();
+            }
+            _ => {}
+        }
+
+        self.current_view = previous.view;
+        self.current_base_content = previous.title;
+        self.list_state.select(previous.selected);
+        true
+    }
+
+    /// Rows to move for a navigation key, growing while the key is held.
+    fn nav_step(&mut self, down: bool) -> usize {
+        let now = Instant::now();
+        let repeats = match self.nav_repeat {
+            Some((last_down, last, count))
+                if last_down == down && now.duration_since(last) < KEY_REPEAT_THRESHOLD =>
+            {
+                count + 1
+            }
+            _ => 0,
+        };
+        self.nav_repeat = Some((down, now, repeats));
+
+        let level = (repeats / REPEATS_PER_STEP).min(self.scroll_steps.len().saturating_sub(1));
+        self.scroll_steps.get(level).copied().unwrap_or(1).max(1)
+    }
+
+    /// Moves the selection by `step` rows. Single steps wrap around like
+    /// `next`/`previous`; bigger ones stop at the ends of the list.
+    fn move_selection(&mut self, down: bool, step: usize) {
+        let items_count = self.items_count();
+        if step <= 1 || items_count == 0 {
+            if down {
+                self.next();
+            } else {
+                self.previous();
+            }
+            return;
+        }
+
+        let i = self.list_state.selected().unwrap_or(0);
+        let i = if down {
+            (i + step).min(items_count - 1)
+        } else {
+            i.saturating_sub(step)
+        };
+        self.list_state.select(Some(i));
+    }
+
     pub fn get_selected_index(&self) -> Option<usize> {
         self.list_state.selected()
     }
 
+    /// Adds to the message log without showing anything in the status bar.
+    pub fn log(&mut self, message: String) {
+        if self.message_log.len() == MESSAGE_LOG_SIZE {
+            self.message_log.pop_front();
+        }
+        self.message_log.push_back(message);
+    }
+
     pub fn show_message(&mut self, message: String, timeout: u64) {
+        self.log(message.clone());
+        self.status_message = Some(message);
+        self.status_message_timeout = Some(timeout);
+    }
+
+    /// Shows a message without logging it, for ones repeated while a key
+    /// is held.
+    pub fn flash(&mut self, message: String, timeout: u64) {
         self.status_message = Some(message);
         self.status_message_timeout = Some(timeout);
     }
@@ -139,6 +769,56 @@ impl App {
         self.status_message = None;
         self.status_message_timeout = None;
     }
+
+    /// Appends songs to the queue. With `queue_dedupe` set, songs already
+    /// queued or playing are left out; returns how many were.
+    pub fn enqueue(&mut self, songs: Vec<Song>) -> usize {
+        if !self.queue_dedupe {
+            self.queue.extend(songs);
+            return 0;
+        }
+        let mut seen: HashSet<String> = self.queue.iter().map(|s| s.id.clone()).collect();
+        seen.extend(self.playing_id.clone());
+        let before = self.queue.len();
+        let count = songs.len();
+        self.queue
+            .extend(songs.into_iter().filter(|s| seen.insert(s.id.clone())));
+        count - (self.queue.len() - before)
+    }
+
+    /// Drops repeated songs from the queue, keeping each first occurrence.
+    /// Returns how many were removed; `u` brings them back.
+    pub fn dedupe_queue(&mut self) -> usize {
+        let mut seen = HashSet::new();
+        let deduped: Vec<Song> = self
+            .queue
+            .iter()
+            .filter(|s| seen.insert(s.id.clone()))
+            .cloned()
+            .collect();
+        let removed = self.queue.len() - deduped.len();
+        if removed > 0 {
+            self.replace_queue(deduped);
+        }
+        removed
+    }
+
+    /// Schedules a search-as-you-type query once typing pauses.
+    fn typed_search(&mut self) {
+        if self.search_as_you_type {
+            self.search_due = (!self.search_string.trim().is_empty())
+                .then(|| Instant::now() + SEARCH_DEBOUNCE);
+        }
+    }
+
+    /// Swaps in a new queue, keeping the old one around for `u` to restore.
+    pub fn replace_queue(&mut self, songs: Vec<Song>) {
+        self.queue_undo = Some(std::mem::replace(&mut self.queue, songs));
+    }
+
+    pub fn ask_confirm(&mut self, prompt: String, action: ConfirmAction) {
+        self.pending_confirm = Some(PendingConfirm { prompt, action });
+    }
 }
 
 pub struct Tui {
@@ -149,7 +829,7 @@ impl Tui {
     pub fn new() -> Result<Self> {
         enable_raw_mode()?;
         let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen)?;
+        execute!(stdout, EnterAlternateScreen, EnableBracketedPaste)?;
         let backend = CrosstermBackend::new(stdout);
         let terminal = Terminal::new(backend)?;
         Ok(Self { terminal })
@@ -162,21 +842,81 @@ impl Tui {
         Ok(())
     }
 
-    pub fn handle_event(&mut self, app: &mut App) -> Result<Option<Action>> {
-        if event::poll(std::time::Duration::from_millis(100))?
-            && let Event::Key(key) = event::read()?
-            && key.kind == KeyEventKind::Press
-        {
-            return Ok(handle_key(key, app));
+    pub fn draw_preflight(&mut self, preflight: &Preflight, palette: &Palette) -> Result<()> {
+        self.terminal.draw(|f| render_preflight(f, preflight, palette))?;
+        Ok(())
+    }
+
+    /// Keys on the startup checks screen: `Some(true)` carries on,
+    /// `Some(false)` quits.
+    pub fn handle_preflight_event(&mut self, preflight: &mut Preflight) -> Result<Option<bool>> {
+        if !event::poll(std::time::Duration::from_millis(100))? {
+            return Ok(None);
+        }
+        let Event::Key(key) = event::read()? else {
+            return Ok(None);
+        };
+        if key.kind != KeyEventKind::Press {
+            return Ok(None);
+        }
+        match key.code {
+            KeyCode::Enter | KeyCode::Char('c') => return Ok(Some(true)),
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(Some(false)),
+            KeyCode::Char('r') => preflight.retry_selected(),
+            KeyCode::Char('R') => preflight.retry_failed(),
+            KeyCode::Down | KeyCode::Char('j') => {
+                preflight.selected = (preflight.selected + 1).min(preflight.rows.len() - 1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                preflight.selected = preflight.selected.saturating_sub(1);
+            }
+            _ => {}
         }
         Ok(None)
     }
+
+    pub fn handle_event(&mut self, app: &mut App) -> Result<Option<Action>> {
+        if !event::poll(std::time::Duration::from_millis(100))? {
+            return Ok(None);
+        }
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => Ok(handle_key(key, app)),
+            Event::Paste(text) => {
+                handle_paste(&text, app);
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Inserts pasted text into whichever prompt is open, in one go. Line breaks
+/// become spaces and other control characters are dropped. Outside a prompt
+/// pastes are ignored so they can't fire keybindings.
+fn handle_paste(text: &str, app: &mut App) {
+    let text: String = text
+        .chars()
+        .filter_map(|c| match c {
+            '\n' | '\r' | '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect();
+    if let Some(picker) = app.playlist_picker.as_mut().filter(|p| p.naming) {
+        picker.name.push_str(&text);
+    } else if let Some(name) = app.playlist_rename.as_mut() {
+        name.push_str(&text);
+    } else if app.in_search {
+        app.search_string.push_str(&text);
+    } else if app.in_command {
+        app.command_string.push_str(&text);
+    }
 }
 
 impl Drop for Tui {
     fn drop(&mut self) {
         disable_raw_mode().ok();
-        execute!(io::stdout(), LeaveAlternateScreen).ok();
+        execute!(io::stdout(), DisableBracketedPaste, LeaveAlternateScreen).ok();
     }
 }
 
@@ -191,6 +931,133 @@ fn handle_key(key: KeyEvent, app: &mut App) -> Option<Action> {
         return None;
     }
 
+    if app.log_open {
+        if matches!(
+            key.code,
+            KeyCode::Char('q') | KeyCode::Char('L') | KeyCode::Esc
+        ) {
+            app.log_open = false;
+        }
+        return None;
+    }
+
+    if app.downloads_open {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Char('O') | KeyCode::Esc => {
+                app.downloads_open = false;
+            }
+            KeyCode::Down | KeyCode::Char('j') => app.downloads.move_selection(true),
+            KeyCode::Up | KeyCode::Char('k') => app.downloads.move_selection(false),
+            KeyCode::Char('p') => app.downloads.toggle_pause(),
+            KeyCode::Char('x') => app.downloads.cancel(app.song_cache.as_ref()),
+            _ => {}
+        }
+        return None;
+    }
+
+    if let Some(picker) = app.playlist_picker.as_mut() {
+        if picker.naming {
+            match key.code {
+                KeyCode::Enter if !picker.name.trim().is_empty() => return Some(Action::SaveToPlaylist),
+                KeyCode::Esc => picker.naming = false,
+                KeyCode::Backspace => {
+                    picker.name.pop();
+                }
+                KeyCode::Char(c) => picker.name.push(c),
+                _ => {}
+            }
+            return None;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => app.playlist_picker = None,
+            KeyCode::Down | KeyCode::Char('j') => {
+                picker.selected = (picker.selected + 1).min(picker.playlists.len());
+            }
+            KeyCode::Up | KeyCode::Char('k') => picker.selected = picker.selected.saturating_sub(1),
+            KeyCode::Enter if picker.selected == 0 => picker.naming = true,
+            KeyCode::Enter => return Some(Action::SaveToPlaylist),
+            _ => {}
+        }
+        return None;
+    }
+
+    if let Some(name) = app.playlist_rename.as_mut() {
+        match key.code {
+            KeyCode::Enter if !name.trim().is_empty() => return Some(Action::RenamePlaylist),
+            KeyCode::Esc => app.playlist_rename = None,
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Char(c) => name.push(c),
+            _ => {}
+        }
+        return None;
+    }
+
+    if let Some(about) = &app.about {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Char('I') | KeyCode::Esc => {
+                app.about = None;
+            }
+            KeyCode::Char('y') => {
+                let text = about.lines().join("\n");
+                match copy_to_clipboard(&text) {
+                    Ok(()) => app.show_message("Copied to clipboard".to_string(), 1500),
+                    Err(e) => app.show_message(format!("Copy failed: {}", e), 3000),
+                }
+            }
+            _ => {}
+        }
+        return None;
+    }
+
+    if let Some(lyrics) = app.lyrics.as_mut() {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Char('Y') | KeyCode::Esc => {
+                app.lyrics = None;
+            }
+            KeyCode::Char('[') => lyrics.nudge(false),
+            KeyCode::Char(']') => lyrics.nudge(true),
+            KeyCode::Down | KeyCode::Char('j') => lyrics.scroll_by(1),
+            KeyCode::Up | KeyCode::Char('k') => lyrics.scroll_by(-1),
+            KeyCode::PageDown => lyrics.scroll_by(lyrics.page.max(1) as isize),
+            KeyCode::PageUp => lyrics.scroll_by(-(lyrics.page.max(1) as isize)),
+            KeyCode::Char('g') | KeyCode::Home => lyrics.scroll = 0,
+            KeyCode::Char('G') | KeyCode::End => lyrics.scroll_by(isize::MAX),
+            _ => {}
+        }
+        return None;
+    }
+
+    if let Some(panel) = app.info_panel.as_mut() {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Char('v') | KeyCode::Esc => {
+                app.info_panel = None;
+            }
+            KeyCode::Down | KeyCode::Char('j') => panel.move_selection(true),
+            KeyCode::Up | KeyCode::Char('k') => panel.move_selection(false),
+            KeyCode::PageDown => panel.scroll_by(5),
+            KeyCode::PageUp => panel.scroll_by(-5),
+            KeyCode::Enter | KeyCode::Char('l') => return Some(Action::OpenSimilarArtist),
+            _ => {}
+        }
+        return None;
+    }
+
+    if app.pending_confirm.is_some() {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                return Some(Action::Confirm);
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                app.pending_confirm = None;
+                app.show_message("Cancelled".to_string(), 1500);
+            }
+            _ => {}
+        }
+        return None;
+    }
+
     if app.in_search {
         match key.code {
             KeyCode::Enter => {
@@ -199,99 +1066,608 @@ fn handle_key(key: KeyEvent, app: &mut App) -> Option<Action> {
             }
             KeyCode::Esc => {
                 app.in_search = false;
+                app.search_due = None;
                 app.search_string.clear();
             }
             KeyCode::Backspace => {
                 app.search_string.pop();
+                app.typed_search();
             }
             KeyCode::Char(c) => {
                 app.search_string.push(c);
+                app.typed_search();
             }
             _ => {}
         }
         return None;
     }
 
-    match key.code {
-        KeyCode::Up | KeyCode::Char('k') => {
-            app.previous();
-        }
-        KeyCode::Down | KeyCode::Char('j') => {
-            app.next();
-        }
-        KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => {
-            return Some(Action::Select);
-        }
-        KeyCode::Char('q') | KeyCode::Esc => {
-            if app.current_view == ViewType::Artists {
-                return Some(Action::Quit);
-            } else {
-                // Go back
-                match app.current_view {
-                    ViewType::Albums => {
-                        app.current_view = ViewType::Artists;
-                        app.current_artist_id = None;
-                        app.current_base_content = "Artists".to_string();
-                    }
-                    ViewType::Songs => {
-                        app.current_view = ViewType::Albums;
-                        app.current_album_id = None;
-                    }
-                    ViewType::Search => {
-                        app.current_view = ViewType::Artists;
-                        app.search_results.clear();
-                        app.current_base_content = "Artists".to_string();
-                    }
-                    _ => {}
-                }
+    if app.in_command {
+        match key.code {
+            KeyCode::Enter => {
+                app.in_command = false;
+                return Some(Action::Command);
             }
-        }
-        KeyCode::Left | KeyCode::Char('h') => match app.current_view {
-            ViewType::Albums => {
-                app.current_view = ViewType::Artists;
-                app.current_artist_id = None;
-                app.current_base_content = "Artists".to_string();
+            KeyCode::Esc => {
+                app.in_command = false;
+                app.command_string.clear();
             }
-            ViewType::Songs => {
-                app.current_view = ViewType::Albums;
-                app.current_album_id = None;
+            KeyCode::Backspace => {
+                app.command_string.pop();
             }
-            ViewType::Search => {
-                app.current_view = ViewType::Artists;
-                app.search_results.clear();
-                app.current_base_content = "Artists".to_string();
+            KeyCode::Char(c) => {
+                app.command_string.push(c);
             }
             _ => {}
-        },
-        KeyCode::Char('/') | KeyCode::Char('i') => {
-            app.in_search = true;
-            app.search_string.clear();
-        }
-        KeyCode::Char('a') => {
-            return Some(Action::AddToQueue);
         }
-        KeyCode::Char('n') => {
-            return Some(Action::PlayNext);
+        return None;
+    }
+
+    if !matches!(
+        key.code,
+        KeyCode::Up | KeyCode::Down | KeyCode::Char('k') | KeyCode::Char('j')
+    ) {
+        app.nav_repeat = None;
+    }
+
+    if key.code == KeyCode::Char('r') && key.modifiers.contains(KeyModifiers::CONTROL) {
+        return Some(Action::Refresh);
+    }
+
+    match key.code {
+        KeyCode::Char(digit @ '0'..='9') if key.modifiers.contains(KeyModifiers::ALT) => {
+            return Some(Action::SeekPercent(digit as u8 - b'0'));
         }
-        KeyCode::Char('r') if !app.queue.is_empty() => {
-            app.queue.remove(0);
-            app.show_message("Removed from queue".to_string(), 1500);
+        // Shift with 0-5, which most terminals send as the symbol above the digit
+        KeyCode::Char(digit @ '0'..='5') if key.modifiers.contains(KeyModifiers::SHIFT) => {
+            return Some(Action::RatePlaying(digit as u8 - b'0'));
         }
-        KeyCode::Char('c') if !app.queue.is_empty() => {
-            app.queue.clear();
-            app.show_message("Queue cleared".to_string(), 1500);
+        KeyCode::Char(symbol @ (')' | '!' | '@' | '#' | '$' | '%')) => {
+            let rating = [')', '!', '@', '#', '$', '%'].iter().position(|&s| s == symbol)?;
+            return Some(Action::RatePlaying(rating as u8));
         }
-        KeyCode::Char('p') => {
-            return Some(Action::RestartQueue);
+        KeyCode::Char(digit @ '0'..='5')
+            if matches!(app.current_view, ViewType::Songs | ViewType::Search | ViewType::Albums) =>
+        {
+            return Some(Action::Rate(digit as u8 - b'0'));
         }
-        KeyCode::Char('?') => {
-            app.help_open = true;
+        KeyCode::Char(c) if c == app.long_jump.forward_key => {
+            return Some(Action::Jump(app.long_jump.forward as i64));
         }
-        KeyCode::Char(' ') => {
-            return Some(Action::TogglePause);
+        KeyCode::Char(c) if c == app.long_jump.back_key => {
+            return Some(Action::Jump(-(app.long_jump.back as i64)));
         }
         _ => {}
     }
+
+    let binding = BINDINGS
+        .iter()
+        .find(|binding| binding.keys.contains(&key.code) && (binding.applies)(app))?;
+    (binding.run)(app)
+}
+
+/// A key of the main views. The first binding for a key that applies
+/// handles it, and the status bar hints at the bindings that apply, in
+/// this order, so the most useful come first.
+struct Binding {
+    keys: &'static [KeyCode],
+    hint: Option<(&'static str, &'static str)>,
+    applies: fn(&App) -> bool,
+    run: fn(&mut App) -> Option<Action>,
+}
+
+// The short seek, or the long jumps while a podcast plays
+fn seek_step(app: &App, direction: i64) -> Action {
+    match (app.playing_podcast, direction > 0) {
+        (true, true) => Action::Jump(app.long_jump.forward as i64),
+        (true, false) => Action::Jump(-(app.long_jump.back as i64)),
+        (false, _) => Action::Seek(direction * SEEK_STEP),
+    }
+}
+
+fn always(_: &App) -> bool {
+    true
+}
+
+fn in_playlist(app: &App) -> bool {
+    app.open_playlist().is_some()
+}
+
+fn plays_songs(app: &App) -> bool {
+    matches!(app.current_view, ViewType::Songs | ViewType::Search | ViewType::Episodes)
+}
+
+const SELECT: &[KeyCode] = &[KeyCode::Right, KeyCode::Char('l'), KeyCode::Enter];
+
+const BINDINGS: &[Binding] = &[
+    Binding {
+        keys: &[KeyCode::Up, KeyCode::Char('k')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            let step = app.nav_step(false);
+            app.move_selection(false, step);
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Down, KeyCode::Char('j')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            let step = app.nav_step(true);
+            app.move_selection(true, step);
+            None
+        },
+    },
+    Binding {
+        keys: SELECT,
+        hint: Some(("enter", "play")),
+        applies: |app| plays_songs(app) || in_playlist(app),
+        run: |_| Some(Action::Select),
+    },
+    Binding {
+        keys: SELECT,
+        hint: Some(("enter", "open")),
+        applies: always,
+        run: |_| Some(Action::Select),
+    },
+    // The queue keys, for the playlist being looked at instead
+    Binding {
+        keys: &[KeyCode::Char('r')],
+        hint: Some(("r", "remove")),
+        applies: in_playlist,
+        run: |_| Some(Action::RemoveFromPlaylist),
+    },
+    Binding {
+        keys: &[KeyCode::Char('J')],
+        hint: Some(("J/K", "move")),
+        applies: in_playlist,
+        run: |_| Some(Action::MoveInPlaylist(1)),
+    },
+    Binding {
+        keys: &[KeyCode::Char('K')],
+        hint: None,
+        applies: in_playlist,
+        run: |_| Some(Action::MoveInPlaylist(-1)),
+    },
+    Binding {
+        keys: &[KeyCode::Char('e')],
+        hint: Some(("e", "rename")),
+        applies: in_playlist,
+        run: |app| {
+            app.playlist_rename = app.open_playlist().map(|playlist| playlist.name.clone());
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char('d')],
+        hint: Some(("d", "delete")),
+        applies: in_playlist,
+        run: |_| Some(Action::DeletePlaylist),
+    },
+    Binding {
+        keys: &[KeyCode::Char('a')],
+        hint: Some(("a", "queue")),
+        applies: |app| {
+            in_playlist(app)
+                || matches!(
+                    app.current_view,
+                    ViewType::Albums | ViewType::Songs | ViewType::Search | ViewType::Episodes | ViewType::Folders
+                )
+        },
+        run: |_| Some(Action::AddToQueue),
+    },
+    Binding {
+        keys: &[KeyCode::Char('R')],
+        hint: Some(("R", "play")),
+        applies: |app| app.current_view == ViewType::Albums,
+        run: |_| Some(Action::ReplaceQueue),
+    },
+    Binding {
+        keys: &[KeyCode::Char('p')],
+        hint: Some(("p", "start queue")),
+        applies: |app| app.current_view == ViewType::Songs,
+        run: |_| Some(Action::RestartQueue),
+    },
+    Binding {
+        keys: &[KeyCode::Char('s')],
+        hint: Some(("s", "shuffle")),
+        applies: |app| app.current_view == ViewType::Songs,
+        run: |_| Some(Action::ToggleShuffle),
+    },
+    Binding {
+        keys: &[KeyCode::Char('T')],
+        hint: Some(("T", "top songs")),
+        applies: |app| app.current_view == ViewType::Albums && app.current_artist_id.is_some(),
+        run: |_| Some(Action::TopSongs),
+    },
+    Binding {
+        keys: &[KeyCode::Char('M')],
+        hint: None,
+        applies: |app| app.current_view == ViewType::Albums && app.current_artist_id.is_some(),
+        run: |_| Some(Action::ShuffleArtist),
+    },
+    Binding {
+        keys: &[KeyCode::Char('v')],
+        hint: Some(("v", "artist info")),
+        applies: |app| app.current_view == ViewType::Albums,
+        run: |_| Some(Action::ShowInfo),
+    },
+    Binding {
+        keys: &[KeyCode::Char('v')],
+        hint: Some(("v", "album notes")),
+        applies: |app| app.current_view == ViewType::Songs,
+        run: |_| Some(Action::ShowInfo),
+    },
+    Binding {
+        keys: &[KeyCode::Char('v')],
+        hint: None,
+        applies: |app| app.current_view == ViewType::Artists,
+        run: |_| Some(Action::ShowInfo),
+    },
+    Binding {
+        keys: &[KeyCode::Char('F')],
+        hint: Some(("F", "star")),
+        applies: |app| app.current_view == ViewType::Albums,
+        run: |_| Some(Action::StarAlbum),
+    },
+    Binding {
+        keys: &[KeyCode::Char('d')],
+        hint: Some(("d", "download")),
+        applies: |app| app.current_view == ViewType::Albums,
+        run: |_| Some(Action::DownloadAlbum),
+    },
+    Binding {
+        keys: &[KeyCode::Char('B')],
+        hint: Some(("B", "change order")),
+        applies: |app| app.current_view == ViewType::Albums && app.album_listing.is_some(),
+        run: |app| Some(Action::BrowseAlbums(app.album_listing?.mode.next())),
+    },
+    Binding {
+        keys: &[KeyCode::Char('/'), KeyCode::Char('i')],
+        hint: Some(("/", "search")),
+        applies: |app| matches!(app.current_view, ViewType::Artists | ViewType::Search),
+        run: open_search,
+    },
+    Binding {
+        keys: &[KeyCode::Char('P')],
+        hint: Some(("P", "playlists")),
+        applies: |app| app.current_view == ViewType::Artists,
+        run: |_| Some(Action::OpenPlaylists),
+    },
+    Binding {
+        keys: &[KeyCode::Char('S')],
+        hint: Some(("S", "starred")),
+        applies: |app| app.current_view == ViewType::Artists,
+        run: |_| Some(Action::OpenStarred),
+    },
+    Binding {
+        keys: &[KeyCode::Char('C')],
+        hint: Some(("C", "podcasts")),
+        applies: |app| app.current_view == ViewType::Artists,
+        run: |_| Some(Action::OpenPodcasts),
+    },
+    Binding {
+        keys: &[KeyCode::Char('x')],
+        hint: Some(("x", "shuffle all")),
+        applies: |app| app.current_view == ViewType::Artists,
+        run: |_| Some(Action::ShuffleAll),
+    },
+    Binding {
+        keys: &[KeyCode::Char('B')],
+        hint: Some(("B", "albums")),
+        applies: |app| app.current_view == ViewType::Artists,
+        run: |_| Some(Action::BrowseAlbums(AlbumListMode::Newest)),
+    },
+    // The play queue, from any view
+    Binding {
+        keys: &[KeyCode::Char('r')],
+        hint: Some(("r", "unqueue next")),
+        applies: |app| !app.queue.is_empty(),
+        run: |app| {
+            app.queue.remove(0);
+            app.show_message("Removed from queue".to_string(), 1500);
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char('c')],
+        hint: Some(("c", "clear queue")),
+        applies: |app| !app.queue.is_empty(),
+        run: |app| {
+            app.queue.clear();
+            app.shuffle_all = false;
+            app.show_message("Queue cleared".to_string(), 1500);
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char('u')],
+        hint: Some(("u", "undo queue")),
+        applies: |app| app.queue_undo.is_some(),
+        run: |app| {
+            if let Some(previous) = app.queue_undo.take() {
+                app.replace_queue(previous);
+                app.show_message(format!("Queue restored ({} songs)", app.queue.len()), 1500);
+            }
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Esc],
+        hint: None,
+        applies: |app| app.prefetch.is_some(),
+        run: |_| Some(Action::CancelPrefetch),
+    },
+    Binding {
+        keys: &[KeyCode::Esc],
+        hint: None,
+        applies: |app| app.artist_shuffle.is_some(),
+        run: |_| Some(Action::CancelArtistShuffle),
+    },
+    Binding {
+        keys: &[KeyCode::Left, KeyCode::Char('h')],
+        hint: Some(("h", "back")),
+        applies: |app| app.current_view != ViewType::Artists,
+        run: |app| {
+            app.go_back();
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Left, KeyCode::Char('h')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            app.go_back();
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char(' ')],
+        hint: Some(("space", "pause")),
+        applies: |app| app.current_view == ViewType::Artists,
+        run: |_| Some(Action::TogglePause),
+    },
+    // Unhinted from here on
+    Binding {
+        keys: &[KeyCode::Char(' ')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::TogglePause),
+    },
+    Binding {
+        keys: &[KeyCode::Char('q'), KeyCode::Esc],
+        hint: None,
+        applies: always,
+        run: |app| (!app.go_back()).then_some(Action::Quit),
+    },
+    Binding {
+        keys: &[KeyCode::Char('/'), KeyCode::Char('i')],
+        hint: None,
+        applies: always,
+        run: open_search,
+    },
+    Binding {
+        keys: &[KeyCode::Char(':')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            app.in_command = true;
+            app.command_string.clear();
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char('+'), KeyCode::Char('=')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::VolumeUp),
+    },
+    Binding {
+        keys: &[KeyCode::Char('-')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::VolumeDown),
+    },
+    Binding {
+        keys: &[KeyCode::Char('m')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::ToggleMute),
+    },
+    Binding {
+        keys: &[KeyCode::Char('.')],
+        hint: None,
+        applies: always,
+        run: |app| Some(seek_step(app, 1)),
+    },
+    Binding {
+        keys: &[KeyCode::Char(',')],
+        hint: None,
+        applies: always,
+        run: |app| Some(seek_step(app, -1)),
+    },
+    Binding {
+        keys: &[KeyCode::Char('b')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::Restart),
+    },
+    Binding {
+        keys: &[KeyCode::Char('a')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::AddToQueue),
+    },
+    Binding {
+        keys: &[KeyCode::Char('A')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::AppendAlbum),
+    },
+    Binding {
+        keys: &[KeyCode::Char('R')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::ReplaceQueue),
+    },
+    Binding {
+        keys: &[KeyCode::Char('P')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::OpenPlaylists),
+    },
+    Binding {
+        keys: &[KeyCode::Char('S')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::OpenStarred),
+    },
+    Binding {
+        keys: &[KeyCode::Char('C')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::OpenPodcasts),
+    },
+    Binding {
+        keys: &[KeyCode::Char('E')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::OpenFolders),
+    },
+    Binding {
+        keys: &[KeyCode::Char('z')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::PlayRandomAlbum),
+    },
+    Binding {
+        keys: &[KeyCode::Char('D')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::Prefetch),
+    },
+    Binding {
+        keys: &[KeyCode::Char('d')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::DownloadAlbum),
+    },
+    Binding {
+        keys: &[KeyCode::Char('X')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::SaveFiles),
+    },
+    Binding {
+        keys: &[KeyCode::Char('U')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::Share),
+    },
+    Binding {
+        keys: &[KeyCode::Char('O')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            app.downloads_open = true;
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char('W')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            if app.queue.is_empty() {
+                app.show_message("The queue is empty, add songs with a first".to_string(), 2000);
+                return None;
+            }
+            Some(Action::SaveQueue)
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char('F')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::StarAlbum),
+    },
+    Binding {
+        keys: &[KeyCode::Char('f')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::ToggleStar),
+    },
+    Binding {
+        keys: &[KeyCode::Char('s')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::ToggleShuffle),
+    },
+    Binding {
+        keys: &[KeyCode::Char('n')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::PlayNext),
+    },
+    Binding {
+        keys: &[KeyCode::Char('o')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::ToggleRadio),
+    },
+    Binding {
+        keys: &[KeyCode::Char('Z')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::ToggleJukebox),
+    },
+    Binding {
+        keys: &[KeyCode::Char('p')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::RestartQueue),
+    },
+    Binding {
+        keys: &[KeyCode::Char('?')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            app.help_open = true;
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char('I')],
+        hint: None,
+        applies: always,
+        run: |_| Some(Action::About),
+    },
+    Binding {
+        keys: &[KeyCode::Char('L')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            app.log_open = true;
+            None
+        },
+    },
+    Binding {
+        keys: &[KeyCode::Char('Y')],
+        hint: None,
+        applies: always,
+        run: |app| {
+            app.lyrics = Some(LyricsPanel::new());
+            None
+        },
+    },
+];
+
+fn open_search(app: &mut App) -> Option<Action> {
+    app.in_search = true;
+    app.search_string.clear();
     None
 }
 
@@ -302,12 +1678,64 @@ fn ui(f: &mut Frame, app: &mut App) {
         .split(f.size());
 
     if app.help_open {
-        render_help(f, chunks[0]);
+        render_help(f, chunks[0], &app.palette, &app.long_jump, app.capabilities.as_ref());
+        return;
+    }
+
+    if app.log_open {
+        render_log(f, chunks[0], app);
+        return;
+    }
+
+    if app.downloads_open {
+        render_downloads(f, chunks[0], &app.downloads, &app.palette);
+        render_status(f, chunks[1], app);
+        return;
+    }
+
+    if let Some(picker) = &app.playlist_picker {
+        render_playlist_picker(f, chunks[0], picker, app.queue.len(), &app.palette);
+        render_status(f, chunks[1], app);
+        return;
+    }
+
+    if let Some(about) = &app.about {
+        render_about(f, chunks[0], about, &app.palette);
+        render_status(f, chunks[1], app);
+        return;
+    }
+
+    if let Some(lyrics) = app.lyrics.as_mut() {
+        lyrics.page = chunks[0].height.saturating_sub(2) as usize;
+    }
+    if let Some(lyrics) = &app.lyrics {
+        let position = app.track_time.map_or(Duration::ZERO, |(elapsed, _)| elapsed);
+        render_lyrics(f, chunks[0], lyrics, position, &app.palette);
+        render_status(f, chunks[1], app);
+        return;
+    }
+
+    if let Some(panel) = app.info_panel.as_mut() {
+        render_info(f, chunks[0], panel, &app.palette);
+        render_status(f, chunks[1], app);
         return;
     }
 
     if app.in_search {
         render_search(f, chunks[0], app);
+        render_status(f, chunks[1], app);
+        return;
+    }
+
+    if app.in_command {
+        render_command(f, chunks[0], app);
+        render_status(f, chunks[1], app);
+        return;
+    }
+
+    if app.playlist_rename.is_some() {
+        render_rename(f, chunks[0], app);
+        render_status(f, chunks[1], app);
         return;
     }
 
@@ -315,38 +1743,311 @@ fn ui(f: &mut Frame, app: &mut App) {
     render_status(f, chunks[1], app);
 }
 
+/// "Name (1997)", or just the name when the server doesn't know the year.
+pub fn album_label(name: &str, year: Option<i32>) -> String {
+    match year {
+        Some(year) => format!("{} ({})", name, year),
+        None => name.to_string(),
+    }
+}
+
+/// "4:02", or "1:02:03" past an hour.
+pub fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Cuts `text` down to `width` columns, ending it with "…" if anything was
+/// dropped.
+fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    if width > 0 {
+        out.push('…');
+    }
+    out
+}
+
+/// Byte ranges of `text` where any of the lowercase `terms` occur, ignoring
+/// case, sorted and merged.
+fn match_ranges(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    let chars: Vec<(usize, char)> = text
+        .char_indices()
+        .map(|(i, c)| (i, c.to_lowercase().next().unwrap_or(c)))
+        .collect();
+    let mut ranges = Vec::new();
+    for term in terms {
+        let term: Vec<char> = term.chars().collect();
+        if term.is_empty() {
+            continue;
+        }
+        for (start, window) in chars.windows(term.len()).enumerate() {
+            if window.iter().map(|(_, c)| *c).eq(term.iter().copied()) {
+                let end = chars.get(start + term.len()).map_or(text.len(), |(i, _)| *i);
+                ranges.push(window[0].0..end);
+            }
+        }
+    }
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Like `truncate`, but when the first match would be cut off the text is
+/// shown from just before it instead: "…ng the match…".
+fn truncate_around(text: &str, width: usize, terms: &[String]) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let Some(first) = match_ranges(text, terms).into_iter().next() else {
+        return truncate(text, width);
+    };
+    if text[..first.end].width() < width {
+        return truncate(text, width);
+    }
+    // A little of what comes before, for context
+    let start = text[..first.start]
+        .char_indices()
+        .rev()
+        .take(3)
+        .last()
+        .map_or(first.start, |(i, _)| i);
+    format!("…{}", truncate(&text[start..], width.saturating_sub(1)))
+}
+
+/// `row` as a line with the matches of `terms` picked out, ignoring any in
+/// the first `skip` bytes.
+fn highlight_matches(row: String, skip: usize, terms: &[String], style: Style) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for range in match_ranges(&row, terms) {
+        if range.start < skip {
+            continue;
+        }
+        if range.start > pos {
+            spans.push(Span::raw(row[pos..range.start].to_string()));
+        }
+        spans.push(Span::styled(row[range.clone()].to_string(), style));
+        pos = range.end;
+    }
+    if pos < row.len() {
+        spans.push(Span::raw(row[pos..].to_string()));
+    }
+    Line::from(spans)
+}
+
+/// Fits "title - artist · extra" into `width` columns. The extra part goes
+/// first, then the artist shrinks, and the title is only cut as a last
+/// resort. Cut text is kept around its first match of `terms`.
+fn fit_row(
+    prefix: &str,
+    title: &str,
+    artist: &str,
+    extra: Option<&str>,
+    width: usize,
+    terms: &[String],
+) -> String {
+    let full = match extra {
+        Some(extra) => format!("{}{} - {} · {}", prefix, title, artist, extra),
+        None => format!("{}{} - {}", prefix, title, artist),
+    };
+    if full.width() <= width {
+        return full;
+    }
+    let short = format!("{}{} - {}", prefix, title, artist);
+    if short.width() <= width {
+        return short;
+    }
+    let title_width = width.saturating_sub(prefix.width());
+    let title = truncate_around(title, title_width, terms);
+    // Not worth showing less than a few characters of the artist
+    let artist_width = title_width.saturating_sub(title.width() + 3);
+    if artist_width < 4 {
+        format!("{}{}", prefix, title)
+    } else {
+        format!(
+            "{}{} - {}",
+            prefix,
+            title,
+            truncate_around(artist, artist_width, terms)
+        )
+    }
+}
+
+/// The row for a search result. With a `width`, the song duration is
+/// right-aligned and the text truncated to fit, keeping matches of `terms`
+/// in view; without one the full text is returned.
+pub fn search_result_label(
+    result: &SearchResultItem,
+    width: Option<usize>,
+    terms: &[String],
+) -> String {
+    match result {
+        SearchResultItem::Album {
+            name,
+            artist,
+            year,
+            song_count,
+            ..
+        } => {
+            let title = album_label(name, *year);
+            let songs = song_count.map(|n| format!("{} songs", n));
+            fit_row(
+                RESULT_PREFIX_ALBUM,
+                &title,
+                artist,
+                songs.as_deref(),
+                width.unwrap_or(usize::MAX),
+                terms,
+            )
+        }
+        SearchResultItem::Song {
+            title,
+            artist,
+            album,
+            duration,
+            ..
+        } => {
+            let time = duration.map(format_duration);
+            let Some(width) = width else {
+                let mut row = format!("{}{} - {}", RESULT_PREFIX_SONG, title, artist);
+                for part in [album.as_deref(), time.as_deref()].into_iter().flatten() {
+                    row.push_str(" · ");
+                    row.push_str(part);
+                }
+                return row;
+            };
+            let Some(time) = time else {
+                return fit_row(RESULT_PREFIX_SONG, title, artist, album.as_deref(), width, terms);
+            };
+            let left = fit_row(
+                RESULT_PREFIX_SONG,
+                title,
+                artist,
+                album.as_deref(),
+                width.saturating_sub(time.width() + 1),
+                terms,
+            );
+            let padding = width.saturating_sub(left.width() + time.width());
+            format!("{}{}{}", left, " ".repeat(padding.max(1)), time)
+        }
+    }
+}
+
+/// A 1-5 rating as five stars, filled up to the rating.
+pub fn rating_stars(rating: u8) -> String {
+    let rating = rating.min(5) as usize;
+    format!("{}{}", "★".repeat(rating), "☆".repeat(5 - rating))
+}
+
+fn starred_marker(starred: bool) -> String {
+    if starred { "★ " } else { "" }.to_string()
+}
+
 fn render_list(f: &mut Frame, area: Rect, app: &mut App) {
+    // Borders and the ">> " highlight symbol take their share of the width
+    let row_width = area.width.saturating_sub(2 + HIGHLIGHT_SYMBOL.len() as u16) as usize;
+    // Folders can hold thousands of entries, so only the rows in view are
+    // built; this is the first of them
+    let mut folder_offset = None;
     let items: Vec<ListItem> = match app.current_view {
         ViewType::Artists => app
             .artists
             .iter()
-            .map(|a| ListItem::new(a.name.clone()))
+            .map(|a| ListItem::new(starred_marker(a.starred) + &a.name))
             .collect(),
         ViewType::Albums => app
             .albums
             .iter()
-            .map(|a| ListItem::new(a.name.clone()))
-            .collect(),
-        ViewType::Songs => app
-            .songs
-            .iter()
-            .map(|s| ListItem::new(s.title.clone()))
+            .map(|a| {
+                let mut label = starred_marker(a.starred) + &album_label(&a.name, a.year);
+                if let Some(artist) = &a.artist {
+                    label = format!("{} - {}", label, artist);
+                }
+                ListItem::new(label)
+            })
             .collect(),
+        ViewType::Songs => {
+            // Track numbers only mean something in an album's own order
+            let numbered = app.current_album_id.is_some() && app.playlist_open.is_none() && !app.starred_open;
+            let usual_artist = Song::usual_artist(&app.songs);
+            app.songs
+                .iter()
+                .map(|s| ListItem::new(starred_marker(s.starred) + &s.list_label(numbered, usual_artist)))
+                .collect()
+        }
         ViewType::Search => app
             .search_results
             .iter()
             .map(|r| {
-                let text = match r {
-                    SearchResultItem::Album { name, artist, .. } => {
-                        format!("[A] {} - {}", name, artist)
-                    }
-                    SearchResultItem::Song { title, artist, .. } => {
-                        format!("[S] {} - {}", title, artist)
-                    }
-                };
-                ListItem::new(text)
+                let marker = starred_marker(r.starred());
+                let width = row_width.saturating_sub(marker.width());
+                let row = search_result_label(r, Some(width), &app.search_terms);
+                let mut line = highlight_matches(
+                    row,
+                    RESULT_PREFIX_SONG.len(),
+                    &app.search_terms,
+                    app.palette.matched(),
+                );
+                if !marker.is_empty() {
+                    line.spans.insert(0, Span::raw(marker));
+                }
+                ListItem::new(line)
             })
             .collect(),
+        ViewType::Playlists => app
+            .smart_playlists
+            .iter()
+            .map(|p| ListItem::new(format!("[Smart] {}", p.name)))
+            .chain(app.playlists.iter().map(|p| ListItem::new(playlist_label(p))))
+            .collect(),
+        ViewType::Podcasts => app
+            .podcasts
+            .iter()
+            .map(|c| ListItem::new(podcast_label(c)))
+            .collect(),
+        ViewType::Episodes => app
+            .episodes
+            .iter()
+            .map(|e| ListItem::new(episode_label(e)))
+            .collect(),
+        ViewType::Folders => {
+            let entries = app.folders.last().map_or(&[][..], |f| &f.entries[..]);
+            let height = area.height.saturating_sub(2).max(1) as usize;
+            let mut offset = app.list_state.offset();
+            if let Some(selected) = app.list_state.selected() {
+                offset = offset.clamp((selected + 1).saturating_sub(height), selected);
+            }
+            *app.list_state.offset_mut() = offset;
+            folder_offset = Some(offset);
+            entries
+                .iter()
+                .skip(offset)
+                .take(height)
+                .map(|e| ListItem::new(folder_entry_label(e)))
+                .collect()
+        }
     };
 
     let list = List::new(items)
@@ -355,10 +2056,172 @@ fn render_list(f: &mut Frame, area: Rect, app: &mut App) {
                 .borders(Borders::ALL)
                 .title(app.current_base_content.as_str()),
         )
-        .highlight_style(Style::default().fg(Color::Black).bg(Color::LightBlue))
-        .highlight_symbol(">> ");
+        .highlight_style(app.palette.selection)
+        .highlight_symbol(HIGHLIGHT_SYMBOL);
+
+    match folder_offset {
+        Some(offset) => {
+            let selected = app.list_state.selected().map(|i| i - offset);
+            f.render_stateful_widget(list, area, &mut ListState::default().with_selected(selected));
+        }
+        None => f.render_stateful_widget(list, area, &mut app.list_state),
+    }
+}
+
+/// A directory with a trailing slash, or a file by its title.
+pub fn folder_entry_label(entry: &FolderEntry) -> String {
+    match entry {
+        FolderEntry::Dir { name, .. } => format!("{}/", name),
+        FolderEntry::File(song) => starred_marker(song.starred) + &song.list_label(false, None),
+    }
+}
+
+/// The open folders as a path, cut to the last few when it runs deep.
+pub fn folder_path(folders: &[Folder]) -> String {
+    const SHOWN: usize = 3;
+    let names: Vec<&str> = folders.iter().map(|f| f.name.as_str()).collect();
+    if names.len() > SHOWN {
+        format!("… / {}", names[names.len() - SHOWN..].join(" / "))
+    } else {
+        names.join(" / ")
+    }
+}
+
+fn playlist_label(playlist: &PlaylistData) -> String {
+    match playlist.song_count {
+        Some(1) => format!("{} (1 song)", playlist.name),
+        Some(count) => format!("{} ({} songs)", playlist.name, count),
+        None => playlist.name.clone(),
+    }
+}
+
+pub fn podcast_label(channel: &PodcastChannel) -> String {
+    channel
+        .title
+        .clone()
+        .or_else(|| channel.url.clone())
+        .unwrap_or_else(|| channel.id.clone())
+}
+
+/// An episode with its date, length and whether the server has it, e.g.
+/// "Pilot  2024-01-01  42:10  downloaded".
+pub fn episode_label(episode: &PodcastEpisode) -> String {
+    let status = match episode.status.as_deref() {
+        _ if episode.playable_id().is_some() => "downloaded",
+        Some("new") | None => "not downloaded",
+        Some("downloading") => "downloading",
+        Some("skipped") => "skipped",
+        Some("deleted") => "deleted",
+        Some("error") => "download failed",
+        Some(other) => other,
+    };
+    let mut parts = vec![episode.title.clone().unwrap_or_else(|| episode.id.clone())];
+    // Dates come as RFC 3339; the day is enough
+    parts.extend(episode.publish_date.as_ref().map(|date| date.chars().take(10).collect()));
+    parts.extend(episode.duration.map(format_duration));
+    parts.push(status.to_string());
+    parts.join("  ")
+}
+
+/// Where the playing track sits in what's being played through, e.g.
+/// "track 3 of 12". This is the position in the queue or album being played,
+/// not the track number from the tags.
+pub fn playback_position(app: &App) -> Option<String> {
+    match app.current_playback_source.as_ref()? {
+        // Whatever is still queued counts, so songs added mid-run show up
+        PlaybackSource::Queue => Some(format!(
+            "track {} of {}",
+            app.queue_played,
+            app.queue_played + app.queue.len()
+        )),
+        source => {
+            let (songs, current_index) = source.list()?;
+            let shuffled = source.shuffle().is_some();
+            Some(format!(
+                "track {} of {}{}",
+                current_index + 1,
+                songs.len(),
+                if shuffled { ", shuffled" } else { "" }
+            ))
+        }
+    }
+}
+
+/// Keys worth knowing in the current context, most useful first. The main
+/// views' come from [`BINDINGS`], leaving out those an earlier binding
+/// takes the key from; the panels' are listed here, so keep them in step
+/// with `handle_key`.
+fn key_hints(app: &App) -> Vec<(&'static str, &'static str)> {
+    if app.pending_confirm.is_some() {
+        return vec![("y", "yes"), ("n", "no")];
+    }
+    if app.in_search {
+        return vec![("enter", "search"), ("esc", "cancel")];
+    }
+    if app.in_command {
+        return vec![("enter", "run"), ("esc", "cancel")];
+    }
+    if app.downloads_open {
+        return vec![("p", "pause/resume"), ("x", "cancel"), ("esc", "close")];
+    }
+    if app.playlist_rename.is_some() {
+        return vec![("enter", "rename"), ("esc", "cancel")];
+    }
+    if let Some(picker) = &app.playlist_picker {
+        if picker.naming {
+            return vec![("enter", "create"), ("esc", "back")];
+        }
+        return vec![("enter", "save here"), ("j/k", "move"), ("esc", "cancel")];
+    }
+    if app.about.is_some() {
+        return vec![("y", "copy"), ("esc", "close")];
+    }
+    if app.lyrics.is_some() {
+        return vec![("[/]", "sync earlier/later"), ("j/k", "scroll"), ("PgUp/PgDn", "page"), ("esc", "close")];
+    }
+    if app.info_panel.is_some() {
+        return vec![("enter", "open artist"), ("j/k", "move"), ("PgUp/PgDn", "scroll"), ("esc", "close")];
+    }
+    let mut taken: Vec<KeyCode> = Vec::new();
+    let mut hints = Vec::new();
+    for binding in BINDINGS.iter().filter(|binding| (binding.applies)(app)) {
+        let shadowed = binding.keys.iter().any(|key| taken.contains(key));
+        taken.extend(binding.keys);
+        if !shadowed && let Some(hint) = binding.hint {
+            hints.push(hint);
+        }
+    }
+    hints
+}
 
-    f.render_stateful_widget(list, area, &mut app.list_state);
+/// The hints that fit in `width` columns, dropping the least useful first.
+/// The help key always goes last, wherever `?` opens help.
+fn hint_line(app: &App, width: usize) -> String {
+    let with_help = !(app.in_search
+        || app.in_command
+        || app.pending_confirm.is_some()
+        || app.about.is_some()
+        || app.lyrics.is_some()
+        || app.info_panel.is_some()
+        || app.downloads_open
+        || app.playlist_picker.is_some()
+        || app.playlist_rename.is_some());
+    let mut hints: Vec<String> = key_hints(app)
+        .iter()
+        .map(|(key, label)| format!("{}:{}", key, label))
+        .collect();
+    loop {
+        let line = hints
+            .iter()
+            .map(String::as_str)
+            .chain(with_help.then_some("?:help"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if line.width() <= width || hints.is_empty() {
+            return line;
+        }
+        hints.pop();
+    }
 }
 
 fn render_status(f: &mut Frame, area: Rect, app: &mut App) {
@@ -368,15 +2231,50 @@ fn render_status(f: &mut Frame, area: Rect, app: &mut App) {
         String::new()
     };
 
-    let status_text = if let Some(msg) = &app.status_message {
+    let status_text = if let Some(confirm) = &app.pending_confirm {
+        format!("{} (y/n)", confirm.prompt)
+    } else if let Some(msg) = &app.status_message {
         format!("{}{}", queue_info, msg)
     } else {
         format!("{}{}", queue_info, app.current_base_content)
     };
 
-    let help_text = "press ? for help";
+    let time = app.track_time.map(|(elapsed, duration)| {
+        let elapsed = format_duration(elapsed.as_secs() as i64);
+        match duration {
+            Some(duration) => format!("{} / {}", elapsed, format_duration(duration)),
+            None => elapsed,
+        }
+    });
+    let mut help_parts: Vec<String> = app
+        .insecure_tls
+        .then(|| "TLS UNCHECKED".to_string())
+        .into_iter()
+        .chain(app.prefetch.as_ref().map(Prefetch::progress))
+        .chain(app.artist_shuffle.as_ref().map(ArtistShuffle::progress))
+        .chain(app.saving.as_ref().map(Saving::progress))
+        .chain(app.scan.as_ref().map(|scan| match scan.count {
+            Some(count) => format!("Scanning… {} items", count),
+            None => "Scanning…".to_string(),
+        }))
+        .chain(app.radio.is_some().then(|| "radio".to_string()))
+        .chain(app.jukebox.as_ref().map(Jukebox::label))
+        .chain(app.muted.then(|| "muted".to_string()))
+        .chain(time)
+        .chain(playback_position(app))
+        .collect();
 
     let available_width = area.width as usize;
+    if app.key_hints {
+        // Whatever room the rest of the bar leaves, less separator and gap
+        let used = status_text.width() + help_parts.iter().map(|p| p.width() + 3).sum::<usize>() + 1;
+        help_parts.push(hint_line(app, available_width.saturating_sub(used)));
+    } else {
+        help_parts.push("press ? for help".to_string());
+    }
+    help_parts.retain(|p| !p.is_empty());
+    let help_text = help_parts.join(" | ");
+
     let left_width = status_text.len();
     let right_width = help_text.len();
 
@@ -389,7 +2287,7 @@ fn render_status(f: &mut Frame, area: Rect, app: &mut App) {
     let padding = " ".repeat(middle_spaces);
 
     let status = Paragraph::new(format!("{}{}{}", status_text, padding, help_text))
-        .style(Style::default().fg(Color::White).bg(Color::Blue))
+        .style(app.palette.panel)
         .alignment(Alignment::Left);
 
     f.render_widget(status, area);
@@ -404,17 +2302,365 @@ fn render_search(f: &mut Frame, area: Rect, app: &mut App) {
     let search_text = format!("Search: {}", app.search_string);
     let search_box = Paragraph::new(search_text)
         .block(Block::default().borders(Borders::ALL).title("Search"))
-        .style(Style::default().fg(Color::White).bg(Color::Blue));
+        .style(app.palette.panel);
 
     f.render_widget(search_box, chunks[0]);
 }
 
-fn render_help(f: &mut Frame, area: Rect) {
+fn render_command(f: &mut Frame, area: Rect, app: &mut App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(area);
+
+    let command_text = format!(":{}", app.command_string);
+    let command_box = Paragraph::new(command_text)
+        .block(Block::default().borders(Borders::ALL).title("Command"))
+        .style(app.palette.panel);
+
+    f.render_widget(command_box, chunks[0]);
+}
+
+fn render_rename(f: &mut Frame, area: Rect, app: &mut App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(area);
+
+    let name = app.playlist_rename.as_deref().unwrap_or_default();
+    let rename_box = Paragraph::new(format!("New name: {}", name))
+        .block(Block::default().borders(Borders::ALL).title("Rename playlist"))
+        .style(app.palette.panel);
+
+    f.render_widget(rename_box, chunks[0]);
+}
+
+fn render_log(f: &mut Frame, area: Rect, app: &App) {
+    // Newest at the bottom; only the tail that fits is shown
+    let height = area.height.saturating_sub(2) as usize;
+    let skip = app.message_log.len().saturating_sub(height);
+    let lines: Vec<Line> = app
+        .message_log
+        .iter()
+        .skip(skip)
+        .map(|m| Line::from(m.as_str()))
+        .collect();
+
+    let log_block = Block::default()
+        .borders(Borders::ALL)
+        .title("Messages")
+        .style(app.palette.panel);
+
+    f.render_widget(Paragraph::new(lines).block(log_block), area);
+}
+
+const SPINNER: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+fn render_preflight(f: &mut Frame, preflight: &Preflight, palette: &Palette) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(f.size());
+
+    let frame = (preflight.started.elapsed().as_millis() / 100) as usize % SPINNER.len();
+    let items: Vec<ListItem> = preflight
+        .rows
+        .iter()
+        .map(|(check, status)| {
+            let (mark, detail, style) = match status {
+                CheckStatus::Running => (SPINNER[frame], "checking…", Style::default()),
+                CheckStatus::Passed(detail) => ('✓', detail.as_str(), Style::default().fg(palette.success)),
+                CheckStatus::Failed(reason) => ('✗', reason.as_str(), Style::default().fg(palette.error)),
+            };
+            let mut lines = vec![Line::styled(
+                format!("{} {:<20}{}", mark, check.label(), detail),
+                style,
+            )];
+            if matches!(status, CheckStatus::Failed(_)) {
+                lines.push(Line::from(format!("    {}", check.remedy())));
+            }
+            ListItem::new(lines)
+        })
+        .collect();
+
+    let title = if !preflight.finished() {
+        "Startup checks"
+    } else if preflight.all_passed() {
+        "Startup checks: all passed"
+    } else {
+        "Startup checks: some failed"
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(palette.selection);
+    let mut state = ListState::default();
+    state.select(Some(preflight.selected));
+    f.render_stateful_widget(list, chunks[0], &mut state);
+
+    let keys = Paragraph::new("enter:continue | r:retry | R:retry failed | q:quit").style(palette.panel);
+    f.render_widget(keys, chunks[1]);
+}
+
+fn render_downloads(f: &mut Frame, area: Rect, downloads: &Downloads, palette: &Palette) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Downloads: {}", downloads.stats()))
+        .style(palette.panel);
+
+    if downloads.items.is_empty() {
+        let text = "Nothing to download. Press d on an album to add it.";
+        f.render_widget(Paragraph::new(Line::from(text)).block(block), area);
+        return;
+    }
+
+    let items: Vec<ListItem> = downloads
+        .items
+        .iter()
+        .map(|item| {
+            let status = item.status();
+            let mut line = format!(
+                "{:<12} {}  {}/{}",
+                status.label(),
+                item.title,
+                item.done,
+                item.total()
+            );
+            if item.bytes > 0 {
+                line += &format!(" · {}", format_size(item.bytes));
+            }
+            if !item.failed.is_empty() {
+                line += &format!(" · {} failed (p retries)", item.failed.len());
+            }
+            let style = match status {
+                Status::Active => Style::default().fg(palette.accent),
+                Status::Failed => Style::default().fg(palette.error),
+                Status::Done => Style::default().fg(palette.success),
+                _ => Style::default(),
+            };
+            ListItem::new(line).style(style)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(palette.selection);
+    let mut state = ListState::default();
+    state.select(Some(downloads.selected));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_playlist_picker(
+    f: &mut Frame,
+    area: Rect,
+    picker: &PlaylistPicker,
+    queued: usize,
+    palette: &Palette,
+) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("Save {} queued songs to", queued))
+        .style(palette.panel);
+    let new = if picker.naming {
+        format!("New playlist: {}_", picker.name)
+    } else {
+        "+ New playlist".to_string()
+    };
+    let items: Vec<ListItem> = std::iter::once(ListItem::new(new))
+        .chain(picker.playlists.iter().map(|p| ListItem::new(playlist_label(p))))
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(palette.selection);
+    let mut state = ListState::default();
+    state.select(Some(picker.selected));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_lyrics(
+    f: &mut Frame,
+    area: Rect,
+    panel: &LyricsPanel,
+    position: Duration,
+    palette: &Palette,
+) {
+    let mut title = match &panel.title {
+        Some(song) => format!("Lyrics: {}", song),
+        None => "Lyrics".to_string(),
+    };
+    if panel.offset_ms != 0 {
+        title += &format!(" (sync {:+.1}s)", panel.offset_ms as f64 / 1000.0);
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .style(palette.panel);
+
+    let message = |text: &str| Paragraph::new(Line::from(text.to_string())).block(block.clone());
+    let lyrics = match &panel.lyrics {
+        _ if !panel.playing() => return f.render_widget(message("Nothing is playing"), area),
+        None => return f.render_widget(message("Loading lyrics…"), area),
+        Some(Err(e)) => {
+            return f.render_widget(message(&format!("Couldn't load lyrics: {}", e)), area);
+        }
+        Some(Ok(None)) => return f.render_widget(message("No lyrics for this song"), area),
+        Some(Ok(Some(lyrics))) => lyrics,
+    };
+
+    let height = area.height.saturating_sub(2) as usize;
+    let (lines, top) = match lyrics {
+        Lyrics::Synced(lines) => {
+            // Keep the current line in the middle
+            let current = panel.current_line(position);
+            let top = current.map_or(0, |i| i.saturating_sub(height / 2));
+            let lines: Vec<Line> = lines
+                .iter()
+                .enumerate()
+                .map(|(i, (_, text))| {
+                    let style = if Some(i) == current {
+                        palette.accent_bold()
+                    } else {
+                        Style::default()
+                    };
+                    Line::styled(text.clone(), style)
+                })
+                .collect();
+            (lines, top)
+        }
+        Lyrics::Plain(lines) => {
+            let top = panel.scroll.min(lines.len().saturating_sub(height));
+            (lines.iter().map(|l| Line::from(l.clone())).collect(), top)
+        }
+    };
+
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .alignment(Alignment::Center)
+        .scroll((top as u16, 0));
+    f.render_widget(paragraph, area);
+}
+
+fn render_info(f: &mut Frame, area: Rect, panel: &mut InfoPanel, palette: &Palette) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(panel.title.clone())
+        .style(palette.panel);
+    let message = |text: &str| Paragraph::new(Line::from(text.to_string())).block(block.clone());
+    let info = match &panel.info {
+        None => return f.render_widget(message("Loading…"), area),
+        Some(Err(e)) => {
+            return f.render_widget(message(&format!("Couldn't load the info: {}", e)), area);
+        }
+        Some(Ok(None)) => return f.render_widget(message("No info available"), area),
+        Some(Ok(Some(info))) => info,
+    };
+
+    // Similar artists get up to half the height, the text the rest
+    let similar_height = match info.similar.len() {
+        0 => 0,
+        n => (n as u16 + 2).min(area.height / 2),
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(similar_height)])
+        .split(area);
+
+    let width = chunks[0].width.saturating_sub(2) as usize;
+    let mut lines = info_panel::wrap(info.text.as_deref().unwrap_or("No text available"), width);
+    if let Some(url) = &info.link {
+        lines.push(String::new());
+        lines.extend(info_panel::wrap(url, width));
+    }
+    panel.overflow = lines.len().saturating_sub(chunks[0].height.saturating_sub(2) as usize);
+    panel.scroll = panel.scroll.min(panel.overflow);
+    let biography = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+        .block(block)
+        .scroll((panel.scroll as u16, 0));
+    f.render_widget(biography, chunks[0]);
+
+    if similar_height == 0 {
+        return;
+    }
+    let items: Vec<ListItem> = info
+        .similar
+        .iter()
+        .map(|artist| match artist.library_id() {
+            Some(_) => ListItem::new(artist.name.clone()),
+            None => ListItem::new(format!("{} (not in library)", artist.name))
+                .style(Style::default().add_modifier(Modifier::DIM)),
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Similar artists (enter opens)")
+                .style(palette.panel),
+        )
+        .highlight_style(palette.selection)
+        .highlight_symbol(HIGHLIGHT_SYMBOL);
+    let mut state = ListState::default();
+    state.select(Some(panel.selected));
+    f.render_stateful_widget(list, chunks[1], &mut state);
+}
+
+fn render_about(f: &mut Frame, area: Rect, about: &AboutInfo, palette: &Palette) {
+    let lines: Vec<Line> = about.lines().into_iter().map(Line::from).collect();
+    let about_block = Block::default()
+        .borders(Borders::ALL)
+        .title("About (y to copy)")
+        .style(palette.panel);
+
+    let about_paragraph = Paragraph::new(lines)
+        .block(about_block)
+        .alignment(Alignment::Left);
+
+    f.render_widget(about_paragraph, area);
+}
+
+/// Puts text on the system clipboard with an OSC 52 escape, which the
+/// terminal handles, so this works over SSH too.
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", encoded)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn render_help(
+    f: &mut Frame,
+    area: Rect,
+    palette: &Palette,
+    long_jump: &LongJumpConfig,
+    capabilities: Option<&ServerCapabilities>,
+) {
+    // Greyed out, saying so, when the server is known not to support it
+    let gated = |text: &'static str, supported: fn(&ServerCapabilities) -> bool| {
+        if capabilities.is_none_or(supported) {
+            Line::from(text)
+        } else {
+            Line::styled(format!("{} (not on this server)", text), Style::default().add_modifier(Modifier::DIM))
+        }
+    };
     let help_text = vec![
         Line::from("Navigation:"),
         Line::from("  ↑/↓ or j/k    - Navigate up/down"),
         Line::from("  →/l or Enter  - Select item / Play song"),
         Line::from("  ←/h           - Go back"),
+        Line::from("  Ctrl+R        - Reload the artists, albums or songs from the server"),
+        Line::from(""),
+        Line::from("Playlists:"),
+        Line::from("  P              - Open playlists (smart and from the server)"),
+        Line::from("  r / J / K      - In a server playlist: remove / move down / move up a song"),
+        Line::from("  e / d          - In a server playlist: rename / delete it"),
+        Line::from("  S              - Open starred songs"),
+        Line::from("  C              - Open podcasts"),
+        Line::from("  E              - Browse the library by folder"),
+        Line::from("  T              - In an artist's albums: their top songs"),
+        Line::from("  M              - In an artist's albums: shuffle all their songs"),
+        Line::from("  v              - Artist info: biography and similar artists (enter opens one)"),
+        Line::from("                   In an album's songs: the album notes"),
+        Line::from("  B              - In Artists: list all albums, newest first (B again changes the order)"),
         Line::from(""),
         Line::from("Search:"),
         Line::from("  / or i         - Open search"),
@@ -423,22 +2669,66 @@ fn render_help(f: &mut Frame, area: Rect) {
         Line::from("  Backspace      - Delete character"),
         Line::from(""),
         Line::from("Queue:"),
-        Line::from("  a              - Add song to queue"),
+        Line::from("  a              - Add song (or album in Albums) to queue"),
+        Line::from("  A              - Add whole album to queue"),
+        Line::from("  R              - Replace queue with album and play"),
+        Line::from("  x              - In Artists: play random songs from the whole library"),
+        Line::from("  z              - Play a random album (again for another)"),
+        Line::from("  u              - Undo last queue replace"),
+        Line::from("  f              - Star / unstar the selected artist, album or song"),
+        Line::from("  F              - Star the album and all its songs"),
+        Line::from("  1..5 / 0       - Rate the selected song or album / clear its rating"),
+        Line::from("  Shift+1..5 / 0 - Rate the playing song / clear its rating"),
         Line::from("  n              - Play next in queue"),
+        Line::from("  o              - Radio on/off: similar songs once the queue runs out"),
+        Line::from("  Z              - Jukebox on/off: play on the server's speakers"),
         Line::from("  r              - Remove first from queue"),
         Line::from("  c              - Clear queue"),
+        Line::from("  W              - Save the queue as a server playlist, or add it to one"),
         Line::from("  p              - Start/restart queue"),
+        Line::from("  D              - Download the queue for offline play (Esc stops)"),
+        Line::from("  d              - Download the album for offline play"),
+        Line::from("  O              - Downloads (p pauses/resumes, x cancels)"),
+        Line::from("  X              - Save the song or album as files in ~/Music/danavi"),
+        Line::from("  U              - Share the song or album: make a link and copy it"),
         Line::from("  space          - Pause/resume playback"),
+        Line::from("  , / .          - Seek back / ahead 10 seconds (jump, for podcasts)"),
+        Line::from("  b              - Restart the current track"),
+        Line::from("  + / -          - Volume up / down"),
+        Line::from("  m              - Mute / unmute"),
+        Line::from("  Alt+0..9       - Seek to 0%..90% of the track"),
+        Line::from(format!(
+            "  {} / {}          - Jump ahead {}s / back {}s",
+            long_jump.forward_key, long_jump.back_key, long_jump.forward, long_jump.back
+        )),
+        Line::from("  s              - Shuffle the rest of the album, playlist or starred songs"),
+        Line::from(""),
+        Line::from("Commands:"),
+        Line::from("  :              - Open command prompt"),
+        Line::from("  :export <file> - Save queue as M3U8 (-p for library paths)"),
+        Line::from("  :import <file> - Append an M3U playlist to the queue"),
+        Line::from("  :about         - Show server and diagnostics info"),
+        Line::from("  :messages      - Show the message log"),
+        Line::from("  :queue dedupe  - Remove repeated songs from the queue"),
+        Line::from("  :prefetch      - Same as D"),
+        Line::from("  :scan          - Scan the library for new files (admins only)"),
+        Line::from("  :starred       - Same as S"),
+        Line::from("  :star-album    - Star the album and all its songs"),
+        Line::from("  :unstar-album  - Unstar the album and all its songs"),
         Line::from(""),
         Line::from("General:"),
         Line::from("  ?              - Show this help menu"),
+        Line::from("  I              - About / diagnostics (y copies it)"),
+        Line::from("  L              - Message log"),
+        Line::from("  Y              - Lyrics (PgUp/PgDn page)"),
+        gated("  [ / ]          - In lyrics: shift synced ones from the server", ServerCapabilities::synced_lyrics),
         Line::from("  q/Escape       - Quit app"),
     ];
 
     let help_block = Block::default()
         .borders(Borders::ALL)
         .title("Help")
-        .style(Style::default().fg(Color::White).bg(Color::Blue));
+        .style(palette.panel);
 
     let help_paragraph = Paragraph::new(help_text)
         .block(help_block)
@@ -1,158 +1,1543 @@
+//! Library model and Subsonic API response types.
+
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
+/// Contents of `config.json`. Fields added after the first release default
+/// when missing, so older files keep loading.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Server root, e.g. `http://localhost:4533`
     #[serde(alias = "baseUrl")]
     pub base_url: String,
     pub username: String,
     pub password: String,
     #[serde(default = "default_true", alias = "showEasterEggs")]
     pub show_easter_eggs: bool,
+    /// What Enter does on a song inside an album
+    #[serde(default)]
+    pub on_album_select: AlbumSelectMode,
+    /// Address for the MPD protocol server, off when unset
+    #[serde(default)]
+    pub mpd_listen: Option<String>,
+    /// Register MPRIS as `org.mpris.MediaPlayer2.danavi.<name>`
+    #[serde(default)]
+    pub mpris_instance: Option<String>,
+    /// Portal shortcut trigger -> action name
+    #[serde(default)]
+    pub global_hotkeys: HashMap<String, String>,
+    #[serde(default)]
+    pub pause_on_output_change: bool,
+    /// File kept updated with the playing song, for status bars
+    #[serde(default)]
+    pub status_file: Option<String>,
+    #[serde(default = "default_status_format")]
+    pub status_format: String,
+    /// Days to remember where a partly played album was left; 0 turns it off
+    #[serde(default = "default_album_progress_days")]
+    pub album_progress_days: u64,
+    /// Leave out songs already queued or playing when adding to the queue
+    #[serde(default)]
+    pub queue_dedupe: bool,
+    /// Search while typing, once the keys pause
+    #[serde(default)]
+    pub search_as_you_type: bool,
+    /// Keys for the current view in the status bar, instead of just "press ? for help"
+    #[serde(default = "default_true")]
+    pub key_hints: bool,
+    /// Where downloaded songs are kept, instead of the cache dir
+    #[serde(default)]
+    pub download_dir: Option<String>,
+    /// Report plays to the server, which forwards them to Last.fm etc.
+    #[serde(default)]
+    pub scrobble: bool,
+    /// Days a scrobble that couldn't be sent is kept for retrying
+    #[serde(default = "default_scrobble_horizon_days")]
+    pub scrobble_horizon_days: u64,
+    /// Show what's playing in the server's now-playing list; unset, it
+    /// follows `scrobble`
+    #[serde(default)]
+    pub report_now_playing: Option<bool>,
+    /// Minutes a song must run for its position to be bookmarked on the
+    /// server when left part way; 0 turns bookmarks off
+    #[serde(default = "default_bookmark_after_minutes")]
+    pub bookmark_after_minutes: u64,
+    /// Days a share link works for; 0 keeps it working until deleted
+    #[serde(default = "default_share_expiry_days")]
+    pub share_expiry_days: u64,
+    /// Keep songs on disk as they stream, so playing them again needs no
+    /// network
+    #[serde(default = "default_true")]
+    pub stream_cache: bool,
+    /// MB the songs kept from streaming may take up; the ones played longest
+    /// ago make room
+    #[serde(default = "default_stream_cache_mb")]
+    pub stream_cache_mb: u64,
+    /// Minutes cached artist, album and song lists count as fresh. Older
+    /// ones still show at once but are fetched again behind them; 0 turns
+    /// the cache off
+    #[serde(default = "default_metadata_cache_minutes")]
+    pub metadata_cache_minutes: u64,
+    /// Keep the server's play queue in step, to carry on from other devices
+    #[serde(default = "default_true")]
+    pub sync_play_queue: bool,
+    /// Roll into the artist's next album when an album ends
+    #[serde(default)]
+    pub continue_artist: bool,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    /// Which ReplayGain to level tracks with
+    #[serde(default)]
+    pub replaygain: ReplayGainMode,
+    /// dB added on top of the ReplayGain
+    #[serde(default)]
+    pub replaygain_preamp: f32,
+    /// Level tracks by measuring them, whatever their tags say
+    #[serde(default)]
+    pub normalize_loudness: bool,
+    /// Loudness `normalize_loudness` aims for
+    #[serde(default = "default_normalize_target_lufs")]
+    pub normalize_target_lufs: f32,
+    /// Milliseconds pausing and resuming fade over; 0 cuts straight away
+    #[serde(default = "default_pause_fade_ms")]
+    pub pause_fade_ms: u64,
+    /// Long seek steps for podcasts, audiobooks and DJ sets
+    #[serde(default)]
+    pub long_jump: LongJumpConfig,
+    /// Kbps the server transcodes streams down to; 0 streams files as they are
+    #[serde(default)]
+    pub max_bitrate: u32,
+    /// JSON has no comments, so this note on `stream_format` is written
+    /// next to it and ignored when read
+    #[serde(rename = "//stream_format", skip_deserializing, serialize_with = "stream_format_help")]
+    pub stream_format_help: (),
+    /// What the server sends songs as: `raw` for the files as they are,
+    /// `mp3` to transcode them first
+    #[serde(default)]
+    pub stream_format: StreamFormat,
+    /// How to log in: `token` sends a salted hash of the password, `legacy`
+    /// the password itself, hex encoded, for servers that only take that
+    #[serde(default)]
+    pub auth_method: AuthMethod,
+    /// PEM file of certificates to trust on top of the system's, e.g. a
+    /// self-signed server's
+    #[serde(default)]
+    pub tls_ca_file: Option<String>,
+    /// Skip checking the server's certificate altogether
+    #[serde(default)]
+    pub tls_accept_invalid_certs: bool,
+    /// Rows per step as a held j/k keeps repeating; `[1]` turns it off
+    #[serde(default = "default_scroll_acceleration")]
+    pub scroll_acceleration: Vec<usize>,
+}
+
+fn stream_format_help<S: serde::Serializer>(_: &(), serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(
+        "raw: the original files, fetched again as MP3 when one won't play; mp3: transcoded by the server, which always plays",
+    )
 }
 
 fn default_true() -> bool {
     true
 }
 
-#[derive(Debug, Clone)]
+pub(crate) fn default_status_format() -> String {
+    "{artist} - {title}".to_string()
+}
+
+pub(crate) fn default_album_progress_days() -> u64 {
+    30
+}
+
+pub(crate) fn default_scrobble_horizon_days() -> u64 {
+    14
+}
+
+pub(crate) fn default_bookmark_after_minutes() -> u64 {
+    10
+}
+
+pub(crate) fn default_share_expiry_days() -> u64 {
+    7
+}
+
+pub(crate) fn default_stream_cache_mb() -> u64 {
+    2048
+}
+
+pub(crate) fn default_metadata_cache_minutes() -> u64 {
+    60
+}
+
+pub(crate) fn default_normalize_target_lufs() -> f32 {
+    -14.0
+}
+
+pub(crate) fn default_pause_fade_ms() -> u64 {
+    200
+}
+
+pub(crate) fn default_scroll_acceleration() -> Vec<usize> {
+    vec![1, 3, 8]
+}
+
+/// See [`Config::long_jump`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LongJumpConfig {
+    /// Seconds `forward_key` skips ahead
+    pub forward: u64,
+    /// Seconds `back_key` skips back
+    pub back: u64,
+    pub forward_key: char,
+    pub back_key: char,
+}
+
+impl Default for LongJumpConfig {
+    fn default() -> Self {
+        Self {
+            forward: 30,
+            back: 15,
+            forward_key: '>',
+            back_key: '<',
+        }
+    }
+}
+
+/// See [`Config::theme`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ThemeConfig {
+    /// Which default palette to use
+    #[serde(default)]
+    pub background: Background,
+}
+
+/// The terminal background the colors are picked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Background {
+    /// Ask the terminal, assuming dark if it won't say
+    #[default]
+    Auto,
+    /// Light text on a dark background
+    Dark,
+    /// Dark text on a light background
+    Light,
+}
+
+/// See [`Config::on_album_select`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlbumSelectMode {
+    /// Play from the song, carrying on through the album
+    #[default]
+    Play,
+    /// Add the album to the end of the queue
+    Append,
+    /// Make the album from the song on the queue, and play it
+    ReplaceQueue,
+}
+
+/// An artist as listed by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Artist {
     pub id: String,
     pub name: String,
+    #[serde(default)]
+    pub starred: bool,
 }
 
-#[derive(Debug, Clone)]
+/// An album as listed under an artist, or in an album list, where the
+/// artist is named too.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Album {
     pub id: String,
     pub name: String,
+    pub year: Option<i32>,
+    pub starred: bool,
+    #[serde(default)]
+    pub artist: Option<String>,
+    /// User rating from 1 to 5
+    #[serde(default)]
+    pub rating: Option<u8>,
+    #[serde(default)]
+    pub genre: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+/// A playable track. Everything but the id and title is optional since
+/// servers fill in different subsets.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Song {
     pub id: String,
     pub title: String,
     pub artist: Option<String>,
     pub album: Option<String>,
     pub album_artist: Option<String>,
+    /// Length in seconds
     pub duration: Option<i64>,
+    /// File path in the server's library, if the server exposes it
+    pub path: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<i32>,
+    pub starred: bool,
+    /// User rating from 1 to 5
+    pub rating: Option<u8>,
+    #[serde(default)]
+    pub replay_gain: Option<ReplayGain>,
+    /// Cover art id, for [`SubsonicClient::get_cover_art`](crate::client::SubsonicClient::get_cover_art)
+    #[serde(default)]
+    pub cover_art: Option<String>,
+    /// Extension of the original file, like "flac"
+    #[serde(default)]
+    pub suffix: Option<String>,
+    /// Position on its disc, from 1
+    #[serde(default)]
+    pub track: Option<u32>,
+    #[serde(default)]
+    pub disc_number: Option<u32>,
+    /// A podcast episode, which isn't scrobbled or used to seed the radio
+    #[serde(default)]
+    pub podcast: bool,
+}
+
+impl Song {
+    /// The title, plus the track artist when it isn't obvious from the
+    /// album, as on compilations. Without an album artist the track artist
+    /// is compared with `usual_artist`, the one most of the list is by.
+    /// `numbered` puts the track number first, as "03. Title", when the
+    /// server has one.
+    ///
+    /// ```
+    /// use danavi::types::Song;
+    ///
+    /// let song = |title: &str, artist: &str| Song {
+    ///     title: title.to_string(),
+    ///     artist: Some(artist.to_string()),
+    ///     ..Default::default()
+    /// };
+    /// let album = [song("One", "Low"), song("Two", "Low"), song("Three", "Low & Dirty Three")];
+    /// let usual = Song::usual_artist(&album);
+    /// assert_eq!(usual, Some("Low"));
+    /// assert_eq!(album[0].list_label(false, usual), "One");
+    /// assert_eq!(album[2].list_label(false, usual), "Three — Low & Dirty Three");
+    ///
+    /// let compilation = Song { album_artist: Some("Various Artists".to_string()), ..album[0].clone() };
+    /// assert_eq!(compilation.list_label(false, usual), "One — Low");
+    ///
+    /// let numbered = Song { track: Some(3), ..album[0].clone() };
+    /// assert_eq!(numbered.list_label(true, usual), "03. One");
+    /// assert_eq!(numbered.list_label(false, usual), "One");
+    /// assert_eq!(album[0].list_label(true, usual), "One");
+    /// ```
+    pub fn list_label(&self, numbered: bool, usual_artist: Option<&str>) -> String {
+        let title = match self.track.filter(|_| numbered) {
+            Some(track) => format!("{:02}. {}", track, self.title),
+            None => self.title.clone(),
+        };
+        let Some(artist) = &self.artist else {
+            return title;
+        };
+        let show_artist = match self.album_artist.as_deref().or(usual_artist) {
+            Some(album_artist) => {
+                album_artist.eq_ignore_ascii_case("Various Artists")
+                    || album_artist.eq_ignore_ascii_case("VA")
+                    || !album_artist.eq_ignore_ascii_case(artist)
+            }
+            None => true,
+        };
+        if show_artist {
+            format!("{} — {}", title, artist)
+        } else {
+            title
+        }
+    }
+
+    /// Puts an album's songs in disc and track order, a song without a disc
+    /// number being on the first. Unless every song has a track number the
+    /// order is left as it is.
+    ///
+    /// ```
+    /// use danavi::types::Song;
+    ///
+    /// let song = |title: &str, disc: Option<u32>, track: Option<u32>| Song {
+    ///     title: title.to_string(),
+    ///     disc_number: disc,
+    ///     track,
+    ///     ..Default::default()
+    /// };
+    /// let titles = |songs: &[Song]| songs.iter().map(|s| s.title.clone()).collect::<Vec<_>>();
+    ///
+    /// let mut songs = vec![
+    ///     song("2-1", Some(2), Some(1)),
+    ///     song("1-2", None, Some(2)),
+    ///     song("1-1", Some(1), Some(1)),
+    ///     song("1-3", None, Some(3)),
+    /// ];
+    /// Song::sort_album_tracks(&mut songs);
+    /// assert_eq!(titles(&songs), ["1-1", "1-2", "1-3", "2-1"]);
+    ///
+    /// let mut songs = vec![song("B", None, Some(2)), song("Untracked", None, None), song("A", None, Some(1))];
+    /// Song::sort_album_tracks(&mut songs);
+    /// assert_eq!(titles(&songs), ["B", "Untracked", "A"]);
+    /// ```
+    pub fn sort_album_tracks(songs: &mut [Song]) {
+        if songs.iter().all(|s| s.track.is_some()) {
+            songs.sort_by_key(|s| (s.disc_number.unwrap_or(1), s.track));
+        }
+    }
+
+    /// The artist of more than half of `songs`, if one is.
+    pub fn usual_artist(songs: &[Song]) -> Option<&str> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for artist in songs.iter().filter_map(|song| song.artist.as_deref()) {
+            *counts.entry(artist).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .find(|(_, count)| count * 2 > songs.len())
+            .map(|(artist, _)| artist)
+    }
+}
+
+/// Loudness normalization data, as the server reads it from the file's
+/// tags. Gains are in dB, peaks as a fraction of full scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayGain {
+    #[serde(default)]
+    pub track_gain: Option<f32>,
+    #[serde(default)]
+    pub album_gain: Option<f32>,
+    #[serde(default)]
+    pub track_peak: Option<f32>,
+    #[serde(default)]
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// The volume multiplier for `mode`, with `preamp` dB added. Missing
+    /// gains count as 0 dB (album mode falls back to the track gain), and
+    /// the result is capped so the peak doesn't clip.
+    ///
+    /// ```
+    /// use danavi::types::{ReplayGain, ReplayGainMode};
+    ///
+    /// let gain = ReplayGain {
+    ///     track_gain: Some(-6.0),
+    ///     album_gain: Some(6.0),
+    ///     album_peak: Some(0.8),
+    ///     ..Default::default()
+    /// };
+    /// assert!((gain.multiplier(ReplayGainMode::Track, 0.0) - 0.501).abs() < 0.001);
+    /// // +6 dB would take the 0.8 peak past full scale
+    /// assert_eq!(gain.multiplier(ReplayGainMode::Album, 0.0), 1.25);
+    /// assert_eq!(ReplayGain::default().multiplier(ReplayGainMode::Track, 0.0), 1.0);
+    /// assert_eq!(gain.multiplier(ReplayGainMode::Off, 0.0), 1.0);
+    /// ```
+    pub fn multiplier(&self, mode: ReplayGainMode, preamp: f32) -> f32 {
+        let (gain, peak) = match mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => (self.track_gain, self.track_peak),
+            ReplayGainMode::Album => (
+                self.album_gain.or(self.track_gain),
+                self.album_peak.or(self.track_peak),
+            ),
+        };
+        let multiplier = 10f32.powf((gain.unwrap_or(0.0) + preamp) / 20.0);
+        match peak.filter(|&p| p > 0.0) {
+            Some(peak) => multiplier.min(1.0 / peak),
+            None => multiplier,
+        }
+    }
+}
+
+/// See [`Config::replaygain`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayGainMode {
+    /// Each song's own gain
+    Track,
+    /// The album's gain, keeping the levels within an album
+    Album,
+    /// Leave the volume alone
+    #[default]
+    Off,
+}
+
+/// See [`Config::stream_format`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamFormat {
+    /// The original file, untouched
+    #[default]
+    Raw,
+    /// Transcoded to MP3, which always decodes
+    Mp3,
+}
+
+impl StreamFormat {
+    /// The `format` parameter the server is sent.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StreamFormat::Raw => "raw",
+            StreamFormat::Mp3 => "mp3",
+        }
+    }
+}
+
+/// See [`Config::auth_method`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// Salted token, moving to legacy if the server says it can't check one
+    #[default]
+    Token,
+    /// `p=enc:` with the hex encoded password
+    Legacy,
+}
+
+// API Response types. These mirror the JSON under `subsonic-response` and
+// are returned as-is by `SubsonicClient`.
+
+/// The answer to `ping`, saying which API and server this is.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PingResponse {
+    pub version: String,
+    #[serde(default, rename = "type")]
+    pub server_type: Option<String>,
+    #[serde(default, rename = "serverVersion")]
+    pub server_version: Option<String>,
+    #[serde(default, rename = "openSubsonic")]
+    pub open_subsonic: bool,
+}
+
+/// The answer to `getOpenSubsonicExtensions`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenSubsonicExtensionsResponse {
+    #[serde(default, rename = "openSubsonicExtensions")]
+    pub extensions: Vec<OpenSubsonicExtension>,
+}
+
+/// An OpenSubsonic extension and the versions of it the server has.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenSubsonicExtension {
+    pub name: String,
+    #[serde(default)]
+    pub versions: Vec<u32>,
+}
+
+/// The answer to `getUser`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserResponse {
+    pub user: User,
+}
+
+/// ```
+/// use danavi::types::User;
+///
+/// let user: User = serde_json::from_str(r#"{"username": "alice", "streamRole": false}"#)?;
+/// assert!(!user.stream_role);
+/// let user: User = serde_json::from_str(r#"{"username": "alice"}"#)?;
+/// assert!(user.stream_role);
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub username: String,
+    /// Taken as allowed when the server leaves it out
+    #[serde(default = "default_true", rename = "streamRole")]
+    pub stream_role: bool,
 }
 
-#[derive(Debug, Clone)]
-pub enum SearchResultItem {
-    Album {
-        id: String,
-        name: String,
-        artist: String,
-        artist_id: String,
-    },
-    Song {
-        id: String,
-        title: String,
-        artist: String,
-        album: Option<String>,
-        duration: Option<i64>,
-    },
-}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ViewType {
-    Artists,
-    Albums,
-    Songs,
-    Search,
-}
-
-#[derive(Debug, Clone)]
-pub enum PlaybackSource {
-    Queue,
-    Album {
-        album_songs: Vec<Song>,
-        current_index: usize,
-    },
-    Search,
-}
-
-// API Response types
-#[derive(Debug, Deserialize)]
+/// The answer to `getScanStatus` and `startScan`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScanStatusResponse {
+    #[serde(rename = "scanStatus")]
+    pub scan_status: ScanStatus,
+}
+
+/// Whether a library scan is running, and how far it got.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScanStatus {
+    pub scanning: bool,
+    #[serde(default)]
+    pub count: Option<u64>,
+}
+
+/// The envelope every JSON answer comes in, around the endpoint's own
+/// response type.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubsonicResponse<T> {
+    #[serde(rename = "subsonic-response")]
+    pub response: T,
+}
+
+/// Reads a list that some servers (Airsonic-Advanced, LMS) send as a bare
+/// object when it has only one item, for `deserialize_with`.
+///
+/// ```
+/// use danavi::types::{AlbumResponse, ArtistResponse, ArtistsResponse, SearchResponse};
+///
+/// // Airsonic-Advanced, an artist with one album
+/// let one: ArtistResponse = serde_json::from_str(r#"{"artist": {
+///     "id": "2", "name": "Nils Frahm", "coverArt": "ar-2", "albumCount": 1,
+///     "album": {"id": "17", "name": "Spaces", "artist": "Nils Frahm", "artistId": "2",
+///         "coverArt": "al-17", "songCount": 11, "duration": 5863, "year": 2013}
+/// }}"#)?;
+/// assert_eq!(one.artist.album.len(), 1);
+/// assert_eq!(one.artist.album[0].name, "Spaces");
+/// // Navidrome, the same as the spec has it
+/// let many: ArtistResponse = serde_json::from_str(r#"{"artist": {
+///     "id": "2", "name": "Nils Frahm",
+///     "album": [{"id": "17", "name": "Spaces"}, {"id": "18", "name": "Felt"}]
+/// }}"#)?;
+/// assert_eq!(many.artist.album.len(), 2);
+/// // Only featured on other albums, so the list is left out
+/// let none: ArtistResponse = serde_json::from_str(r#"{"artist": {"id": "5", "name": "Guest"}}"#)?;
+/// assert!(none.artist.album.is_empty());
+///
+/// // LMS, an index letter with one artist next to one with two
+/// let artists: ArtistsResponse = serde_json::from_str(r#"{"artists": {"ignoredArticles": "The", "index": [
+///     {"name": "Q", "artist": {"id": "ar-9", "name": "Quasimoto", "albumCount": 1}},
+///     {"name": "R", "artist": [{"id": "ar-3", "name": "Radiohead"}, {"id": "ar-4", "name": "Röyksopp"}]}
+/// ]}}"#)?;
+/// let counts: Vec<usize> = artists.artists.index.iter().map(|i| i.artist.len()).collect();
+/// assert_eq!(counts, [1, 2]);
+///
+/// // Airsonic-Advanced, a single
+/// let album: AlbumResponse = serde_json::from_str(r#"{"album": {
+///     "id": "21", "name": "Says", "artist": "Nils Frahm", "songCount": 1,
+///     "song": {"id": "305", "parent": "21", "title": "Says", "artist": "Nils Frahm",
+///         "album": "Says", "duration": 498, "suffix": "flac", "isDir": false}
+/// }}"#)?;
+/// assert_eq!(album.album.song[0].title, "Says");
+///
+/// // LMS, one album and one song found
+/// let found: SearchResponse = serde_json::from_str(r#"{"searchResult3": {
+///     "album": {"id": "21", "name": "Says", "artist": "Nils Frahm", "artistId": "2"},
+///     "song": {"id": "305", "title": "Says", "artist": "Nils Frahm", "album": "Says"}
+/// }}"#)?;
+/// let found = found.search_result3.unwrap();
+/// assert_eq!((found.album.unwrap().len(), found.song.unwrap().len()), (1, 1));
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+pub(crate) fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct OneOrMany<T>(std::marker::PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> serde::de::Visitor<'de> for OneOrMany<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a list or a single object")
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> Result<Vec<T>, A::Error> {
+            Vec::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Vec<T>, A::Error> {
+            T::deserialize(serde::de::value::MapAccessDeserializer::new(map)).map(|one| vec![one])
+        }
+
+        fn visit_unit<E>(self) -> Result<Vec<T>, E> {
+            Ok(Vec::new())
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany(std::marker::PhantomData))
+}
+
+// `one_or_many` for lists that may be missing altogether
+fn maybe_one_or_many<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    one_or_many(deserializer).map(Some)
+}
+
+/// The answer to `getArtists`.
+#[derive(Debug, Clone, Deserialize)]
 pub struct ArtistsResponse {
     pub artists: ArtistsData,
 }
 
-#[derive(Debug, Deserialize)]
+/// Every artist, grouped under index letters.
+#[derive(Debug, Clone, Deserialize)]
 pub struct ArtistsData {
+    /// Space separated words left out when sorting, e.g. "The El La"
+    #[serde(default, rename = "ignoredArticles")]
+    pub ignored_articles: String,
     pub index: Vec<IndexEntry>,
 }
 
-#[derive(Debug, Deserialize)]
+/// The artists under one index letter.
+#[derive(Debug, Clone, Deserialize)]
 pub struct IndexEntry {
+    #[serde(deserialize_with = "one_or_many")]
     pub artist: Vec<ArtistData>,
 }
 
-#[derive(Debug, Deserialize)]
+/// An artist in a listing.
+#[derive(Debug, Clone, Deserialize)]
 pub struct ArtistData {
     pub id: String,
     pub name: String,
+    #[serde(default, rename = "albumCount")]
+    pub album_count: Option<u64>,
+    #[serde(default)]
+    pub starred: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// The answer to `getArtist`.
+#[derive(Debug, Clone, Deserialize)]
 pub struct ArtistResponse {
     pub artist: ArtistDetail,
 }
 
-#[derive(Debug, Deserialize)]
+/// An artist with their albums.
+#[derive(Debug, Clone, Deserialize)]
 pub struct ArtistDetail {
     pub name: String,
+    /// Left out for artists only credited on other artists' albums
+    #[serde(default, deserialize_with = "one_or_many")]
     pub album: Vec<AlbumData>,
+    #[serde(default, rename = "coverArt")]
+    pub cover_art: Option<String>,
+}
+
+/// The answer to `getArtistInfo2`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArtistInfo2Response {
+    /// Missing from servers that have nothing on the artist
+    #[serde(default, rename = "artistInfo2")]
+    pub artist_info2: ArtistInfo,
 }
 
-#[derive(Debug, Deserialize)]
+/// What the server knows about an artist, usually from Last.fm.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ArtistInfo {
+    /// May contain HTML; see [`ArtistInfo::biography_text`]
+    #[serde(default)]
+    pub biography: Option<String>,
+    #[serde(default, rename = "lastFmUrl")]
+    pub last_fm_url: Option<String>,
+    #[serde(default, rename = "similarArtist")]
+    pub similar_artist: Vec<SimilarArtist>,
+}
+
+/// An artist like the one asked about. Only those in the library have an id.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimilarArtist {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default, rename = "albumCount")]
+    pub album_count: Option<u64>,
+}
+
+impl SimilarArtist {
+    /// The artist's id, if they're in the library.
+    pub fn library_id(&self) -> Option<&str> {
+        self.id.as_deref().filter(|id| !id.is_empty())
+    }
+}
+
+impl ArtistInfo {
+    /// The biography as plain text, see [`html_to_text`].
+    pub fn biography_text(&self) -> Option<String> {
+        html_to_text(self.biography.as_deref()?)
+    }
+}
+
+/// The answer to `getAlbumInfo2`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlbumInfo2Response {
+    /// Missing, or empty, from servers that have nothing on the album
+    #[serde(default, rename = "albumInfo")]
+    pub album_info: AlbumInfo,
+}
+
+/// What the server knows about an album, usually from Last.fm.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumInfo {
+    /// May contain HTML; see [`AlbumInfo::notes_text`]
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub last_fm_url: Option<String>,
+    #[serde(default)]
+    pub music_brainz_id: Option<String>,
+    #[serde(default)]
+    pub cover_art: Option<String>,
+    #[serde(default)]
+    pub small_image_url: Option<String>,
+    #[serde(default)]
+    pub medium_image_url: Option<String>,
+    #[serde(default)]
+    pub large_image_url: Option<String>,
+}
+
+impl AlbumInfo {
+    /// The notes as plain text, see [`html_to_text`].
+    pub fn notes_text(&self) -> Option<String> {
+        html_to_text(self.notes.as_deref()?)
+    }
+}
+
+/// Server-supplied HTML as plain text: tags dropped, entities decoded, and
+/// paragraphs kept apart by blank lines. `None` when nothing is left.
+///
+/// ```
+/// use danavi::types::html_to_text;
+///
+/// let html = "Rock &amp; roll band.<br/><p>Formed in 1990.</p> <a href=\"https://last.fm\">Read more</a>";
+/// assert_eq!(html_to_text(html).unwrap(), "Rock & roll band.\n\nFormed in 1990.\n\nRead more");
+/// assert!(html_to_text(" <br> ").is_none());
+/// ```
+pub fn html_to_text(html: &str) -> Option<String> {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim_start_matches('/').to_lowercase();
+        let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("");
+        if matches!(name, "br" | "p" | "div" | "li") {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    let text = crate::lyrics::decode_entities(&text);
+    let paragraphs: Vec<String> = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect();
+    (!paragraphs.is_empty()).then(|| paragraphs.join("\n\n"))
+}
+
+/// An album in a listing.
+#[derive(Debug, Clone, Deserialize)]
 pub struct AlbumData {
     pub id: String,
     pub name: String,
+    #[serde(default)]
+    pub year: Option<i32>,
+    #[serde(default)]
+    pub starred: Option<String>,
+    #[serde(default)]
+    pub artist: Option<String>,
+    #[serde(default, rename = "artistId")]
+    pub artist_id: Option<String>,
+    #[serde(default, rename = "coverArt")]
+    pub cover_art: Option<String>,
+    #[serde(default, rename = "userRating")]
+    pub user_rating: Option<u8>,
+    #[serde(default)]
+    pub genre: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// The answer to `getAlbum`.
+#[derive(Debug, Clone, Deserialize)]
 pub struct AlbumResponse {
     pub album: AlbumDetail,
 }
 
-#[derive(Debug, Deserialize)]
+/// An album with its songs.
+#[derive(Debug, Clone, Deserialize)]
 pub struct AlbumDetail {
     pub name: String,
     pub artist: Option<String>,
+    #[serde(default)]
+    pub year: Option<i32>,
+    #[serde(default, rename = "coverArt")]
+    pub cover_art: Option<String>,
+    #[serde(default, rename = "userRating")]
+    pub user_rating: Option<u8>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
     pub song: Vec<SongData>,
 }
 
-#[derive(Debug, Deserialize)]
+/// A song as the server describes it.
+#[derive(Debug, Clone, Deserialize)]
 pub struct SongData {
     pub id: String,
     pub title: String,
     pub artist: Option<String>,
     #[serde(default)]
+    pub album: Option<String>,
+    #[serde(default)]
     pub duration: Option<i64>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub year: Option<i32>,
+    #[serde(default)]
+    pub starred: Option<String>,
+    #[serde(default, rename = "userRating")]
+    pub user_rating: Option<u8>,
+    #[serde(default, rename = "replayGain")]
+    pub replay_gain: Option<ReplayGain>,
+    #[serde(default, rename = "coverArt")]
+    pub cover_art: Option<String>,
+    #[serde(default)]
+    pub suffix: Option<String>,
+    #[serde(default)]
+    pub track: Option<u32>,
+    #[serde(default, rename = "discNumber")]
+    pub disc_number: Option<u32>,
+}
+
+/// The answer to `getSong`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SongResponse {
+    pub song: SongData,
+}
+
+/// The songs of the endpoints that answer with a plain list of them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SongList {
+    #[serde(default)]
+    pub song: Vec<SongData>,
+}
+
+/// The answer to `getRandomSongs`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RandomSongsResponse {
+    #[serde(rename = "randomSongs")]
+    pub random_songs: SongList,
+}
+
+/// The answer to `getAlbumList2`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlbumList2Response {
+    #[serde(rename = "albumList2")]
+    pub album_list2: AlbumList,
+}
+
+/// A page of albums.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlbumList {
+    #[serde(default)]
+    pub album: Vec<AlbumData>,
+}
+
+/// The answer to `getTopSongs`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopSongsResponse {
+    #[serde(rename = "topSongs")]
+    pub top_songs: SongList,
+}
+
+/// The answer to `getSimilarSongs2`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimilarSongs2Response {
+    #[serde(rename = "similarSongs2")]
+    pub similar_songs2: SongList,
+}
+
+/// The answer to `getSongsByGenre`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SongsByGenreResponse {
+    #[serde(rename = "songsByGenre")]
+    pub songs_by_genre: SongList,
 }
 
-#[derive(Debug, Deserialize)]
+/// The answer to `getStarred2`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Starred2Response {
+    pub starred2: SongList,
+}
+
+/// The answer to `getIndexes`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexesResponse {
+    pub indexes: Indexes,
+}
+
+/// The top of the library's folder tree.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Indexes {
+    /// As in [`ArtistsData::ignored_articles`]
+    #[serde(default, rename = "ignoredArticles")]
+    pub ignored_articles: String,
+    #[serde(default)]
+    pub index: Vec<FolderIndex>,
+    /// Files lying directly in a music folder
+    #[serde(default)]
+    pub child: Vec<DirectoryChild>,
+}
+
+/// Top-level folders under one letter.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FolderIndex {
+    pub name: String,
+    #[serde(default)]
+    pub artist: Vec<TopFolder>,
+}
+
+/// A folder at the top of a music folder.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopFolder {
+    pub id: String,
+    pub name: String,
+}
+
+/// The answer to `getMusicDirectory`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MusicDirectoryResponse {
+    pub directory: MusicDirectory,
+}
+
+/// A folder and what is in it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MusicDirectory {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub child: Vec<DirectoryChild>,
+}
+
+/// A directory's entry: another directory, or a file with the usual song
+/// fields.
+///
+/// ```
+/// use danavi::types::MusicDirectory;
+///
+/// let dir: MusicDirectory = serde_json::from_str(r#"{"id": "d-1", "name": "Box Set", "child": [
+///     {"id": "d-2", "title": "CD 1", "isDir": true},
+///     {"id": "tr-1", "title": "Prelude", "isDir": false, "duration": 140}
+/// ]}"#)?;
+/// assert!(dir.child[0].is_dir);
+/// assert_eq!(dir.child[1].song.duration, Some(140));
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct DirectoryChild {
+    #[serde(default, rename = "isDir")]
+    pub is_dir: bool,
+    /// For a directory, just the id and its name as the title
+    #[serde(flatten)]
+    pub song: SongData,
+}
+
+/// The answer to `getBookmarks`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookmarksResponse {
+    pub bookmarks: BookmarkList,
+}
+
+/// The user's bookmarks.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookmarkList {
+    #[serde(default)]
+    pub bookmark: Vec<Bookmark>,
+}
+
+/// A saved position in a song, kept by the server per user.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Bookmark {
+    /// Milliseconds into the song
+    pub position: u64,
+    pub entry: SongData,
+}
+
+/// The answer to `createShare`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SharesResponse {
+    pub shares: ShareList,
+}
+
+/// The share just created.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShareList {
+    #[serde(default)]
+    pub share: Vec<Share>,
+}
+
+/// A public link to songs, an album or a playlist, that plays without
+/// logging in.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Share {
+    pub id: String,
+    pub url: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// When the link stops working, if ever
+    #[serde(default)]
+    pub expires: Option<String>,
+    #[serde(default)]
+    pub visit_count: u64,
+}
+
+/// The answer to `jukeboxControl`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JukeboxStatusResponse {
+    #[serde(rename = "jukeboxStatus")]
+    pub jukebox_status: JukeboxStatus,
+}
+
+/// Where the server's own player is, after any jukebox action.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JukeboxStatus {
+    /// Index into the jukebox playlist; -1 when there's nothing to play
+    pub current_index: i32,
+    pub playing: bool,
+    /// Volume from 0 to 1
+    #[serde(default)]
+    pub gain: f32,
+    /// Seconds into the current song
+    #[serde(default)]
+    pub position: Option<u32>,
+}
+
+/// The answer to `getPlayQueue`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayQueueResponse {
+    /// Missing when nothing has been saved yet
+    #[serde(default, rename = "playQueue")]
+    pub play_queue: Option<PlayQueue>,
+}
+
+/// The queue shared between a user's clients, so listening can carry on
+/// from another device.
+///
+/// ```
+/// use danavi::types::PlayQueue;
+///
+/// let queue: PlayQueue = serde_json::from_str(r#"{
+///     "current": "tr-2", "position": 61000, "changed": "2024-03-05T10:20:30.5Z", "changedBy": "phone",
+///     "entry": [{"id": "tr-1", "title": "One"}, {"id": "tr-gone"}, {"id": "tr-2", "title": "Two"}]
+/// }"#)?;
+/// let (songs, unresolved) = queue.songs();
+/// assert_eq!((songs.len(), unresolved), (2, 1));
+/// assert_eq!(queue.changed_ms(), Some(1_709_634_030_500));
+///
+/// // The same moment with an offset, with or without the colon
+/// let changed_at = |changed: &str| {
+///     serde_json::from_value::<PlayQueue>(serde_json::json!({ "changed": changed })).unwrap().changed_ms()
+/// };
+/// assert_eq!(changed_at("2024-03-05T15:50:30.500+05:30"), Some(1_709_634_030_500));
+/// assert_eq!(changed_at("2024-03-05T15:50:30.500+0530"), Some(1_709_634_030_500));
+/// assert_eq!(changed_at("2024-03-05T05:20:30.5-05"), Some(1_709_634_030_500));
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayQueue {
+    #[serde(default)]
+    pub current: Option<String>,
+    /// Milliseconds into the current song
+    #[serde(default)]
+    pub position: Option<u64>,
+    /// When it was saved, as an ISO 8601 timestamp
+    #[serde(default)]
+    pub changed: Option<String>,
+    /// The client that saved it
+    #[serde(default, rename = "changedBy")]
+    pub changed_by: Option<String>,
+    #[serde(default)]
+    entry: Vec<QueueEntry>,
+}
+
+// Songs deleted since the queue was saved come back without their details,
+// and are only counted
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum QueueEntry {
+    Song(Box<SongData>),
+    Unresolved(serde::de::IgnoredAny),
+}
+
+impl PlayQueue {
+    /// The songs that still resolve, and how many didn't.
+    pub fn songs(&self) -> (Vec<SongData>, usize) {
+        let songs: Vec<SongData> = self
+            .entry
+            .iter()
+            .filter_map(|entry| match entry {
+                QueueEntry::Song(song) => Some(SongData::clone(song)),
+                QueueEntry::Unresolved(_) => None,
+            })
+            .collect();
+        let unresolved = self.entry.len() - songs.len();
+        (songs, unresolved)
+    }
+
+    /// `changed` as Unix time in milliseconds.
+    pub fn changed_ms(&self) -> Option<u64> {
+        parse_timestamp_ms(self.changed.as_deref()?)
+    }
+}
+
+// Reads "2024-03-05T10:20:30.5Z" and the like, with or without fractional
+// seconds and with a "Z", "+hh:mm", "+hhmm" or "+hh" offset
+fn parse_timestamp_ms(text: &str) -> Option<u64> {
+    let (date, time) = text.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(at) => time.split_at(at),
+        None => (time, ""),
+    };
+    let (clock, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut clock = clock.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next().flatten().unwrap_or(0));
+    let millis = format!("{:0<3}", fraction).get(..3)?.parse::<i64>().ok()?;
+    let offset_minutes = match offset.split_at_checked(1) {
+        Some((sign, hours_minutes)) if sign != "Z" => {
+            let (hours, minutes) = match hours_minutes.split_once(':') {
+                Some(split) => split,
+                None => hours_minutes.split_at_checked(2).filter(|(_, m)| !m.is_empty()).unwrap_or((hours_minutes, "0")),
+            };
+            let minutes = hours.parse::<i64>().ok()? * 60 + minutes.parse::<i64>().ok()?;
+            if sign == "-" { -minutes } else { minutes }
+        }
+        _ => 0,
+    };
+
+    // Days since the epoch for a proleptic Gregorian date
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second - offset_minutes * 60;
+    u64::try_from(seconds * 1000 + millis).ok()
+}
+
+/// The answer to `getPodcasts`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PodcastsResponse {
+    pub podcasts: PodcastList,
+}
+
+/// The podcast channels the server follows.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PodcastList {
+    #[serde(default)]
+    pub channel: Vec<PodcastChannel>,
+}
+
+/// A podcast, with its episodes when they were asked for.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PodcastChannel {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Only filled in when episodes are asked for
+    #[serde(default)]
+    pub episode: Vec<PodcastEpisode>,
+}
+
+/// A podcast episode, playable once the server has downloaded it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PodcastEpisode {
+    pub id: String,
+    /// What to stream; missing until the server has downloaded the episode
+    #[serde(default, rename = "streamId")]
+    pub stream_id: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// "new", "downloading", "completed", "error", "deleted" or "skipped"
+    #[serde(default)]
+    pub status: Option<String>,
+    /// Length in seconds
+    #[serde(default)]
+    pub duration: Option<i64>,
+    #[serde(default, rename = "publishDate")]
+    pub publish_date: Option<String>,
+}
+
+impl PodcastEpisode {
+    /// The id to stream, if the server has the episode to play.
+    ///
+    /// ```
+    /// use danavi::types::PodcastEpisode;
+    ///
+    /// let episode: PodcastEpisode =
+    ///     serde_json::from_str(r#"{ "id": "ep-1", "streamId": "tr-9", "status": "completed" }"#)?;
+    /// assert_eq!(episode.playable_id(), Some("tr-9"));
+    /// let episode: PodcastEpisode = serde_json::from_str(r#"{ "id": "ep-2", "status": "new" }"#)?;
+    /// assert_eq!(episode.playable_id(), None);
+    /// # Ok::<(), serde_json::Error>(())
+    /// ```
+    pub fn playable_id(&self) -> Option<&str> {
+        let downloaded = self.status.as_deref().is_none_or(|status| status == "completed");
+        self.stream_id.as_deref().filter(|_| downloaded)
+    }
+}
+
+/// The answer to `getPlaylists`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistsResponse {
+    pub playlists: PlaylistList,
+}
+
+/// The playlists the user can see.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistList {
+    #[serde(default)]
+    pub playlist: Vec<PlaylistData>,
+}
+
+/// A playlist kept on the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistData {
+    pub id: String,
+    pub name: String,
+    #[serde(default, rename = "songCount")]
+    pub song_count: Option<u64>,
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+/// The answer to `getPlaylist`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistResponse {
+    pub playlist: PlaylistDetail,
+}
+
+/// A playlist with its songs. Entries the server can no longer resolve
+/// to a song are left out rather than failing the whole playlist.
+///
+/// ```
+/// use danavi::types::PlaylistResponse;
+///
+/// let response: PlaylistResponse = serde_json::from_str(r#"{"playlist": {
+///     "id": "pl-1", "name": "Road trip", "songCount": 2,
+///     "entry": [{"id": "tr-1", "title": "Drive"}, {"id": "tr-gone"}]
+/// }}"#)?;
+/// assert_eq!(response.playlist.entry.len(), 1);
+/// assert_eq!(response.playlist.song_count, Some(2));
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistDetail {
+    pub id: String,
+    pub name: String,
+    #[serde(default, rename = "songCount")]
+    pub song_count: Option<u64>,
+    #[serde(default, deserialize_with = "resolvable_songs")]
+    pub entry: Vec<SongData>,
+}
+
+fn resolvable_songs<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<SongData>, D::Error> {
+    let entries = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| serde_json::from_value(entry).ok())
+        .collect())
+}
+
+/// The answer to `getLyricsBySongId`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LyricsListResponse {
+    #[serde(rename = "lyricsList")]
+    pub lyrics_list: LyricsList,
+}
+
+/// Lyrics for a song, one per language or kind.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LyricsList {
+    #[serde(default, rename = "structuredLyrics")]
+    pub structured_lyrics: Vec<StructuredLyrics>,
+}
+
+/// Lyrics as OpenSubsonic sends them, synced or not.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StructuredLyrics {
+    #[serde(default)]
+    pub lang: Option<String>,
+    pub synced: bool,
+    /// Milliseconds to show every line earlier by
+    #[serde(default)]
+    pub offset: Option<i64>,
+    #[serde(default)]
+    pub line: Vec<LyricLine>,
+}
+
+/// A line of lyrics, with when it starts if synced.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LyricLine {
+    /// Milliseconds into the track, for synced lyrics
+    #[serde(default)]
+    pub start: Option<i64>,
+    pub value: String,
+}
+
+/// The answer to `getLyrics`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LyricsResponse {
+    pub lyrics: PlainLyrics,
+}
+
+/// Unsynced lyrics found by artist and title.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlainLyrics {
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+/// What `search3` or, on servers without it, `search2` found.
+///
+/// ```
+/// use danavi::client::parse_response;
+/// use danavi::types::SearchResponse;
+///
+/// let search3: SearchResponse = parse_response("search3", br#"{"subsonic-response": {
+///     "status": "ok", "version": "1.16.1", "searchResult3": {
+///         "album": [{"id": "al-1", "name": "Tides", "artist": "Mara", "artistId": "ar-1", "songCount": 9}],
+///         "song": [{"id": "tr-1", "title": "Low Tide", "artist": "Mara", "album": "Tides"}]
+///     }
+/// }}"#)?;
+/// let found = search3.into_result().unwrap();
+/// let album = &found.album.unwrap()[0];
+/// assert_eq!((album.name.as_str(), album.artist_id.as_str(), album.folder), ("Tides", "ar-1", false));
+/// assert_eq!(found.song.unwrap()[0].title, "Low Tide");
+///
+/// // search2 lists albums as directories, named by title under their artist's
+/// let search2: SearchResponse = parse_response("search2", br#"{"subsonic-response": {
+///     "status": "ok", "version": "1.10.2", "searchResult2": {
+///         "artist": {"id": "dir-1", "name": "Mara"},
+///         "album": {"id": "dir-2", "parent": "dir-1", "title": "Tides", "artist": "Mara", "isDir": true},
+///         "song": [{"id": "dir-3", "parent": "dir-2", "title": "Low Tide", "artist": "Mara",
+///                   "album": "Tides", "isDir": false, "duration": 201}]
+///     }
+/// }}"#)?;
+/// let found = search2.into_result().unwrap();
+/// let album = &found.album.unwrap()[0];
+/// assert_eq!((album.id.as_str(), album.name.as_str(), album.artist_id.as_str()), ("dir-2", "Tides", "dir-1"));
+/// assert!(album.folder && album.song_count.is_none());
+/// assert_eq!(found.song.unwrap()[0].duration, Some(201));
+///
+/// let nothing: SearchResponse = parse_response("search2", br#"{"subsonic-response": {
+///     "status": "ok", "version": "1.10.2", "searchResult2": {}
+/// }}"#)?;
+/// assert!(nothing.into_result().unwrap().album.unwrap().is_empty());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Deserialize)]
 pub struct SearchResponse {
     #[serde(rename = "searchResult3")]
     pub search_result3: Option<SearchResult3>,
+    #[serde(default, rename = "searchResult2")]
+    pub search_result2: Option<SearchResult2>,
+}
+
+impl SearchResponse {
+    /// The results in `search3`'s shape, whichever endpoint answered.
+    pub fn into_result(self) -> Option<SearchResult3> {
+        if let Some(result) = self.search_result3 {
+            return Some(result);
+        }
+        let result = self.search_result2?;
+        let album = result
+            .album
+            .into_iter()
+            .map(|dir| SearchAlbum {
+                id: dir.id,
+                name: dir.title,
+                artist: dir.artist.unwrap_or_default(),
+                artist_id: dir.parent.unwrap_or_default(),
+                year: dir.year,
+                song_count: None,
+                starred: dir.starred,
+                cover_art: dir.cover_art,
+                user_rating: dir.user_rating,
+                folder: true,
+            })
+            .collect();
+        Some(SearchResult3 {
+            album: Some(album),
+            song: Some(result.song),
+        })
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// What `search3` found.
+#[derive(Debug, Clone, Deserialize)]
 pub struct SearchResult3 {
+    #[serde(default, deserialize_with = "maybe_one_or_many")]
     pub album: Option<Vec<SearchAlbum>>,
+    #[serde(default, deserialize_with = "maybe_one_or_many")]
     pub song: Option<Vec<SearchSong>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// What `search2` found.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchResult2 {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub album: Vec<SearchFolder>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub song: Vec<SearchSong>,
+}
+
+/// An album `search3` found.
+#[derive(Debug, Clone, Deserialize)]
 pub struct SearchAlbum {
     pub id: String,
     pub name: String,
     pub artist: String,
     #[serde(rename = "artistId")]
     pub artist_id: String,
+    #[serde(default)]
+    pub year: Option<i32>,
+    #[serde(default, rename = "songCount")]
+    pub song_count: Option<u32>,
+    #[serde(default)]
+    pub starred: Option<String>,
+    #[serde(default, rename = "coverArt")]
+    pub cover_art: Option<String>,
+    #[serde(default, rename = "userRating")]
+    pub user_rating: Option<u8>,
+    /// A directory from `search2`, so its id and `artist_id` are folder ids
+    /// for `getMusicDirectory` rather than `getAlbum` and `getArtist`
+    #[serde(skip)]
+    pub folder: bool,
+}
+
+/// An album as `search2` finds it: the directory holding its files.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchFolder {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub artist: Option<String>,
+    /// The artist's directory
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub year: Option<i32>,
+    #[serde(default)]
+    pub starred: Option<String>,
+    #[serde(default, rename = "coverArt")]
+    pub cover_art: Option<String>,
+    #[serde(default, rename = "userRating")]
+    pub user_rating: Option<u8>,
 }
 
-#[derive(Debug, Deserialize)]
+/// A song `search3` found.
+#[derive(Debug, Clone, Deserialize)]
 pub struct SearchSong {
     pub id: String,
     pub title: String,
@@ -160,6 +1545,20 @@ pub struct SearchSong {
     pub album: Option<String>,
     #[serde(default)]
     pub duration: Option<i64>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default, rename = "replayGain")]
+    pub replay_gain: Option<ReplayGain>,
+    #[serde(default)]
+    pub starred: Option<String>,
+    #[serde(default, rename = "coverArt")]
+    pub cover_art: Option<String>,
+    #[serde(default, rename = "userRating")]
+    pub user_rating: Option<u8>,
+    #[serde(default)]
+    pub genre: Option<String>,
+    #[serde(default)]
+    pub year: Option<i32>,
 }
 
 